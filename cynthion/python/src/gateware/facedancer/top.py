@@ -21,7 +21,7 @@ from luna_soc.gateware.core.spiflash import ECP5ConfigurationFlashInterface, SPI
 from luna_soc.gateware.cpu           import InterruptController, VexRiscv
 from luna_soc.gateware.provider      import cynthion as provider
 
-from . import advertiser, info
+from . import advertiser, info, usb_bus
 
 
 # - component: Soc ------------------------------------------------------------
@@ -80,6 +80,12 @@ class Soc(Component):
         self.advertiser_base      = 0x00001400
         self.info_base            = 0x00001500
         self.user0_base           = 0x00001600
+        self.usb0_bus_base        = 0x00001700
+        self.usb0_bus_irq         = 14
+        self.usb1_bus_base        = 0x00001800
+        self.usb1_bus_irq         = 15
+        self.usb2_bus_base        = 0x00001900
+        self.usb2_bus_irq         = 16
 
         # cpu
         self.cpu = VexRiscv(
@@ -178,6 +184,11 @@ class Soc(Component):
         self.interrupt_controller.add(self.usb0_ep_in,      name="usb0_ep_in",      number=self.usb0_ep_in_irq)
         self.interrupt_controller.add(self.usb0_ep_out,     name="usb0_ep_out",     number=self.usb0_ep_out_irq)
 
+        # usb0 - bus state
+        self.usb0_bus = usb_bus.Peripheral()
+        self.csr_decoder.add(self.usb0_bus.bus, addr=self.usb0_bus_base, name="usb0_bus")
+        self.interrupt_controller.add(self.usb0_bus, name="usb0_bus", number=self.usb0_bus_irq)
+
         # usb1 - aux_phy
         self.usb1            = usb2.device.Peripheral()
         self.usb1_ep_control = usb2.ep_control.Peripheral()
@@ -192,6 +203,11 @@ class Soc(Component):
         self.interrupt_controller.add(self.usb1_ep_in,      name="usb1_ep_in",      number=self.usb1_ep_in_irq)
         self.interrupt_controller.add(self.usb1_ep_out,     name="usb1_ep_out",     number=self.usb1_ep_out_irq)
 
+        # usb1 - bus state
+        self.usb1_bus = usb_bus.Peripheral()
+        self.csr_decoder.add(self.usb1_bus.bus, addr=self.usb1_bus_base, name="usb1_bus")
+        self.interrupt_controller.add(self.usb1_bus, name="usb1_bus", number=self.usb1_bus_irq)
+
         # usb2 - control_phy
         self.usb2            = usb2.device.Peripheral()
         self.usb2_ep_control = usb2.ep_control.Peripheral()
@@ -206,6 +222,11 @@ class Soc(Component):
         self.interrupt_controller.add(self.usb2_ep_in,      name="usb2_ep_in",      number=self.usb2_ep_in_irq)
         self.interrupt_controller.add(self.usb2_ep_out,     name="usb2_ep_out",     number=self.usb2_ep_out_irq)
 
+        # usb2 - bus state
+        self.usb2_bus = usb_bus.Peripheral()
+        self.csr_decoder.add(self.usb2_bus.bus, addr=self.usb2_bus_base, name="usb2_bus")
+        self.interrupt_controller.add(self.usb2_bus, name="usb2_bus", number=self.usb2_bus_irq)
+
         # apollo advertiser
         self.advertiser_provider = provider.ApolloAdvertiserProvider("int")
         self.advertiser = advertiser.Peripheral(pad=self.advertiser_provider.pins, clk_freq_hz=clock_frequency_hz)
@@ -289,7 +310,8 @@ class Soc(Component):
         usb0_device.add_endpoint(self.usb0_ep_in)
         usb0_device.add_endpoint(self.usb0_ep_out)
         m.d.comb += self.usb0.attach(usb0_device)
-        m.submodules += [ulpi0_provider, self.usb0, usb0_device]
+        m.d.comb += self.usb0_bus.attach(usb0_device)
+        m.submodules += [ulpi0_provider, self.usb0, usb0_device, self.usb0_bus]
 
         # usb1 - aux_phy
         ulpi1_provider = provider.ULPIProvider(["aux_phy", "host_phy"])
@@ -298,7 +320,8 @@ class Soc(Component):
         usb1_device.add_endpoint(self.usb1_ep_in)
         usb1_device.add_endpoint(self.usb1_ep_out)
         m.d.comb += self.usb1.attach(usb1_device)
-        m.submodules += [ulpi1_provider, self.usb1, usb1_device]
+        m.d.comb += self.usb1_bus.attach(usb1_device)
+        m.submodules += [ulpi1_provider, self.usb1, usb1_device, self.usb1_bus]
 
         # usb2 - control_phy
         ulpi2_provider = provider.ULPIProvider(["control_phy", "sideband_phy"])
@@ -307,7 +330,8 @@ class Soc(Component):
         usb2_device.add_endpoint(self.usb2_ep_in)
         usb2_device.add_endpoint(self.usb2_ep_out)
         m.d.comb += self.usb2.attach(usb2_device)
-        m.submodules += [ulpi2_provider, self.usb2, usb2_device]
+        m.d.comb += self.usb2_bus.attach(usb2_device)
+        m.submodules += [ulpi2_provider, self.usb2, usb2_device, self.usb2_bus]
 
         # advertiser
         m.submodules += [self.advertiser, self.advertiser_provider]
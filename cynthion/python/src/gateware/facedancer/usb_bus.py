@@ -0,0 +1,125 @@
+#
+# This file is part of Cynthion.
+#
+# Copyright (c) 2025 Great Scott Gadgets <info@greatscottgadgets.com>
+# SPDX-License-Identifier: BSD-3-Clause
+
+from amaranth              import *
+from amaranth.lib          import wiring
+from amaranth.lib.wiring   import In, Out, flipped, connect
+
+from amaranth_soc          import csr
+
+
+class Peripheral(wiring.Component):
+    """ Peripheral reporting the bus state of a LUNA USBDevice.
+
+    Complements the eptri ``usb2.device.Peripheral`` with the frame number
+    and suspend state of the device and raises an interrupt on start-of-frame,
+    suspend and resume.
+
+    Registers
+    ---------
+    status : read
+        Current frame number and suspend state.
+    ev_enable : read/write
+        Enables the interrupt for each event.
+    ev_pending : read/write
+        Events which have occurred. Write '1' to a field to clear it.
+    """
+
+    class Status(csr.Register, access="r"):
+        """Status register
+
+            frame_number: The frame number of the last start-of-frame packet.
+            suspended:    Indicates that the bus has been idle for more than 3ms and the
+                          device is suspended.
+        """
+        frame_number : csr.Field(csr.action.R,      unsigned(11))
+        suspended    : csr.Field(csr.action.R,      unsigned(1))
+        _0           : csr.Field(csr.action.ResR0W0, unsigned(4))
+
+    class EvEnable(csr.Register, access="rw"):
+        """Event enable register
+
+            sof:     Set this bit to '1' to raise an interrupt on each start-of-frame packet.
+            suspend: Set this bit to '1' to raise an interrupt when the bus is suspended.
+            resume:  Set this bit to '1' to raise an interrupt when the bus resumes.
+        """
+        sof     : csr.Field(csr.action.RW,      unsigned(1))
+        suspend : csr.Field(csr.action.RW,      unsigned(1))
+        resume  : csr.Field(csr.action.RW,      unsigned(1))
+        _0      : csr.Field(csr.action.ResR0W0, unsigned(5))
+
+    class EvPending(csr.Register, access="rw"):
+        """Event pending register
+
+            sof:     A start-of-frame packet has been received. Write '1' to clear.
+            suspend: The bus has been suspended. Write '1' to clear.
+            resume:  The bus has resumed after a suspend. Write '1' to clear.
+        """
+        sof     : csr.Field(csr.action.RW1C,    unsigned(1))
+        suspend : csr.Field(csr.action.RW1C,    unsigned(1))
+        resume  : csr.Field(csr.action.RW1C,    unsigned(1))
+        _0      : csr.Field(csr.action.ResR0W0, unsigned(5))
+
+    def __init__(self):
+        # registers
+        regs = csr.Builder(addr_width=4, data_width=8)
+        self._status     = regs.add("status",     self.Status(),    offset=0x00)
+        self._ev_enable  = regs.add("ev_enable",  self.EvEnable(),  offset=0x08)
+        self._ev_pending = regs.add("ev_pending", self.EvPending(), offset=0x09)
+
+        # bridge
+        self._bridge = csr.Bridge(regs.as_memory_map())
+
+        # bus
+        super().__init__({
+            "bus" : In(self._bridge.bus.signature),
+            "irq" : Out(unsigned(1)),
+        })
+        self.bus.memory_map = self._bridge.bus.memory_map
+
+        # device state, see attach()
+        self.frame_number = Signal(11)
+        self.sof_detected = Signal()
+        self.suspended    = Signal()
+
+    def attach(self, device):
+        """ Returns the statements connecting this peripheral to a LUNA ``USBDevice``. """
+        return [
+            self.frame_number .eq(device.frame_number),
+            self.sof_detected .eq(device.sof_detected),
+            self.suspended    .eq(device.suspended),
+        ]
+
+    def elaborate(self, platform):
+        m = Module()
+        m.submodules += self._bridge
+
+        connect(m, self.bus, self._bridge.bus)
+
+        # status
+        m.d.comb += [
+            self._status.f.frame_number.r_data .eq(self.frame_number),
+            self._status.f.suspended.r_data    .eq(self.suspended),
+        ]
+
+        # suspend and resume are the edges of the suspend state
+        suspended_prev = Signal()
+        m.d.sync += suspended_prev.eq(self.suspended)
+
+        m.d.comb += [
+            self._ev_pending.f.sof.set     .eq(self.sof_detected),
+            self._ev_pending.f.suspend.set .eq(self.suspended & ~suspended_prev),
+            self._ev_pending.f.resume.set  .eq(~self.suspended & suspended_prev),
+        ]
+
+        # interrupt
+        m.d.comb += self.irq.eq(
+            (self._ev_pending.f.sof.data     & self._ev_enable.f.sof.data)     |
+            (self._ev_pending.f.suspend.data & self._ev_enable.f.suspend.data) |
+            (self._ev_pending.f.resume.data  & self._ev_enable.f.resume.data)
+        )
+
+        return m
@@ -12,8 +12,12 @@ pub mod timer;
 pub mod usb;
 
 // re-export dependencies
+#[cfg(feature = "embassy-usb-driver")]
+pub use embassy_usb_driver;
 #[cfg(feature = "usb")]
 pub use smolusb;
+#[cfg(feature = "usb-device")]
+pub use usb_device;
 
 pub use embedded_hal as hal;
 pub use embedded_hal_0 as hal_0;
@@ -3,6 +3,11 @@
 /// Re-export smolusb error type
 pub use smolusb::error::ErrorKind as Error;
 
+#[cfg(feature = "usb-device")]
+pub mod bus;
+#[cfg(feature = "embassy-usb-driver")]
+pub mod embassy;
+
 /// Default timeout for USB operations
 pub const DEFAULT_TIMEOUT: usize = 10_000_000;
 
@@ -11,14 +16,14 @@ pub const DEFAULT_TIMEOUT: usize = 10_000_000;
 /// For example:
 ///
 ///     impl_usb! {
-///         Usb0: USB0, USB0_EP_CONTROL, USB0_EP_IN, USB0_EP_OUT,
-///         Usb1: USB1, USB1_EP_CONTROL, USB1_EP_IN, USB1_EP_OUT,
+///         Usb0: usb0, USB0, USB0_EP_CONTROL, USB0_EP_IN, USB0_EP_OUT, USB0_BUS,
+///         Usb1: usb1, USB1, USB1_EP_CONTROL, USB1_EP_IN, USB1_EP_OUT, USB1_BUS,
 ///     }
 ///
 #[macro_export]
 macro_rules! impl_usb {
     ($(
-        $USBX:ident: $IDX:ident, $USBX_DEVICE:ty, $USBX_EP_CONTROL:ty, $USBX_EP_IN:ty, $USBX_EP_OUT:ty,
+        $USBX:ident: $IDX:ident, $USBX_DEVICE:ty, $USBX_EP_CONTROL:ty, $USBX_EP_IN:ty, $USBX_EP_OUT:ty, $USBX_BUS:ty,
     )+) => {
         $(
             pub struct $USBX {
@@ -26,6 +31,7 @@ macro_rules! impl_usb {
                 pub ep_control: $USBX_EP_CONTROL,
                 pub ep_in: $USBX_EP_IN,
                 pub ep_out: $USBX_EP_OUT,
+                pub bus: $USBX_BUS,
                 pub device_speed: Speed,
             }
 
@@ -36,12 +42,14 @@ macro_rules! impl_usb {
                     ep_control: $USBX_EP_CONTROL,
                     ep_in: $USBX_EP_IN,
                     ep_out: $USBX_EP_OUT,
+                    bus: $USBX_BUS,
                 ) -> Self {
                     Self {
                         device,
                         ep_control,
                         ep_in,
                         ep_out,
+                        bus,
                         device_speed: Speed::Unknown,
                     }
                 }
@@ -54,8 +62,9 @@ macro_rules! impl_usb {
                     $USBX_EP_CONTROL,
                     $USBX_EP_IN,
                     $USBX_EP_OUT,
+                    $USBX_BUS,
                 ) {
-                    (self.device, self.ep_control, self.ep_in, self.ep_out)
+                    (self.device, self.ep_control, self.ep_in, self.ep_out, self.bus)
                 }
 
                 /// Obtain a static [`Usb0`] instance for use in e.g. interrupt handlers
@@ -70,6 +79,7 @@ macro_rules! impl_usb {
                         ep_control: <$USBX_EP_CONTROL>::steal(),
                         ep_in: <$USBX_EP_IN>::steal(),
                         ep_out: <$USBX_EP_OUT>::steal(),
+                        bus: <$USBX_BUS>::steal(),
                         device_speed: Speed::Unknown,
                     }
                 }
@@ -99,18 +109,24 @@ macro_rules! impl_usb {
 
             impl $USBX {
                 /// Enable all device interrupt events.
+                ///
+                /// Start-of-frame events are raised for every (micro)frame
+                /// and must be enabled separately with
+                /// [`set_start_of_frame_events()`](Self::set_start_of_frame_events).
                 pub fn enable_events(&self) {
                     // clear all pending event
                     self.device.ev_pending().modify(|r, w| w.mask().bit(r.mask().bit()));
                     self.ep_control.ev_pending().modify(|r, w| w.mask().bit(r.mask().bit()));
                     self.ep_in.ev_pending().modify(|r, w| w.mask().bit(r.mask().bit()));
                     self.ep_out.ev_pending().modify(|r, w| w.mask().bit(r.mask().bit()));
+                    self.bus.ev_pending().write(|w| w.sof().bit(true).suspend().bit(true).resume().bit(true));
 
                     // enable all events
                     self.device.ev_enable().write(|w| w.mask().bit(true));
                     self.ep_control.ev_enable().write(|w| w.mask().bit(true));
                     self.ep_in.ev_enable().write(|w| w.mask().bit(true));
                     self.ep_out.ev_enable().write(|w| w.mask().bit(true));
+                    self.bus.ev_enable().modify(|_, w| w.suspend().bit(true).resume().bit(true));
                 }
 
                 /// Disable all device interrupt events.
@@ -120,12 +136,57 @@ macro_rules! impl_usb {
                     self.ep_control.ev_pending().modify(|r, w| w.mask().bit(r.mask().bit()));
                     self.ep_in.ev_pending().modify(|r, w| w.mask().bit(r.mask().bit()));
                     self.ep_out.ev_pending().modify(|r, w| w.mask().bit(r.mask().bit()));
+                    self.bus.ev_pending().write(|w| w.sof().bit(true).suspend().bit(true).resume().bit(true));
 
                     // disable all events
                     self.device.ev_enable().write(|w| w.mask().bit(false));
                     self.ep_control.ev_enable().write(|w| w.mask().bit(false));
                     self.ep_in.ev_enable().write(|w| w.mask().bit(false));
                     self.ep_out.ev_enable().write(|w| w.mask().bit(false));
+                    self.bus.ev_enable().write(|w| w.sof().bit(false).suspend().bit(false).resume().bit(false));
+                }
+
+                /// Enable or disable start-of-frame interrupt events.
+                pub fn set_start_of_frame_events(&self, enabled: bool) {
+                    self.bus.ev_enable().modify(|_, w| w.sof().bit(enabled));
+                }
+
+                /// Returns the frame number of the last start-of-frame packet.
+                #[must_use]
+                pub fn frame_number(&self) -> u16 {
+                    self.bus.status().read().frame_number().bits()
+                }
+
+                /// Returns `true` if the bus has been idle for more than
+                /// 3ms and the device is suspended.
+                #[must_use]
+                pub fn is_suspended(&self) -> bool {
+                    self.bus.status().read().suspended().bit()
+                }
+
+                /// Returns and clears the next pending bus event.
+                ///
+                /// Returns `UsbEvent::Suspend`, `UsbEvent::Resume` or
+                /// `UsbEvent::StartOfFrame` with the current frame number.
+                /// If both a suspend and a resume are pending only the one
+                /// matching the current bus state is returned.
+                pub fn take_bus_event(&self) -> Option<$crate::smolusb::event::UsbEvent> {
+                    use $crate::smolusb::event::UsbEvent;
+
+                    let pending = self.bus.ev_pending().read();
+                    let (suspend, resume) = (pending.suspend().bit(), pending.resume().bit());
+                    if suspend || resume {
+                        self.bus.ev_pending().write(|w| w.suspend().bit(suspend).resume().bit(resume));
+                        let suspended = if suspend && resume { self.is_suspended() } else { suspend };
+                        return Some(if suspended { UsbEvent::Suspend } else { UsbEvent::Resume });
+                    }
+
+                    if pending.sof().bit() {
+                        self.bus.ev_pending().write(|w| w.sof().bit(true));
+                        return Some(UsbEvent::StartOfFrame(self.frame_number()));
+                    }
+
+                    None
                 }
 
                 /// Returns the address of the control endpoint.
@@ -138,6 +199,13 @@ macro_rules! impl_usb {
             // - trait: UsbDriverOperations -----------------------------------
 
             impl UsbDriverOperations for $USBX {
+                /// The endpoint FIFOs hold a single packet per transaction.
+                const PERIPHERAL_LIMITS: $crate::smolusb::validate::PeripheralLimits =
+                    $crate::smolusb::validate::PeripheralLimits {
+                        max_packet_size: smolusb::EP_MAX_PACKET_SIZE,
+                        high_bandwidth: false,
+                    };
+
                 /// Connect the device.
                 fn connect(&mut self, device_speed: Speed) {
                     // set the device speed
@@ -317,6 +385,7 @@ macro_rules! impl_usb {
                 pub static TX_ACK_ACTIVE: [core::sync::atomic::AtomicBool; EP_MAX_ENDPOINTS] =
                     [ATOMIC_FALSE; EP_MAX_ENDPOINTS];
 
+                $crate::__impl_usb_bus_state!();
             }
 
             impl UnsafeUsbDriverOperations for $USBX {
@@ -551,10 +620,99 @@ macro_rules! impl_usb {
 
                     bytes_written
                 }
+
+                fn write_packet<I>(&self, endpoint_number: u8, iter: I) -> Result<usize, $crate::smolusb::error::EndpointBusy>
+                where
+                    I: Iterator<Item = u8>
+                {
+                    // check if ep_in is available
+                    if self.ep_in_busy(endpoint_number, "usb::write_packet()") {
+                        return Err($crate::smolusb::error::EndpointBusy);
+                    }
+
+                    let mut bytes_written: usize = 0;
+                    for byte in iter.take(smolusb::EP_MAX_PACKET_SIZE) {
+                        self.ep_in.data().write(|w| unsafe { w.byte().bits(byte) });
+                        bytes_written += 1;
+                    }
+
+                    // prime the IN endpoint to send it
+                    unsafe {
+                        self.set_tx_ack_active(endpoint_number);
+                    }
+                    self.ep_in
+                        .endpoint()
+                        .write(|w| unsafe { w.number().bits(endpoint_number) });
+
+                    Ok(bytes_written)
+                }
+            }
+
+            // - trait: IsochronousEndpoint -----------------------------------
+
+            impl $crate::smolusb::traits::IsochronousEndpoint for $USBX {
+                fn write_isochronous<I>(&self, endpoint_number: u8, iter: I) -> usize
+                where
+                    I: Iterator<Item = u8>
+                {
+                    // the IN FIFO is shared by all endpoints
+                    let status = self.ep_in.status().read();
+                    if status.have().bit() {
+                        if status.epno().bits() != endpoint_number {
+                            log::debug!("  {} write_isochronous() ep_in busy with endpoint {}",
+                                        stringify!($USBX), status.epno().bits());
+                            return 0;
+                        }
+                        // the host did not poll for the previous packet, discard it
+                        self.ep_in.reset().write(|w| w.fifo().bit(true));
+                    }
+
+                    let mut bytes_written: usize = 0;
+                    for byte in iter.take(smolusb::EP_MAX_PACKET_SIZE) {
+                        self.ep_in.data().write(|w| unsafe { w.byte().bits(byte) });
+                        bytes_written += 1;
+                    }
+
+                    // prime the IN endpoint, there is no handshake to wait for
+                    self.ep_in
+                        .endpoint()
+                        .write(|w| unsafe { w.number().bits(endpoint_number) });
+
+                    bytes_written
+                }
+
+                fn read_isochronous(&self, endpoint_number: u8, buffer: &mut [u8]) -> usize {
+                    let bytes_read = self.read(endpoint_number, buffer);
+                    self.ep_out_prime_receive(endpoint_number);
+                    bytes_read
+                }
             }
 
             // mark implementation as complete
             impl UsbDriver for $USBX {}
+
+            // - trait: usb_device::bus::UsbBus -------------------------------
+
+            $crate::__impl_usb_bus!($USBX, $IDX);
         )+
     }
 }
+
+// - usb-device ---------------------------------------------------------------
+
+// The `usb-device` feature is resolved here rather than inside
+// `impl_usb!` where it would be evaluated against the caller's features.
+
+#[cfg(not(feature = "usb-device"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_usb_bus_state {
+    () => {};
+}
+
+#[cfg(not(feature = "usb-device"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_usb_bus {
+    ($USBX:ident, $IDX:ident) => {};
+}
@@ -0,0 +1,542 @@
+//! `usb-device` bus implementation for luna eptri peripherals
+//!
+//! Enabled by the `usb-device` feature, this provides an
+//! implementation of [`usb_device::bus::UsbBus`] for each peripheral
+//! generated by [`impl_usb!`](crate::impl_usb).
+//!
+//! For example:
+//!
+//!     let usb0 = hal::Usb0::new(
+//!         peripherals.USB0,
+//!         peripherals.USB0_EP_CONTROL,
+//!         peripherals.USB0_EP_IN,
+//!         peripherals.USB0_EP_OUT,
+//!         peripherals.USB0_BUS,
+//!     );
+//!     let usb_bus = UsbBusAllocator::new(usb0);
+//!     let mut serial = usbd_serial::SerialPort::new(&usb_bus);
+//!     let mut usb_dev = UsbDeviceBuilder::new(&usb_bus, UsbVidPid(0x1209, 0x0001)).build();
+//!
+//!     loop {
+//!         if usb_dev.poll(&mut [&mut serial]) {
+//!             // ...
+//!         }
+//!     }
+//!
+//! `UsbBus::poll()` consumes the peripheral's pending events directly
+//! so it should either be called with the USB interrupts disabled or
+//! from the USB interrupt handlers themselves.
+
+use core::cell::UnsafeCell;
+
+use usb_device::bus::PollResult;
+use usb_device::endpoint::{EndpointAddress, EndpointType};
+use usb_device::{UsbDirection, UsbError};
+
+use smolusb::EP_MAX_ENDPOINTS;
+
+// - UsbBusState --------------------------------------------------------------
+
+#[derive(Clone, Copy)]
+struct State {
+    allocated_out: u16,
+    allocated_in: u16,
+    max_packet_size_out: [u16; EP_MAX_ENDPOINTS],
+    max_packet_size_in: [u16; EP_MAX_ENDPOINTS],
+    stalled_out: u16,
+    stalled_in: u16,
+    pending_out: u16,
+    pending_setup: bool,
+}
+
+/// Endpoint allocation and transfer state for a [`usb_device::bus::UsbBus`]
+/// implementation.
+///
+/// The eptri peripheral has no registers for endpoint configuration
+/// so we keep track of it here.
+pub struct UsbBusState(UnsafeCell<State>);
+
+// SAFETY: all access to the inner state happens inside a critical section
+unsafe impl Sync for UsbBusState {}
+
+impl Default for UsbBusState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsbBusState {
+    pub const fn new() -> Self {
+        Self(UnsafeCell::new(State {
+            allocated_out: 0,
+            allocated_in: 0,
+            max_packet_size_out: [0; EP_MAX_ENDPOINTS],
+            max_packet_size_in: [0; EP_MAX_ENDPOINTS],
+            stalled_out: 0,
+            stalled_in: 0,
+            pending_out: 0,
+            pending_setup: false,
+        }))
+    }
+
+    #[inline(always)]
+    fn with<R>(&self, f: impl FnOnce(&mut State) -> R) -> R {
+        riscv::interrupt::free(|| f(unsafe { &mut *self.0.get() }))
+    }
+
+    /// Allocate an endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`UsbError::InvalidEndpoint`] if the requested endpoint
+    /// is already allocated or out of range and
+    /// [`UsbError::EndpointOverflow`] if there are no free endpoints.
+    pub fn alloc_ep(
+        &self,
+        ep_dir: UsbDirection,
+        ep_addr: Option<EndpointAddress>,
+        ep_type: EndpointType,
+        max_packet_size: u16,
+    ) -> usb_device::Result<EndpointAddress> {
+        if usize::from(max_packet_size) > smolusb::EP_MAX_PACKET_SIZE {
+            return Err(UsbError::EndpointMemoryOverflow);
+        }
+
+        self.with(|state| {
+            let (allocated, max_packet_sizes) = match ep_dir {
+                UsbDirection::Out => (&mut state.allocated_out, &mut state.max_packet_size_out),
+                UsbDirection::In => (&mut state.allocated_in, &mut state.max_packet_size_in),
+            };
+
+            let index = match ep_addr {
+                Some(ep_addr) => {
+                    let index = ep_addr.index();
+                    if index >= EP_MAX_ENDPOINTS || *allocated & (1 << index) != 0 {
+                        return Err(UsbError::InvalidEndpoint);
+                    }
+                    index
+                }
+                None => {
+                    // endpoint 0 is reserved for control transfers
+                    let first = if ep_type == EndpointType::Control {
+                        0
+                    } else {
+                        1
+                    };
+                    (first..EP_MAX_ENDPOINTS)
+                        .find(|index| *allocated & (1 << index) == 0)
+                        .ok_or(UsbError::EndpointOverflow)?
+                }
+            };
+
+            *allocated |= 1 << index;
+            max_packet_sizes[index] = max_packet_size;
+
+            Ok(EndpointAddress::from_parts(index, ep_dir))
+        })
+    }
+
+    /// Returns a bitmask of the allocated endpoints in the given direction.
+    pub fn allocated(&self, ep_dir: UsbDirection) -> u16 {
+        self.with(|state| match ep_dir {
+            UsbDirection::Out => state.allocated_out,
+            UsbDirection::In => state.allocated_in,
+        })
+    }
+
+    /// Returns the maximum packet size of the given endpoint.
+    pub fn max_packet_size(&self, ep_addr: EndpointAddress) -> Option<usize> {
+        self.with(|state| {
+            let index = ep_addr.index();
+            let (allocated, max_packet_sizes) = match ep_addr.direction() {
+                UsbDirection::Out => (state.allocated_out, &state.max_packet_size_out),
+                UsbDirection::In => (state.allocated_in, &state.max_packet_size_in),
+            };
+            if index < EP_MAX_ENDPOINTS && allocated & (1 << index) != 0 {
+                Some(usize::from(max_packet_sizes[index]))
+            } else {
+                None
+            }
+        })
+    }
+
+    pub fn set_stalled(&self, ep_addr: EndpointAddress, stalled: bool) {
+        self.with(|state| {
+            let mask = match ep_addr.direction() {
+                UsbDirection::Out => &mut state.stalled_out,
+                UsbDirection::In => &mut state.stalled_in,
+            };
+            if stalled {
+                *mask |= 1 << ep_addr.index();
+            } else {
+                *mask &= !(1 << ep_addr.index());
+            }
+        });
+    }
+
+    pub fn is_stalled(&self, ep_addr: EndpointAddress) -> bool {
+        self.with(|state| {
+            let mask = match ep_addr.direction() {
+                UsbDirection::Out => state.stalled_out,
+                UsbDirection::In => state.stalled_in,
+            };
+            mask & (1 << ep_addr.index()) != 0
+        })
+    }
+
+    /// Record receipt of a setup packet on the control endpoint.
+    pub fn set_setup_pending(&self) {
+        self.with(|state| state.pending_setup = true);
+    }
+
+    /// Returns `true` and clears the flag if a setup packet is waiting to be read.
+    pub fn take_setup_pending(&self) -> bool {
+        self.with(|state| core::mem::replace(&mut state.pending_setup, false))
+    }
+
+    /// Record receipt of a packet on the given OUT endpoint.
+    pub fn set_out_pending(&self, endpoint_number: u8) {
+        self.with(|state| state.pending_out |= 1 << endpoint_number);
+    }
+
+    /// Returns `true` and clears the flag if a packet is waiting to be
+    /// read from the given OUT endpoint.
+    pub fn take_out_pending(&self, endpoint_number: u8) -> bool {
+        self.with(|state| {
+            let pending = state.pending_out & (1 << endpoint_number) != 0;
+            state.pending_out &= !(1 << endpoint_number);
+            pending
+        })
+    }
+
+    /// Returns the pending events which have not been read yet.
+    pub fn pending(&self) -> PollResult {
+        self.with(|state| {
+            if state.pending_out == 0 && !state.pending_setup {
+                PollResult::None
+            } else {
+                PollResult::Data {
+                    ep_out: state.pending_out,
+                    ep_in_complete: 0,
+                    ep_setup: u16::from(state.pending_setup),
+                }
+            }
+        })
+    }
+
+    /// Clear transfer state following a bus reset.
+    pub fn reset(&self) {
+        self.with(|state| {
+            state.stalled_out = 0;
+            state.stalled_in = 0;
+            state.pending_out = 0;
+            state.pending_setup = false;
+        });
+    }
+}
+
+// - macros -------------------------------------------------------------------
+
+/// Generates the [`UsbBusState`] for an `impl_usb!` peripheral.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_usb_bus_state {
+    () => {
+        pub static USB_BUS_STATE: $crate::usb::bus::UsbBusState =
+            $crate::usb::bus::UsbBusState::new();
+    };
+}
+
+/// Generates the [`usb_device::bus::UsbBus`] implementation for an
+/// `impl_usb!` peripheral.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_usb_bus {
+    ($USBX:ident, $IDX:ident) => {
+        // SAFETY: usb-device requires the bus to be Sync. The eptri
+        // registers are only accessed from a single hart and the
+        // shared endpoint state is protected by a critical section.
+        unsafe impl Sync for $USBX {}
+
+        impl $USBX {
+            /// Prime all allocated OUT endpoints for reception.
+            fn usb_bus_prime_all(&self) {
+                use $crate::smolusb::traits::ReadEndpoint;
+                use $crate::usb_device::UsbDirection;
+
+                let allocated = $IDX::USB_BUS_STATE.allocated(UsbDirection::Out);
+                for endpoint_number in 0..$crate::smolusb::EP_MAX_ENDPOINTS as u8 {
+                    if allocated & (1 << endpoint_number) != 0 {
+                        self.ep_out_prime_receive(endpoint_number);
+                    }
+                }
+            }
+        }
+
+        impl $crate::usb_device::bus::UsbBus for $USBX {
+            fn alloc_ep(
+                &mut self,
+                ep_dir: $crate::usb_device::UsbDirection,
+                ep_addr: Option<$crate::usb_device::endpoint::EndpointAddress>,
+                ep_type: $crate::usb_device::endpoint::EndpointType,
+                max_packet_size: u16,
+                _interval: u8,
+            ) -> $crate::usb_device::Result<$crate::usb_device::endpoint::EndpointAddress> {
+                $IDX::USB_BUS_STATE.alloc_ep(ep_dir, ep_addr, ep_type, max_packet_size)
+            }
+
+            fn enable(&mut self) {
+                use $crate::smolusb::device::Speed;
+                use $crate::smolusb::traits::UsbDriverOperations;
+
+                let device_speed = match self.device_speed {
+                    Speed::Unknown => Speed::High,
+                    device_speed => device_speed,
+                };
+                self.connect(device_speed);
+            }
+
+            fn reset(&self) {
+                use $crate::smolusb::traits::{UnsafeUsbDriverOperations, UsbDriverOperations};
+
+                // reset device address to 0
+                self.set_address(0);
+
+                // reset FIFOs
+                self.ep_control.reset().write(|w| w.fifo().bit(true));
+                self.ep_in.reset().write(|w| w.fifo().bit(true));
+                self.ep_out.reset().write(|w| w.fifo().bit(true));
+
+                // clear status for all IN endpoints
+                for endpoint_number in 0..$crate::smolusb::EP_MAX_ENDPOINTS as u8 {
+                    unsafe {
+                        self.clear_tx_ack_active(endpoint_number);
+                    }
+                }
+
+                $IDX::USB_BUS_STATE.reset();
+                self.usb_bus_prime_all();
+
+                log::trace!("{}::UsbBus::reset()", stringify!($USBX));
+            }
+
+            fn set_device_address(&self, address: u8) {
+                use $crate::smolusb::traits::UsbDriverOperations;
+                self.set_address(address);
+            }
+
+            fn write(
+                &self,
+                ep_addr: $crate::usb_device::endpoint::EndpointAddress,
+                buf: &[u8],
+            ) -> $crate::usb_device::Result<usize> {
+                use $crate::smolusb::traits::UnsafeUsbDriverOperations;
+                use $crate::usb_device::UsbError;
+
+                let Some(max_packet_size) = $IDX::USB_BUS_STATE.max_packet_size(ep_addr) else {
+                    return Err(UsbError::InvalidEndpoint);
+                };
+                if buf.len() > max_packet_size {
+                    return Err(UsbError::BufferOverflow);
+                }
+
+                // the IN fifo is shared by all endpoints
+                for endpoint_number in 0..$crate::smolusb::EP_MAX_ENDPOINTS as u8 {
+                    if unsafe { self.is_tx_ack_active(endpoint_number) } {
+                        return Err(UsbError::WouldBlock);
+                    }
+                }
+
+                let endpoint_number = ep_addr.index() as u8;
+                for byte in buf {
+                    self.ep_in.data().write(|w| unsafe { w.byte().bits(*byte) });
+                }
+                unsafe {
+                    self.set_tx_ack_active(endpoint_number);
+                }
+                self.ep_in
+                    .endpoint()
+                    .write(|w| unsafe { w.number().bits(endpoint_number) });
+
+                log::trace!(
+                    "  TX {} IN {} {} bytes",
+                    stringify!($USBX),
+                    endpoint_number,
+                    buf.len()
+                );
+
+                Ok(buf.len())
+            }
+
+            fn read(
+                &self,
+                ep_addr: $crate::usb_device::endpoint::EndpointAddress,
+                buf: &mut [u8],
+            ) -> $crate::usb_device::Result<usize> {
+                use $crate::smolusb::traits::ReadEndpoint;
+                use $crate::usb_device::UsbError;
+
+                let endpoint_number = ep_addr.index() as u8;
+
+                // setup packets arrive on the control endpoint fifo
+                if endpoint_number == 0 && $IDX::USB_BUS_STATE.take_setup_pending() {
+                    let mut bytes_read = 0;
+                    while self.ep_control.status().read().have().bit() {
+                        let byte = self.ep_control.data().read().byte().bits();
+                        if let Some(b) = buf.get_mut(bytes_read) {
+                            *b = byte;
+                        }
+                        bytes_read += 1;
+                    }
+                    // prime for the data or status stage
+                    self.ep_out_prime_receive(0);
+                    return if bytes_read > buf.len() {
+                        Err(UsbError::BufferOverflow)
+                    } else {
+                        Ok(bytes_read)
+                    };
+                }
+
+                if !$IDX::USB_BUS_STATE.take_out_pending(endpoint_number) {
+                    return Err(UsbError::WouldBlock);
+                }
+
+                let mut bytes_read = 0;
+                while self.ep_out.status().read().have().bit() {
+                    let byte = self.ep_out.data().read().byte().bits();
+                    if let Some(b) = buf.get_mut(bytes_read) {
+                        *b = byte;
+                    }
+                    bytes_read += 1;
+                }
+                self.ep_out_prime_receive(endpoint_number);
+
+                log::trace!(
+                    "  RX {} OUT {} {} bytes read",
+                    stringify!($USBX),
+                    endpoint_number,
+                    bytes_read
+                );
+
+                if bytes_read > buf.len() {
+                    Err(UsbError::BufferOverflow)
+                } else {
+                    Ok(bytes_read)
+                }
+            }
+
+            fn set_stalled(
+                &self,
+                ep_addr: $crate::usb_device::endpoint::EndpointAddress,
+                stalled: bool,
+            ) {
+                use $crate::smolusb::setup::Direction;
+                use $crate::smolusb::traits::UsbDriverOperations;
+                use $crate::usb_device::UsbDirection;
+
+                let endpoint_number = ep_addr.index() as u8;
+                match (ep_addr.direction(), stalled) {
+                    (UsbDirection::In, true) => self.stall_endpoint_in(endpoint_number),
+                    (UsbDirection::Out, true) => self.stall_endpoint_out(endpoint_number),
+                    (UsbDirection::In, false) => {
+                        self.ep_in.stall().write(|w| w.stalled().bit(false));
+                        self.ep_in
+                            .endpoint()
+                            .write(|w| unsafe { w.number().bits(endpoint_number) });
+                        self.clear_feature_endpoint_halt(endpoint_number, Direction::DeviceToHost);
+                    }
+                    (UsbDirection::Out, false) => {
+                        self.ep_out
+                            .endpoint()
+                            .write(|w| unsafe { w.number().bits(endpoint_number) });
+                        self.ep_out.stall().write(|w| w.stalled().bit(false));
+                        self.clear_feature_endpoint_halt(endpoint_number, Direction::HostToDevice);
+                    }
+                }
+                $IDX::USB_BUS_STATE.set_stalled(ep_addr, stalled);
+            }
+
+            fn is_stalled(&self, ep_addr: $crate::usb_device::endpoint::EndpointAddress) -> bool {
+                $IDX::USB_BUS_STATE.is_stalled(ep_addr)
+            }
+
+            fn suspend(&self) {
+                // the eptri peripheral has no low-power state
+            }
+
+            fn resume(&self) {
+                // the eptri peripheral has no low-power state
+            }
+
+            fn poll(&self) -> $crate::usb_device::bus::PollResult {
+                use $crate::smolusb::traits::UnsafeUsbDriverOperations;
+                use $crate::usb_device::bus::PollResult;
+
+                // bus reset
+                if self.device.ev_pending().read().mask().bit() {
+                    self.device
+                        .ev_pending()
+                        .modify(|r, w| w.mask().bit(r.mask().bit()));
+                    return PollResult::Reset;
+                }
+
+                // suspend or resume, usb-device has no use for start-of-frame
+                match self.take_bus_event() {
+                    Some($crate::smolusb::event::UsbEvent::Suspend) => return PollResult::Suspend,
+                    Some($crate::smolusb::event::UsbEvent::Resume) => return PollResult::Resume,
+                    _ => (),
+                }
+
+                // setup packet received
+                if self.ep_control.ev_pending().read().mask().bit() {
+                    self.ep_control
+                        .ev_pending()
+                        .modify(|r, w| w.mask().bit(r.mask().bit()));
+                    $IDX::USB_BUS_STATE.set_setup_pending();
+                }
+
+                // send complete
+                let mut ep_in_complete = 0;
+                if self.ep_in.ev_pending().read().mask().bit() {
+                    self.ep_in
+                        .ev_pending()
+                        .modify(|r, w| w.mask().bit(r.mask().bit()));
+                    let endpoint_number = self.ep_in.status().read().epno().bits();
+                    unsafe {
+                        self.clear_tx_ack_active(endpoint_number);
+                    }
+                    ep_in_complete |= 1 << endpoint_number;
+                }
+
+                // packet received
+                if self.ep_out.ev_pending().read().mask().bit() {
+                    self.ep_out
+                        .ev_pending()
+                        .modify(|r, w| w.mask().bit(r.mask().bit()));
+                    let endpoint_number = self.ep_out.status().read().epno().bits();
+                    $IDX::USB_BUS_STATE.set_out_pending(endpoint_number);
+                }
+
+                // report everything that has not been read yet
+                match ($IDX::USB_BUS_STATE.pending(), ep_in_complete) {
+                    (PollResult::None, 0) => PollResult::None,
+                    (
+                        PollResult::Data {
+                            ep_out, ep_setup, ..
+                        },
+                        ep_in_complete,
+                    ) => PollResult::Data {
+                        ep_out,
+                        ep_in_complete,
+                        ep_setup,
+                    },
+                    (_, ep_in_complete) => PollResult::Data {
+                        ep_out: 0,
+                        ep_in_complete,
+                        ep_setup: 0,
+                    },
+                }
+            }
+        }
+    };
+}
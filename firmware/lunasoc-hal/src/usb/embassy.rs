@@ -0,0 +1,559 @@
+//! `embassy-usb` driver implementation for luna eptri peripherals
+//!
+//! Enabled by the `embassy-usb-driver` feature, this provides an
+//! implementation of [`embassy_usb_driver::Driver`] for any
+//! peripheral generated by [`impl_usb!`](crate::impl_usb).
+//!
+//! The driver is woken by forwarding the peripheral's interrupt
+//! events to a static [`State`]:
+//!
+//!     static STATE: embassy::State = embassy::State::new();
+//!
+//!     // in the USBx_EP_* interrupt handlers
+//!     STATE.signal_event(UsbEvent::SendComplete(endpoint_number));
+//!
+//! The device must be connected with
+//! [`UsbDriverOperations::connect()`](smolusb::traits::UsbDriverOperations::connect)
+//! before the driver is created.
+
+use core::future::poll_fn;
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::task::Poll;
+
+use embassy_usb_driver as driver;
+use embassy_usb_driver::{
+    EndpointAddress, EndpointAllocError, EndpointError, EndpointInfo, EndpointType, Event,
+    Unsupported,
+};
+
+use smolusb::asynch::{Signal, UsbEventSignals};
+use smolusb::event::UsbEvent;
+use smolusb::setup::Direction;
+use smolusb::traits::UsbDriver;
+use smolusb::EP_MAX_ENDPOINTS;
+
+/// Maximum packet size of the control endpoint.
+const EP0_MAX_PACKET_SIZE: usize = 64;
+
+// - State --------------------------------------------------------------------
+
+/// Shared state between the driver and the USB interrupt handlers.
+pub struct State {
+    signals: UsbEventSignals,
+    /// Enabled endpoints, OUT in the low half-word and IN in the high half-word.
+    enabled: AtomicU32,
+    enabled_out: [Signal; EP_MAX_ENDPOINTS],
+    enabled_in: [Signal; EP_MAX_ENDPOINTS],
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const SIGNAL: Signal = Signal::new();
+        Self {
+            signals: UsbEventSignals::new(),
+            enabled: AtomicU32::new(0),
+            enabled_out: [SIGNAL; EP_MAX_ENDPOINTS],
+            enabled_in: [SIGNAL; EP_MAX_ENDPOINTS],
+        }
+    }
+
+    /// Forward an event from the USB interface's interrupt handler.
+    pub fn signal_event(&self, event: UsbEvent) {
+        self.signals.signal_event(event);
+    }
+
+    fn mask(ep_addr: EndpointAddress) -> u32 {
+        let bit = 1 << ep_addr.index();
+        if ep_addr.is_in() {
+            bit << 16
+        } else {
+            bit
+        }
+    }
+
+    fn is_enabled(&self, ep_addr: EndpointAddress) -> bool {
+        self.enabled.load(Ordering::Acquire) & Self::mask(ep_addr) != 0
+    }
+
+    fn set_enabled(&self, ep_addr: EndpointAddress, enabled: bool) {
+        if enabled {
+            self.enabled.fetch_or(Self::mask(ep_addr), Ordering::AcqRel);
+        } else {
+            self.enabled
+                .fetch_and(!Self::mask(ep_addr), Ordering::AcqRel);
+        }
+        self.enabled_signal(ep_addr).signal();
+    }
+
+    fn enabled_signal(&self, ep_addr: EndpointAddress) -> &Signal {
+        if ep_addr.is_in() {
+            &self.enabled_in[ep_addr.index()]
+        } else {
+            &self.enabled_out[ep_addr.index()]
+        }
+    }
+
+    /// Returns the signal used to wake transfers on the given endpoint.
+    fn transfer_signal(&self, ep_addr: EndpointAddress) -> &Signal {
+        let endpoint_number = ep_addr.index() as u8;
+        if ep_addr.is_in() {
+            self.signals.send_complete(endpoint_number)
+        } else {
+            self.signals.receive_packet(endpoint_number)
+        }
+    }
+}
+
+// - Driver -------------------------------------------------------------------
+
+/// [`embassy_usb_driver::Driver`] implementation for a smolusb hal driver.
+pub struct Driver<'d, D> {
+    usb: &'d D,
+    state: &'d State,
+    allocated_out: u16,
+    allocated_in: u16,
+}
+
+impl<'d, D> Driver<'d, D> {
+    pub fn new(usb: &'d D, state: &'d State) -> Self {
+        Self {
+            usb,
+            state,
+            allocated_out: 0,
+            allocated_in: 0,
+        }
+    }
+
+    fn alloc_endpoint<Dir>(
+        &mut self,
+        direction: driver::Direction,
+        ep_type: EndpointType,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Endpoint<'d, D, Dir>, EndpointAllocError> {
+        if usize::from(max_packet_size) > smolusb::EP_MAX_PACKET_SIZE {
+            return Err(EndpointAllocError);
+        }
+
+        let allocated = match direction {
+            driver::Direction::Out => &mut self.allocated_out,
+            driver::Direction::In => &mut self.allocated_in,
+        };
+
+        let index = match ep_addr {
+            Some(ep_addr) => {
+                let index = ep_addr.index();
+                if index == 0 || index >= EP_MAX_ENDPOINTS || *allocated & (1 << index) != 0 {
+                    return Err(EndpointAllocError);
+                }
+                index
+            }
+            // endpoint 0 is reserved for the control pipe
+            None => (1..EP_MAX_ENDPOINTS)
+                .find(|index| *allocated & (1 << index) == 0)
+                .ok_or(EndpointAllocError)?,
+        };
+        *allocated |= 1 << index;
+
+        Ok(Endpoint {
+            usb: self.usb,
+            state: self.state,
+            info: EndpointInfo {
+                addr: EndpointAddress::from_parts(index, direction),
+                ep_type,
+                max_packet_size,
+                interval_ms,
+            },
+            _direction: PhantomData,
+        })
+    }
+}
+
+impl<'d, D> driver::Driver<'d> for Driver<'d, D>
+where
+    D: UsbDriver + 'd,
+{
+    type EndpointOut = Endpoint<'d, D, Out>;
+    type EndpointIn = Endpoint<'d, D, In>;
+    type ControlPipe = ControlPipe<'d, D>;
+    type Bus = Bus<'d, D>;
+
+    fn alloc_endpoint_out(
+        &mut self,
+        ep_type: EndpointType,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointOut, EndpointAllocError> {
+        self.alloc_endpoint(
+            driver::Direction::Out,
+            ep_type,
+            ep_addr,
+            max_packet_size,
+            interval_ms,
+        )
+    }
+
+    fn alloc_endpoint_in(
+        &mut self,
+        ep_type: EndpointType,
+        ep_addr: Option<EndpointAddress>,
+        max_packet_size: u16,
+        interval_ms: u8,
+    ) -> Result<Self::EndpointIn, EndpointAllocError> {
+        self.alloc_endpoint(
+            driver::Direction::In,
+            ep_type,
+            ep_addr,
+            max_packet_size,
+            interval_ms,
+        )
+    }
+
+    fn start(self, control_max_packet_size: u16) -> (Self::Bus, Self::ControlPipe) {
+        if usize::from(control_max_packet_size) != EP0_MAX_PACKET_SIZE {
+            log::warn!(
+                "embassy::Driver::start() - unsupported control max packet size {}",
+                control_max_packet_size
+            );
+        }
+
+        (
+            Bus {
+                usb: self.usb,
+                state: self.state,
+                powered: false,
+                stalled_out: 0,
+                stalled_in: 0,
+            },
+            ControlPipe {
+                usb: self.usb,
+                state: self.state,
+            },
+        )
+    }
+}
+
+// - Bus ----------------------------------------------------------------------
+
+/// [`embassy_usb_driver::Bus`] implementation for a smolusb hal driver.
+pub struct Bus<'d, D> {
+    usb: &'d D,
+    state: &'d State,
+    powered: bool,
+    stalled_out: u16,
+    stalled_in: u16,
+}
+
+impl<'d, D> driver::Bus for Bus<'d, D>
+where
+    D: UsbDriver,
+{
+    async fn enable(&mut self) {
+        self.usb.bus_reset();
+    }
+
+    async fn disable(&mut self) {
+        for index in 1..EP_MAX_ENDPOINTS {
+            self.endpoint_set_enabled(
+                EndpointAddress::from_parts(index, driver::Direction::Out),
+                false,
+            );
+            self.endpoint_set_enabled(
+                EndpointAddress::from_parts(index, driver::Direction::In),
+                false,
+            );
+        }
+    }
+
+    async fn poll(&mut self) -> Event {
+        // the device is bus-powered and already connected
+        if !self.powered {
+            self.powered = true;
+            return Event::PowerDetected;
+        }
+
+        // suspend and resume are only reported if forwarded by the interrupt handler
+        match poll_fn(|cx| self.state.signals.poll_bus_event(cx)).await {
+            UsbEvent::Suspend => Event::Suspend,
+            UsbEvent::Resume => Event::Resume,
+            _ => {
+                self.stalled_out = 0;
+                self.stalled_in = 0;
+                Event::Reset
+            }
+        }
+    }
+
+    fn endpoint_set_enabled(&mut self, ep_addr: EndpointAddress, enabled: bool) {
+        let endpoint_number = ep_addr.index() as u8;
+
+        if enabled && !ep_addr.is_in() {
+            // discard any stale packet and prime for reception
+            self.state.transfer_signal(ep_addr).reset();
+            self.usb.ep_out_prime_receive(endpoint_number);
+        }
+
+        self.state.set_enabled(ep_addr, enabled);
+
+        if !enabled {
+            // wake any pending transfer so it can return EndpointError::Disabled
+            self.state.transfer_signal(ep_addr).signal();
+        }
+    }
+
+    fn endpoint_set_stalled(&mut self, ep_addr: EndpointAddress, stalled: bool) {
+        let endpoint_number = ep_addr.index() as u8;
+        let mask = if ep_addr.is_in() {
+            &mut self.stalled_in
+        } else {
+            &mut self.stalled_out
+        };
+
+        match (ep_addr.is_in(), stalled) {
+            (true, true) => self.usb.stall_endpoint_in(endpoint_number),
+            (false, true) => self.usb.stall_endpoint_out(endpoint_number),
+            (true, false) => self
+                .usb
+                .clear_feature_endpoint_halt(endpoint_number, Direction::DeviceToHost),
+            (false, false) => self
+                .usb
+                .clear_feature_endpoint_halt(endpoint_number, Direction::HostToDevice),
+        }
+
+        if stalled {
+            *mask |= 1 << endpoint_number;
+        } else {
+            *mask &= !(1 << endpoint_number);
+        }
+    }
+
+    fn endpoint_is_stalled(&mut self, ep_addr: EndpointAddress) -> bool {
+        let mask = if ep_addr.is_in() {
+            self.stalled_in
+        } else {
+            self.stalled_out
+        };
+        mask & (1 << ep_addr.index()) != 0
+    }
+
+    async fn remote_wakeup(&mut self) -> Result<(), Unsupported> {
+        Err(Unsupported)
+    }
+}
+
+// - Endpoint -----------------------------------------------------------------
+
+/// Marker type for OUT endpoints.
+pub enum Out {}
+
+/// Marker type for IN endpoints.
+pub enum In {}
+
+/// [`embassy_usb_driver::Endpoint`] implementation for a smolusb hal driver.
+pub struct Endpoint<'d, D, Dir> {
+    usb: &'d D,
+    state: &'d State,
+    info: EndpointInfo,
+    _direction: PhantomData<Dir>,
+}
+
+impl<'d, D, Dir> driver::Endpoint for Endpoint<'d, D, Dir> {
+    fn info(&self) -> &EndpointInfo {
+        &self.info
+    }
+
+    async fn wait_enabled(&mut self) {
+        let ep_addr = self.info.addr;
+        poll_fn(|cx| {
+            if self.state.is_enabled(ep_addr) {
+                return Poll::Ready(());
+            }
+            match self.state.enabled_signal(ep_addr).poll_wait(cx) {
+                Poll::Ready(()) if self.state.is_enabled(ep_addr) => Poll::Ready(()),
+                _ => Poll::Pending,
+            }
+        })
+        .await;
+    }
+}
+
+impl<'d, D> driver::EndpointOut for Endpoint<'d, D, Out>
+where
+    D: UsbDriver,
+{
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, EndpointError> {
+        let ep_addr = self.info.addr;
+        let endpoint_number = ep_addr.index() as u8;
+
+        poll_fn(|cx| {
+            if !self.state.is_enabled(ep_addr) {
+                return Poll::Ready(Err(EndpointError::Disabled));
+            }
+            self.state.transfer_signal(ep_addr).poll_wait(cx).map(Ok)
+        })
+        .await?;
+
+        if !self.state.is_enabled(ep_addr) {
+            return Err(EndpointError::Disabled);
+        }
+
+        let bytes_read = self.usb.read(endpoint_number, buf);
+        self.usb.ep_out_prime_receive(endpoint_number);
+
+        if bytes_read > buf.len() {
+            Err(EndpointError::BufferOverflow)
+        } else {
+            Ok(bytes_read)
+        }
+    }
+}
+
+impl<'d, D> driver::EndpointIn for Endpoint<'d, D, In>
+where
+    D: UsbDriver,
+{
+    async fn write(&mut self, buf: &[u8]) -> Result<(), EndpointError> {
+        let ep_addr = self.info.addr;
+
+        if buf.len() > usize::from(self.info.max_packet_size) {
+            return Err(EndpointError::BufferOverflow);
+        }
+        if !self.state.is_enabled(ep_addr) {
+            return Err(EndpointError::Disabled);
+        }
+
+        write_packet(self.usb, self.state, ep_addr, buf).await;
+
+        if self.state.is_enabled(ep_addr) {
+            Ok(())
+        } else {
+            Err(EndpointError::Disabled)
+        }
+    }
+}
+
+/// Write a single packet and wait for the host to acknowledge it.
+async fn write_packet<D>(usb: &D, state: &State, ep_addr: EndpointAddress, buf: &[u8])
+where
+    D: UsbDriver,
+{
+    let signal = state.transfer_signal(ep_addr);
+    signal.reset();
+
+    // the driver resets a busy endpoint before giving up on it so
+    // the packet can be retried straight away
+    while usb
+        .write_packet(ep_addr.index() as u8, buf.iter().copied())
+        .is_err()
+    {
+        log::warn!("write_packet() endpoint {} busy, retrying", ep_addr.index());
+    }
+
+    signal.wait().await;
+}
+
+fn ep0_out() -> EndpointAddress {
+    EndpointAddress::from_parts(0, driver::Direction::Out)
+}
+
+fn ep0_in() -> EndpointAddress {
+    EndpointAddress::from_parts(0, driver::Direction::In)
+}
+
+// - ControlPipe --------------------------------------------------------------
+
+/// [`embassy_usb_driver::ControlPipe`] implementation for a smolusb hal driver.
+pub struct ControlPipe<'d, D> {
+    usb: &'d D,
+    state: &'d State,
+}
+
+impl<'d, D> driver::ControlPipe for ControlPipe<'d, D>
+where
+    D: UsbDriver,
+{
+    fn max_packet_size(&self) -> usize {
+        EP0_MAX_PACKET_SIZE
+    }
+
+    async fn setup(&mut self) -> [u8; 8] {
+        let setup_packet = poll_fn(|cx| self.state.signals.poll_setup_packet(cx)).await;
+
+        let setup_packet = match setup_packet {
+            Some(setup_packet) => setup_packet.into(),
+            None => {
+                // the interrupt handler left the setup packet in the fifo
+                let mut buffer = [0_u8; 8];
+                self.usb.read_control(&mut buffer);
+                buffer
+            }
+        };
+
+        // prime for the data or status stage
+        self.state.transfer_signal(ep0_out()).reset();
+        self.usb.ep_out_prime_receive(0);
+
+        setup_packet
+    }
+
+    async fn data_out(
+        &mut self,
+        buf: &mut [u8],
+        _first: bool,
+        last: bool,
+    ) -> Result<usize, EndpointError> {
+        self.state.transfer_signal(ep0_out()).wait().await;
+
+        let bytes_read = self.usb.read(0, buf);
+        if !last {
+            self.usb.ep_out_prime_receive(0);
+        }
+
+        if bytes_read > buf.len() {
+            Err(EndpointError::BufferOverflow)
+        } else {
+            Ok(bytes_read)
+        }
+    }
+
+    async fn data_in(
+        &mut self,
+        data: &[u8],
+        _first: bool,
+        _last: bool,
+    ) -> Result<(), EndpointError> {
+        if data.len() > EP0_MAX_PACKET_SIZE {
+            return Err(EndpointError::BufferOverflow);
+        }
+
+        // the status stage zlp from the host is received by the
+        // endpoint primed in setup()
+        write_packet(self.usb, self.state, ep0_in(), data).await;
+
+        Ok(())
+    }
+
+    async fn accept(&mut self) {
+        write_packet(self.usb, self.state, ep0_in(), &[]).await;
+    }
+
+    async fn reject(&mut self) {
+        self.usb.stall_endpoint_in(0);
+        self.usb.stall_endpoint_out(0);
+    }
+
+    async fn accept_set_address(&mut self, addr: u8) {
+        self.accept().await;
+        self.usb.set_address(addr);
+    }
+}
@@ -13,3 +13,6 @@ PROVIDE(USB2_EP_CONTROL = DefaultHandler);
 PROVIDE(USB2_EP_IN = DefaultHandler);
 PROVIDE(USB2_EP_OUT = DefaultHandler);
 
+PROVIDE(USB0_BUS = DefaultHandler);
+PROVIDE(USB1_BUS = DefaultHandler);
+PROVIDE(USB2_BUS = DefaultHandler);
@@ -22,6 +22,9 @@ extern "C" {
     fn USB2_EP_CONTROL();
     fn USB2_EP_IN();
     fn USB2_EP_OUT();
+    fn USB0_BUS();
+    fn USB1_BUS();
+    fn USB2_BUS();
 }
 #[doc(hidden)]
 #[repr(C)]
@@ -32,7 +35,7 @@ pub union Vector {
 #[cfg(feature = "rt")]
 #[doc(hidden)]
 #[no_mangle]
-pub static __EXTERNAL_INTERRUPTS: [Vector; 14] = [
+pub static __EXTERNAL_INTERRUPTS: [Vector; 17] = [
     Vector { _handler: TIMER0 },
     Vector { _handler: TIMER1 },
     Vector { _handler: USB0 },
@@ -65,6 +68,9 @@ pub static __EXTERNAL_INTERRUPTS: [Vector; 14] = [
     Vector {
         _handler: USB2_EP_OUT,
     },
+    Vector { _handler: USB0_BUS },
+    Vector { _handler: USB1_BUS },
+    Vector { _handler: USB2_BUS },
 ];
 #[doc(hidden)]
 pub mod interrupt;
@@ -1127,6 +1133,144 @@ impl core::fmt::Debug for USER0 {
 }
 #[doc = "user0"]
 pub mod user0;
+#[doc = "usb0_bus"]
+pub struct USB0_BUS {
+    _marker: PhantomData<*const ()>,
+}
+unsafe impl Send for USB0_BUS {}
+impl USB0_BUS {
+    #[doc = r"Pointer to the register block"]
+    pub const PTR: *const usb0_bus::RegisterBlock = 0xf000_1700 as *const _;
+    #[doc = r"Return the pointer to the register block"]
+    #[inline(always)]
+    pub const fn ptr() -> *const usb0_bus::RegisterBlock {
+        Self::PTR
+    }
+    #[doc = r" Steal an instance of this peripheral"]
+    #[doc = r""]
+    #[doc = r" # Safety"]
+    #[doc = r""]
+    #[doc = r" Ensure that the new instance of the peripheral cannot be used in a way"]
+    #[doc = r" that may race with any existing instances, for example by only"]
+    #[doc = r" accessing read-only or write-only registers, or by consuming the"]
+    #[doc = r" original peripheral and using critical sections to coordinate"]
+    #[doc = r" access between multiple new instances."]
+    #[doc = r""]
+    #[doc = r" Additionally, other software such as HALs may rely on only one"]
+    #[doc = r" peripheral instance existing to ensure memory safety; ensure"]
+    #[doc = r" no stolen instances are passed to such software."]
+    pub unsafe fn steal() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+impl Deref for USB0_BUS {
+    type Target = usb0_bus::RegisterBlock;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*Self::PTR }
+    }
+}
+impl core::fmt::Debug for USB0_BUS {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("USB0_BUS").finish()
+    }
+}
+#[doc = "usb0_bus"]
+pub mod usb0_bus;
+#[doc = "usb1_bus"]
+pub struct USB1_BUS {
+    _marker: PhantomData<*const ()>,
+}
+unsafe impl Send for USB1_BUS {}
+impl USB1_BUS {
+    #[doc = r"Pointer to the register block"]
+    pub const PTR: *const usb1_bus::RegisterBlock = 0xf000_1800 as *const _;
+    #[doc = r"Return the pointer to the register block"]
+    #[inline(always)]
+    pub const fn ptr() -> *const usb1_bus::RegisterBlock {
+        Self::PTR
+    }
+    #[doc = r" Steal an instance of this peripheral"]
+    #[doc = r""]
+    #[doc = r" # Safety"]
+    #[doc = r""]
+    #[doc = r" Ensure that the new instance of the peripheral cannot be used in a way"]
+    #[doc = r" that may race with any existing instances, for example by only"]
+    #[doc = r" accessing read-only or write-only registers, or by consuming the"]
+    #[doc = r" original peripheral and using critical sections to coordinate"]
+    #[doc = r" access between multiple new instances."]
+    #[doc = r""]
+    #[doc = r" Additionally, other software such as HALs may rely on only one"]
+    #[doc = r" peripheral instance existing to ensure memory safety; ensure"]
+    #[doc = r" no stolen instances are passed to such software."]
+    pub unsafe fn steal() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+impl Deref for USB1_BUS {
+    type Target = usb1_bus::RegisterBlock;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*Self::PTR }
+    }
+}
+impl core::fmt::Debug for USB1_BUS {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("USB1_BUS").finish()
+    }
+}
+#[doc = "usb1_bus"]
+pub mod usb1_bus;
+#[doc = "usb2_bus"]
+pub struct USB2_BUS {
+    _marker: PhantomData<*const ()>,
+}
+unsafe impl Send for USB2_BUS {}
+impl USB2_BUS {
+    #[doc = r"Pointer to the register block"]
+    pub const PTR: *const usb2_bus::RegisterBlock = 0xf000_1900 as *const _;
+    #[doc = r"Return the pointer to the register block"]
+    #[inline(always)]
+    pub const fn ptr() -> *const usb2_bus::RegisterBlock {
+        Self::PTR
+    }
+    #[doc = r" Steal an instance of this peripheral"]
+    #[doc = r""]
+    #[doc = r" # Safety"]
+    #[doc = r""]
+    #[doc = r" Ensure that the new instance of the peripheral cannot be used in a way"]
+    #[doc = r" that may race with any existing instances, for example by only"]
+    #[doc = r" accessing read-only or write-only registers, or by consuming the"]
+    #[doc = r" original peripheral and using critical sections to coordinate"]
+    #[doc = r" access between multiple new instances."]
+    #[doc = r""]
+    #[doc = r" Additionally, other software such as HALs may rely on only one"]
+    #[doc = r" peripheral instance existing to ensure memory safety; ensure"]
+    #[doc = r" no stolen instances are passed to such software."]
+    pub unsafe fn steal() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+impl Deref for USB2_BUS {
+    type Target = usb2_bus::RegisterBlock;
+    #[inline(always)]
+    fn deref(&self) -> &Self::Target {
+        unsafe { &*Self::PTR }
+    }
+}
+impl core::fmt::Debug for USB2_BUS {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("USB2_BUS").finish()
+    }
+}
+#[doc = "usb2_bus"]
+pub mod usb2_bus;
 #[no_mangle]
 static mut DEVICE_PERIPHERALS: bool = false;
 #[doc = r" All the peripherals."]
@@ -1178,6 +1322,12 @@ pub struct Peripherals {
     pub INFO: INFO,
     #[doc = "user0"]
     pub USER0: USER0,
+    #[doc = "usb0_bus"]
+    pub USB0_BUS: USB0_BUS,
+    #[doc = "usb1_bus"]
+    pub USB1_BUS: USB1_BUS,
+    #[doc = "usb2_bus"]
+    pub USB2_BUS: USB2_BUS,
 }
 impl Peripherals {
     #[doc = r" Returns all the peripherals *once*."]
@@ -1223,6 +1373,9 @@ impl Peripherals {
             ADVERTISER: ADVERTISER::steal(),
             INFO: INFO::steal(),
             USER0: USER0::steal(),
+            USB0_BUS: USB0_BUS::steal(),
+            USB1_BUS: USB1_BUS::steal(),
+            USB2_BUS: USB2_BUS::steal(),
         }
     }
 }
@@ -30,6 +30,12 @@ pub enum Interrupt {
     USB2_EP_IN = 12,
     #[doc = "13 - usb2_ep_out"]
     USB2_EP_OUT = 13,
+    #[doc = "14 - usb0_bus"]
+    USB0_BUS = 14,
+    #[doc = "15 - usb1_bus"]
+    USB1_BUS = 15,
+    #[doc = "16 - usb2_bus"]
+    USB2_BUS = 16,
 }
 #[doc = r" TryFromInterruptError"]
 #[derive(Debug, Copy, Clone)]
@@ -53,6 +59,9 @@ impl Interrupt {
             11 => Ok(Interrupt::USB2_EP_CONTROL),
             12 => Ok(Interrupt::USB2_EP_IN),
             13 => Ok(Interrupt::USB2_EP_OUT),
+            14 => Ok(Interrupt::USB0_BUS),
+            15 => Ok(Interrupt::USB1_BUS),
+            16 => Ok(Interrupt::USB2_BUS),
             _ => Err(TryFromInterruptError(())),
         }
     }
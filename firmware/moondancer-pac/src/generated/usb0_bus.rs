@@ -0,0 +1,43 @@
+#[repr(C)]
+#[doc = "Register block"]
+pub struct RegisterBlock {
+    status: STATUS,
+    _reserved1: [u8; 0x06],
+    ev_enable: EV_ENABLE,
+    ev_pending: EV_PENDING,
+}
+impl RegisterBlock {
+    #[doc = "0x00 - Status register frame_number: The frame number of the last start-of-frame packet. suspended: Indicates that the bus has been idle for more than 3ms and the device is suspended."]
+    #[inline(always)]
+    pub const fn status(&self) -> &STATUS {
+        &self.status
+    }
+    #[doc = "0x08 - Event enable register sof: Set this bit to '1' to raise an interrupt on each start-of-frame packet. suspend: Set this bit to '1' to raise an interrupt when the bus is suspended. resume: Set this bit to '1' to raise an interrupt when the bus resumes."]
+    #[inline(always)]
+    pub const fn ev_enable(&self) -> &EV_ENABLE {
+        &self.ev_enable
+    }
+    #[doc = "0x09 - Event pending register sof: A start-of-frame packet has been received. Write '1' to clear. suspend: The bus has been suspended. Write '1' to clear. resume: The bus has resumed after a suspend. Write '1' to clear."]
+    #[inline(always)]
+    pub const fn ev_pending(&self) -> &EV_PENDING {
+        &self.ev_pending
+    }
+}
+#[doc = "status (rw) register accessor: Status register frame_number: The frame number of the last start-of-frame packet. suspended: Indicates that the bus has been idle for more than 3ms and the device is suspended.\n\nYou can [`read`](crate::Reg::read) this register and get [`status::R`]. You can [`reset`](crate::Reg::reset), [`write`](crate::Reg::write), [`write_with_zero`](crate::Reg::write_with_zero) this register using [`status::W`]. You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@status`]
+module"]
+#[doc(alias = "status")]
+pub type STATUS = crate::Reg<status::STATUS_SPEC>;
+#[doc = "Status register frame_number: The frame number of the last start-of-frame packet. suspended: Indicates that the bus has been idle for more than 3ms and the device is suspended."]
+pub mod status;
+#[doc = "ev_enable (rw) register accessor: Event enable register sof: Set this bit to '1' to raise an interrupt on each start-of-frame packet. suspend: Set this bit to '1' to raise an interrupt when the bus is suspended. resume: Set this bit to '1' to raise an interrupt when the bus resumes.\n\nYou can [`read`](crate::Reg::read) this register and get [`ev_enable::R`]. You can [`reset`](crate::Reg::reset), [`write`](crate::Reg::write), [`write_with_zero`](crate::Reg::write_with_zero) this register using [`ev_enable::W`]. You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@ev_enable`]
+module"]
+#[doc(alias = "ev_enable")]
+pub type EV_ENABLE = crate::Reg<ev_enable::EV_ENABLE_SPEC>;
+#[doc = "Event enable register sof: Set this bit to '1' to raise an interrupt on each start-of-frame packet. suspend: Set this bit to '1' to raise an interrupt when the bus is suspended. resume: Set this bit to '1' to raise an interrupt when the bus resumes."]
+pub mod ev_enable;
+#[doc = "ev_pending (rw) register accessor: Event pending register sof: A start-of-frame packet has been received. Write '1' to clear. suspend: The bus has been suspended. Write '1' to clear. resume: The bus has resumed after a suspend. Write '1' to clear.\n\nYou can [`read`](crate::Reg::read) this register and get [`ev_pending::R`]. You can [`reset`](crate::Reg::reset), [`write`](crate::Reg::write), [`write_with_zero`](crate::Reg::write_with_zero) this register using [`ev_pending::W`]. You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api).\n\nFor information about available fields see [`mod@ev_pending`]
+module"]
+#[doc(alias = "ev_pending")]
+pub type EV_PENDING = crate::Reg<ev_pending::EV_PENDING_SPEC>;
+#[doc = "Event pending register sof: A start-of-frame packet has been received. Write '1' to clear. suspend: The bus has been suspended. Write '1' to clear. resume: The bus has resumed after a suspend. Write '1' to clear."]
+pub mod ev_pending;
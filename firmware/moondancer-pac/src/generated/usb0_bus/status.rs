@@ -0,0 +1,53 @@
+#[doc = "Register `status` reader"]
+pub type R = crate::R<STATUS_SPEC>;
+#[doc = "Register `status` writer"]
+pub type W = crate::W<STATUS_SPEC>;
+#[doc = "Field `frame_number` reader - frame_number field"]
+pub type FRAME_NUMBER_R = crate::FieldReader<u16>;
+#[doc = "Field `suspended` reader - suspended field"]
+pub type SUSPENDED_R = crate::BitReader;
+#[doc = "Field `_0` reader - _0 field"]
+pub type _0_R = crate::FieldReader;
+#[doc = "Field `_0` writer - _0 field"]
+pub type _0_W<'a, REG> = crate::FieldWriter<'a, REG, 4>;
+impl R {
+    #[doc = "Bits 0:10 - frame_number field"]
+    #[inline(always)]
+    pub fn frame_number(&self) -> FRAME_NUMBER_R {
+        FRAME_NUMBER_R::new(self.bits & 0x07ff)
+    }
+    #[doc = "Bit 11 - suspended field"]
+    #[inline(always)]
+    pub fn suspended(&self) -> SUSPENDED_R {
+        SUSPENDED_R::new(((self.bits >> 11) & 1) != 0)
+    }
+    #[doc = "Bits 12:15 - _0 field"]
+    #[inline(always)]
+    pub fn _0(&self) -> _0_R {
+        _0_R::new(((self.bits >> 12) & 0x0f) as u8)
+    }
+}
+impl W {
+    #[doc = "Bits 12:15 - _0 field"]
+    #[inline(always)]
+    pub fn _0(&mut self) -> _0_W<STATUS_SPEC> {
+        _0_W::new(self, 12)
+    }
+}
+#[doc = "Status register frame_number: The frame number of the last start-of-frame packet. suspended: Indicates that the bus has been idle for more than 3ms and the device is suspended.\n\nYou can [`read`](crate::Reg::read) this register and get [`status::R`](R). You can [`reset`](crate::Reg::reset), [`write`](crate::Reg::write), [`write_with_zero`](crate::Reg::write_with_zero) this register using [`status::W`](W). You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api)."]
+pub struct STATUS_SPEC;
+impl crate::RegisterSpec for STATUS_SPEC {
+    type Ux = u16;
+}
+#[doc = "`read()` method returns [`status::R`](R) reader structure"]
+impl crate::Readable for STATUS_SPEC {}
+#[doc = "`write(|w| ..)` method takes [`status::W`](W) writer structure"]
+impl crate::Writable for STATUS_SPEC {
+    type Safety = crate::Unsafe;
+    const ZERO_TO_MODIFY_FIELDS_BITMAP: u16 = 0;
+    const ONE_TO_MODIFY_FIELDS_BITMAP: u16 = 0;
+}
+#[doc = "`reset()` method sets status to value 0"]
+impl crate::Resettable for STATUS_SPEC {
+    const RESET_VALUE: u16 = 0;
+}
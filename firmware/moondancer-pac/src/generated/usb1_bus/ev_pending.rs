@@ -0,0 +1,81 @@
+#[doc = "Register `ev_pending` reader"]
+pub type R = crate::R<EV_PENDING_SPEC>;
+#[doc = "Register `ev_pending` writer"]
+pub type W = crate::W<EV_PENDING_SPEC>;
+#[doc = "Field `sof` reader - sof field"]
+pub type SOF_R = crate::BitReader;
+#[doc = "Field `sof` writer - sof field"]
+pub type SOF_W<'a, REG> = crate::BitWriter<'a, REG>;
+#[doc = "Field `suspend` reader - suspend field"]
+pub type SUSPEND_R = crate::BitReader;
+#[doc = "Field `suspend` writer - suspend field"]
+pub type SUSPEND_W<'a, REG> = crate::BitWriter<'a, REG>;
+#[doc = "Field `resume` reader - resume field"]
+pub type RESUME_R = crate::BitReader;
+#[doc = "Field `resume` writer - resume field"]
+pub type RESUME_W<'a, REG> = crate::BitWriter<'a, REG>;
+#[doc = "Field `_0` reader - _0 field"]
+pub type _0_R = crate::FieldReader;
+#[doc = "Field `_0` writer - _0 field"]
+pub type _0_W<'a, REG> = crate::FieldWriter<'a, REG, 5>;
+impl R {
+    #[doc = "Bit 0 - sof field"]
+    #[inline(always)]
+    pub fn sof(&self) -> SOF_R {
+        SOF_R::new((self.bits & 1) != 0)
+    }
+    #[doc = "Bit 1 - suspend field"]
+    #[inline(always)]
+    pub fn suspend(&self) -> SUSPEND_R {
+        SUSPEND_R::new(((self.bits >> 1) & 1) != 0)
+    }
+    #[doc = "Bit 2 - resume field"]
+    #[inline(always)]
+    pub fn resume(&self) -> RESUME_R {
+        RESUME_R::new(((self.bits >> 2) & 1) != 0)
+    }
+    #[doc = "Bits 3:7 - _0 field"]
+    #[inline(always)]
+    pub fn _0(&self) -> _0_R {
+        _0_R::new((self.bits >> 3) & 0x1f)
+    }
+}
+impl W {
+    #[doc = "Bit 0 - sof field"]
+    #[inline(always)]
+    pub fn sof(&mut self) -> SOF_W<EV_PENDING_SPEC> {
+        SOF_W::new(self, 0)
+    }
+    #[doc = "Bit 1 - suspend field"]
+    #[inline(always)]
+    pub fn suspend(&mut self) -> SUSPEND_W<EV_PENDING_SPEC> {
+        SUSPEND_W::new(self, 1)
+    }
+    #[doc = "Bit 2 - resume field"]
+    #[inline(always)]
+    pub fn resume(&mut self) -> RESUME_W<EV_PENDING_SPEC> {
+        RESUME_W::new(self, 2)
+    }
+    #[doc = "Bits 3:7 - _0 field"]
+    #[inline(always)]
+    pub fn _0(&mut self) -> _0_W<EV_PENDING_SPEC> {
+        _0_W::new(self, 3)
+    }
+}
+#[doc = "Event pending register sof: A start-of-frame packet has been received. Write '1' to clear. suspend: The bus has been suspended. Write '1' to clear. resume: The bus has resumed after a suspend. Write '1' to clear.\n\nYou can [`read`](crate::Reg::read) this register and get [`ev_pending::R`](R). You can [`reset`](crate::Reg::reset), [`write`](crate::Reg::write), [`write_with_zero`](crate::Reg::write_with_zero) this register using [`ev_pending::W`](W). You can also [`modify`](crate::Reg::modify) this register. See [API](https://docs.rs/svd2rust/#read--modify--write-api)."]
+pub struct EV_PENDING_SPEC;
+impl crate::RegisterSpec for EV_PENDING_SPEC {
+    type Ux = u8;
+}
+#[doc = "`read()` method returns [`ev_pending::R`](R) reader structure"]
+impl crate::Readable for EV_PENDING_SPEC {}
+#[doc = "`write(|w| ..)` method takes [`ev_pending::W`](W) writer structure"]
+impl crate::Writable for EV_PENDING_SPEC {
+    type Safety = crate::Unsafe;
+    const ZERO_TO_MODIFY_FIELDS_BITMAP: u8 = 0;
+    const ONE_TO_MODIFY_FIELDS_BITMAP: u8 = 0;
+}
+#[doc = "`reset()` method sets ev_pending to value 0"]
+impl crate::Resettable for EV_PENDING_SPEC {
+    const RESET_VALUE: u8 = 0;
+}
@@ -7,7 +7,7 @@
 ///
 ///     picocom --imap crcrlf -b 115200 /dev/ttyACM0
 ///
-use log::{error, info};
+use log::{error, info, warn};
 
 use moondancer::event::InterruptEvent;
 use moondancer::UsbInterface;
@@ -15,12 +15,12 @@ use moondancer::{hal, pac};
 
 use hal::smolusb;
 use smolusb::class::acm;
-use smolusb::control::Control;
-use smolusb::device::{Descriptors, Speed};
+use smolusb::control::{Control, ControlEvent};
+use smolusb::device::{Descriptors, DeviceEvent, Speed, UsbDevice};
 use smolusb::event::UsbEvent;
-use smolusb::setup::{Direction, Request, RequestType, SetupPacket};
+use smolusb::setup::{Direction, SetupPacket};
 use smolusb::traits::UnsafeUsbDriverOperations;
-use smolusb::traits::{ReadControl, ReadEndpoint, UsbDriverOperations, WriteEndpoint};
+use smolusb::traits::{ReadControl, UsbDriverOperations};
 
 use pac::csr::interrupt;
 
@@ -28,6 +28,8 @@ use pac::csr::interrupt;
 
 const DEVICE_SPEED: Speed = Speed::Full;
 const MAX_CONTROL_RESPONSE_SIZE: usize = 8;
+const MAX_PACKET_SIZE: usize = 64;
+const SERIAL_BUFFER_SIZE: usize = 512;
 
 // - global static state ------------------------------------------------------
 
@@ -156,22 +158,26 @@ fn main() -> ! {
         peripherals.USB0_EP_CONTROL,
         peripherals.USB0_EP_IN,
         peripherals.USB0_EP_OUT,
+        peripherals.USB0_BUS,
     );
 
     // usb0 control endpoint
-    let mut control_usb0 = Control::<_, MAX_CONTROL_RESPONSE_SIZE>::new(
+    let control_usb0 = Control::<_, MAX_CONTROL_RESPONSE_SIZE>::new(
         0,
         Descriptors {
             device_speed: DEVICE_SPEED,
             device_descriptor: acm::DEVICE_DESCRIPTOR,
-            configuration_descriptor: acm::CONFIGURATION_DESCRIPTOR_0,
+            configuration_descriptors: &[acm::CONFIGURATION_DESCRIPTOR_0],
             string_descriptor_zero: acm::STRING_DESCRIPTOR_0,
             string_descriptors: acm::STRING_DESCRIPTORS,
-            other_speed_configuration_descriptor: Some(acm::OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0),
+            other_speed_configuration_descriptors: Some(&[
+                acm::OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0,
+            ]),
             device_qualifier_descriptor: Some(acm::DEVICE_QUALIFIER_DESCRIPTOR),
+            binary_object_store: None,
             microsoft10: None,
-        }
-        .set_total_lengths(),
+            microsoft20: None,
+        },
     );
 
     // disconnect device
@@ -201,50 +207,48 @@ fn main() -> ! {
         usb0.enable_events();
     }
 
+    // usb0 serial port
+    let serial = acm::CdcAcm::<SERIAL_BUFFER_SIZE>::new(0, 3, 4, 4, MAX_PACKET_SIZE);
+
+    // usb0 device routes class requests and endpoint events to the serial port
+    let mut device_usb0 = UsbDevice::new(control_usb0, (serial,));
+
     info!("Peripherals initialized, entering main loop.");
 
     loop {
         if let Some(event) = EVENT_QUEUE.dequeue() {
-            use smolusb::event::UsbEvent::*;
             use InterruptEvent::Usb;
             use UsbInterface::Target;
 
             match event {
-                // Usb0 received a control event
-                Usb(
-                    Target,
-                    event @ (BusReset
-                    | ReceiveControl(0)
-                    | ReceiveSetupPacket(0, _)
-                    | ReceivePacket(0)
-                    | SendComplete(0)),
-                ) => {
-                    if let Some(setup_packet) = control_usb0.dispatch_event(&usb0, event) {
-                        // class requests are not handled by control
-                        handle_class_request(&usb0, setup_packet);
+                // Usb0 received an event
+                Usb(Target, event) => {
+                    match device_usb0.dispatch_event(&usb0, event) {
+                        // requests not handled by control or the serial port
+                        Some(DeviceEvent::Control(ControlEvent::SetupPacket(setup_packet))) => {
+                            warn!("Stall - unhandled request: {:?}", setup_packet);
+                            match setup_packet.direction() {
+                                Direction::DeviceToHost => usb0.stall_endpoint_in(0),
+                                Direction::HostToDevice => usb0.stall_endpoint_out(0),
+                            }
+                        }
+                        Some(event) => info!("Usb0 event: {:?}", event),
+                        None => (),
                     }
-                }
 
-                // Handle serial data on OUT ep 0x04
-                Usb(Target, ReceivePacket(4)) => {
-                    let mut rx_buffer: [u8; smolusb::EP_MAX_PACKET_SIZE] =
-                        [0; smolusb::EP_MAX_PACKET_SIZE];
-                    let bytes_read = usb0.read(4, &mut rx_buffer);
+                    let (serial,) = device_usb0.classes_mut();
+                    if serial.bytes_available() > 0 {
+                        let mut buffer = [0; SERIAL_BUFFER_SIZE];
+                        let bytes_read = serial.read(&usb0, &mut buffer);
 
-                    // convert to uppercase
-                    for b in rx_buffer[0..bytes_read].iter_mut() {
-                        if 'a' as u8 <= *b && *b <= 'z' as u8 {
-                            *b -= 32;
+                        // convert to uppercase
+                        for b in buffer[0..bytes_read].iter_mut() {
+                            b.make_ascii_uppercase();
                         }
-                    }
-
-                    // echo back on IN ep 0x84
-                    usb0.write(4, rx_buffer[0..bytes_read].iter().cloned());
-                }
 
-                // Handle send complete, prime OUT ep 0x04 to receive next packet
-                Usb(Target, SendComplete(4)) => {
-                    usb0.ep_out_prime_receive(4);
+                        // echo back on IN ep 0x84
+                        serial.write(&usb0, &buffer[0..bytes_read]);
+                    }
                 }
 
                 // unhandled
@@ -255,46 +259,3 @@ fn main() -> ! {
         }
     }
 }
-
-// - class request handler ---------------------------------------------------
-
-fn handle_class_request<D>(usb: &D, setup_packet: SetupPacket)
-where
-    D: ReadControl + ReadEndpoint + WriteEndpoint + UsbDriverOperations + UnsafeUsbDriverOperations,
-{
-    let request_type = setup_packet.request_type();
-    let request = setup_packet.request();
-    let direction = setup_packet.direction();
-
-    match (request_type, request) {
-        (RequestType::Class, Request::ClassOrVendor(class_request)) => {
-            use acm::serial::ClassRequest::{self, *};
-            let class_request = ClassRequest::from(class_request);
-
-            // In testing, macOS and Linux are fine will all requests being stalled; while Windows
-            // seems to be happy as long as SET_LINE_CODING is implemented. We'll implement only
-            // that, and stall every other handler.
-            match (direction, class_request) {
-                (Direction::HostToDevice, SetLineCoding) => {
-                    // 32 - comes with 7 bytes of data
-                    usb.ep_out_prime_receive(4);
-                }
-                // we can just stall the reset
-                (Direction::DeviceToHost, _) => {
-                    // IN
-                    usb.stall_endpoint_out(0);
-                }
-                (Direction::HostToDevice, _) => {
-                    // OUT
-                    usb.stall_endpoint_in(0);
-                }
-            }
-        }
-        _ => {
-            error!(
-                "handle_class_request error - unhandled control request: {:?} {:?}",
-                request_type, request
-            );
-        }
-    }
-}
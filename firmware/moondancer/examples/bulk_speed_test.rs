@@ -11,7 +11,7 @@ use smolusb::control::Control;
 use smolusb::descriptor::{
     ConfigurationDescriptor, ConfigurationDescriptorHeader, DescriptorType, DeviceDescriptor,
     DeviceQualifierDescriptor, EndpointDescriptor, InterfaceDescriptor, InterfaceDescriptorHeader,
-    LanguageId, StringDescriptor, StringDescriptorZero,
+    LanguageId, StringDescriptorZero,
 };
 use smolusb::device::{Descriptors, Speed};
 use smolusb::event::UsbEvent;
@@ -175,6 +175,7 @@ fn main_loop() -> GreatResult<()> {
         peripherals.USB0_EP_CONTROL,
         peripherals.USB0_EP_IN,
         peripherals.USB0_EP_OUT,
+        peripherals.USB0_BUS,
     );
 
     // usb0 control endpoint
@@ -183,14 +184,17 @@ fn main_loop() -> GreatResult<()> {
         Descriptors {
             device_speed: DEVICE_SPEED,
             device_descriptor: USB_DEVICE_DESCRIPTOR,
-            configuration_descriptor: USB_CONFIGURATION_DESCRIPTOR_0,
-            other_speed_configuration_descriptor: Some(USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0),
+            configuration_descriptors: &[USB_CONFIGURATION_DESCRIPTOR_0],
+            other_speed_configuration_descriptors: Some(&[
+                USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0,
+            ]),
             device_qualifier_descriptor: Some(USB_DEVICE_QUALIFIER_DESCRIPTOR),
             string_descriptor_zero: USB_STRING_DESCRIPTOR_0,
-            string_descriptors: USB_STRING_DESCRIPTORS,
+            string_descriptors: strings::STRING_DESCRIPTORS,
+            binary_object_store: None,
             microsoft10: None,
-        }
-        .set_total_lengths(),
+            microsoft20: None,
+        },
     );
 
     // connect device
@@ -502,9 +506,9 @@ static USB_DEVICE_DESCRIPTOR: DeviceDescriptor = DeviceDescriptor {
     bMaxPacketSize: 64,
     idVendor: cynthion::shared::usb::bVendorId::example,
     idProduct: cynthion::shared::usb::bProductId::example,
-    iManufacturer: 1,
-    iProduct: 2,
-    iSerialNumber: 3,
+    iManufacturer: strings::MANUFACTURER,
+    iProduct: strings::PRODUCT,
+    iSerialNumber: strings::SERIAL_NUMBER,
     bNumConfigurations: 1,
     ..DeviceDescriptor::new()
 };
@@ -520,10 +524,10 @@ static USB_DEVICE_QUALIFIER_DESCRIPTOR: DeviceQualifierDescriptor = DeviceQualif
     ..DeviceQualifierDescriptor::new()
 };
 
-static USB_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDescriptor::new(
+const USB_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDescriptor::new(
     ConfigurationDescriptorHeader {
         bConfigurationValue: 1,
-        iConfiguration: 1,
+        iConfiguration: strings::MANUFACTURER,
         bmAttributes: 0x80, // 0b1000_0000 = bus-powered
         bMaxPower: 50,      // 50 * 2 mA = 100 mA
         ..ConfigurationDescriptorHeader::new()
@@ -535,7 +539,7 @@ static USB_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDe
             bInterfaceClass: 0x00,
             bInterfaceSubClass: 0x00,
             bInterfaceProtocol: 0x00,
-            iInterface: 2,
+            iInterface: strings::PRODUCT,
             ..InterfaceDescriptorHeader::new()
         },
         &[
@@ -564,12 +568,12 @@ static USB_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDe
     )],
 );
 
-static USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
+const USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
     ConfigurationDescriptor::new(
         ConfigurationDescriptorHeader {
             bDescriptorType: DescriptorType::OtherSpeedConfiguration as u8,
             bConfigurationValue: 1,
-            iConfiguration: 1,
+            iConfiguration: strings::MANUFACTURER,
             bmAttributes: 0x80, // 0b1000_0000 = bus-powered
             bMaxPower: 50,      // 50 * 2 mA = 100 mA
             ..ConfigurationDescriptorHeader::new()
@@ -581,7 +585,7 @@ static USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
                 bInterfaceClass: 0x00,
                 bInterfaceSubClass: 0x00,
                 bInterfaceProtocol: 0x00,
-                iInterface: 2,
+                iInterface: strings::PRODUCT,
                 ..InterfaceDescriptorHeader::new()
             },
             &[
@@ -612,14 +616,11 @@ static USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
 
 static USB_STRING_DESCRIPTOR_0: StringDescriptorZero =
     StringDescriptorZero::new(&[LanguageId::EnglishUnitedStates]);
-static USB_STRING_DESCRIPTOR_1: StringDescriptor =
-    StringDescriptor::new(cynthion::shared::usb::bManufacturerString::bulk_speed_test);
-static USB_STRING_DESCRIPTOR_2: StringDescriptor =
-    StringDescriptor::new(cynthion::shared::usb::bProductString::bulk_speed_test);
-static USB_STRING_DESCRIPTOR_3: StringDescriptor = StringDescriptor::new("0000000000000000");
-
-static USB_STRING_DESCRIPTORS: &[&StringDescriptor] = &[
-    &USB_STRING_DESCRIPTOR_1,
-    &USB_STRING_DESCRIPTOR_2,
-    &USB_STRING_DESCRIPTOR_3,
-];
+
+smolusb::string_descriptors! {
+    mod strings {
+        MANUFACTURER = cynthion::shared::usb::bManufacturerString::bulk_speed_test,
+        PRODUCT = cynthion::shared::usb::bProductString::bulk_speed_test,
+        SERIAL_NUMBER = "0000000000000000",
+    }
+}
@@ -0,0 +1,201 @@
+#![no_std]
+#![no_main]
+
+/// Implementation of an USB ACM-Serial device using embassy-usb.
+///
+/// To test:
+///
+///     picocom --imap crcrlf -b 115200 /dev/ttyACM0
+///
+use log::info;
+
+use moondancer::{hal, pac};
+
+use hal::smolusb;
+use hal::usb::embassy::{Driver, State};
+use smolusb::class::acm;
+use smolusb::device::Speed;
+use smolusb::event::UsbEvent;
+use smolusb::setup::SetupPacket;
+use smolusb::traits::{ReadControl, UnsafeUsbDriverOperations, UsbDriverOperations};
+
+use embassy_futures::join::join;
+use embassy_usb::class::cdc_acm::{self, CdcAcmClass};
+use embassy_usb::driver::EndpointError;
+use embassy_usb::Builder;
+
+use pac::csr::interrupt;
+
+// - constants ----------------------------------------------------------------
+
+const DEVICE_SPEED: Speed = Speed::Full;
+const MAX_PACKET_SIZE: u16 = 64;
+
+// - global static state ------------------------------------------------------
+
+static STATE: State = State::new();
+
+// - MachineExternal interrupt handler ----------------------------------------
+
+#[allow(non_snake_case)]
+#[no_mangle]
+extern "C" fn MachineExternal() {
+    let usb0 = unsafe { hal::Usb0::summon() };
+
+    // get pending interrupt
+    let Ok(pending) = pac::csr::interrupt::pending() else {
+        return;
+    };
+
+    match pending {
+        pac::Interrupt::USB0 => {
+            usb0.bus_reset();
+            usb0.device
+                .ev_pending()
+                .modify(|r, w| w.mask().bit(r.mask().bit()));
+            STATE.signal_event(UsbEvent::BusReset);
+        }
+        pac::Interrupt::USB0_EP_CONTROL => {
+            let endpoint = usb0.ep_control.status().read().epno().bits();
+            let mut buffer = [0_u8; 8];
+            let _bytes_read = usb0.read_control(&mut buffer);
+            let setup_packet = SetupPacket::from(buffer);
+            usb0.ep_control
+                .ev_pending()
+                .modify(|r, w| w.mask().bit(r.mask().bit()));
+            STATE.signal_event(UsbEvent::ReceiveSetupPacket(endpoint, setup_packet));
+        }
+        pac::Interrupt::USB0_EP_IN => {
+            let endpoint = usb0.ep_in.status().read().epno().bits();
+            unsafe {
+                usb0.clear_tx_ack_active(endpoint);
+            }
+            usb0.ep_in
+                .ev_pending()
+                .modify(|r, w| w.mask().bit(r.mask().bit()));
+            STATE.signal_event(UsbEvent::SendComplete(endpoint));
+        }
+        pac::Interrupt::USB0_EP_OUT => {
+            let endpoint = usb0.ep_out.status().read().epno().bits();
+            usb0.ep_out
+                .ev_pending()
+                .modify(|r, w| w.mask().bit(r.mask().bit()));
+            STATE.signal_event(UsbEvent::ReceivePacket(endpoint));
+        }
+        pac::Interrupt::USB0_BUS => {
+            if let Some(event) = usb0.take_bus_event() {
+                STATE.signal_event(event);
+            }
+        }
+        _ => (),
+    }
+}
+
+// - main entry point ---------------------------------------------------------
+
+#[cfg(feature = "vexriscv")]
+#[riscv_rt::pre_init]
+unsafe fn pre_main() {
+    pac::cpu::vexriscv::flush_icache();
+    #[cfg(feature = "vexriscv_dcache")]
+    pac::cpu::vexriscv::flush_dcache();
+}
+
+#[riscv_rt::entry]
+fn main() -> ! {
+    let peripherals = pac::Peripherals::take().unwrap();
+
+    // initialize logging
+    moondancer::log::init();
+    info!("logging initialized");
+
+    // usb0: Target
+    let mut usb0 = hal::Usb0::new(
+        peripherals.USB0,
+        peripherals.USB0_EP_CONTROL,
+        peripherals.USB0_EP_IN,
+        peripherals.USB0_EP_OUT,
+        peripherals.USB0_BUS,
+    );
+
+    // disconnect device
+    usb0.disconnect();
+    unsafe {
+        riscv::asm::delay(6_000_000);
+    }
+
+    // connect device
+    usb0.connect(DEVICE_SPEED);
+    info!("Connected USB0 device: {:?}", DEVICE_SPEED);
+
+    // enable interrupts
+    unsafe {
+        // set mstatus register: interrupt enable
+        riscv::interrupt::enable();
+
+        // set mie register: machine external interrupts enable
+        riscv::register::mie::set_mext();
+
+        // write csr: enable usb0 interrupts and events
+        interrupt::enable(pac::Interrupt::USB0);
+        interrupt::enable(pac::Interrupt::USB0_EP_CONTROL);
+        interrupt::enable(pac::Interrupt::USB0_EP_IN);
+        interrupt::enable(pac::Interrupt::USB0_EP_OUT);
+        interrupt::enable(pac::Interrupt::USB0_BUS);
+        usb0.enable_events();
+    }
+
+    // embassy-usb device
+    let driver = Driver::new(&usb0, &STATE);
+
+    let mut config = embassy_usb::Config::new(acm::VENDOR_ID, acm::PRODUCT_ID);
+    config.manufacturer = Some("Cynthion Project");
+    config.product = Some("USB-to-serial");
+    config.serial_number = Some("100");
+    config.max_packet_size_0 = 64;
+
+    let mut config_descriptor = [0; 256];
+    let mut bos_descriptor = [0; 256];
+    let mut control_buf = [0; 64];
+    let mut cdc_state = cdc_acm::State::new();
+
+    let mut builder = Builder::new(
+        driver,
+        config,
+        &mut config_descriptor,
+        &mut bos_descriptor,
+        &mut [],
+        &mut control_buf,
+    );
+    let mut class = CdcAcmClass::new(&mut builder, &mut cdc_state, MAX_PACKET_SIZE);
+    let mut usb = builder.build();
+
+    info!("Peripherals initialized, entering main loop.");
+
+    let echo = async {
+        loop {
+            class.wait_connection().await;
+            info!("Connected");
+            let _ = echo(&mut class).await;
+            info!("Disconnected");
+        }
+    };
+
+    embassy_futures::block_on(join(usb.run(), echo)).0
+}
+
+// - echo ---------------------------------------------------------------------
+
+async fn echo<'d>(class: &mut CdcAcmClass<'d, Driver<'d, hal::Usb0>>) -> Result<(), EndpointError> {
+    let mut buffer = [0; MAX_PACKET_SIZE as usize];
+    loop {
+        let bytes_read = class.read_packet(&mut buffer).await?;
+
+        // convert to uppercase
+        for b in buffer[0..bytes_read].iter_mut() {
+            b.make_ascii_uppercase();
+        }
+
+        class.write_packet(&buffer[0..bytes_read]).await?;
+    }
+}
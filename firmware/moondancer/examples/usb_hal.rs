@@ -7,11 +7,11 @@ use log::{debug, error, info};
 use libgreat::GreatResult;
 
 use crate::hal::smolusb;
-use smolusb::control::Control;
+use smolusb::control::{Control, ControlEvent};
 use smolusb::descriptor::{
     ConfigurationDescriptor, ConfigurationDescriptorHeader, DescriptorType, DeviceDescriptor,
     DeviceQualifierDescriptor, EndpointDescriptor, InterfaceDescriptor, InterfaceDescriptorHeader,
-    LanguageId, StringDescriptor, StringDescriptorZero,
+    LanguageId, StringDescriptorZero,
 };
 use smolusb::device::{Descriptors, Speed};
 use smolusb::event::UsbEvent;
@@ -172,6 +172,7 @@ fn main_loop() -> GreatResult<()> {
         peripherals.USB0_EP_CONTROL,
         peripherals.USB0_EP_IN,
         peripherals.USB0_EP_OUT,
+        peripherals.USB0_BUS,
     );
 
     // control
@@ -180,14 +181,17 @@ fn main_loop() -> GreatResult<()> {
         Descriptors {
             device_speed: DEVICE_SPEED,
             device_descriptor: USB_DEVICE_DESCRIPTOR,
-            configuration_descriptor: USB_CONFIGURATION_DESCRIPTOR_0,
-            other_speed_configuration_descriptor: Some(USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0),
+            configuration_descriptors: &[USB_CONFIGURATION_DESCRIPTOR_0],
+            other_speed_configuration_descriptors: Some(&[
+                USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0,
+            ]),
             device_qualifier_descriptor: Some(USB_DEVICE_QUALIFIER_DESCRIPTOR),
             string_descriptor_zero: USB_STRING_DESCRIPTOR_0,
-            string_descriptors: USB_STRING_DESCRIPTORS,
+            string_descriptors: strings::STRING_DESCRIPTORS,
+            binary_object_store: None,
             microsoft10: None,
-        }
-        .set_total_lengths(),
+            microsoft20: None,
+        },
     );
 
     // connect device
@@ -230,7 +234,9 @@ fn main_loop() -> GreatResult<()> {
                     | ReceivePacket(0)
                     | SendComplete(0)),
                 ) => {
-                    if let Some(setup_packet) = control.dispatch_event(&usb0, event) {
+                    if let Some(ControlEvent::SetupPacket(setup_packet)) =
+                        control.dispatch_event(&usb0, event)
+                    {
                         // vendor requests are not handled by control
                         handle_vendor_request(&usb0, setup_packet, control.data());
                     }
@@ -386,9 +392,9 @@ static USB_DEVICE_DESCRIPTOR: DeviceDescriptor = DeviceDescriptor {
     bMaxPacketSize: 64,
     idVendor: cynthion::shared::usb::bVendorId::example,
     idProduct: cynthion::shared::usb::bProductId::example,
-    iManufacturer: 1,
-    iProduct: 2,
-    iSerialNumber: 3,
+    iManufacturer: strings::MANUFACTURER,
+    iProduct: strings::PRODUCT,
+    iSerialNumber: strings::SERIAL_NUMBER,
     bNumConfigurations: 1,
     ..DeviceDescriptor::new()
 };
@@ -403,10 +409,10 @@ static USB_DEVICE_QUALIFIER_DESCRIPTOR: DeviceQualifierDescriptor = DeviceQualif
     ..DeviceQualifierDescriptor::new()
 };
 
-static USB_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDescriptor::new(
+const USB_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDescriptor::new(
     ConfigurationDescriptorHeader {
         bConfigurationValue: 1,
-        iConfiguration: 4,
+        iConfiguration: strings::CONFIGURATION_1,
         bmAttributes: 0x80, // 0b1000_0000 = bus-powered
         bMaxPower: 50,      // 50 * 2 mA = 100 mA
         ..ConfigurationDescriptorHeader::new()
@@ -418,7 +424,7 @@ static USB_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDe
             bInterfaceClass: 0x00,
             bInterfaceSubClass: 0x00,
             bInterfaceProtocol: 0x00,
-            iInterface: 5,
+            iInterface: strings::INTERFACE_0,
             ..InterfaceDescriptorHeader::new()
         },
         &[
@@ -440,12 +446,12 @@ static USB_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDe
     )],
 );
 
-static USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
+const USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
     ConfigurationDescriptor::new(
         ConfigurationDescriptorHeader {
             bDescriptorType: DescriptorType::OtherSpeedConfiguration as u8,
             bConfigurationValue: 1,
-            iConfiguration: 6,
+            iConfiguration: strings::OTHER_CONFIGURATION_1,
             bmAttributes: 0x80, // 0b1000_0000 = bus-powered
             bMaxPower: 50,      // 50 * 2 mA = 100 mA
             ..ConfigurationDescriptorHeader::new()
@@ -457,7 +463,7 @@ static USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
                 bInterfaceClass: 0x00,
                 bInterfaceSubClass: 0x00,
                 bInterfaceProtocol: 0x00,
-                iInterface: 7,
+                iInterface: strings::OTHER_INTERFACE_0,
                 ..InterfaceDescriptorHeader::new()
             },
             &[
@@ -481,22 +487,15 @@ static USB_OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
 
 static USB_STRING_DESCRIPTOR_0: StringDescriptorZero =
     StringDescriptorZero::new(&[LanguageId::EnglishUnitedStates]);
-static USB_STRING_DESCRIPTOR_1: StringDescriptor =
-    StringDescriptor::new(cynthion::shared::usb::bManufacturerString::example);
-static USB_STRING_DESCRIPTOR_2: StringDescriptor =
-    StringDescriptor::new(cynthion::shared::usb::bProductString::example);
-static USB_STRING_DESCRIPTOR_3: StringDescriptor = StringDescriptor::new("0000000000000000");
-pub static USB_STRING_DESCRIPTOR_4: StringDescriptor = StringDescriptor::new("config 1");
-pub static USB_STRING_DESCRIPTOR_5: StringDescriptor = StringDescriptor::new("interface 0");
-pub static USB_STRING_DESCRIPTOR_6: StringDescriptor = StringDescriptor::new("other config 1");
-pub static USB_STRING_DESCRIPTOR_7: StringDescriptor = StringDescriptor::new("other interface 0");
-
-static USB_STRING_DESCRIPTORS: &[&StringDescriptor] = &[
-    &USB_STRING_DESCRIPTOR_1,
-    &USB_STRING_DESCRIPTOR_2,
-    &USB_STRING_DESCRIPTOR_3,
-    &USB_STRING_DESCRIPTOR_4,
-    &USB_STRING_DESCRIPTOR_5,
-    &USB_STRING_DESCRIPTOR_6,
-    &USB_STRING_DESCRIPTOR_7,
-];
+
+smolusb::string_descriptors! {
+    mod strings {
+        MANUFACTURER = cynthion::shared::usb::bManufacturerString::example,
+        PRODUCT = cynthion::shared::usb::bProductString::example,
+        SERIAL_NUMBER = "0000000000000000",
+        CONFIGURATION_1 = "config 1",
+        INTERFACE_0 = "interface 0",
+        OTHER_CONFIGURATION_1 = "other config 1",
+        OTHER_INTERFACE_0 = "other interface 0",
+    }
+}
@@ -5,7 +5,7 @@ use heapless::mpmc::MpMcQueue as Queue;
 use log::{debug, error, info, trace, warn};
 
 use crate::hal::smolusb;
-use smolusb::control::Control;
+use smolusb::control::{Control, ControlEvent};
 use smolusb::descriptor::StringDescriptor;
 use smolusb::device::{Descriptors, Speed};
 use smolusb::setup::{Direction, Recipient, RequestType, SetupPacket};
@@ -151,24 +151,20 @@ impl Firmware<'_> {
         // FIXME crimes should not have to be committed in order to provide smolusb with a dynamic string table!
         #[allow(clippy::items_after_statements)]
         let string_descriptors = {
+            use moondancer::usb::strings;
             static mut UUID: heapless::String<16> = heapless::String::new();
             static mut ISERIALNUMBER: StringDescriptor = StringDescriptor::new("0000000000000000");
+            static mut STRING_DESCRIPTORS: [&StringDescriptor; 6] =
+                [unsafe { &*core::ptr::addr_of!(ISERIALNUMBER) }; 6];
             #[allow(static_mut_refs)]
             unsafe {
                 UUID = uuid.clone();
                 ISERIALNUMBER = StringDescriptor::new(UUID.as_str());
+                // replace the default serial number with the flash uuid
+                STRING_DESCRIPTORS.copy_from_slice(strings::STRING_DESCRIPTORS);
+                STRING_DESCRIPTORS[usize::from(strings::SERIAL_NUMBER) - 1] =
+                    &*core::ptr::addr_of!(ISERIALNUMBER);
             }
-            static mut STRING_DESCRIPTORS: [&StringDescriptor; 9] = [
-                &moondancer::usb::STRING_DESCRIPTOR_1,
-                &moondancer::usb::STRING_DESCRIPTOR_2,
-                unsafe { &*core::ptr::addr_of!(ISERIALNUMBER) },
-                &moondancer::usb::STRING_DESCRIPTOR_4,
-                &moondancer::usb::STRING_DESCRIPTOR_5,
-                &moondancer::usb::STRING_DESCRIPTOR_6,
-                &moondancer::usb::STRING_DESCRIPTOR_7,
-                &moondancer::usb::STRING_DESCRIPTOR_8,
-                &moondancer::usb::STRING_DESCRIPTOR_9,
-            ];
             unsafe { &*core::ptr::addr_of!(STRING_DESCRIPTORS) }
         };
 
@@ -178,6 +174,7 @@ impl Firmware<'_> {
             peripherals.USB2_EP_CONTROL,
             peripherals.USB2_EP_IN,
             peripherals.USB2_EP_OUT,
+            peripherals.USB2_BUS,
         );
 
         // usb0: target
@@ -186,6 +183,7 @@ impl Firmware<'_> {
             peripherals.USB0_EP_CONTROL,
             peripherals.USB0_EP_IN,
             peripherals.USB0_EP_OUT,
+            peripherals.USB0_BUS,
         );
 
         // format bcdDevice
@@ -200,14 +198,14 @@ impl Firmware<'_> {
                     bcdDevice: bcd_device,
                     ..moondancer::usb::DEVICE_DESCRIPTOR
                 },
-                configuration_descriptor: moondancer::usb::CONFIGURATION_DESCRIPTOR_0,
+                configuration_descriptors: &moondancer::usb::CONFIGURATION_DESCRIPTORS,
                 string_descriptor_zero: moondancer::usb::STRING_DESCRIPTOR_0,
                 string_descriptors,
                 // optional
                 device_qualifier_descriptor: Some(moondancer::usb::DEVICE_QUALIFIER_DESCRIPTOR),
-                other_speed_configuration_descriptor: Some(
-                    moondancer::usb::OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0,
-                ),
+                // derived from the high-speed configuration
+                other_speed_configuration_descriptors: None,
+                binary_object_store: None,
                 microsoft10: Some(smolusb::descriptor::microsoft10::Descriptors {
                     string_descriptor: moondancer::usb::STRING_DESCRIPTOR_0XEE,
                     compat_id_feature_descriptor:
@@ -215,6 +213,7 @@ impl Firmware<'_> {
                     extended_properties_feature_descriptor:
                         moondancer::usb::MS_OS_10_EXTENDED_PROPERTIES_FEATURE_DESCRIPTOR,
                 }),
+                microsoft20: None,
             },
         );
 
@@ -313,7 +312,7 @@ impl<'a> Firmware<'a> {
                         | SendComplete(0)),
                     ) => {
                         trace!("Usb(Control, {:?})", event);
-                        if let Some(setup_packet) =
+                        if let Some(ControlEvent::SetupPacket(setup_packet)) =
                             self.usb2_control.dispatch_event(&self.usb2, event)
                         {
                             // vendor requests are not handled by control
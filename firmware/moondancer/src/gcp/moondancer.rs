@@ -180,6 +180,11 @@ impl Moondancer {
                 // no-op, just pass it on through
                 event
             }
+
+            UsbEvent::Suspend | UsbEvent::Resume | UsbEvent::StartOfFrame(_) => {
+                // drop event, because - currently - we're not using it in moondancer.py
+                return;
+            }
         };
 
         // enqueue interrupt event
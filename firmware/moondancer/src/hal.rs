@@ -24,7 +24,7 @@ use lunasoc_hal::smolusb::traits::{
 };
 use lunasoc_hal::usb::DEFAULT_TIMEOUT;
 lunasoc_hal::impl_usb! {
-    Usb0: usb0, pac::USB0, pac::USB0_EP_CONTROL, pac::USB0_EP_IN, pac::USB0_EP_OUT,
-    Usb1: usb1, pac::USB1, pac::USB1_EP_CONTROL, pac::USB1_EP_IN, pac::USB1_EP_OUT,
-    Usb2: usb2, pac::USB2, pac::USB2_EP_CONTROL, pac::USB2_EP_IN, pac::USB2_EP_OUT,
+    Usb0: usb0, pac::USB0, pac::USB0_EP_CONTROL, pac::USB0_EP_IN, pac::USB0_EP_OUT, pac::USB0_BUS,
+    Usb1: usb1, pac::USB1, pac::USB1_EP_CONTROL, pac::USB1_EP_IN, pac::USB1_EP_OUT, pac::USB1_BUS,
+    Usb2: usb2, pac::USB2, pac::USB2_EP_CONTROL, pac::USB2_EP_IN, pac::USB2_EP_OUT, pac::USB2_BUS,
 }
@@ -3,7 +3,7 @@ use crate::hal::smolusb;
 use smolusb::descriptor::{
     microsoft10, ConfigurationDescriptor, ConfigurationDescriptorHeader, DescriptorType,
     DeviceDescriptor, DeviceQualifierDescriptor, EndpointDescriptor, InterfaceDescriptor,
-    InterfaceDescriptorHeader, LanguageId, StringDescriptor, StringDescriptorZero,
+    InterfaceDescriptorHeader, LanguageId, StringDescriptorZero,
 };
 
 // - vendor request -----------------------------------------------------------
@@ -75,28 +75,21 @@ pub static DEVICE_DESCRIPTOR: DeviceDescriptor = DeviceDescriptor {
     bMaxPacketSize: 64,
     idVendor: cynthion::shared::usb::bVendorId::cynthion,
     idProduct: cynthion::shared::usb::bProductId::cynthion,
-    iManufacturer: 1,
-    iProduct: 2,
-    iSerialNumber: 3,
+    iManufacturer: strings::MANUFACTURER,
+    iProduct: strings::PRODUCT,
+    iSerialNumber: strings::SERIAL_NUMBER,
     bNumConfigurations: 1,
     ..DeviceDescriptor::new()
 };
 
-pub static DEVICE_QUALIFIER_DESCRIPTOR: DeviceQualifierDescriptor = DeviceQualifierDescriptor {
-    bcdUSB: 0x0200,
-    bDeviceClass: 0x00,    // Composite
-    bDeviceSubClass: 0x00, // Composite
-    bDeviceProtocol: 0x00, // Composite
-    bMaxPacketSize0: 64,
-    bNumConfigurations: 1,
-    ..DeviceQualifierDescriptor::new()
-};
+pub static DEVICE_QUALIFIER_DESCRIPTOR: DeviceQualifierDescriptor =
+    DeviceQualifierDescriptor::from_device_descriptor(&DEVICE_DESCRIPTOR);
 
 pub static CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDescriptor::new(
     ConfigurationDescriptorHeader {
         bDescriptorType: DescriptorType::Configuration as u8,
         bConfigurationValue: 1,
-        iConfiguration: 4,
+        iConfiguration: strings::CONFIGURATION_0,
         bmAttributes: 0x80, // 0b1000_0000 = bus-powered
         bMaxPower: 250,     // 250 * 2 mA = 500 mA ?
         ..ConfigurationDescriptorHeader::new()
@@ -109,7 +102,7 @@ pub static CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDe
                 bInterfaceClass: 0xff, // Vendor-specific
                 bInterfaceSubClass: cynthion::shared::usb::bInterfaceSubClass::moondancer,
                 bInterfaceProtocol: cynthion::shared::usb::bInterfaceProtocol::moondancer,
-                iInterface: 5,
+                iInterface: strings::INTERFACE_0,
                 ..InterfaceDescriptorHeader::new()
             },
             &[
@@ -137,7 +130,7 @@ pub static CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDe
                 bInterfaceClass: 0xff, // Vendor-specific
                 bInterfaceSubClass: 0,
                 bInterfaceProtocol: 0,
-                iInterface: 6,
+                iInterface: strings::INTERFACE_1,
                 ..InterfaceDescriptorHeader::new()
             },
             &[],
@@ -145,60 +138,7 @@ pub static CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor = ConfigurationDe
     ],
 );
 
-pub static OTHER_SPEED_CONFIGURATION_DESCRIPTOR_0: ConfigurationDescriptor =
-    ConfigurationDescriptor::new(
-        ConfigurationDescriptorHeader {
-            bDescriptorType: DescriptorType::OtherSpeedConfiguration as u8,
-            bConfigurationValue: 1,
-            iConfiguration: 7,
-            bmAttributes: 0x80, // 0b1000_0000 = bus-powered
-            bMaxPower: 250,     // 250 * 2 mA = 500 mA ?
-            ..ConfigurationDescriptorHeader::new()
-        },
-        &[
-            // Moondancer control interface
-            InterfaceDescriptor::new(
-                InterfaceDescriptorHeader {
-                    iInterfaceNumber: 0,
-                    bAlternateSetting: 0,
-                    bInterfaceClass: 0xff, // Vendor-specific
-                    bInterfaceSubClass: cynthion::shared::usb::bInterfaceSubClass::moondancer,
-                    bInterfaceProtocol: cynthion::shared::usb::bInterfaceProtocol::moondancer,
-                    iInterface: 8,
-                    ..InterfaceDescriptorHeader::new()
-                },
-                &[
-                    EndpointDescriptor {
-                        bEndpointAddress: cynthion::shared::libgreat::endpoints::bulk_in_address, // IN
-                        bmAttributes: 0x02, // Bulk
-                        wMaxPacketSize: 64,
-                        bInterval: 0,
-                        ..EndpointDescriptor::new()
-                    },
-                    EndpointDescriptor {
-                        bEndpointAddress: cynthion::shared::libgreat::endpoints::bulk_out_address, // OUT
-                        bmAttributes: 0x02, // Bulk
-                        wMaxPacketSize: 64,
-                        bInterval: 0,
-                        ..EndpointDescriptor::new()
-                    },
-                ],
-            ),
-            // Apollo stub interface
-            InterfaceDescriptor::new(
-                InterfaceDescriptorHeader {
-                    iInterfaceNumber: 1,
-                    bAlternateSetting: 0,
-                    bInterfaceClass: 0xff, // Vendor-specific
-                    bInterfaceSubClass: 0,
-                    bInterfaceProtocol: 0,
-                    iInterface: 9,
-                    ..InterfaceDescriptorHeader::new()
-                },
-                &[],
-            ),
-        ],
-    );
+pub static CONFIGURATION_DESCRIPTORS: [ConfigurationDescriptor; 1] = [CONFIGURATION_DESCRIPTOR_0];
 
 pub const COMPATIBLE_ID: [u8; 8] = *b"WINUSB\0\0";
 
@@ -223,41 +163,17 @@ pub const MS_OS_10_EXTENDED_PROPERTIES_FEATURE_DESCRIPTOR:
 pub static STRING_DESCRIPTOR_0: StringDescriptorZero =
     StringDescriptorZero::new(&[LanguageId::EnglishUnitedStates]);
 
-// manufacturer
-pub static STRING_DESCRIPTOR_1: StringDescriptor =
-    StringDescriptor::new(cynthion::shared::usb::bManufacturerString::moondancer);
-// product
-pub static STRING_DESCRIPTOR_2: StringDescriptor =
-    StringDescriptor::new(cynthion::shared::usb::bProductString::moondancer);
-// serial
-pub static STRING_DESCRIPTOR_3: StringDescriptor = StringDescriptor::new("0000000000000000");
-
-// configuration #0
-pub static STRING_DESCRIPTOR_4: StringDescriptor = StringDescriptor::new("config0");
-// interface #0
-pub static STRING_DESCRIPTOR_5: StringDescriptor = StringDescriptor::new("Facedancer Control");
-// interface #1
-pub static STRING_DESCRIPTOR_6: StringDescriptor = StringDescriptor::new("Apollo Stub");
-
-// other-speed configuration #0
-pub static STRING_DESCRIPTOR_7: StringDescriptor = StringDescriptor::new("other config0");
-// interface #0
-pub static STRING_DESCRIPTOR_8: StringDescriptor = StringDescriptor::new("other interface0");
-// interface #1
-pub static STRING_DESCRIPTOR_9: StringDescriptor = StringDescriptor::new("other interface1");
+smolusb::string_descriptors! {
+    pub mod strings {
+        MANUFACTURER = cynthion::shared::usb::bManufacturerString::moondancer,
+        PRODUCT = cynthion::shared::usb::bProductString::moondancer,
+        SERIAL_NUMBER = "0000000000000000",
+        CONFIGURATION_0 = "config0",
+        INTERFACE_0 = "Facedancer Control",
+        INTERFACE_1 = "Apollo Stub",
+    }
+}
 
 // microsoft os 1.0 string descriptor
 pub static STRING_DESCRIPTOR_0XEE: microsoft10::StringDescriptor =
     microsoft10::StringDescriptor::new(microsoft10::VendorRequest::Microsoft);
-
-pub static STRING_DESCRIPTORS: &[&StringDescriptor] = &[
-    &STRING_DESCRIPTOR_1,
-    &STRING_DESCRIPTOR_2,
-    &STRING_DESCRIPTOR_3,
-    &STRING_DESCRIPTOR_4,
-    &STRING_DESCRIPTOR_5,
-    &STRING_DESCRIPTOR_6,
-    &STRING_DESCRIPTOR_7,
-    &STRING_DESCRIPTOR_8,
-    &STRING_DESCRIPTOR_9,
-];
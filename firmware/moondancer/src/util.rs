@@ -93,6 +93,12 @@ pub fn get_usb_interrupt_event() -> InterruptEvent {
             InterruptEvent::Usb(Target, UsbEvent::ReceivePacket(endpoint_number))
         }),
 
+        // USB0_BUS Suspend / Resume / StartOfFrame
+        pac::Interrupt::USB0_BUS => match usb0.take_bus_event() {
+            Some(event) => InterruptEvent::Usb(Target, event),
+            None => InterruptEvent::UnhandledInterrupt(pending),
+        },
+
         // - usb1 interrupts - "aux_phy" (host on r0.4) --
 
         // USB1 BusReset
@@ -151,6 +157,12 @@ pub fn get_usb_interrupt_event() -> InterruptEvent {
             InterruptEvent::Usb(Aux, UsbEvent::ReceivePacket(endpoint_number))
         }
 
+        // USB1_BUS Suspend / Resume / StartOfFrame
+        pac::Interrupt::USB1_BUS => match usb1.take_bus_event() {
+            Some(event) => InterruptEvent::Usb(Aux, event),
+            None => InterruptEvent::UnhandledInterrupt(pending),
+        },
+
         // - usb2 interrupts - "control_phy" (sideband on r0.4) --
 
         // USB2 BusReset
@@ -209,6 +221,12 @@ pub fn get_usb_interrupt_event() -> InterruptEvent {
             InterruptEvent::Usb(Control, UsbEvent::ReceivePacket(endpoint_number))
         }
 
+        // USB2_BUS Suspend / Resume / StartOfFrame
+        pac::Interrupt::USB2_BUS => match usb2.take_bus_event() {
+            Some(event) => InterruptEvent::Usb(Control, event),
+            None => InterruptEvent::UnhandledInterrupt(pending),
+        },
+
         // Unhandled
         _ => InterruptEvent::UnhandledInterrupt(pending),
     }
@@ -0,0 +1,856 @@
+//! Async endpoint support
+//!
+//! [`UsbEventSignals`] turns the [`UsbEvent`] stream generated by the
+//! USB interface's interrupt handler into wakeups for async tasks.
+//!
+//! The interrupt handler forwards each event:
+//!
+//! ```ignore
+//! static SIGNALS: UsbEventSignals = UsbEventSignals::new();
+//!
+//! fn usb0_interrupt_handler(event: UsbEvent) {
+//!     SIGNALS.signal_event(event);
+//! }
+//! ```
+//!
+//! Class code can then be written as straight-line async functions:
+//!
+//! ```ignore
+//! let usb = AsyncDriver::new(&usb0, &SIGNALS, Speed::High);
+//! loop {
+//!     let bytes_read = usb.read_async(0x02, &mut buffer).await;
+//!     usb.write_async(0x81, buffer[..bytes_read].iter().copied()).await;
+//! }
+//! ```
+
+use core::cell::UnsafeCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
+use core::task::{Context, Poll, Waker};
+
+use crate::device::Speed;
+use crate::event::UsbEvent;
+use crate::setup::SetupPacket;
+use crate::traits::{
+    AsyncReadControl, AsyncReadEndpoint, AsyncWriteEndpoint, ReadControl, ReadEndpoint,
+    WriteEndpoint,
+};
+use crate::EP_MAX_ENDPOINTS;
+
+// - AtomicWaker --------------------------------------------------------------
+
+const WAITING: u8 = 0;
+const REGISTERING: u8 = 0b01;
+const WAKING: u8 = 0b10;
+
+/// A waker slot that can be shared between a task and an interrupt handler.
+struct AtomicWaker {
+    state: AtomicU8,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+// SAFETY: access to `waker` is serialized by `state`
+unsafe impl Sync for AtomicWaker {}
+
+impl AtomicWaker {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    fn register(&self, waker: &Waker) {
+        match self.state.compare_exchange(
+            WAITING,
+            REGISTERING,
+            Ordering::Acquire,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => {
+                // SAFETY: we hold the REGISTERING lock
+                let slot = unsafe { &mut *self.waker.get() };
+                match slot {
+                    Some(old) if old.will_wake(waker) => (),
+                    _ => *slot = Some(waker.clone()),
+                }
+
+                if self
+                    .state
+                    .compare_exchange(REGISTERING, WAITING, Ordering::AcqRel, Ordering::Acquire)
+                    .is_err()
+                {
+                    // a wake arrived while we were registering
+                    let waker = slot.take();
+                    self.state.swap(WAITING, Ordering::AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            }
+            Err(WAKING) => waker.wake_by_ref(),
+            Err(_) => (),
+        }
+    }
+
+    fn wake(&self) {
+        if self.state.fetch_or(WAKING, Ordering::AcqRel) == WAITING {
+            // SAFETY: we hold the WAKING lock
+            let waker = unsafe { (*self.waker.get()).take() };
+            self.state.fetch_and(!WAKING, Ordering::Release);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
+
+// - Signal -------------------------------------------------------------------
+
+/// A single pending event flag with an associated waker.
+pub struct Signal {
+    pending: AtomicBool,
+    waker: AtomicWaker,
+}
+
+impl Default for Signal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Signal {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            pending: AtomicBool::new(false),
+            waker: AtomicWaker::new(),
+        }
+    }
+
+    /// Set the signal and wake any waiting task.
+    pub fn signal(&self) {
+        self.pending.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+
+    /// Clear the signal.
+    pub fn reset(&self) {
+        self.pending.store(false, Ordering::Release);
+    }
+
+    /// Returns `true` if the signal is set.
+    pub fn is_pending(&self) -> bool {
+        self.pending.load(Ordering::Acquire)
+    }
+
+    /// Poll the signal, clearing it if it was set.
+    pub fn poll_wait(&self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.pending.swap(false, Ordering::AcqRel) {
+            return Poll::Ready(());
+        }
+        self.waker.register(cx.waker());
+        if self.pending.swap(false, Ordering::AcqRel) {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Wait for the signal to be set.
+    pub fn wait(&self) -> SignalFuture<'_> {
+        SignalFuture { signal: self }
+    }
+}
+
+/// Future returned by [`Signal::wait`].
+pub struct SignalFuture<'a> {
+    signal: &'a Signal,
+}
+
+impl<'a> Future for SignalFuture<'a> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.signal.poll_wait(cx)
+    }
+}
+
+// - UsbEventSignals ----------------------------------------------------------
+
+/// Per-endpoint signals driven by the [`UsbEvent`] interrupt stream.
+pub struct UsbEventSignals {
+    bus_reset: Signal,
+    bus_state: Signal,
+    suspended: AtomicBool,
+    start_of_frame: Signal,
+    frame_number: AtomicU32,
+    control: Signal,
+    setup_packet: [AtomicU32; 2],
+    has_setup_packet: AtomicBool,
+    receive_packet: [Signal; EP_MAX_ENDPOINTS],
+    send_complete: [Signal; EP_MAX_ENDPOINTS],
+}
+
+impl Default for UsbEventSignals {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UsbEventSignals {
+    #[must_use]
+    pub const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const SIGNAL: Signal = Signal::new();
+        Self {
+            bus_reset: Signal::new(),
+            bus_state: Signal::new(),
+            suspended: AtomicBool::new(false),
+            start_of_frame: Signal::new(),
+            frame_number: AtomicU32::new(0),
+            control: Signal::new(),
+            setup_packet: [AtomicU32::new(0), AtomicU32::new(0)],
+            has_setup_packet: AtomicBool::new(false),
+            receive_packet: [SIGNAL; EP_MAX_ENDPOINTS],
+            send_complete: [SIGNAL; EP_MAX_ENDPOINTS],
+        }
+    }
+
+    /// Forward an event from the USB interface's interrupt handler.
+    ///
+    /// Events for endpoints outside `0..EP_MAX_ENDPOINTS` are ignored.
+    pub fn signal_event(&self, event: UsbEvent) {
+        match event {
+            UsbEvent::BusReset => {
+                self.suspended.store(false, Ordering::Release);
+                self.bus_reset.signal();
+            }
+            UsbEvent::ReceiveControl(_) => {
+                self.has_setup_packet.store(false, Ordering::Release);
+                self.control.signal();
+            }
+            UsbEvent::ReceiveSetupPacket(_, setup_packet) => {
+                let [a, b, c, d, e, f, g, h] = <[u8; 8]>::from(setup_packet);
+                self.setup_packet[0].store(u32::from_le_bytes([a, b, c, d]), Ordering::Relaxed);
+                self.setup_packet[1].store(u32::from_le_bytes([e, f, g, h]), Ordering::Relaxed);
+                self.has_setup_packet.store(true, Ordering::Release);
+                self.control.signal();
+            }
+            UsbEvent::ReceivePacket(endpoint_number) => {
+                if let Some(signal) = self.receive_packet.get(usize::from(endpoint_number)) {
+                    signal.signal();
+                }
+            }
+            // the packet has already been read by the interrupt handler
+            // so there is nothing left for an async reader to consume
+            #[cfg(feature = "chonky_events")]
+            UsbEvent::ReceiveBuffer(endpoint_number, _, _) => {
+                log::warn!(
+                    "UsbEventSignals::signal_event() - ignoring ReceiveBuffer({})",
+                    endpoint_number
+                );
+            }
+            UsbEvent::SendComplete(endpoint_number) => {
+                if let Some(signal) = self.send_complete.get(usize::from(endpoint_number)) {
+                    signal.signal();
+                }
+            }
+            UsbEvent::Suspend => {
+                self.suspended.store(true, Ordering::Release);
+                self.bus_state.signal();
+            }
+            UsbEvent::Resume => {
+                self.suspended.store(false, Ordering::Release);
+                self.bus_state.signal();
+            }
+            UsbEvent::StartOfFrame(frame_number) => {
+                self.frame_number
+                    .store(u32::from(frame_number), Ordering::Relaxed);
+                self.start_of_frame.signal();
+            }
+        }
+    }
+
+    /// Returns `true` if the bus is suspended.
+    #[must_use]
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::Acquire)
+    }
+
+    /// Returns the frame number of the last start-of-frame packet.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn frame_number(&self) -> u16 {
+        self.frame_number.load(Ordering::Relaxed) as u16
+    }
+
+    /// Wait for a bus reset.
+    pub fn wait_bus_reset(&self) -> SignalFuture<'_> {
+        self.bus_reset.wait()
+    }
+
+    /// Wait for a bus reset or a change of the suspend state.
+    ///
+    /// Returns [`UsbEvent::BusReset`], [`UsbEvent::Suspend`] or
+    /// [`UsbEvent::Resume`]. Only the latest suspend state is reported
+    /// if the bus was suspended and resumed before the task was polled.
+    pub fn poll_bus_event(&self, cx: &mut Context<'_>) -> Poll<UsbEvent> {
+        if self.bus_reset.poll_wait(cx).is_ready() {
+            // a bus reset also ends any suspend
+            self.bus_state.reset();
+            return Poll::Ready(UsbEvent::BusReset);
+        }
+        match self.bus_state.poll_wait(cx) {
+            Poll::Ready(()) if self.is_suspended() => Poll::Ready(UsbEvent::Suspend),
+            Poll::Ready(()) => Poll::Ready(UsbEvent::Resume),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Wait for a setup packet on the control endpoint.
+    ///
+    /// Returns the setup packet if it was read by the interrupt handler.
+    pub fn poll_setup_packet(&self, cx: &mut Context<'_>) -> Poll<Option<SetupPacket>> {
+        match self.control.poll_wait(cx) {
+            Poll::Ready(()) => {
+                if self.has_setup_packet.swap(false, Ordering::AcqRel) {
+                    let a = self.setup_packet[0].load(Ordering::Relaxed).to_le_bytes();
+                    let b = self.setup_packet[1].load(Ordering::Relaxed).to_le_bytes();
+                    Poll::Ready(Some(SetupPacket::from([
+                        a[0], a[1], a[2], a[3], b[0], b[1], b[2], b[3],
+                    ])))
+                } else {
+                    Poll::Ready(None)
+                }
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Returns the receive signal for the given OUT endpoint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `endpoint_number` is not less than [`EP_MAX_ENDPOINTS`].
+    #[must_use]
+    pub fn receive_packet(&self, endpoint_number: u8) -> &Signal {
+        &self.receive_packet[usize::from(endpoint_number)]
+    }
+
+    /// Returns the send complete signal for the given IN endpoint.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `endpoint_number` is not less than [`EP_MAX_ENDPOINTS`].
+    #[must_use]
+    pub fn send_complete(&self, endpoint_number: u8) -> &Signal {
+        &self.send_complete[usize::from(endpoint_number)]
+    }
+
+    /// Returns the start-of-frame signal.
+    #[must_use]
+    pub fn start_of_frame(&self) -> &Signal {
+        &self.start_of_frame
+    }
+}
+
+// - AsyncDriver --------------------------------------------------------------
+
+/// Wraps a [`UsbDriver`](crate::traits::UsbDriver) with async
+/// endpoint operations driven by a [`UsbEventSignals`].
+///
+/// Writes are split into packets of the maximum packet size for the
+/// given device speed.
+pub struct AsyncDriver<'a, D> {
+    driver: &'a D,
+    signals: &'a UsbEventSignals,
+    device_speed: Speed,
+}
+
+impl<'a, D> AsyncDriver<'a, D> {
+    pub fn new(driver: &'a D, signals: &'a UsbEventSignals, device_speed: Speed) -> Self {
+        Self {
+            driver,
+            signals,
+            device_speed,
+        }
+    }
+
+    /// Returns the wrapped driver.
+    #[must_use]
+    pub fn driver(&self) -> &'a D {
+        self.driver
+    }
+
+    /// Returns the signals driving this driver.
+    #[must_use]
+    pub fn signals(&self) -> &'a UsbEventSignals {
+        self.signals
+    }
+}
+
+impl<'d, D: ReadControl> AsyncReadControl for AsyncDriver<'d, D> {
+    type ReadControlFuture<'a>
+        = ReadControlFuture<'a, D>
+    where
+        Self: 'a;
+
+    fn read_control_async(&self) -> Self::ReadControlFuture<'_> {
+        ReadControlFuture {
+            driver: self.driver,
+            signals: self.signals,
+        }
+    }
+}
+
+impl<'d, D: ReadEndpoint> AsyncReadEndpoint for AsyncDriver<'d, D> {
+    type ReadFuture<'a>
+        = ReadFuture<'a, D>
+    where
+        Self: 'a;
+
+    fn read_async<'a>(&'a self, endpoint_number: u8, buffer: &'a mut [u8]) -> Self::ReadFuture<'a> {
+        ReadFuture {
+            driver: self.driver,
+            signal: self.signals.receive_packet(endpoint_number),
+            endpoint_number,
+            buffer,
+            primed: false,
+        }
+    }
+}
+
+impl<'d, D: WriteEndpoint> AsyncWriteEndpoint for AsyncDriver<'d, D> {
+    type WriteFuture<'a, I>
+        = WriteFuture<'a, D, I>
+    where
+        Self: 'a,
+        I: Iterator<Item = u8> + Unpin + 'a;
+
+    fn write_async<'a, I>(&'a self, endpoint_number: u8, iter: I) -> Self::WriteFuture<'a, I>
+    where
+        I: Iterator<Item = u8> + Unpin + 'a,
+    {
+        WriteFuture {
+            driver: self.driver,
+            signal: self.signals.send_complete(endpoint_number),
+            endpoint_number,
+            packet_size: crate::max_packet_size(self.device_speed, endpoint_number),
+            iter,
+            bytes_written: 0,
+            in_flight: false,
+            complete: false,
+        }
+    }
+}
+
+// - futures ------------------------------------------------------------------
+
+/// Future returned by [`AsyncReadControl::read_control_async`].
+pub struct ReadControlFuture<'a, D> {
+    driver: &'a D,
+    signals: &'a UsbEventSignals,
+}
+
+impl<'a, D: ReadControl> Future for ReadControlFuture<'a, D> {
+    type Output = SetupPacket;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.signals.poll_setup_packet(cx) {
+            Poll::Ready(Some(setup_packet)) => Poll::Ready(setup_packet),
+            Poll::Ready(None) => {
+                // the interrupt handler left the setup packet in the fifo
+                let mut buffer = [0_u8; 8];
+                self.driver.read_control(&mut buffer);
+                Poll::Ready(SetupPacket::from(buffer))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Future returned by [`AsyncReadEndpoint::read_async`].
+pub struct ReadFuture<'a, D> {
+    driver: &'a D,
+    signal: &'a Signal,
+    endpoint_number: u8,
+    buffer: &'a mut [u8],
+    primed: bool,
+}
+
+impl<'a, D: ReadEndpoint> Future for ReadFuture<'a, D> {
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if !this.primed {
+            // discard any stale packet notification before priming
+            this.signal.reset();
+            this.driver.ep_out_prime_receive(this.endpoint_number);
+            this.primed = true;
+        }
+        match this.signal.poll_wait(cx) {
+            Poll::Ready(()) => Poll::Ready(this.driver.read(this.endpoint_number, this.buffer)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Future returned by [`AsyncWriteEndpoint::write_async`].
+///
+/// Each packet is queued once the host has acknowledged the previous
+/// one so the future never blocks waiting for the endpoint. A transfer
+/// ending on a full packet is terminated with a zero length packet.
+///
+/// A packet the driver could not queue as the endpoint was still busy
+/// is retried on the next poll.
+pub struct WriteFuture<'a, D, I> {
+    driver: &'a D,
+    signal: &'a Signal,
+    endpoint_number: u8,
+    packet_size: usize,
+    iter: I,
+    bytes_written: usize,
+    in_flight: bool,
+    complete: bool,
+}
+
+impl<'a, D, I> Future for WriteFuture<'a, D, I>
+where
+    D: WriteEndpoint,
+    I: Iterator<Item = u8> + Unpin,
+{
+    type Output = usize;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            if !this.in_flight {
+                // discard any stale completion before queueing the packet
+                this.signal.reset();
+
+                let Ok(bytes_written) = this.driver.write_packet(
+                    this.endpoint_number,
+                    this.iter.by_ref().take(this.packet_size),
+                ) else {
+                    log::warn!(
+                        "WriteFuture endpoint {} busy, retrying",
+                        this.endpoint_number
+                    );
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                };
+                this.bytes_written += bytes_written;
+
+                // a short or zero length packet ends the transfer
+                this.complete = bytes_written < this.packet_size;
+                this.in_flight = true;
+            }
+
+            match this.signal.poll_wait(cx) {
+                Poll::Ready(()) if this.complete => return Poll::Ready(this.bytes_written),
+                Poll::Ready(()) => this.in_flight = false,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::{Cell, RefCell};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+    use std::task::Wake;
+    use std::vec::Vec;
+
+    use crate::error::EndpointBusy;
+
+    // - fixtures -------------------------------------------------------------
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[derive(Default)]
+    struct MockDriver {
+        primed: RefCell<Vec<u8>>,
+        packets: RefCell<Vec<Vec<u8>>>,
+        busy: Cell<usize>,
+    }
+
+    impl ReadControl for MockDriver {
+        fn read_control(&self, buffer: &mut [u8]) -> usize {
+            buffer.copy_from_slice(&[0x80, 0x06, 0x00, 0x01, 0x00, 0x00, 0x40, 0x00]);
+            buffer.len()
+        }
+    }
+
+    impl ReadEndpoint for MockDriver {
+        fn ep_out_prime_receive(&self, endpoint_number: u8) {
+            self.primed.borrow_mut().push(endpoint_number);
+        }
+        fn ep_out_enable(&self) {}
+        fn read(&self, _endpoint_number: u8, buffer: &mut [u8]) -> usize {
+            buffer[..3].copy_from_slice(&[1, 2, 3]);
+            3
+        }
+    }
+
+    impl WriteEndpoint for MockDriver {
+        fn write<I>(&self, _endpoint_number: u8, iter: I) -> usize
+        where
+            I: Iterator<Item = u8>,
+        {
+            let packet: Vec<u8> = iter.collect();
+            let bytes_written = packet.len();
+            self.packets.borrow_mut().push(packet);
+            bytes_written
+        }
+        fn write_requested<I>(&self, endpoint_number: u8, requested_length: usize, iter: I) -> usize
+        where
+            I: Iterator<Item = u8>,
+        {
+            self.write(endpoint_number, iter.take(requested_length))
+        }
+        fn write_with_packet_size<I>(
+            &self,
+            endpoint_number: u8,
+            requested_length: Option<usize>,
+            iter: I,
+            _packet_size: usize,
+        ) -> usize
+        where
+            I: Iterator<Item = u8>,
+        {
+            self.write(
+                endpoint_number,
+                iter.take(requested_length.unwrap_or(usize::MAX)),
+            )
+        }
+        fn write_packet<I>(&self, endpoint_number: u8, iter: I) -> Result<usize, EndpointBusy>
+        where
+            I: Iterator<Item = u8>,
+        {
+            // fails the given number of times before the endpoint is free
+            if self.busy.get() > 0 {
+                self.busy.set(self.busy.get() - 1);
+                return Err(EndpointBusy);
+            }
+            Ok(self.write(endpoint_number, iter))
+        }
+    }
+
+    fn poll<F: Future + Unpin>(future: &mut F, waker: &Waker) -> Poll<F::Output> {
+        Pin::new(future).poll(&mut Context::from_waker(waker))
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_signal_wakes_task() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let signal = Signal::new();
+
+        let mut future = signal.wait();
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+
+        signal.signal();
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll(&mut future, &waker), Poll::Ready(()));
+        assert!(!signal.is_pending());
+    }
+
+    #[test]
+    fn test_bus_events() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+        let signals = UsbEventSignals::new();
+
+        assert!(signals.poll_bus_event(&mut cx).is_pending());
+
+        signals.signal_event(UsbEvent::Suspend);
+        assert!(signals.is_suspended());
+        assert!(matches!(
+            signals.poll_bus_event(&mut cx),
+            Poll::Ready(UsbEvent::Suspend)
+        ));
+
+        signals.signal_event(UsbEvent::Resume);
+        assert!(matches!(
+            signals.poll_bus_event(&mut cx),
+            Poll::Ready(UsbEvent::Resume)
+        ));
+        assert!(signals.poll_bus_event(&mut cx).is_pending());
+
+        // a bus reset ends the suspend
+        signals.signal_event(UsbEvent::Suspend);
+        signals.signal_event(UsbEvent::BusReset);
+        assert!(!signals.is_suspended());
+        assert!(matches!(
+            signals.poll_bus_event(&mut cx),
+            Poll::Ready(UsbEvent::BusReset)
+        ));
+        assert!(signals.poll_bus_event(&mut cx).is_pending());
+
+        signals.signal_event(UsbEvent::StartOfFrame(0x7ff));
+        assert!(signals.start_of_frame().is_pending());
+        assert_eq!(signals.frame_number(), 0x7ff);
+    }
+
+    #[test]
+    fn test_read_async() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let signals = UsbEventSignals::new();
+        let driver = MockDriver::default();
+        let usb = AsyncDriver::new(&driver, &signals, Speed::Full);
+
+        // a stale packet notification from an earlier read is discarded
+        signals.signal_event(UsbEvent::ReceivePacket(2));
+
+        let mut buffer = [0_u8; 8];
+        let mut future = usb.read_async(2, &mut buffer);
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        assert_eq!(*driver.primed.borrow(), [2]);
+
+        // packets on other endpoints don't complete the read
+        signals.signal_event(UsbEvent::ReceivePacket(1));
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+
+        signals.signal_event(UsbEvent::ReceivePacket(2));
+        assert_eq!(poll(&mut future, &waker), Poll::Ready(3));
+        assert_eq!(buffer[..3], [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_write_async() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let signals = UsbEventSignals::new();
+        let driver = MockDriver::default();
+        let usb = AsyncDriver::new(&driver, &signals, Speed::Full);
+
+        // a stale completion from an earlier transfer is discarded
+        signals.signal_event(UsbEvent::SendComplete(1));
+
+        let mut future = usb.write_async(1, [0xaa, 0xbb].into_iter());
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        assert_eq!(*driver.packets.borrow(), [[0xaa, 0xbb]]);
+
+        signals.signal_event(UsbEvent::SendComplete(1));
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(poll(&mut future, &waker), Poll::Ready(2));
+    }
+
+    #[test]
+    fn test_write_async_packets() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let signals = UsbEventSignals::new();
+        let driver = MockDriver::default();
+        let usb = AsyncDriver::new(&driver, &signals, Speed::Full);
+
+        // one packet is queued per completion
+        let mut future = usb.write_async(1, (0..130).map(|byte| byte as u8));
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        assert_eq!(driver.packets.borrow().len(), 1);
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        assert_eq!(driver.packets.borrow().len(), 1);
+
+        signals.signal_event(UsbEvent::SendComplete(1));
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        signals.signal_event(UsbEvent::SendComplete(1));
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        signals.signal_event(UsbEvent::SendComplete(1));
+        assert_eq!(poll(&mut future, &waker), Poll::Ready(130));
+
+        let lengths: Vec<usize> = driver.packets.borrow().iter().map(Vec::len).collect();
+        assert_eq!(lengths, [64, 64, 2]);
+
+        // a transfer ending on a full packet is terminated by a zlp
+        driver.packets.borrow_mut().clear();
+        let mut future = usb.write_async(1, [0_u8; 64].into_iter());
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        signals.signal_event(UsbEvent::SendComplete(1));
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        signals.signal_event(UsbEvent::SendComplete(1));
+        assert_eq!(poll(&mut future, &waker), Poll::Ready(64));
+
+        let lengths: Vec<usize> = driver.packets.borrow().iter().map(Vec::len).collect();
+        assert_eq!(lengths, [64, 0]);
+    }
+
+    #[test]
+    fn test_write_async_busy() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let signals = UsbEventSignals::new();
+        let driver = MockDriver::default();
+        let usb = AsyncDriver::new(&driver, &signals, Speed::Full);
+
+        // a packet that could not be queued is retried on the next poll
+        driver.busy.set(2);
+        let mut future = usb.write_async(1, [0xaa, 0xbb].into_iter());
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert!(driver.packets.borrow().is_empty());
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+        assert!(driver.packets.borrow().is_empty());
+
+        // until the endpoint is free
+        assert_eq!(poll(&mut future, &waker), Poll::Pending);
+        assert_eq!(*driver.packets.borrow(), [[0xaa, 0xbb]]);
+        signals.signal_event(UsbEvent::SendComplete(1));
+        assert_eq!(poll(&mut future, &waker), Poll::Ready(2));
+    }
+
+    #[test]
+    fn test_read_control_async() {
+        let counter = Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let signals = UsbEventSignals::new();
+        let driver = MockDriver::default();
+        let usb = AsyncDriver::new(&driver, &signals, Speed::Full);
+
+        // setup packet read by the interrupt handler
+        let mut future = usb.read_control_async();
+        assert!(poll(&mut future, &waker).is_pending());
+        let setup_packet = SetupPacket {
+            request_type: 0x00,
+            request: 0x09,
+            value: 0x0001,
+            index: 0x0000,
+            length: 0x0000,
+        };
+        signals.signal_event(UsbEvent::ReceiveSetupPacket(0, setup_packet));
+        let Poll::Ready(received) = poll(&mut future, &waker) else {
+            panic!("expected a setup packet");
+        };
+        assert_eq!(received.request, 0x09);
+        assert_eq!(received.value, 0x0001);
+
+        // setup packet left in the fifo
+        let mut future = usb.read_control_async();
+        signals.signal_event(UsbEvent::ReceiveControl(0));
+        let Poll::Ready(received) = poll(&mut future, &waker) else {
+            panic!("expected a setup packet");
+        };
+        assert_eq!(received.request, 0x06);
+        assert_eq!(received.length, 0x40);
+    }
+}
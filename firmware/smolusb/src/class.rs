@@ -1,3 +1,204 @@
 //! USB device and interface classes
 
 pub mod acm;
+pub mod audio;
+pub mod dfu;
+pub mod hid;
+pub mod msc;
+pub mod ncm;
+pub mod usbtmc;
+
+use crate::event::UsbEvent;
+use crate::setup::SetupPacket;
+use crate::traits::UsbDriver;
+
+// - ClassEvent ---------------------------------------------------------------
+
+/// Events generated by the classes in this crate while handling a
+/// control request that need to be handled by the application.
+///
+/// Reported by [`UsbDevice::dispatch_event()`](crate::device::UsbDevice::dispatch_event)
+/// as [`DeviceEvent::Class`](crate::device::DeviceEvent::Class).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClassEvent<'d> {
+    Acm(acm::AcmEvent),
+    Audio(audio::AudioEvent),
+    Dfu(dfu::DfuEvent),
+    Hid(hid::HidEvent<'d>),
+    Ncm(ncm::NcmEvent),
+}
+
+impl From<acm::AcmEvent> for ClassEvent<'_> {
+    fn from(event: acm::AcmEvent) -> Self {
+        ClassEvent::Acm(event)
+    }
+}
+
+impl From<audio::AudioEvent> for ClassEvent<'_> {
+    fn from(event: audio::AudioEvent) -> Self {
+        ClassEvent::Audio(event)
+    }
+}
+
+impl From<dfu::DfuEvent> for ClassEvent<'_> {
+    fn from(event: dfu::DfuEvent) -> Self {
+        ClassEvent::Dfu(event)
+    }
+}
+
+impl<'d> From<hid::HidEvent<'d>> for ClassEvent<'d> {
+    fn from(event: hid::HidEvent<'d>) -> Self {
+        ClassEvent::Hid(event)
+    }
+}
+
+impl From<ncm::NcmEvent> for ClassEvent<'_> {
+    fn from(event: ncm::NcmEvent) -> Self {
+        ClassEvent::Ncm(event)
+    }
+}
+
+// - ControlResult ------------------------------------------------------------
+
+/// The result of offering a control request to a [`UsbClass`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlResult<'d> {
+    /// The request is not supported by the class.
+    Unhandled,
+    /// The request was handled by the class.
+    Handled,
+    /// The request was handled by the class and generated an event
+    /// for the application.
+    Event(ClassEvent<'d>),
+}
+
+impl<'d, E> From<Option<E>> for ControlResult<'d>
+where
+    E: Into<ClassEvent<'d>>,
+{
+    fn from(event: Option<E>) -> Self {
+        match event {
+            Some(event) => ControlResult::Event(event.into()),
+            None => ControlResult::Handled,
+        }
+    }
+}
+
+// - UsbClass -----------------------------------------------------------------
+
+/// A USB class implementation that can be composed into a
+/// [`UsbDevice`](crate::device::UsbDevice).
+///
+/// The device routes control requests addressed to an interface or
+/// endpoint to the class that owns it and endpoint events to the class
+/// that owns the endpoint. Requests addressed to the device are offered
+/// to each class in turn.
+pub trait UsbClass<D>
+where
+    D: UsbDriver,
+{
+    /// Returns `true` if the class owns the given interface.
+    fn has_interface(&self, interface_number: u8) -> bool;
+
+    /// Returns `true` if the class owns the given endpoint address.
+    ///
+    /// The address includes the direction bit, e.g. `0x81` for IN
+    /// endpoint `1`.
+    fn has_endpoint(&self, _endpoint_address: u8) -> bool {
+        false
+    }
+
+    /// Handles a control request that was not handled by the
+    /// [`Control`](crate::control::Control) endpoint.
+    ///
+    /// `data` holds any data received from the host during the data
+    /// stage of the request.
+    ///
+    /// Returns [`ControlResult::Unhandled`] if the request is not
+    /// supported by the class in which case it is passed on to the
+    /// application.
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &'d [u8],
+    ) -> ControlResult<'d>;
+
+    /// Handles a [`UsbEvent::ReceivePacket`] or [`UsbEvent::SendComplete`]
+    /// event for one of the class endpoints.
+    fn handle_endpoint_event(&mut self, _usb: &D, _event: UsbEvent) {}
+
+    /// Returns the class to its initial state after a bus reset.
+    fn reset(&mut self);
+
+    /// Handles a change of the active device configuration.
+    ///
+    /// `configuration` is `0` if the device has been returned to the
+    /// unconfigured state.
+    fn configuration_changed(&mut self, usb: &D, configuration: u8);
+
+    /// Handles an alternate setting change for one of the class interfaces.
+    fn alternate_setting_changed(
+        &mut self,
+        _usb: &D,
+        _interface_number: u8,
+        _alternate_setting: u8,
+    ) {
+    }
+
+    /// Handles a suspend of the bus.
+    fn suspend(&mut self, _usb: &D) {}
+
+    /// Handles the end of a bus suspend.
+    fn resume(&mut self, _usb: &D) {}
+
+    /// Handles a start-of-frame packet with the given frame number.
+    fn start_of_frame(&mut self, _usb: &D, _frame_number: u16) {}
+}
+
+// - UsbClasses ---------------------------------------------------------------
+
+/// A set of [`UsbClass`] implementations.
+///
+/// Implemented for tuples of up to eight classes.
+pub trait UsbClasses<D>
+where
+    D: UsbDriver,
+{
+    /// Calls `f` for each class in order until it returns `true`.
+    ///
+    /// Returns `true` if `f` returned `true` for any class.
+    fn any(&mut self, f: &mut dyn FnMut(&mut dyn UsbClass<D>) -> bool) -> bool;
+
+    /// Calls `f` for each class in order.
+    fn for_each(&mut self, f: &mut dyn FnMut(&mut dyn UsbClass<D>)) {
+        self.any(&mut |class| {
+            f(class);
+            false
+        });
+    }
+}
+
+macro_rules! impl_usb_classes {
+    ($($class:ident $index:tt),+) => {
+        impl<D, $($class),+> UsbClasses<D> for ($($class,)+)
+        where
+            D: UsbDriver,
+            $($class: UsbClass<D>,)+
+        {
+            fn any(&mut self, f: &mut dyn FnMut(&mut dyn UsbClass<D>) -> bool) -> bool {
+                $(f(&mut self.$index))||+
+            }
+        }
+    };
+}
+
+impl_usb_classes!(C0 0);
+impl_usb_classes!(C0 0, C1 1);
+impl_usb_classes!(C0 0, C1 1, C2 2);
+impl_usb_classes!(C0 0, C1 1, C2 2, C3 3);
+impl_usb_classes!(C0 0, C1 1, C2 2, C3 3, C4 4);
+impl_usb_classes!(C0 0, C1 1, C2 2, C3 3, C4 4, C5 5);
+impl_usb_classes!(C0 0, C1 1, C2 2, C3 3, C4 4, C5 5, C6 6);
+impl_usb_classes!(C0 0, C1 1, C2 2, C3 3, C4 4, C5 5, C6 6, C7 7);
@@ -1,9 +1,18 @@
+//! USB Communications Device Class - Abstract Control Model (CDC-ACM)
+
+use log::{trace, warn};
+
+use crate::class::{ControlResult, UsbClass};
 use crate::descriptor::{
     ClassSpecificDescriptor, ConfigurationDescriptor, ConfigurationDescriptorHeader,
     DescriptorType, DeviceDescriptor, DeviceQualifierDescriptor, EndpointDescriptor,
     InterfaceDescriptor, InterfaceDescriptorHeader, LanguageId, StringDescriptor,
     StringDescriptorZero,
 };
+use crate::event::UsbEvent;
+use crate::ringbuffer::RingBuffer;
+use crate::setup::{Direction, Recipient, Request, RequestType, SetupPacket};
+use crate::traits::UsbDriver;
 
 pub const VENDOR_ID: u16 = 0x1209; // https://pid.codes/1209/
 pub const PRODUCT_ID: u16 = 0x0001; // pid.codes Test PID 1
@@ -241,3 +250,688 @@ pub const STRING_DESCRIPTORS: &[&StringDescriptor] = &[
     &STRING_DESCRIPTOR_5,
     &STRING_DESCRIPTOR_6,
 ];
+
+// - LineCoding ---------------------------------------------------------------
+
+/// Line coding structure as sent by `SET_LINE_CODING` and
+/// returned by `GET_LINE_CODING`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineCoding {
+    /// Data terminal rate in bits per second.
+    pub data_rate: u32,
+    /// Stop bits: 0 = 1 stop bit, 1 = 1.5 stop bits, 2 = 2 stop bits
+    pub stop_bits: u8,
+    /// Parity: 0 = None, 1 = Odd, 2 = Even, 3 = Mark, 4 = Space
+    pub parity: u8,
+    /// Data bits: 5, 6, 7, 8 or 16
+    pub data_bits: u8,
+}
+
+impl LineCoding {
+    /// Returns the default line coding of 115200 8N1.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            data_rate: 115_200,
+            stop_bits: 0,
+            parity: 0,
+            data_bits: 8,
+        }
+    }
+
+    /// Parses a line coding structure received from the host.
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [r0, r1, r2, r3, stop_bits, parity, data_bits, ..] => Some(Self {
+                data_rate: u32::from_le_bytes([*r0, *r1, *r2, *r3]),
+                stop_bits: *stop_bits,
+                parity: *parity,
+                data_bits: *data_bits,
+            }),
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; 7] {
+        let [r0, r1, r2, r3] = self.data_rate.to_le_bytes();
+        [r0, r1, r2, r3, self.stop_bits, self.parity, self.data_bits]
+    }
+}
+
+impl Default for LineCoding {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// - SerialState --------------------------------------------------------------
+
+/// `bmUartState` bitmap sent to the host with a `SERIAL_STATE` notification.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SerialState(pub u16);
+
+impl SerialState {
+    /// bRxCarrier - state of the receiver carrier detection mechanism (DCD)
+    pub const DCD: u16 = 1 << 0;
+    /// bTxCarrier - state of the transmission carrier (DSR)
+    pub const DSR: u16 = 1 << 1;
+    /// bBreak - state of break detection mechanism
+    pub const BREAK: u16 = 1 << 2;
+    /// bRingSignal - state of ring signal detection
+    pub const RING_SIGNAL: u16 = 1 << 3;
+    /// bFraming - a framing error has occurred
+    pub const FRAMING: u16 = 1 << 4;
+    /// bParity - a parity error has occurred
+    pub const PARITY: u16 = 1 << 5;
+    /// bOverRun - received data has been discarded due to overrun
+    pub const OVERRUN: u16 = 1 << 6;
+}
+
+// - AcmEvent -----------------------------------------------------------------
+
+/// Events generated by [`CdcAcm`] that may need to be handled by the
+/// application.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AcmEvent {
+    /// The host changed the line coding.
+    LineCodingChanged(LineCoding),
+    /// The host changed the DTR or RTS control lines.
+    ControlLineStateChanged { dtr: bool, rts: bool },
+    /// The host requested a break of the given duration in milliseconds.
+    ///
+    /// A duration of `0xffff` lasts until a duration of `0` is received.
+    SendBreak(u16),
+    /// Data was received from the host and can be read with [`CdcAcm::read()`].
+    DataReceived,
+}
+
+// - CdcAcm -------------------------------------------------------------------
+
+/// A CDC-ACM serial port.
+///
+/// Class requests received by [`Control`](crate::control::Control) are
+/// passed to [`CdcAcm::handle_setup_packet()`] and endpoint events to
+/// [`CdcAcm::handle_event()`]. Serial data is buffered in ring buffers
+/// of `BUFFER_SIZE` bytes which must be at least as large as the
+/// maximum packet size of the bulk endpoints.
+pub struct CdcAcm<const BUFFER_SIZE: usize> {
+    interface_number: u8,
+    notification_endpoint: u8,
+    data_endpoint_in: u8,
+    data_endpoint_out: u8,
+    max_packet_size: usize,
+
+    line_coding: LineCoding,
+    dtr: bool,
+    rts: bool,
+    serial_state: Option<SerialState>,
+
+    configured: bool,
+    rx_buffer: RingBuffer<BUFFER_SIZE>,
+    rx_paused: bool,
+    tx_buffer: RingBuffer<BUFFER_SIZE>,
+    tx_busy: bool,
+    tx_zlp_pending: bool,
+    notification_busy: bool,
+}
+
+impl<const BUFFER_SIZE: usize> CdcAcm<BUFFER_SIZE> {
+    /// Creates a new CDC-ACM serial port for the given Communications
+    /// Class interface and endpoint numbers.
+    #[must_use]
+    pub const fn new(
+        interface_number: u8,
+        notification_endpoint: u8,
+        data_endpoint_in: u8,
+        data_endpoint_out: u8,
+        max_packet_size: usize,
+    ) -> Self {
+        Self {
+            interface_number,
+            notification_endpoint,
+            data_endpoint_in,
+            data_endpoint_out,
+            max_packet_size,
+            line_coding: LineCoding::new(),
+            dtr: false,
+            rts: false,
+            serial_state: None,
+            configured: false,
+            rx_buffer: RingBuffer::new(),
+            rx_paused: false,
+            tx_buffer: RingBuffer::new(),
+            tx_busy: false,
+            tx_zlp_pending: false,
+            notification_busy: false,
+        }
+    }
+
+    /// Returns the current line coding.
+    #[must_use]
+    pub fn line_coding(&self) -> LineCoding {
+        self.line_coding
+    }
+
+    /// Returns the state of the DTR control line.
+    #[must_use]
+    pub fn dtr(&self) -> bool {
+        self.dtr
+    }
+
+    /// Returns the state of the RTS control line.
+    #[must_use]
+    pub fn rts(&self) -> bool {
+        self.rts
+    }
+
+    /// Returns the number of received bytes waiting to be read.
+    #[must_use]
+    pub fn bytes_available(&self) -> usize {
+        self.rx_buffer.len()
+    }
+
+    /// Starts the serial port once the host has selected a
+    /// configuration containing it.
+    pub fn configure<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        self.reset();
+        self.configured = true;
+        usb.ep_out_prime_receive(self.data_endpoint_out);
+    }
+
+    /// Stops the serial port and discards any buffered data.
+    pub fn reset(&mut self) {
+        self.configured = false;
+        self.dtr = false;
+        self.rts = false;
+        self.serial_state = None;
+        self.rx_buffer.clear();
+        self.rx_paused = false;
+        self.tx_buffer.clear();
+        self.tx_busy = false;
+        self.tx_zlp_pending = false;
+        self.notification_busy = false;
+    }
+
+    /// Returns `true` if the [`SetupPacket`] is a class request for this interface.
+    #[must_use]
+    pub fn is_class_request(&self, setup_packet: &SetupPacket) -> bool {
+        setup_packet.request_type() == RequestType::Class
+            && setup_packet.recipient() == Recipient::Interface
+            && (setup_packet.index & 0xff) as u8 == self.interface_number
+    }
+
+    /// Handles a class request passed on by the control endpoint.
+    ///
+    /// `data` holds any data received from the host during the data
+    /// stage of the request. Requests that are not addressed to this
+    /// interface are ignored.
+    pub fn handle_setup_packet<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &[u8],
+    ) -> Option<AcmEvent>
+    where
+        D: UsbDriver,
+    {
+        use serial::ClassRequest;
+
+        if !self.is_class_request(&setup_packet) {
+            return None;
+        }
+
+        let Request::ClassOrVendor(request) = setup_packet.request() else {
+            return None;
+        };
+
+        match (setup_packet.direction(), ClassRequest::from(request)) {
+            (Direction::HostToDevice, ClassRequest::SetLineCoding) => {
+                // the control endpoint has already acknowledged the data stage
+                let Some(line_coding) = LineCoding::from_bytes(data) else {
+                    warn!("CdcAcm SET_LINE_CODING with invalid data: {:?}", data);
+                    return None;
+                };
+                trace!("CdcAcm SET_LINE_CODING {:?}", line_coding);
+                self.line_coding = line_coding;
+                Some(AcmEvent::LineCodingChanged(line_coding))
+            }
+            (Direction::DeviceToHost, ClassRequest::GetLineCoding) => {
+                let requested_length = usize::from(setup_packet.length);
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    self.line_coding
+                        .to_bytes()
+                        .into_iter()
+                        .take(requested_length),
+                );
+                None
+            }
+            (Direction::HostToDevice, ClassRequest::SetControlLineState) => {
+                self.dtr = setup_packet.value & 0b01 != 0;
+                self.rts = setup_packet.value & 0b10 != 0;
+                usb.write(control_endpoint, [].into_iter());
+                Some(AcmEvent::ControlLineStateChanged {
+                    dtr: self.dtr,
+                    rts: self.rts,
+                })
+            }
+            (Direction::HostToDevice, ClassRequest::SendBreak) => {
+                usb.write(control_endpoint, [].into_iter());
+                Some(AcmEvent::SendBreak(setup_packet.value))
+            }
+            (direction, request) => {
+                warn!("CdcAcm stall - unhandled class request {:?}", request);
+                match direction {
+                    Direction::DeviceToHost => usb.stall_endpoint_in(control_endpoint),
+                    Direction::HostToDevice => usb.stall_endpoint_out(control_endpoint),
+                }
+                None
+            }
+        }
+    }
+
+    /// Handles a USB event for the notification and data endpoints.
+    pub fn handle_event<D>(&mut self, usb: &D, event: UsbEvent) -> Option<AcmEvent>
+    where
+        D: UsbDriver,
+    {
+        match event {
+            UsbEvent::BusReset => {
+                self.reset();
+                None
+            }
+            UsbEvent::ReceivePacket(endpoint_number)
+                if self.configured && endpoint_number == self.data_endpoint_out =>
+            {
+                let mut packet_buffer = [0; crate::EP_MAX_PACKET_SIZE];
+                let bytes_read = usb.read(endpoint_number, &mut packet_buffer);
+                let bytes_pushed = self.rx_buffer.push(&packet_buffer[..bytes_read]);
+                if bytes_pushed < bytes_read {
+                    warn!(
+                        "CdcAcm receive buffer overflow, dropped {} bytes",
+                        bytes_read - bytes_pushed
+                    );
+                }
+                self.prime_receive(usb);
+                if bytes_read > 0 {
+                    Some(AcmEvent::DataReceived)
+                } else {
+                    None
+                }
+            }
+            UsbEvent::SendComplete(endpoint_number)
+                if self.configured && endpoint_number == self.data_endpoint_in =>
+            {
+                self.tx_busy = false;
+                self.flush(usb);
+                None
+            }
+            UsbEvent::SendComplete(endpoint_number)
+                if self.configured && endpoint_number == self.notification_endpoint =>
+            {
+                self.notification_busy = false;
+                if let Some(serial_state) = self.serial_state.take() {
+                    self.send_serial_state(usb, serial_state);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads received data into `buffer` and returns the number of bytes read.
+    pub fn read<D>(&mut self, usb: &D, buffer: &mut [u8]) -> usize
+    where
+        D: UsbDriver,
+    {
+        let bytes_read = self.rx_buffer.pop(buffer);
+        if self.rx_paused {
+            self.prime_receive(usb);
+        }
+        bytes_read
+    }
+
+    /// Queues `data` for transmission to the host and returns the number
+    /// of bytes queued.
+    pub fn write<D>(&mut self, usb: &D, data: &[u8]) -> usize
+    where
+        D: UsbDriver,
+    {
+        if !self.configured {
+            return 0;
+        }
+        let bytes_queued = self.tx_buffer.push(data);
+        self.flush(usb);
+        bytes_queued
+    }
+
+    /// Sends a `SERIAL_STATE` notification to the host.
+    ///
+    /// If a notification is already in flight the latest state will be
+    /// sent once it has completed.
+    pub fn set_serial_state<D>(&mut self, usb: &D, serial_state: SerialState)
+    where
+        D: UsbDriver,
+    {
+        if !self.configured {
+            return;
+        }
+        if self.notification_busy {
+            self.serial_state = Some(serial_state);
+        } else {
+            self.send_serial_state(usb, serial_state);
+        }
+    }
+
+    fn send_serial_state<D>(&mut self, usb: &D, serial_state: SerialState)
+    where
+        D: UsbDriver,
+    {
+        let [state_lo, state_hi] = serial_state.0.to_le_bytes();
+        let notification = [
+            0b1010_0001, // bmRequestType: DeviceToHost | Class | Interface
+            0x20,        // bNotification: SERIAL_STATE
+            0x00,        // wValue
+            0x00,
+            self.interface_number, // wIndex
+            0x00,
+            0x02, // wLength
+            0x00,
+            state_lo,
+            state_hi,
+        ];
+        self.notification_busy = true;
+        usb.write(self.notification_endpoint, notification.into_iter());
+    }
+
+    fn prime_receive<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        // only accept another packet once there's room for it
+        if self.rx_buffer.available() >= self.max_packet_size {
+            self.rx_paused = false;
+            usb.ep_out_prime_receive(self.data_endpoint_out);
+        } else {
+            self.rx_paused = true;
+        }
+    }
+
+    fn flush<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        if self.tx_busy {
+            return;
+        }
+
+        if self.tx_buffer.is_empty() {
+            // terminate a transfer that ended on a packet boundary
+            if self.tx_zlp_pending {
+                self.tx_zlp_pending = false;
+                self.tx_busy = true;
+                usb.write(self.data_endpoint_in, [].into_iter());
+            }
+            return;
+        }
+
+        let packet_length = self.tx_buffer.len().min(self.max_packet_size);
+        let bytes_written = usb.write_with_packet_size(
+            self.data_endpoint_in,
+            Some(packet_length),
+            self.tx_buffer.iter().take(packet_length),
+            self.max_packet_size,
+        );
+        if bytes_written > 0 {
+            self.tx_buffer.consume(bytes_written);
+            self.tx_busy = true;
+            self.tx_zlp_pending = bytes_written == self.max_packet_size;
+        }
+    }
+}
+
+impl<D, const BUFFER_SIZE: usize> UsbClass<D> for CdcAcm<BUFFER_SIZE>
+where
+    D: UsbDriver,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        interface_number == self.interface_number
+    }
+
+    fn has_endpoint(&self, endpoint_address: u8) -> bool {
+        endpoint_address == self.notification_endpoint | 0x80
+            || endpoint_address == self.data_endpoint_in | 0x80
+            || endpoint_address == self.data_endpoint_out
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &'d [u8],
+    ) -> ControlResult<'d> {
+        if !self.is_class_request(&setup_packet) {
+            return ControlResult::Unhandled;
+        }
+        self.handle_setup_packet(usb, control_endpoint, setup_packet, data)
+            .into()
+    }
+
+    fn handle_endpoint_event(&mut self, usb: &D, event: UsbEvent) {
+        self.handle_event(usb, event);
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn configuration_changed(&mut self, usb: &D, configuration: u8) {
+        if configuration == 0 {
+            self.reset();
+        } else {
+            self.configure(usb);
+        }
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::device::{Descriptors, Speed};
+    use crate::testing::{class_request, Operation, VirtualHost};
+
+    // - fixtures -------------------------------------------------------------
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[CONFIGURATION_DESCRIPTOR_0];
+
+    const LINE_CODING_9600_7E1: [u8; 7] = [0x80, 0x25, 0x00, 0x00, 0x00, 0x02, 0x07];
+
+    const DESCRIPTORS: Descriptors = Descriptors {
+        device_speed: Speed::Full,
+        device_descriptor: DEVICE_DESCRIPTOR,
+        configuration_descriptors: CONFIGURATION_DESCRIPTORS,
+        string_descriptor_zero: STRING_DESCRIPTOR_0,
+        string_descriptors: STRING_DESCRIPTORS,
+        device_qualifier_descriptor: None,
+        other_speed_configuration_descriptors: None,
+        binary_object_store: None,
+        microsoft10: None,
+        microsoft20: None,
+    };
+
+    fn serial(host: &VirtualHost<64>) -> CdcAcm<128> {
+        let mut serial = CdcAcm::new(0, 3, 4, 4, 64);
+        serial.configure(&host.usb);
+        host.usb.take_operations();
+        serial
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_line_coding() {
+        let mut host = VirtualHost::configured_with(DESCRIPTORS);
+        let mut serial = serial(&host);
+        let mut events = Vec::new();
+
+        host.control_out_with(
+            class_request(Direction::HostToDevice, Recipient::Interface, 0x20, 0, 0, 7),
+            &LINE_CODING_9600_7E1,
+            |usb, setup_packet, data| {
+                events.extend(serial.handle_setup_packet(usb, 0, setup_packet, data));
+            },
+        )
+        .unwrap();
+
+        let line_coding = LineCoding {
+            data_rate: 9600,
+            stop_bits: 0,
+            parity: 2,
+            data_bits: 7,
+        };
+        assert_eq!(events, [AcmEvent::LineCodingChanged(line_coding)]);
+        assert_eq!(serial.line_coding(), line_coding);
+
+        let data = host
+            .control_in_with(
+                class_request(Direction::DeviceToHost, Recipient::Interface, 0x21, 0, 0, 7),
+                |usb, setup_packet, data| {
+                    serial.handle_setup_packet(usb, 0, setup_packet, data);
+                },
+            )
+            .unwrap();
+        assert_eq!(data, LINE_CODING_9600_7E1);
+    }
+
+    #[test]
+    fn test_control_line_state() {
+        let mut host = VirtualHost::configured_with(DESCRIPTORS);
+        let mut serial = serial(&host);
+        let mut events = Vec::new();
+
+        host.control_out_with(
+            class_request(
+                Direction::HostToDevice,
+                Recipient::Interface,
+                0x22,
+                0b11,
+                0,
+                0,
+            ),
+            &[],
+            |usb, setup_packet, data| {
+                events.extend(serial.handle_setup_packet(usb, 0, setup_packet, data));
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            events,
+            [AcmEvent::ControlLineStateChanged {
+                dtr: true,
+                rts: true
+            }]
+        );
+        assert!(serial.dtr() && serial.rts());
+
+        // unknown class requests are stalled
+        let result = host.control_in_with(
+            class_request(Direction::DeviceToHost, Recipient::Interface, 0x42, 0, 0, 2),
+            |usb, setup_packet, data| {
+                serial.handle_setup_packet(usb, 0, setup_packet, data);
+            },
+        );
+        assert!(result.is_err());
+        assert!(host.usb.operations().contains(&Operation::StallIn(0)));
+    }
+
+    #[test]
+    fn test_read() {
+        let host: VirtualHost<64> = VirtualHost::configured_with(DESCRIPTORS);
+        let mut serial = serial(&host);
+        let mut buffer = [0; 64];
+
+        host.usb.push_out_data(4, b"hello");
+        assert_eq!(
+            serial.handle_event(&host.usb, UsbEvent::ReceivePacket(4)),
+            Some(AcmEvent::DataReceived)
+        );
+        assert_eq!(serial.bytes_available(), 5);
+        assert_eq!(serial.read(&host.usb, &mut buffer), 5);
+        assert_eq!(&buffer[..5], b"hello");
+
+        // stop receiving once the buffer can't hold another packet
+        host.usb.push_out_data(4, &[0xaa; 64]);
+        serial.handle_event(&host.usb, UsbEvent::ReceivePacket(4));
+        host.usb.push_out_data(4, &[0xbb; 32]);
+        serial.handle_event(&host.usb, UsbEvent::ReceivePacket(4));
+        assert_eq!(
+            host.usb.take_operations(),
+            [
+                Operation::Read(4, 5),
+                Operation::PrimeReceive(4),
+                Operation::Read(4, 64),
+                Operation::PrimeReceive(4),
+                Operation::Read(4, 32),
+            ]
+        );
+
+        // and resume once it's been read
+        assert_eq!(serial.read(&host.usb, &mut buffer), 64);
+        assert_eq!(host.usb.take_operations(), [Operation::PrimeReceive(4)]);
+    }
+
+    #[test]
+    fn test_write() {
+        let host: VirtualHost<64> = VirtualHost::configured_with(DESCRIPTORS);
+        let mut serial = serial(&host);
+        let data: Vec<u8> = (0..128).collect();
+
+        // data is sent one packet at a time
+        assert_eq!(serial.write(&host.usb, &data[..100]), 100);
+        assert_eq!(host.usb.writes(4), [data[..64].to_vec()]);
+        serial.handle_event(&host.usb, UsbEvent::SendComplete(4));
+        assert_eq!(host.usb.writes(4)[1], data[64..100]);
+        host.usb.take_operations();
+
+        // a transfer ending on a packet boundary is terminated with a zlp
+        serial.handle_event(&host.usb, UsbEvent::SendComplete(4));
+        assert_eq!(serial.write(&host.usb, &data[..64]), 64);
+        serial.handle_event(&host.usb, UsbEvent::SendComplete(4));
+        serial.handle_event(&host.usb, UsbEvent::SendComplete(4));
+        assert_eq!(host.usb.writes(4), [data[..64].to_vec(), Vec::new()]);
+    }
+
+    #[test]
+    fn test_serial_state() {
+        let host: VirtualHost<64> = VirtualHost::configured_with(DESCRIPTORS);
+        let mut serial = serial(&host);
+
+        serial.set_serial_state(&host.usb, SerialState(SerialState::DCD | SerialState::DSR));
+        serial.set_serial_state(&host.usb, SerialState(SerialState::RING_SIGNAL));
+        serial.set_serial_state(&host.usb, SerialState(SerialState::BREAK));
+        serial.handle_event(&host.usb, UsbEvent::SendComplete(3));
+
+        // only the latest pending state is sent
+        assert_eq!(
+            host.usb.writes(3),
+            [
+                [0xa1, 0x20, 0, 0, 0, 0, 2, 0, 0b0011, 0].to_vec(),
+                [0xa1, 0x20, 0, 0, 0, 0, 2, 0, 0b0100, 0].to_vec(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1636 @@
+//! USB Audio Class 2.0 (UAC2)
+//!
+//! Provides a `const` builder for the class-specific Audio Control and
+//! Audio Streaming descriptors, an [`AudioControl`] interface which
+//! handles the CUR and RANGE requests for a clock source and a feature
+//! unit, and an [`AudioStream`] which moves PCM samples between a ring
+//! buffer and an isochronous endpoint.
+//!
+//! For example, the Audio Control descriptors of a stereo speaker:
+//!
+//!     use smolusb::class::audio::*;
+//!
+//!     const AUDIO_CONTROL: [u8; 64] = ClassDescriptors::new()
+//!         .header(category::DESKTOP_SPEAKER)
+//!         .clock_source(1, clock_attributes::INTERNAL_PROGRAMMABLE, clock_controls::FREQUENCY_READ_WRITE)
+//!         .input_terminal(2, terminal_type::USB_STREAMING, 1, 2, channel_config::STEREO, 0)
+//!         .feature_unit(3, 2, &[feature_controls::MUTE_READ_WRITE | feature_controls::VOLUME_READ_WRITE, 0, 0])
+//!         .output_terminal(4, terminal_type::SPEAKER, 3, 1, 0)
+//!         .to_bytes();
+//!
+//!     assert_eq!(&AUDIO_CONTROL[6..8], &[64, 0]); // wTotalLength
+
+use log::{debug, trace, warn};
+
+use crate::class::{ControlResult, UsbClass};
+use crate::device::Speed;
+use crate::event::UsbEvent;
+use crate::isochronous::FrameSchedule;
+use crate::ringbuffer::RingBuffer;
+use crate::setup::{Direction, Recipient, RequestType, SetupPacket};
+use crate::traits::{IsochronousEndpoint, UsbDriver};
+
+/// Audio interface class code
+pub const INTERFACE_CLASS: u8 = 0x01;
+/// Audio function and interface protocol code for UAC2
+pub const PROTOCOL_IP_VERSION_02_00: u8 = 0x20;
+/// Audio function subclass code
+pub const FUNCTION_SUBCLASS_UNDEFINED: u8 = 0x00;
+
+/// Audio interface subclass codes
+pub mod subclass {
+    pub const AUDIO_CONTROL: u8 = 0x01;
+    pub const AUDIO_STREAMING: u8 = 0x02;
+    pub const MIDI_STREAMING: u8 = 0x03;
+}
+
+/// Audio function category codes
+pub mod category {
+    pub const DESKTOP_SPEAKER: u8 = 0x01;
+    pub const HOME_THEATER: u8 = 0x02;
+    pub const MICROPHONE: u8 = 0x03;
+    pub const HEADSET: u8 = 0x04;
+    pub const TELEPHONE: u8 = 0x05;
+    pub const CONVERTER: u8 = 0x06;
+    pub const VOICE_SOUND_RECORDER: u8 = 0x07;
+    pub const IO_BOX: u8 = 0x08;
+    pub const MUSICAL_INSTRUMENT: u8 = 0x09;
+    pub const PRO_AUDIO: u8 = 0x0a;
+    pub const AUDIO_VIDEO: u8 = 0x0b;
+    pub const CONTROL_PANEL: u8 = 0x0c;
+    pub const OTHER: u8 = 0xff;
+}
+
+/// Terminal type codes
+pub mod terminal_type {
+    pub const USB_STREAMING: u16 = 0x0101;
+    pub const MICROPHONE: u16 = 0x0201;
+    pub const SPEAKER: u16 = 0x0301;
+    pub const HEADPHONES: u16 = 0x0302;
+    pub const HEADSET: u16 = 0x0402;
+    pub const LINE_CONNECTOR: u16 = 0x0603;
+}
+
+/// Spatial locations for `bmChannelConfig`
+pub mod channel_config {
+    pub const MONO: u32 = 0;
+    pub const FRONT_LEFT: u32 = 1 << 0;
+    pub const FRONT_RIGHT: u32 = 1 << 1;
+    pub const FRONT_CENTER: u32 = 1 << 2;
+    pub const STEREO: u32 = FRONT_LEFT | FRONT_RIGHT;
+}
+
+/// Clock source `bmAttributes`
+pub mod clock_attributes {
+    pub const EXTERNAL: u8 = 0b000;
+    pub const INTERNAL_FIXED: u8 = 0b001;
+    pub const INTERNAL_VARIABLE: u8 = 0b010;
+    pub const INTERNAL_PROGRAMMABLE: u8 = 0b011;
+    pub const SYNCHRONIZED_TO_SOF: u8 = 0b100;
+}
+
+/// Clock source `bmControls`
+pub mod clock_controls {
+    pub const FREQUENCY_READ_ONLY: u8 = 0b0000_0001;
+    pub const FREQUENCY_READ_WRITE: u8 = 0b0000_0011;
+    pub const VALIDITY_READ_ONLY: u8 = 0b0000_0100;
+}
+
+/// Feature unit `bmaControls`
+pub mod feature_controls {
+    pub const MUTE_READ_ONLY: u32 = 0b0000_0001;
+    pub const MUTE_READ_WRITE: u32 = 0b0000_0011;
+    pub const VOLUME_READ_ONLY: u32 = 0b0000_0100;
+    pub const VOLUME_READ_WRITE: u32 = 0b0000_1100;
+}
+
+/// Class-specific descriptor types
+pub mod descriptor_type {
+    pub const CS_INTERFACE: u8 = 0x24;
+    pub const CS_ENDPOINT: u8 = 0x25;
+}
+
+/// Audio Control interface descriptor subtypes
+pub mod ac_subtype {
+    pub const HEADER: u8 = 0x01;
+    pub const INPUT_TERMINAL: u8 = 0x02;
+    pub const OUTPUT_TERMINAL: u8 = 0x03;
+    pub const FEATURE_UNIT: u8 = 0x06;
+    pub const CLOCK_SOURCE: u8 = 0x0a;
+}
+
+/// Audio Streaming interface descriptor subtypes
+pub mod as_subtype {
+    pub const AS_GENERAL: u8 = 0x01;
+    pub const FORMAT_TYPE: u8 = 0x02;
+}
+
+/// Audio data format type I bitmap for `bmFormats`
+pub mod format {
+    pub const FORMAT_TYPE_I: u8 = 0x01;
+    pub const PCM: u32 = 1 << 0;
+    pub const PCM8: u32 = 1 << 1;
+    pub const IEEE_FLOAT: u32 = 1 << 2;
+}
+
+/// Class-specific isochronous audio data endpoint descriptor
+///
+/// Follows the isochronous data endpoint descriptor of an Audio
+/// Streaming interface, see: [`InterfaceDescriptor::with_endpoint_class_descriptors()`](crate::descriptor::InterfaceDescriptor::with_endpoint_class_descriptors)
+pub const ISOCHRONOUS_DATA_ENDPOINT_DESCRIPTOR: [u8; 8] = [
+    8,
+    descriptor_type::CS_ENDPOINT,
+    0x01, // EP_GENERAL
+    0x00, // bmAttributes
+    0x00, // bmControls
+    0x00, // bLockDelayUnits
+    0x00, // wLockDelay
+    0x00,
+];
+
+// - ClassDescriptors ---------------------------------------------------------
+
+/// Builder for the class-specific descriptors of an Audio Control or
+/// Audio Streaming interface
+///
+/// All methods are `const` so descriptors can be declared as constants.
+/// `N` is the capacity in bytes and building descriptors that exceed it
+/// will fail to compile. String indices are left as zero.
+///
+/// The Audio Control [`header()`](Self::header) must be added first and
+/// its `wTotalLength` is updated as further descriptors are added.
+#[derive(Clone, Copy)]
+pub struct ClassDescriptors<const N: usize> {
+    bytes: [u8; N],
+    length: usize,
+    has_header: bool,
+}
+
+impl<const N: usize> ClassDescriptors<N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            length: 0,
+            has_header: false,
+        }
+    }
+
+    /// Returns the length of the descriptors in bytes.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.length
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.length]
+    }
+
+    /// Returns the descriptors as an array for use in a `const`
+    /// interface descriptor.
+    ///
+    /// Fails to compile if the descriptors do not fill the capacity `N`
+    /// exactly.
+    #[must_use]
+    pub const fn to_bytes(&self) -> [u8; N] {
+        assert!(
+            self.length == N,
+            "ClassDescriptors length must equal capacity"
+        );
+        self.bytes
+    }
+
+    // - audio control --
+
+    /// Class-specific Audio Control interface header descriptor
+    #[must_use]
+    pub const fn header(self, category: u8) -> Self {
+        assert!(self.length == 0, "Audio Control header must come first");
+        let mut descriptors = self.push(&[
+            9,
+            descriptor_type::CS_INTERFACE,
+            ac_subtype::HEADER,
+            0x00, // bcdADC v2.0
+            0x02,
+            category,
+            9, // wTotalLength
+            0,
+            0x00, // bmControls
+        ]);
+        descriptors.has_header = true;
+        descriptors
+    }
+
+    /// Clock source descriptor
+    #[must_use]
+    pub const fn clock_source(self, clock_id: u8, attributes: u8, controls: u8) -> Self {
+        self.push(&[
+            8,
+            descriptor_type::CS_INTERFACE,
+            ac_subtype::CLOCK_SOURCE,
+            clock_id,
+            attributes,
+            controls,
+            0, // bAssocTerminal
+            0, // iClockSource
+        ])
+    }
+
+    /// Input terminal descriptor
+    #[must_use]
+    pub const fn input_terminal(
+        self,
+        terminal_id: u8,
+        terminal_type: u16,
+        clock_source_id: u8,
+        channels: u8,
+        channel_config: u32,
+        controls: u16,
+    ) -> Self {
+        let [t0, t1] = terminal_type.to_le_bytes();
+        let [c0, c1, c2, c3] = channel_config.to_le_bytes();
+        let [m0, m1] = controls.to_le_bytes();
+        self.push(&[
+            17,
+            descriptor_type::CS_INTERFACE,
+            ac_subtype::INPUT_TERMINAL,
+            terminal_id,
+            t0,
+            t1,
+            0, // bAssocTerminal
+            clock_source_id,
+            channels,
+            c0,
+            c1,
+            c2,
+            c3,
+            0, // iChannelNames
+            m0,
+            m1,
+            0, // iTerminal
+        ])
+    }
+
+    /// Output terminal descriptor
+    #[must_use]
+    pub const fn output_terminal(
+        self,
+        terminal_id: u8,
+        terminal_type: u16,
+        source_id: u8,
+        clock_source_id: u8,
+        controls: u16,
+    ) -> Self {
+        let [t0, t1] = terminal_type.to_le_bytes();
+        let [m0, m1] = controls.to_le_bytes();
+        self.push(&[
+            12,
+            descriptor_type::CS_INTERFACE,
+            ac_subtype::OUTPUT_TERMINAL,
+            terminal_id,
+            t0,
+            t1,
+            0, // bAssocTerminal
+            source_id,
+            clock_source_id,
+            m0,
+            m1,
+            0, // iTerminal
+        ])
+    }
+
+    /// Feature unit descriptor
+    ///
+    /// `controls` holds the `bmaControls` of the master channel
+    /// followed by those of each logical channel.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn feature_unit(self, unit_id: u8, source_id: u8, controls: &[u32]) -> Self {
+        let length = 6 + controls.len() * 4;
+        let mut descriptors = self.push(&[
+            length as u8,
+            descriptor_type::CS_INTERFACE,
+            ac_subtype::FEATURE_UNIT,
+            unit_id,
+            source_id,
+        ]);
+        let mut index = 0;
+        while index < controls.len() {
+            descriptors = descriptors.push(&controls[index].to_le_bytes());
+            index += 1;
+        }
+        descriptors.push(&[0]) // iFeature
+    }
+
+    // - audio streaming --
+
+    /// Class-specific Audio Streaming interface descriptor for PCM data
+    #[must_use]
+    pub const fn streaming_general(
+        self,
+        terminal_link: u8,
+        channels: u8,
+        channel_config: u32,
+    ) -> Self {
+        let [f0, f1, f2, f3] = format::PCM.to_le_bytes();
+        let [c0, c1, c2, c3] = channel_config.to_le_bytes();
+        self.push(&[
+            16,
+            descriptor_type::CS_INTERFACE,
+            as_subtype::AS_GENERAL,
+            terminal_link,
+            0x00, // bmControls
+            format::FORMAT_TYPE_I,
+            f0,
+            f1,
+            f2,
+            f3,
+            channels,
+            c0,
+            c1,
+            c2,
+            c3,
+            0, // iChannelNames
+        ])
+    }
+
+    /// Type I format type descriptor
+    #[must_use]
+    pub const fn format_type_i(self, subslot_size: u8, bit_resolution: u8) -> Self {
+        self.push(&[
+            6,
+            descriptor_type::CS_INTERFACE,
+            as_subtype::FORMAT_TYPE,
+            format::FORMAT_TYPE_I,
+            subslot_size,
+            bit_resolution,
+        ])
+    }
+
+    // - helpers --
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn push(mut self, bytes: &[u8]) -> Self {
+        assert!(
+            self.length + bytes.len() <= N,
+            "ClassDescriptors capacity exceeded"
+        );
+        let mut index = 0;
+        while index < bytes.len() {
+            self.bytes[self.length] = bytes[index];
+            self.length += 1;
+            index += 1;
+        }
+        // update the header's wTotalLength
+        if self.has_header {
+            let [l0, l1] = (self.length as u16).to_le_bytes();
+            self.bytes[6] = l0;
+            self.bytes[7] = l1;
+        }
+        self
+    }
+}
+
+impl<const N: usize> Default for ClassDescriptors<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// - ClassRequest -------------------------------------------------------------
+
+/// Audio Class Requests
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum ClassRequest {
+    Cur = 0x01,
+    Range = 0x02,
+    Unknown(u8),
+}
+
+impl From<u8> for ClassRequest {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => ClassRequest::Cur,
+            0x02 => ClassRequest::Range,
+            _ => ClassRequest::Unknown(value),
+        }
+    }
+}
+
+/// Clock source control selectors
+pub mod clock_control_selector {
+    pub const SAMPLING_FREQUENCY: u8 = 0x01;
+    pub const CLOCK_VALID: u8 = 0x02;
+}
+
+/// Feature unit control selectors
+pub mod feature_control_selector {
+    pub const MUTE: u8 = 0x01;
+    pub const VOLUME: u8 = 0x02;
+}
+
+// - AudioEvent ---------------------------------------------------------------
+
+/// Events reported by [`AudioControl`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AudioEvent {
+    /// The host selected a new sample rate.
+    SampleRateChanged(u32),
+    /// The host muted or unmuted a channel, `0` is the master channel.
+    MuteChanged { channel: u8, mute: bool },
+    /// The host changed the volume of a channel in 1/256 dB, `0` is the
+    /// master channel.
+    VolumeChanged { channel: u8, volume: i16 },
+}
+
+// - AudioControl -------------------------------------------------------------
+
+/// An Audio Control interface with a single clock source and a
+/// feature unit with mute and volume controls for a master channel
+/// and `CHANNELS` logical channels.
+///
+/// Class requests received by [`Control`](crate::control::Control) are
+/// passed to [`AudioControl::handle_setup_packet()`].
+pub struct AudioControl<'a, const CHANNELS: usize> {
+    interface_number: u8,
+    clock_source_id: u8,
+    feature_unit_id: u8,
+    sample_rates: &'a [u32],
+    volume_range: (i16, i16, i16),
+
+    sample_rate: u32,
+    master_mute: bool,
+    master_volume: i16,
+    mute: [bool; CHANNELS],
+    volume: [i16; CHANNELS],
+}
+
+impl<'a, const CHANNELS: usize> AudioControl<'a, CHANNELS> {
+    /// Creates a new Audio Control interface.
+    ///
+    /// `sample_rates` lists the discrete sample rates supported by the
+    /// clock source, the first of which is selected initially.
+    #[must_use]
+    pub const fn new(
+        interface_number: u8,
+        clock_source_id: u8,
+        feature_unit_id: u8,
+        sample_rates: &'a [u32],
+    ) -> Self {
+        assert!(
+            !sample_rates.is_empty(),
+            "at least one sample rate required"
+        );
+        Self {
+            interface_number,
+            clock_source_id,
+            feature_unit_id,
+            sample_rates,
+            volume_range: (-100 * 256, 0, 256),
+            sample_rate: sample_rates[0],
+            master_mute: false,
+            master_volume: 0,
+            mute: [false; CHANNELS],
+            volume: [0; CHANNELS],
+        }
+    }
+
+    /// Sets the volume range reported to the host as minimum, maximum
+    /// and resolution in 1/256 dB. The default is -100 dB to 0 dB in
+    /// steps of 1 dB.
+    #[must_use]
+    pub const fn with_volume_range(mut self, minimum: i16, maximum: i16, resolution: i16) -> Self {
+        self.volume_range = (minimum, maximum, resolution);
+        self
+    }
+
+    /// Returns the current sample rate.
+    #[must_use]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Returns `true` if the channel is muted, `0` is the master channel.
+    #[must_use]
+    pub fn mute(&self, channel: u8) -> bool {
+        match channel {
+            0 => self.master_mute,
+            channel => self
+                .mute
+                .get(usize::from(channel) - 1)
+                .copied()
+                .unwrap_or(false),
+        }
+    }
+
+    /// Returns the volume of the channel in 1/256 dB, `0` is the master channel.
+    #[must_use]
+    pub fn volume(&self, channel: u8) -> i16 {
+        match channel {
+            0 => self.master_volume,
+            channel => self
+                .volume
+                .get(usize::from(channel) - 1)
+                .copied()
+                .unwrap_or(0),
+        }
+    }
+
+    /// Handles a class request passed on by the control endpoint.
+    ///
+    /// `data` holds any data received from the host during the data
+    /// stage of the request. Requests that are not addressed to this
+    /// interface are ignored.
+    pub fn handle_setup_packet<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &[u8],
+    ) -> Option<AudioEvent>
+    where
+        D: UsbDriver,
+    {
+        let [interface_number, entity_id] = setup_packet.index.to_le_bytes();
+        if setup_packet.request_type() != RequestType::Class
+            || setup_packet.recipient() != Recipient::Interface
+            || interface_number != self.interface_number
+        {
+            return None;
+        }
+
+        let direction = setup_packet.direction();
+        let request = ClassRequest::from(setup_packet.request);
+        let [channel, control_selector] = setup_packet.value.to_le_bytes();
+        let requested_length = usize::from(setup_packet.length);
+        trace!(
+            "AudioControl {:?} {:?} entity:{} selector:{} channel:{}",
+            direction,
+            request,
+            entity_id,
+            control_selector,
+            channel
+        );
+
+        let handled = if entity_id == self.clock_source_id {
+            self.handle_clock_source(
+                usb,
+                control_endpoint,
+                direction,
+                &request,
+                control_selector,
+                requested_length,
+                data,
+            )
+        } else if entity_id == self.feature_unit_id && usize::from(channel) <= CHANNELS {
+            self.handle_feature_unit(
+                usb,
+                control_endpoint,
+                direction,
+                &request,
+                control_selector,
+                channel,
+                requested_length,
+                data,
+            )
+        } else {
+            None
+        };
+
+        match handled {
+            Some(event) => event,
+            None => {
+                warn!(
+                    "AudioControl stall - unhandled {:?} for entity:{} selector:{}",
+                    request, entity_id, control_selector
+                );
+                match direction {
+                    Direction::DeviceToHost => usb.stall_endpoint_in(control_endpoint),
+                    // data requests have already been acknowledged by the control endpoint
+                    Direction::HostToDevice if requested_length > 0 => (),
+                    Direction::HostToDevice => usb.stall_endpoint_out(control_endpoint),
+                }
+                None
+            }
+        }
+    }
+
+    /// Returns `None` if the request was not handled.
+    #[allow(clippy::too_many_arguments, clippy::option_option)]
+    fn handle_clock_source<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        direction: Direction,
+        request: &ClassRequest,
+        control_selector: u8,
+        requested_length: usize,
+        data: &[u8],
+    ) -> Option<Option<AudioEvent>>
+    where
+        D: UsbDriver,
+    {
+        match (direction, request, control_selector) {
+            (
+                Direction::DeviceToHost,
+                ClassRequest::Cur,
+                clock_control_selector::SAMPLING_FREQUENCY,
+            ) => {
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    self.sample_rate
+                        .to_le_bytes()
+                        .into_iter()
+                        .take(requested_length),
+                );
+                Some(None)
+            }
+            (
+                Direction::HostToDevice,
+                ClassRequest::Cur,
+                clock_control_selector::SAMPLING_FREQUENCY,
+            ) => {
+                let Ok(bytes) = <[u8; 4]>::try_from(data) else {
+                    warn!("AudioControl invalid sample rate length: {}", data.len());
+                    return Some(None);
+                };
+                let sample_rate = u32::from_le_bytes(bytes);
+                if !self.sample_rates.contains(&sample_rate) {
+                    warn!("AudioControl unsupported sample rate: {}", sample_rate);
+                    return Some(None);
+                }
+                debug!("AudioControl sample rate: {}", sample_rate);
+                self.sample_rate = sample_rate;
+                Some(Some(AudioEvent::SampleRateChanged(sample_rate)))
+            }
+            (
+                Direction::DeviceToHost,
+                ClassRequest::Range,
+                clock_control_selector::SAMPLING_FREQUENCY,
+            ) => {
+                // each supported rate is a subrange of (minimum, maximum, resolution)
+                #[allow(clippy::cast_possible_truncation)]
+                let count = self.sample_rates.len() as u16;
+                let subranges = self.sample_rates.iter().flat_map(|rate| {
+                    let bytes = rate.to_le_bytes();
+                    bytes.into_iter().chain(bytes).chain([0; 4])
+                });
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    count
+                        .to_le_bytes()
+                        .into_iter()
+                        .chain(subranges)
+                        .take(requested_length),
+                );
+                Some(None)
+            }
+            (Direction::DeviceToHost, ClassRequest::Cur, clock_control_selector::CLOCK_VALID) => {
+                usb.write_requested(control_endpoint, requested_length, [1].into_iter());
+                Some(None)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns `None` if the request was not handled.
+    #[allow(clippy::too_many_arguments, clippy::option_option)]
+    fn handle_feature_unit<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        direction: Direction,
+        request: &ClassRequest,
+        control_selector: u8,
+        channel: u8,
+        requested_length: usize,
+        data: &[u8],
+    ) -> Option<Option<AudioEvent>>
+    where
+        D: UsbDriver,
+    {
+        let index = usize::from(channel);
+        match (direction, request, control_selector) {
+            (Direction::DeviceToHost, ClassRequest::Cur, feature_control_selector::MUTE) => {
+                let mute = self.mute(channel);
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    [u8::from(mute)].into_iter(),
+                );
+                Some(None)
+            }
+            (Direction::HostToDevice, ClassRequest::Cur, feature_control_selector::MUTE) => {
+                let mute = data.first().copied()? != 0;
+                match index {
+                    0 => self.master_mute = mute,
+                    index => self.mute[index - 1] = mute,
+                }
+                Some(Some(AudioEvent::MuteChanged { channel, mute }))
+            }
+            (Direction::DeviceToHost, ClassRequest::Cur, feature_control_selector::VOLUME) => {
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    self.volume(channel)
+                        .to_le_bytes()
+                        .into_iter()
+                        .take(requested_length),
+                );
+                Some(None)
+            }
+            (Direction::HostToDevice, ClassRequest::Cur, feature_control_selector::VOLUME) => {
+                let bytes = <[u8; 2]>::try_from(data).ok()?;
+                let (minimum, maximum, _) = self.volume_range;
+                let volume = i16::from_le_bytes(bytes).clamp(minimum, maximum);
+                match index {
+                    0 => self.master_volume = volume,
+                    index => self.volume[index - 1] = volume,
+                }
+                Some(Some(AudioEvent::VolumeChanged { channel, volume }))
+            }
+            (Direction::DeviceToHost, ClassRequest::Range, feature_control_selector::VOLUME) => {
+                let (minimum, maximum, resolution) = self.volume_range;
+                let response = 1_u16
+                    .to_le_bytes()
+                    .into_iter()
+                    .chain(minimum.to_le_bytes())
+                    .chain(maximum.to_le_bytes())
+                    .chain(resolution.to_le_bytes());
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    response.take(requested_length),
+                );
+                Some(None)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<'a, D, const CHANNELS: usize> UsbClass<D> for AudioControl<'a, CHANNELS>
+where
+    D: UsbDriver,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        interface_number == self.interface_number
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &'d [u8],
+    ) -> ControlResult<'d> {
+        if setup_packet.request_type() != RequestType::Class
+            || setup_packet.recipient() != Recipient::Interface
+            || (setup_packet.index & 0xff) as u8 != self.interface_number
+        {
+            return ControlResult::Unhandled;
+        }
+        self.handle_setup_packet(usb, control_endpoint, setup_packet, data)
+            .into()
+    }
+
+    fn reset(&mut self) {}
+
+    fn configuration_changed(&mut self, _usb: &D, _configuration: u8) {}
+}
+
+// - AudioStream --------------------------------------------------------------
+
+/// An Audio Streaming interface with a single isochronous data endpoint.
+///
+/// The stream is started and stopped by passing alternate setting
+/// changes to [`AudioStream::set_alternate_setting()`], endpoint events
+/// to [`AudioStream::handle_event()`] and start-of-frame events to
+/// [`AudioStream::start_of_frame()`]. Samples are buffered in a ring
+/// buffer of `BUFFER_SIZE` bytes:
+///
+/// * For an IN endpoint (e.g. a microphone) the application queues
+///   samples with [`AudioStream::write()`] and one packet is sent to
+///   the host per (micro)frame on start-of-frame. A packet that could
+///   not be queued is retried on the following start-of-frame.
+///   Until a start-of-frame is received the next packet is instead
+///   sent on [`UsbEvent::SendComplete`], paced by the host polling
+///   the endpoint once per (micro)frame, for drivers that report it
+///   for isochronous packets.
+/// * For an OUT endpoint (e.g. a speaker) samples received from the
+///   host are read with [`AudioStream::read()`].
+///
+/// The endpoint is assumed to have a `bInterval` of one (micro)frame.
+///
+/// Start-of-frame pacing follows the host frame timing more closely
+/// and needs a driver that reports [`UsbEvent::StartOfFrame`]. The
+/// `lunasoc-hal` driver does so once start-of-frame events have been
+/// enabled with `set_start_of_frame_events()`.
+pub struct AudioStream<const BUFFER_SIZE: usize> {
+    interface_number: u8,
+    endpoint_number: u8,
+    direction: Direction,
+    frame_size: usize,
+    packets_per_second: u32,
+
+    sample_rate: u32,
+    accumulator: u32,
+    active: bool,
+    start_of_frame: bool,
+    schedule: FrameSchedule,
+    pending_frames: Option<usize>,
+    buffer: RingBuffer<BUFFER_SIZE>,
+}
+
+impl<const BUFFER_SIZE: usize> AudioStream<BUFFER_SIZE> {
+    /// Creates a new Audio Streaming interface.
+    ///
+    /// `frame_size` is the size of one audio frame in bytes, i.e. the
+    /// number of channels multiplied by the subslot size.
+    #[must_use]
+    pub const fn new(
+        interface_number: u8,
+        endpoint_address: u8,
+        frame_size: usize,
+        sample_rate: u32,
+        speed: Speed,
+    ) -> Self {
+        let direction = if endpoint_address & 0x80 == 0 {
+            Direction::HostToDevice
+        } else {
+            Direction::DeviceToHost
+        };
+        let packets_per_second = match speed {
+            Speed::High => 8000,
+            _ => 1000,
+        };
+        Self {
+            interface_number,
+            endpoint_number: endpoint_address & 0x7f,
+            direction,
+            frame_size,
+            packets_per_second,
+            sample_rate,
+            accumulator: 0,
+            active: false,
+            start_of_frame: false,
+            schedule: FrameSchedule::new(speed, 1),
+            pending_frames: None,
+            buffer: RingBuffer::new(),
+        }
+    }
+
+    /// Returns `true` if the host has selected an alternate setting
+    /// with a streaming endpoint.
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Returns the current sample rate.
+    #[must_use]
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Sets the sample rate, e.g. on [`AudioEvent::SampleRateChanged`].
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+        self.accumulator = 0;
+    }
+
+    /// Returns the number of bytes in the buffer.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
+    }
+
+    /// Returns the number of bytes that can be written before the buffer is full.
+    #[must_use]
+    pub fn available(&self) -> usize {
+        self.buffer.available()
+    }
+
+    /// Queues samples to be sent to the host and returns the number of
+    /// bytes queued.
+    pub fn write(&mut self, samples: &[u8]) -> usize {
+        self.buffer.push(samples)
+    }
+
+    /// Reads samples received from the host and returns the number of
+    /// bytes read.
+    pub fn read(&mut self, samples: &mut [u8]) -> usize {
+        self.buffer.pop(samples)
+    }
+
+    /// Stops the stream and discards any buffered samples.
+    pub fn reset(&mut self) {
+        self.active = false;
+        self.accumulator = 0;
+        self.schedule.reset();
+        self.pending_frames = None;
+        self.buffer.clear();
+    }
+
+    /// Handles an alternate setting change of the given interface,
+    /// starting the stream for any non-zero alternate setting.
+    pub fn set_alternate_setting<D>(&mut self, usb: &D, interface_number: u8, alternate_setting: u8)
+    where
+        D: UsbDriver + IsochronousEndpoint,
+    {
+        if interface_number != self.interface_number {
+            return;
+        }
+
+        self.reset();
+        if alternate_setting == 0 {
+            debug!("AudioStream {} stopped", self.interface_number);
+            return;
+        }
+
+        debug!(
+            "AudioStream {} started at {} Hz",
+            self.interface_number, self.sample_rate
+        );
+        self.active = true;
+        match self.direction {
+            Direction::HostToDevice => usb.ep_out_prime_receive(self.endpoint_number),
+            // without start-of-frame the first packet starts the chain
+            // of packets sent on send complete
+            Direction::DeviceToHost if !self.start_of_frame => self.send_packet(usb),
+            Direction::DeviceToHost => (),
+        }
+    }
+
+    /// Handles a start-of-frame, sending the next packet to the host
+    /// for an IN endpoint.
+    pub fn start_of_frame<D>(&mut self, usb: &D, frame_number: u16)
+    where
+        D: IsochronousEndpoint,
+    {
+        self.start_of_frame = true;
+        if !self.active || self.direction != Direction::DeviceToHost {
+            return;
+        }
+        if self.schedule.is_due(frame_number) || self.pending_frames.is_some() {
+            self.send_packet(usb);
+        }
+    }
+
+    /// Handles a USB event for the isochronous data endpoint.
+    pub fn handle_event<D>(&mut self, usb: &D, event: UsbEvent)
+    where
+        D: UsbDriver + IsochronousEndpoint,
+    {
+        match event {
+            UsbEvent::BusReset => self.reset(),
+            UsbEvent::ReceivePacket(endpoint_number)
+                if self.active
+                    && endpoint_number == self.endpoint_number
+                    && self.direction == Direction::HostToDevice =>
+            {
+                let mut packet = [0; crate::EP_MAX_PACKET_SIZE];
+                let bytes_read = usb.read_isochronous(endpoint_number, &mut packet);
+                let bytes_pushed = self.buffer.push(&packet[..bytes_read]);
+                if bytes_pushed < bytes_read {
+                    trace!(
+                        "AudioStream overrun, dropped {} bytes",
+                        bytes_read - bytes_pushed
+                    );
+                }
+            }
+            UsbEvent::SendComplete(endpoint_number)
+                if self.active
+                    && !self.start_of_frame
+                    && endpoint_number == self.endpoint_number
+                    && self.direction == Direction::DeviceToHost =>
+            {
+                self.send_packet(usb);
+            }
+            _ => (),
+        }
+    }
+
+    /// Returns the number of audio frames in the next packet.
+    fn next_packet_frames(&mut self) -> usize {
+        self.accumulator += self.sample_rate;
+        let frames = self.accumulator / self.packets_per_second;
+        self.accumulator %= self.packets_per_second;
+        frames as usize
+    }
+
+    fn send_packet<D>(&mut self, usb: &D)
+    where
+        D: IsochronousEndpoint,
+    {
+        let frames = match self.pending_frames.take() {
+            Some(frames) => frames,
+            None => self.next_packet_frames(),
+        };
+        let available = self.buffer.len() - self.buffer.len() % self.frame_size;
+        let packet_length = (frames * self.frame_size).min(available);
+        if packet_length < frames * self.frame_size {
+            trace!(
+                "AudioStream underrun, {} of {} bytes",
+                packet_length,
+                frames * self.frame_size
+            );
+        }
+        let bytes_written =
+            usb.write_isochronous(self.endpoint_number, self.buffer.iter().take(packet_length));
+        if bytes_written == 0 && packet_length > 0 {
+            trace!("AudioStream endpoint busy, retrying next frame");
+            self.pending_frames = Some(frames);
+            return;
+        }
+        self.buffer.consume(bytes_written);
+    }
+}
+
+impl<D, const BUFFER_SIZE: usize> UsbClass<D> for AudioStream<BUFFER_SIZE>
+where
+    D: UsbDriver + IsochronousEndpoint,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        interface_number == self.interface_number
+    }
+
+    fn has_endpoint(&self, endpoint_address: u8) -> bool {
+        match self.direction {
+            Direction::HostToDevice => endpoint_address == self.endpoint_number,
+            Direction::DeviceToHost => endpoint_address == self.endpoint_number | 0x80,
+        }
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        _usb: &D,
+        _control_endpoint: u8,
+        _setup_packet: SetupPacket,
+        _data: &'d [u8],
+    ) -> ControlResult<'d> {
+        ControlResult::Unhandled
+    }
+
+    fn handle_endpoint_event(&mut self, usb: &D, event: UsbEvent) {
+        self.handle_event(usb, event);
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn configuration_changed(&mut self, _usb: &D, _configuration: u8) {
+        self.reset();
+    }
+
+    fn alternate_setting_changed(&mut self, usb: &D, interface_number: u8, alternate_setting: u8) {
+        self.set_alternate_setting(usb, interface_number, alternate_setting);
+    }
+
+    fn start_of_frame(&mut self, usb: &D, frame_number: u16) {
+        self.start_of_frame(usb, frame_number);
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::control::ControlEvent;
+    use crate::descriptor::parser::{self, Descriptor};
+    use crate::descriptor::{
+        ConfigurationDescriptor, ConfigurationDescriptorHeader, EndpointDescriptor,
+        InterfaceAssociationDescriptor, InterfaceDescriptor, InterfaceDescriptorHeader,
+        SynchronizationType, UsageType,
+    };
+    use crate::event::UsbEvent;
+    use crate::testing::{class_request, HostError, MockUsbDriver, Operation, VirtualHost};
+
+    // - fixtures -------------------------------------------------------------
+
+    const CLOCK_SOURCE_ID: u8 = 1;
+    const INPUT_TERMINAL_ID: u8 = 2;
+    const FEATURE_UNIT_ID: u8 = 3;
+    const OUTPUT_TERMINAL_ID: u8 = 4;
+
+    static SAMPLE_RATES: &[u32] = &[48_000, 44_100];
+
+    const AUDIO_CONTROL: [u8; 64] = ClassDescriptors::new()
+        .header(category::DESKTOP_SPEAKER)
+        .clock_source(
+            CLOCK_SOURCE_ID,
+            clock_attributes::INTERNAL_PROGRAMMABLE,
+            clock_controls::FREQUENCY_READ_WRITE | clock_controls::VALIDITY_READ_ONLY,
+        )
+        .input_terminal(
+            INPUT_TERMINAL_ID,
+            terminal_type::USB_STREAMING,
+            CLOCK_SOURCE_ID,
+            2,
+            channel_config::STEREO,
+            0,
+        )
+        .feature_unit(
+            FEATURE_UNIT_ID,
+            INPUT_TERMINAL_ID,
+            &[
+                feature_controls::MUTE_READ_WRITE | feature_controls::VOLUME_READ_WRITE,
+                0,
+                0,
+            ],
+        )
+        .output_terminal(
+            OUTPUT_TERMINAL_ID,
+            terminal_type::SPEAKER,
+            FEATURE_UNIT_ID,
+            CLOCK_SOURCE_ID,
+            0,
+        )
+        .to_bytes();
+
+    const AUDIO_STREAMING: [u8; 22] = ClassDescriptors::new()
+        .streaming_general(INPUT_TERMINAL_ID, 2, channel_config::STEREO)
+        .format_type_i(2, 16)
+        .to_bytes();
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[
+            InterfaceDescriptor::new_class(
+                InterfaceDescriptorHeader {
+                    iInterfaceNumber: 0,
+                    bInterfaceClass: INTERFACE_CLASS,
+                    bInterfaceSubClass: subclass::AUDIO_CONTROL,
+                    bInterfaceProtocol: PROTOCOL_IP_VERSION_02_00,
+                    ..InterfaceDescriptorHeader::new()
+                },
+                &AUDIO_CONTROL,
+                &[],
+            )
+            .with_association(InterfaceAssociationDescriptor {
+                bFirstInterface: 0,
+                bInterfaceCount: 2,
+                bFunctionClass: INTERFACE_CLASS,
+                bFunctionSubClass: FUNCTION_SUBCLASS_UNDEFINED,
+                bFunctionProtocol: PROTOCOL_IP_VERSION_02_00,
+                ..InterfaceAssociationDescriptor::new()
+            }),
+            InterfaceDescriptor::new(
+                InterfaceDescriptorHeader {
+                    iInterfaceNumber: 1,
+                    bAlternateSetting: 0,
+                    bInterfaceClass: INTERFACE_CLASS,
+                    bInterfaceSubClass: subclass::AUDIO_STREAMING,
+                    bInterfaceProtocol: PROTOCOL_IP_VERSION_02_00,
+                    ..InterfaceDescriptorHeader::new()
+                },
+                &[],
+            ),
+            InterfaceDescriptor::new_class(
+                InterfaceDescriptorHeader {
+                    iInterfaceNumber: 1,
+                    bAlternateSetting: 1,
+                    bInterfaceClass: INTERFACE_CLASS,
+                    bInterfaceSubClass: subclass::AUDIO_STREAMING,
+                    bInterfaceProtocol: PROTOCOL_IP_VERSION_02_00,
+                    ..InterfaceDescriptorHeader::new()
+                },
+                &AUDIO_STREAMING,
+                &[EndpointDescriptor::isochronous(
+                    0x01,
+                    SynchronizationType::Adaptive,
+                    UsageType::Data,
+                    200,
+                    1,
+                    1,
+                )],
+            )
+            .with_endpoint_class_descriptors(&[&ISOCHRONOUS_DATA_ENDPOINT_DESCRIPTOR]),
+        ],
+    )];
+
+    fn request(
+        direction: Direction,
+        request: u8,
+        entity_id: u8,
+        control_selector: u8,
+        channel: u8,
+        length: u16,
+    ) -> SetupPacket {
+        class_request(
+            direction,
+            Recipient::Interface,
+            request,
+            u16::from_le_bytes([channel, control_selector]),
+            u16::from_le_bytes([0, entity_id]),
+            length,
+        )
+    }
+
+    fn control_in(
+        host: &mut VirtualHost<'static, 256>,
+        audio: &mut AudioControl<'static, 2>,
+        setup_packet: SetupPacket,
+    ) -> Result<Vec<u8>, HostError> {
+        host.control_in_with(setup_packet, |usb, setup_packet, data| {
+            assert_eq!(audio.handle_setup_packet(usb, 0, setup_packet, data), None);
+        })
+    }
+
+    fn control_out(
+        host: &mut VirtualHost<'static, 256>,
+        audio: &mut AudioControl<'static, 2>,
+        setup_packet: SetupPacket,
+        data: &[u8],
+    ) -> Option<AudioEvent> {
+        let mut event = None;
+        host.control_out_with(setup_packet, data, |usb, setup_packet, data| {
+            event = audio.handle_setup_packet(usb, 0, setup_packet, data);
+        })
+        .unwrap();
+        event
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_class_descriptors() {
+        let bytes = AUDIO_CONTROL;
+        assert_eq!(bytes.len(), 9 + 8 + 17 + 18 + 12);
+        // wTotalLength covers the header and all units and terminals
+        assert_eq!(&bytes[..9], &[9, 0x24, 0x01, 0x00, 0x02, 0x01, 64, 0, 0]);
+        assert_eq!(&bytes[9..17], &[8, 0x24, 0x0a, 1, 0x03, 0x07, 0, 0]);
+        assert_eq!(&bytes[34..40], &[18, 0x24, 0x06, 3, 2, 0x0f]);
+
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let enumeration = host.enumerate(1).unwrap();
+        let configuration = &enumeration.configuration_descriptors[0];
+        let descriptors: Vec<Descriptor> = parser::parse(configuration)
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        // the class-specific endpoint descriptor follows the isochronous endpoint
+        let endpoint = descriptors
+            .iter()
+            .position(|descriptor| matches!(descriptor, Descriptor::Endpoint(_)))
+            .expect("endpoint descriptor");
+        assert!(matches!(
+            descriptors[endpoint + 1],
+            Descriptor::ClassSpecific(descriptor_type::CS_ENDPOINT, _)
+        ));
+        assert_eq!(
+            &configuration[configuration.len() - 8..],
+            ISOCHRONOUS_DATA_ENDPOINT_DESCRIPTOR
+        );
+    }
+
+    #[test]
+    fn test_sample_rate() {
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut audio: AudioControl<2> =
+            AudioControl::new(0, CLOCK_SOURCE_ID, FEATURE_UNIT_ID, SAMPLE_RATES);
+        let sampling_frequency = clock_control_selector::SAMPLING_FREQUENCY;
+
+        // hosts first read the number of subranges and then all of them
+        let data = control_in(
+            &mut host,
+            &mut audio,
+            request(
+                Direction::DeviceToHost,
+                0x02,
+                CLOCK_SOURCE_ID,
+                sampling_frequency,
+                0,
+                2,
+            ),
+        )
+        .unwrap();
+        assert_eq!(data, [2, 0]);
+        let data = control_in(
+            &mut host,
+            &mut audio,
+            request(
+                Direction::DeviceToHost,
+                0x02,
+                CLOCK_SOURCE_ID,
+                sampling_frequency,
+                0,
+                26,
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            data,
+            [
+                2, 0, // wNumSubRanges
+                0x80, 0xbb, 0, 0, 0x80, 0xbb, 0, 0, 0, 0, 0, 0, // 48 kHz
+                0x44, 0xac, 0, 0, 0x44, 0xac, 0, 0, 0, 0, 0, 0, // 44.1 kHz
+            ]
+        );
+
+        let event = control_out(
+            &mut host,
+            &mut audio,
+            request(
+                Direction::HostToDevice,
+                0x01,
+                CLOCK_SOURCE_ID,
+                sampling_frequency,
+                0,
+                4,
+            ),
+            &44_100_u32.to_le_bytes(),
+        );
+        assert_eq!(event, Some(AudioEvent::SampleRateChanged(44_100)));
+        let data = control_in(
+            &mut host,
+            &mut audio,
+            request(
+                Direction::DeviceToHost,
+                0x01,
+                CLOCK_SOURCE_ID,
+                sampling_frequency,
+                0,
+                4,
+            ),
+        )
+        .unwrap();
+        assert_eq!(data, 44_100_u32.to_le_bytes());
+
+        // unsupported sample rates are ignored
+        let event = control_out(
+            &mut host,
+            &mut audio,
+            request(
+                Direction::HostToDevice,
+                0x01,
+                CLOCK_SOURCE_ID,
+                sampling_frequency,
+                0,
+                4,
+            ),
+            &96_000_u32.to_le_bytes(),
+        );
+        assert_eq!(event, None);
+        assert_eq!(audio.sample_rate(), 44_100);
+
+        let data = control_in(
+            &mut host,
+            &mut audio,
+            request(
+                Direction::DeviceToHost,
+                0x01,
+                CLOCK_SOURCE_ID,
+                clock_control_selector::CLOCK_VALID,
+                0,
+                1,
+            ),
+        )
+        .unwrap();
+        assert_eq!(data, [1]);
+    }
+
+    #[test]
+    fn test_mute_and_volume() {
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut audio: AudioControl<2> =
+            AudioControl::new(0, CLOCK_SOURCE_ID, FEATURE_UNIT_ID, SAMPLE_RATES).with_volume_range(
+                -60 * 256,
+                0,
+                128,
+            );
+        let mute = feature_control_selector::MUTE;
+        let volume = feature_control_selector::VOLUME;
+
+        let event = control_out(
+            &mut host,
+            &mut audio,
+            request(Direction::HostToDevice, 0x01, FEATURE_UNIT_ID, mute, 0, 1),
+            &[1],
+        );
+        assert_eq!(
+            event,
+            Some(AudioEvent::MuteChanged {
+                channel: 0,
+                mute: true
+            })
+        );
+        assert!(audio.mute(0));
+        assert!(!audio.mute(1));
+        let data = control_in(
+            &mut host,
+            &mut audio,
+            request(Direction::DeviceToHost, 0x01, FEATURE_UNIT_ID, mute, 0, 1),
+        )
+        .unwrap();
+        assert_eq!(data, [1]);
+
+        let data = control_in(
+            &mut host,
+            &mut audio,
+            request(Direction::DeviceToHost, 0x02, FEATURE_UNIT_ID, volume, 2, 8),
+        )
+        .unwrap();
+        assert_eq!(data, [1, 0, 0x00, 0xc4, 0x00, 0x00, 0x80, 0x00]);
+
+        // volumes are clamped to the supported range
+        let event = control_out(
+            &mut host,
+            &mut audio,
+            request(Direction::HostToDevice, 0x01, FEATURE_UNIT_ID, volume, 2, 2),
+            &(-80_i16 * 256).to_le_bytes(),
+        );
+        assert_eq!(
+            event,
+            Some(AudioEvent::VolumeChanged {
+                channel: 2,
+                volume: -60 * 256
+            })
+        );
+        let data = control_in(
+            &mut host,
+            &mut audio,
+            request(Direction::DeviceToHost, 0x01, FEATURE_UNIT_ID, volume, 2, 2),
+        )
+        .unwrap();
+        assert_eq!(data, (-60_i16 * 256).to_le_bytes());
+        assert_eq!(audio.volume(1), 0);
+
+        // channel 3 does not exist
+        let result = control_in(
+            &mut host,
+            &mut audio,
+            request(Direction::DeviceToHost, 0x01, FEATURE_UNIT_ID, volume, 3, 2),
+        );
+        assert!(matches!(result, Err(HostError::Stalled(_))));
+    }
+
+    #[test]
+    fn test_playback_stream() {
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut stream: AudioStream<512> =
+            AudioStream::new(1, 0x01, 4, SAMPLE_RATES[0], Speed::Full);
+
+        // selecting the streaming alternate setting starts the stream
+        host.control_out(
+            SetupPacket {
+                request_type: 0b0000_0001, // Standard | Interface
+                request: 11,               // SET_INTERFACE
+                value: 1,
+                index: 1,
+                length: 0,
+            },
+            &[],
+        )
+        .unwrap();
+        for event in host.take_events() {
+            if let ControlEvent::AlternateSettingChanged(interface, alternate_setting) = event {
+                stream.set_alternate_setting(&host.usb, interface, alternate_setting);
+            }
+        }
+        assert!(stream.is_active());
+        assert!(host
+            .usb
+            .take_operations()
+            .contains(&Operation::PrimeReceive(1)));
+
+        let packet: Vec<u8> = (0..192).map(|index| index as u8).collect();
+        host.usb.push_out_data(1, &packet);
+        stream.handle_event(&host.usb, UsbEvent::ReceivePacket(1));
+        assert_eq!(
+            host.usb.take_operations(),
+            [Operation::Read(1, 192), Operation::PrimeReceive(1)]
+        );
+        assert_eq!(stream.len(), 192);
+
+        let mut samples = [0; 256];
+        assert_eq!(stream.read(&mut samples), 192);
+        assert_eq!(&samples[..192], packet);
+
+        // packets received while stopped are ignored
+        stream.set_alternate_setting(&host.usb, 1, 0);
+        host.usb.push_out_data(1, &packet);
+        stream.handle_event(&host.usb, UsbEvent::ReceivePacket(1));
+        assert!(!stream.is_active());
+        assert!(stream.is_empty());
+    }
+
+    #[test]
+    fn test_capture_stream() {
+        let usb = MockUsbDriver::new();
+        // 44.1 kHz, 16-bit mono
+        let mut stream: AudioStream<512> = AudioStream::new(2, 0x82, 2, 44_100, Speed::Full);
+        // start-of-frame packets are received before the stream starts
+        stream.start_of_frame(&usb, 0);
+        stream.set_alternate_setting(&usb, 2, 1);
+        assert!(usb.take_operations().is_empty());
+        let samples: Vec<u8> = (0..=255).collect();
+        assert_eq!(stream.write(&samples), 256);
+
+        // one packet is sent per frame and 44.1 frames per packet are
+        // sent as nine packets of 44 and one of 45 frames
+        for frame_number in 1..=8 {
+            stream.start_of_frame(&usb, frame_number);
+        }
+        let writes: Vec<usize> = usb
+            .take_operations()
+            .into_iter()
+            .filter_map(|operation| match operation {
+                Operation::WriteIsochronous(2, data) => Some(data.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(writes, [88, 88, 80, 0, 0, 0, 0, 0]);
+        assert!(stream.is_empty());
+
+        // a packet that could not be queued is retried on the next frame
+        stream.write(&[0; 256]);
+        usb.set_isochronous_busy(true);
+        stream.start_of_frame(&usb, 9);
+        assert!(usb.take_operations().is_empty());
+        usb.set_isochronous_busy(false);
+        stream.start_of_frame(&usb, 10);
+        stream.start_of_frame(&usb, 11);
+        assert_eq!(
+            usb.take_operations(),
+            [
+                Operation::WriteIsochronous(2, Vec::from([0; 88])),
+                Operation::WriteIsochronous(2, Vec::from([0; 90]))
+            ]
+        );
+
+        // nothing is sent once the stream is stopped
+        stream.set_alternate_setting(&usb, 2, 0);
+        stream.start_of_frame(&usb, 12);
+        assert!(usb.take_operations().is_empty());
+    }
+
+    #[test]
+    fn test_capture_stream_without_start_of_frame() {
+        let usb = MockUsbDriver::new();
+        // 44.1 kHz, 16-bit mono
+        let mut stream: AudioStream<512> = AudioStream::new(2, 0x82, 2, 44_100, Speed::Full);
+
+        // starting the stream sends the first packet and each send
+        // complete sends the next
+        stream.set_alternate_setting(&usb, 2, 1);
+        let samples: Vec<u8> = (0..=255).collect();
+        assert_eq!(stream.write(&samples), 256);
+        for _ in 1..=4 {
+            stream.handle_event(&usb, UsbEvent::SendComplete(2));
+        }
+        let writes: Vec<usize> = usb
+            .take_operations()
+            .into_iter()
+            .filter_map(|operation| match operation {
+                Operation::WriteIsochronous(2, data) => Some(data.len()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(writes, [0, 88, 88, 80, 0]);
+        assert!(stream.is_empty());
+
+        // other endpoints do not send a packet
+        stream.handle_event(&usb, UsbEvent::SendComplete(1));
+        assert!(usb.take_operations().is_empty());
+
+        // a packet that could not be queued is retried on the next send complete
+        stream.write(&[0; 256]);
+        usb.set_isochronous_busy(true);
+        stream.handle_event(&usb, UsbEvent::SendComplete(2));
+        assert!(usb.take_operations().is_empty());
+        usb.set_isochronous_busy(false);
+        stream.handle_event(&usb, UsbEvent::SendComplete(2));
+        assert_eq!(
+            usb.take_operations(),
+            [Operation::WriteIsochronous(2, Vec::from([0; 88]))]
+        );
+
+        // nothing is sent once the stream is stopped
+        stream.set_alternate_setting(&usb, 2, 0);
+        stream.handle_event(&usb, UsbEvent::SendComplete(2));
+        assert!(usb.take_operations().is_empty());
+    }
+}
@@ -0,0 +1,1084 @@
+//! USB Device Firmware Upgrade Class (DFU) v1.1
+//!
+//! Provides the DFU functional descriptor, a [`DfuRuntime`] interface
+//! which lets the host detach an application into DFU mode and a
+//! [`Dfu`] interface which implements the DFU mode state machine on
+//! top of a [`DfuStorage`] backend.
+
+#![allow(non_snake_case)]
+
+use log::{debug, trace, warn};
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+use crate::class::{ControlResult, UsbClass};
+use crate::setup::{Direction, Recipient, RequestType, SetupPacket};
+use crate::traits::UsbDriver;
+
+/// DFU interface class code
+pub const INTERFACE_CLASS: u8 = 0xfe;
+/// DFU interface subclass code
+pub const INTERFACE_SUBCLASS: u8 = 0x01;
+
+/// DFU interface protocol codes
+pub mod protocol {
+    /// The interface is part of an application's runtime configuration.
+    pub const RUNTIME: u8 = 0x01;
+    /// The interface is part of the device's DFU mode configuration.
+    pub const DFU_MODE: u8 = 0x02;
+}
+
+/// DFU functional descriptor `bmAttributes` bits
+pub mod attributes {
+    pub const CAN_DOWNLOAD: u8 = 0b0000_0001;
+    pub const CAN_UPLOAD: u8 = 0b0000_0010;
+    pub const MANIFESTATION_TOLERANT: u8 = 0b0000_0100;
+    pub const WILL_DETACH: u8 = 0b0000_1000;
+}
+
+// - FunctionalDescriptor -----------------------------------------------------
+
+/// DFU functional descriptor
+///
+/// Follows the DFU interface descriptor in the configuration descriptor,
+/// see: [`InterfaceDescriptor::new_class()`](crate::descriptor::InterfaceDescriptor::new_class)
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct FunctionalDescriptor {
+    pub bLength: u8,         // 9
+    pub bDescriptorType: u8, // 0x21 = DFU Functional
+    pub bmAttributes: u8,
+    pub wDetachTimeOut: u16,
+    pub wTransferSize: u16,
+    pub bcdDFUVersion: u16,
+}
+
+impl FunctionalDescriptor {
+    /// Size of the DFU functional descriptor in bytes.
+    pub const LENGTH: usize = core::mem::size_of::<Self>();
+    /// DFU functional descriptor type
+    pub const DESCRIPTOR_TYPE: u8 = 0x21;
+
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(attributes: u8, detach_timeout: u16, transfer_size: usize) -> Self {
+        Self {
+            bLength: Self::LENGTH as u8,
+            bDescriptorType: Self::DESCRIPTOR_TYPE,
+            bmAttributes: attributes,
+            wDetachTimeOut: detach_timeout,
+            wTransferSize: transfer_size as u16,
+            bcdDFUVersion: 0x0110, // v1.1
+        }
+    }
+
+    /// Returns the descriptor as bytes for use in a `const` context.
+    #[must_use]
+    pub const fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let [d0, d1] = self.wDetachTimeOut.to_le_bytes();
+        let [t0, t1] = self.wTransferSize.to_le_bytes();
+        let [v0, v1] = self.bcdDFUVersion.to_le_bytes();
+        [
+            self.bLength,
+            self.bDescriptorType,
+            self.bmAttributes,
+            d0,
+            d1,
+            t0,
+            t1,
+            v0,
+            v1,
+        ]
+    }
+}
+
+// - ClassRequest -------------------------------------------------------------
+
+/// DFU Class Requests
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum ClassRequest {
+    Detach = 0,
+    Download = 1,
+    Upload = 2,
+    GetStatus = 3,
+    ClearStatus = 4,
+    GetState = 5,
+    Abort = 6,
+    Unknown(u8),
+}
+
+impl From<u8> for ClassRequest {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => ClassRequest::Detach,
+            1 => ClassRequest::Download,
+            2 => ClassRequest::Upload,
+            3 => ClassRequest::GetStatus,
+            4 => ClassRequest::ClearStatus,
+            5 => ClassRequest::GetState,
+            6 => ClassRequest::Abort,
+            _ => ClassRequest::Unknown(value),
+        }
+    }
+}
+
+// - State --------------------------------------------------------------------
+
+/// DFU device states
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum State {
+    AppIdle = 0,
+    AppDetach = 1,
+    DfuIdle = 2,
+    DownloadSync = 3,
+    DownloadBusy = 4,
+    DownloadIdle = 5,
+    ManifestSync = 6,
+    Manifest = 7,
+    ManifestWaitReset = 8,
+    UploadIdle = 9,
+    Error = 10,
+}
+
+// - Status -------------------------------------------------------------------
+
+/// DFU status codes
+///
+/// Also used by [`DfuStorage`] to report the reason an operation failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Status {
+    Ok = 0x00,
+    /// File is not targeted for use by this device.
+    Target = 0x01,
+    /// File is for this device but fails some vendor-specific verification test.
+    File = 0x02,
+    /// Device is unable to write memory.
+    Write = 0x03,
+    /// Memory erase function failed.
+    Erase = 0x04,
+    /// Memory erase check failed.
+    CheckErased = 0x05,
+    /// Program memory function failed.
+    Program = 0x06,
+    /// Programmed memory failed verification.
+    Verify = 0x07,
+    /// Cannot program memory due to received address that is out of range.
+    Address = 0x08,
+    /// Received DFU_DNLOAD with wLength = 0, but device does not think it has all of the data yet.
+    NotDone = 0x09,
+    /// Device's firmware is corrupt.
+    Firmware = 0x0a,
+    /// Vendor-specific error.
+    Vendor = 0x0b,
+    /// Device detected unexpected USB reset signaling.
+    UsbReset = 0x0c,
+    /// Device detected unexpected power on reset.
+    PowerOnReset = 0x0d,
+    /// Something went wrong, but the device does not know what it was.
+    Unknown = 0x0e,
+    /// Device stalled an unexpected request.
+    StalledPacket = 0x0f,
+}
+
+// - DfuStorage ---------------------------------------------------------------
+
+/// Storage backend for firmware images written and read by [`Dfu`].
+pub trait DfuStorage {
+    /// Called before the first block of a download is written, for
+    /// example to erase the target region.
+    ///
+    /// # Errors
+    ///
+    /// Returns the DFU [`Status`] to report to the host on failure.
+    fn begin_download(&mut self) -> Result<(), Status> {
+        Ok(())
+    }
+
+    /// Writes a block of the image at the given byte `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the DFU [`Status`] to report to the host on failure.
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Status>;
+
+    /// Reads part of the image at the given byte `offset` into `buffer`,
+    /// returning the number of bytes read.
+    ///
+    /// Returning less than `buffer.len()` bytes ends the upload.
+    ///
+    /// # Errors
+    ///
+    /// Returns the DFU [`Status`] to report to the host on failure.
+    fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, Status>;
+
+    /// Called once the complete image has been downloaded, for
+    /// example to verify it and mark it as bootable.
+    ///
+    /// # Errors
+    ///
+    /// Returns the DFU [`Status`] to report to the host on failure.
+    fn manifest(&mut self) -> Result<(), Status> {
+        Ok(())
+    }
+}
+
+// - DfuEvent -----------------------------------------------------------------
+
+/// Events reported by [`DfuRuntime`] and [`Dfu`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DfuEvent {
+    /// The host requested a detach. The application should enter DFU
+    /// mode on the next bus reset or, if it set the `WILL_DETACH`
+    /// attribute, detach and re-enumerate in DFU mode itself.
+    Detach { timeout: u16 },
+    /// The host started a new download.
+    DownloadStarted,
+    /// A downloaded image was manifested and the application may
+    /// reset the device to run it.
+    Manifested,
+    /// The host aborted an upload or download.
+    Aborted,
+    /// An operation failed with the given status.
+    Error(Status),
+}
+
+/// Writes a DFU_GETSTATUS response.
+fn write_status<D>(usb: &D, control_endpoint: u8, length: usize, status: Status, state: State)
+where
+    D: UsbDriver,
+{
+    // bStatus, bwPollTimeout, bState, iString
+    let response = [status as u8, 0, 0, 0, state as u8, 0];
+    usb.write_requested(control_endpoint, length, response.into_iter().take(length));
+}
+
+/// Returns `true` if the setup packet is a class request for the
+/// given interface.
+fn is_interface_request(setup_packet: &SetupPacket, interface_number: u8) -> bool {
+    setup_packet.request_type() == RequestType::Class
+        && setup_packet.recipient() == Recipient::Interface
+        && (setup_packet.index & 0xff) as u8 == interface_number
+}
+
+// - DfuRuntime ---------------------------------------------------------------
+
+/// The DFU runtime interface of an application.
+///
+/// Class requests received by [`Control`](crate::control::Control) are
+/// passed to [`DfuRuntime::handle_setup_packet()`]. Once the host has
+/// sent DFU_DETACH the application is responsible for entering DFU mode.
+pub struct DfuRuntime {
+    interface_number: u8,
+    state: State,
+}
+
+impl DfuRuntime {
+    #[must_use]
+    pub const fn new(interface_number: u8) -> Self {
+        Self {
+            interface_number,
+            state: State::AppIdle,
+        }
+    }
+
+    /// Returns the current state.
+    #[must_use]
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the interface to the `appIDLE` state.
+    pub fn reset(&mut self) {
+        self.state = State::AppIdle;
+    }
+
+    /// Handles a class request passed on by the control endpoint.
+    pub fn handle_setup_packet<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+    ) -> Option<DfuEvent>
+    where
+        D: UsbDriver,
+    {
+        if !is_interface_request(&setup_packet, self.interface_number) {
+            return None;
+        }
+
+        let requested_length = usize::from(setup_packet.length);
+        match (
+            setup_packet.direction(),
+            ClassRequest::from(setup_packet.request),
+        ) {
+            (Direction::HostToDevice, ClassRequest::Detach) if self.state == State::AppIdle => {
+                debug!("DfuRuntime detach timeout:{}ms", setup_packet.value);
+                self.state = State::AppDetach;
+                usb.write(control_endpoint, [].into_iter());
+                Some(DfuEvent::Detach {
+                    timeout: setup_packet.value,
+                })
+            }
+            (Direction::DeviceToHost, ClassRequest::GetStatus) => {
+                write_status(
+                    usb,
+                    control_endpoint,
+                    requested_length,
+                    Status::Ok,
+                    self.state,
+                );
+                None
+            }
+            (Direction::DeviceToHost, ClassRequest::GetState) => {
+                usb.write_requested(control_endpoint, 1, [self.state as u8].into_iter());
+                None
+            }
+            (direction, request) => {
+                warn!("DfuRuntime stall - unhandled class request {:?}", request);
+                match direction {
+                    Direction::DeviceToHost => usb.stall_endpoint_in(control_endpoint),
+                    Direction::HostToDevice => usb.stall_endpoint_out(control_endpoint),
+                }
+                None
+            }
+        }
+    }
+}
+
+impl<D> UsbClass<D> for DfuRuntime
+where
+    D: UsbDriver,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        interface_number == self.interface_number
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        _data: &'d [u8],
+    ) -> ControlResult<'d> {
+        if !is_interface_request(&setup_packet, self.interface_number) {
+            return ControlResult::Unhandled;
+        }
+        self.handle_setup_packet(usb, control_endpoint, setup_packet).into()
+    }
+
+    fn reset(&mut self) {
+        // the application enters DFU mode on the bus reset following DFU_DETACH
+        if self.state != State::AppDetach {
+            self.reset();
+        }
+    }
+
+    fn configuration_changed(&mut self, _usb: &D, _configuration: u8) {}
+}
+
+// - Dfu ----------------------------------------------------------------------
+
+/// The DFU mode interface of a device.
+///
+/// Class requests received by [`Control`](crate::control::Control) are
+/// passed to [`Dfu::handle_setup_packet()`] along with any data received
+/// for DFU_DNLOAD requests. `TRANSFER_SIZE` is the `wTransferSize` of the
+/// functional descriptor and must not be larger than the receive buffer
+/// of the [`Control`](crate::control::Control) endpoint.
+///
+/// Blocks are written to the [`DfuStorage`] as they are received, so
+/// the device never reports the `dfuDNBUSY` state.
+pub struct Dfu<S, const TRANSFER_SIZE: usize> {
+    interface_number: u8,
+    attributes: u8,
+    storage: S,
+
+    state: State,
+    status: Status,
+    offset: usize,
+    buffer: [u8; TRANSFER_SIZE],
+}
+
+impl<S, const TRANSFER_SIZE: usize> Dfu<S, TRANSFER_SIZE>
+where
+    S: DfuStorage,
+{
+    /// Creates a new DFU mode interface with the given functional
+    /// descriptor [`attributes`](self::attributes).
+    #[must_use]
+    pub const fn new(interface_number: u8, attributes: u8, storage: S) -> Self {
+        Self {
+            interface_number,
+            attributes,
+            storage,
+            state: State::DfuIdle,
+            status: Status::Ok,
+            offset: 0,
+            buffer: [0; TRANSFER_SIZE],
+        }
+    }
+
+    /// Returns the functional descriptor for this interface.
+    #[must_use]
+    pub const fn functional_descriptor(&self, detach_timeout: u16) -> FunctionalDescriptor {
+        FunctionalDescriptor::new(self.attributes, detach_timeout, TRANSFER_SIZE)
+    }
+
+    /// Returns the current state.
+    #[must_use]
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the current status.
+    #[must_use]
+    pub fn status(&self) -> Status {
+        self.status
+    }
+
+    /// Returns a reference to the storage backend.
+    #[must_use]
+    pub fn storage(&self) -> &S {
+        &self.storage
+    }
+
+    /// Returns a mutable reference to the storage backend.
+    pub fn storage_mut(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    /// Returns the interface to the `dfuIDLE` state.
+    pub fn reset(&mut self) {
+        self.state = State::DfuIdle;
+        self.status = Status::Ok;
+        self.offset = 0;
+    }
+
+    /// Handles a class request passed on by the control endpoint.
+    ///
+    /// `data` holds any data received from the host during the data
+    /// stage of the request. Requests that are not addressed to this
+    /// interface are ignored.
+    pub fn handle_setup_packet<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &[u8],
+    ) -> Option<DfuEvent>
+    where
+        D: UsbDriver,
+    {
+        if !is_interface_request(&setup_packet, self.interface_number) {
+            return None;
+        }
+
+        let request = ClassRequest::from(setup_packet.request);
+        let requested_length = usize::from(setup_packet.length);
+        trace!("Dfu {:?} in state {:?}", request, self.state);
+
+        match (setup_packet.direction(), request, self.state) {
+            // - download --
+            (
+                Direction::HostToDevice,
+                ClassRequest::Download,
+                State::DfuIdle | State::DownloadIdle,
+            ) if self.attributes & attributes::CAN_DOWNLOAD != 0 => {
+                self.download(usb, control_endpoint, requested_length, data)
+            }
+
+            // - upload --
+            (Direction::DeviceToHost, ClassRequest::Upload, State::DfuIdle | State::UploadIdle)
+                if self.attributes & attributes::CAN_UPLOAD != 0 =>
+            {
+                self.upload(usb, control_endpoint, requested_length)
+            }
+
+            // - status --
+            (Direction::DeviceToHost, ClassRequest::GetStatus, _) => {
+                let event = self.get_status();
+                write_status(
+                    usb,
+                    control_endpoint,
+                    requested_length,
+                    self.status,
+                    self.state,
+                );
+                if self.state == State::Manifest {
+                    // a device which is not manifestation tolerant
+                    // waits here for the host to reset it
+                    self.state = State::ManifestWaitReset;
+                }
+                event
+            }
+            (Direction::HostToDevice, ClassRequest::ClearStatus, State::Error) => {
+                self.reset();
+                usb.write(control_endpoint, [].into_iter());
+                None
+            }
+            (Direction::DeviceToHost, ClassRequest::GetState, _) => {
+                usb.write_requested(control_endpoint, 1, [self.state as u8].into_iter());
+                None
+            }
+            (
+                Direction::HostToDevice,
+                ClassRequest::Abort,
+                State::DfuIdle
+                | State::DownloadSync
+                | State::DownloadIdle
+                | State::ManifestSync
+                | State::UploadIdle,
+            ) => {
+                debug!("Dfu abort");
+                self.reset();
+                usb.write(control_endpoint, [].into_iter());
+                Some(DfuEvent::Aborted)
+            }
+
+            // - invalid requests --
+            (direction, request, state) => {
+                warn!(
+                    "Dfu stall - {:?} is not valid in state {:?}",
+                    request, state
+                );
+                self.state = State::Error;
+                self.status = Status::StalledPacket;
+                match direction {
+                    Direction::DeviceToHost => usb.stall_endpoint_in(control_endpoint),
+                    // data requests have already been acknowledged by the
+                    // control endpoint so the error is reported by DFU_GETSTATUS
+                    Direction::HostToDevice if requested_length > 0 => (),
+                    Direction::HostToDevice => usb.stall_endpoint_out(control_endpoint),
+                }
+                None
+            }
+        }
+    }
+
+    fn download<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        requested_length: usize,
+        data: &[u8],
+    ) -> Option<DfuEvent>
+    where
+        D: UsbDriver,
+    {
+        // a zero length download ends the transfer
+        if requested_length == 0 {
+            usb.write(control_endpoint, [].into_iter());
+            if self.state == State::DfuIdle {
+                return self.fail(Status::NotDone);
+            }
+            self.state = State::ManifestSync;
+            return None;
+        }
+
+        // the data stage has already been acknowledged by the control endpoint
+        if data.len() > TRANSFER_SIZE {
+            return self.fail(Status::Address);
+        }
+
+        let mut event = None;
+        if self.state == State::DfuIdle {
+            self.offset = 0;
+            if let Err(status) = self.storage.begin_download() {
+                return self.fail(status);
+            }
+            event = Some(DfuEvent::DownloadStarted);
+        }
+        if let Err(status) = self.storage.write(self.offset, data) {
+            return self.fail(status);
+        }
+        self.offset += data.len();
+        self.state = State::DownloadSync;
+        event
+    }
+
+    fn upload<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        requested_length: usize,
+    ) -> Option<DfuEvent>
+    where
+        D: UsbDriver,
+    {
+        if self.state == State::DfuIdle {
+            self.offset = 0;
+        }
+
+        let length = requested_length.min(TRANSFER_SIZE);
+        let bytes_read = match self.storage.read(self.offset, &mut self.buffer[..length]) {
+            Ok(bytes_read) => bytes_read.min(length),
+            Err(status) => {
+                usb.stall_endpoint_in(control_endpoint);
+                return self.fail(status);
+            }
+        };
+        self.offset += bytes_read;
+
+        // a short block ends the upload
+        self.state = if bytes_read < requested_length {
+            State::DfuIdle
+        } else {
+            State::UploadIdle
+        };
+        usb.write_requested(
+            control_endpoint,
+            requested_length,
+            self.buffer[..bytes_read].iter().copied(),
+        );
+
+        None
+    }
+
+    /// Advances the state machine on DFU_GETSTATUS.
+    fn get_status(&mut self) -> Option<DfuEvent> {
+        match self.state {
+            State::DownloadSync => {
+                self.state = State::DownloadIdle;
+                None
+            }
+            State::ManifestSync => {
+                if let Err(status) = self.storage.manifest() {
+                    return self.fail(status);
+                }
+                debug!("Dfu manifested {} bytes", self.offset);
+                self.offset = 0;
+                self.state = if self.attributes & attributes::MANIFESTATION_TOLERANT != 0 {
+                    State::DfuIdle
+                } else {
+                    State::Manifest
+                };
+                Some(DfuEvent::Manifested)
+            }
+            _ => None,
+        }
+    }
+
+    fn fail(&mut self, status: Status) -> Option<DfuEvent> {
+        warn!("Dfu error: {:?}", status);
+        self.state = State::Error;
+        self.status = status;
+        Some(DfuEvent::Error(status))
+    }
+}
+
+impl<D, S, const TRANSFER_SIZE: usize> UsbClass<D> for Dfu<S, TRANSFER_SIZE>
+where
+    D: UsbDriver,
+    S: DfuStorage,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        interface_number == self.interface_number
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &'d [u8],
+    ) -> ControlResult<'d> {
+        if !is_interface_request(&setup_packet, self.interface_number) {
+            return ControlResult::Unhandled;
+        }
+        self.handle_setup_packet(usb, control_endpoint, setup_packet, data).into()
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn configuration_changed(&mut self, _usb: &D, _configuration: u8) {}
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::descriptor::{
+        ConfigurationDescriptor, ConfigurationDescriptorHeader, DescriptorType,
+        InterfaceDescriptor, InterfaceDescriptorHeader,
+    };
+    use crate::testing::{class_request, HostError, Operation, VirtualHost};
+
+    // - fixtures -------------------------------------------------------------
+
+    const TRANSFER_SIZE: usize = 128;
+
+    const FUNCTIONAL_DESCRIPTOR: [u8; FunctionalDescriptor::LENGTH] = FunctionalDescriptor::new(
+        attributes::CAN_DOWNLOAD | attributes::CAN_UPLOAD | attributes::MANIFESTATION_TOLERANT,
+        1000,
+        TRANSFER_SIZE,
+    )
+    .to_bytes();
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[InterfaceDescriptor::new_class(
+            InterfaceDescriptorHeader {
+                iInterfaceNumber: 0,
+                bInterfaceClass: INTERFACE_CLASS,
+                bInterfaceSubClass: INTERFACE_SUBCLASS,
+                bInterfaceProtocol: protocol::DFU_MODE,
+                ..InterfaceDescriptorHeader::new()
+            },
+            &FUNCTIONAL_DESCRIPTOR,
+            &[],
+        )],
+    )];
+
+    #[derive(Default)]
+    struct RamImage {
+        data: Vec<u8>,
+        capacity: usize,
+        erased: bool,
+        manifested: bool,
+    }
+
+    impl DfuStorage for RamImage {
+        fn begin_download(&mut self) -> Result<(), Status> {
+            self.data.clear();
+            self.erased = true;
+            Ok(())
+        }
+
+        fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Status> {
+            if offset + data.len() > self.capacity {
+                return Err(Status::Address);
+            }
+            self.data.truncate(offset);
+            self.data.extend_from_slice(data);
+            Ok(())
+        }
+
+        fn read(&mut self, offset: usize, buffer: &mut [u8]) -> Result<usize, Status> {
+            let data = self.data.get(offset..).unwrap_or(&[]);
+            let length = data.len().min(buffer.len());
+            buffer[..length].copy_from_slice(&data[..length]);
+            Ok(length)
+        }
+
+        fn manifest(&mut self) -> Result<(), Status> {
+            self.manifested = true;
+            Ok(())
+        }
+    }
+
+    fn dfu(attributes: u8, capacity: usize) -> Dfu<RamImage, TRANSFER_SIZE> {
+        Dfu::new(
+            0,
+            attributes,
+            RamImage {
+                capacity,
+                ..RamImage::default()
+            },
+        )
+    }
+
+    fn request(
+        direction: Direction,
+        request: ClassRequest,
+        value: u16,
+        length: u16,
+    ) -> SetupPacket {
+        let request = match request {
+            ClassRequest::Detach => 0,
+            ClassRequest::Download => 1,
+            ClassRequest::Upload => 2,
+            ClassRequest::GetStatus => 3,
+            ClassRequest::ClearStatus => 4,
+            ClassRequest::GetState => 5,
+            ClassRequest::Abort => 6,
+            ClassRequest::Unknown(request) => request,
+        };
+        class_request(direction, Recipient::Interface, request, value, 0, length)
+    }
+
+    /// Issues a request with an OUT data stage, returning any event.
+    fn control_out(
+        host: &mut VirtualHost<'static, 256>,
+        dfu: &mut Dfu<RamImage, TRANSFER_SIZE>,
+        setup_packet: SetupPacket,
+        data: &[u8],
+    ) -> Result<Option<DfuEvent>, HostError> {
+        let mut event = None;
+        host.control_out_with(setup_packet, data, |usb, setup_packet, data| {
+            event = dfu.handle_setup_packet(usb, 0, setup_packet, data);
+        })?;
+        Ok(event)
+    }
+
+    /// Issues a request with an IN data stage, returning the response and any event.
+    fn control_in(
+        host: &mut VirtualHost<'static, 256>,
+        dfu: &mut Dfu<RamImage, TRANSFER_SIZE>,
+        setup_packet: SetupPacket,
+    ) -> Result<(Vec<u8>, Option<DfuEvent>), HostError> {
+        let mut event = None;
+        let data = host.control_in_with(setup_packet, |usb, setup_packet, data| {
+            event = dfu.handle_setup_packet(usb, 0, setup_packet, data);
+        })?;
+        Ok((data, event))
+    }
+
+    fn get_status(
+        host: &mut VirtualHost<'static, 256>,
+        dfu: &mut Dfu<RamImage, TRANSFER_SIZE>,
+    ) -> (Status, State, Option<DfuEvent>) {
+        let (data, event) = control_in(
+            host,
+            dfu,
+            request(Direction::DeviceToHost, ClassRequest::GetStatus, 0, 6),
+        )
+        .unwrap();
+        assert_eq!(data.len(), 6);
+        let status = match data[0] {
+            0x00 => Status::Ok,
+            0x03 => Status::Write,
+            0x08 => Status::Address,
+            0x09 => Status::NotDone,
+            0x0f => Status::StalledPacket,
+            status => panic!("unexpected status 0x{status:02x}"),
+        };
+        let state = match data[4] {
+            2 => State::DfuIdle,
+            5 => State::DownloadIdle,
+            7 => State::Manifest,
+            10 => State::Error,
+            state => panic!("unexpected state {state}"),
+        };
+        (status, state, event)
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_functional_descriptor() {
+        assert_eq!(
+            FUNCTIONAL_DESCRIPTOR,
+            [0x09, 0x21, 0x07, 0xe8, 0x03, 0x80, 0x00, 0x10, 0x01]
+        );
+
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let configuration = host
+            .get_descriptor(DescriptorType::Configuration, 0, 0, 255)
+            .expect("configuration descriptor");
+        assert_eq!(&configuration[18..27], FUNCTIONAL_DESCRIPTOR);
+    }
+
+    #[test]
+    fn test_runtime_detach() {
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut runtime = DfuRuntime::new(0);
+
+        let data = host
+            .control_in_with(
+                request(Direction::DeviceToHost, ClassRequest::GetStatus, 0, 6),
+                |usb, setup_packet, _data| {
+                    runtime.handle_setup_packet(usb, 0, setup_packet);
+                },
+            )
+            .unwrap();
+        assert_eq!(data, [0, 0, 0, 0, State::AppIdle as u8, 0]);
+
+        let mut event = None;
+        host.control_out_with(
+            request(Direction::HostToDevice, ClassRequest::Detach, 500, 0),
+            &[],
+            |usb, setup_packet, _data| {
+                event = runtime.handle_setup_packet(usb, 0, setup_packet);
+            },
+        )
+        .unwrap();
+        assert_eq!(event, Some(DfuEvent::Detach { timeout: 500 }));
+        assert_eq!(runtime.state(), State::AppDetach);
+
+        // download requests are not valid at runtime
+        let result = host.control_out_with(
+            request(Direction::HostToDevice, ClassRequest::Download, 0, 0),
+            &[],
+            |usb, setup_packet, _data| {
+                runtime.handle_setup_packet(usb, 0, setup_packet);
+            },
+        );
+        assert!(matches!(result, Err(HostError::Stalled(_))));
+    }
+
+    #[test]
+    fn test_download() {
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut dfu = dfu(
+            attributes::CAN_DOWNLOAD | attributes::MANIFESTATION_TOLERANT,
+            1024,
+        );
+        let image: Vec<u8> = (0..300).map(|index| (index % 256) as u8).collect();
+
+        for (block_number, block) in image.chunks(TRANSFER_SIZE).enumerate() {
+            let event = control_out(
+                &mut host,
+                &mut dfu,
+                request(
+                    Direction::HostToDevice,
+                    ClassRequest::Download,
+                    block_number as u16,
+                    block.len() as u16,
+                ),
+                block,
+            )
+            .unwrap();
+            assert_eq!(event.is_some(), block_number == 0);
+            assert_eq!(dfu.state(), State::DownloadSync);
+            assert_eq!(
+                get_status(&mut host, &mut dfu),
+                (Status::Ok, State::DownloadIdle, None)
+            );
+        }
+        assert_eq!(dfu.storage().data, image);
+        assert!(dfu.storage().erased);
+
+        // a zero length download starts manifestation
+        control_out(
+            &mut host,
+            &mut dfu,
+            request(Direction::HostToDevice, ClassRequest::Download, 3, 0),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(dfu.state(), State::ManifestSync);
+        assert_eq!(
+            get_status(&mut host, &mut dfu),
+            (Status::Ok, State::DfuIdle, Some(DfuEvent::Manifested))
+        );
+        assert!(dfu.storage().manifested);
+    }
+
+    #[test]
+    fn test_manifest_wait_reset() {
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut dfu = dfu(attributes::CAN_DOWNLOAD, 1024);
+
+        control_out(
+            &mut host,
+            &mut dfu,
+            request(Direction::HostToDevice, ClassRequest::Download, 0, 16),
+            &[0xaa; 16],
+        )
+        .unwrap();
+        get_status(&mut host, &mut dfu);
+        control_out(
+            &mut host,
+            &mut dfu,
+            request(Direction::HostToDevice, ClassRequest::Download, 1, 0),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            get_status(&mut host, &mut dfu),
+            (Status::Ok, State::Manifest, Some(DfuEvent::Manifested))
+        );
+        assert_eq!(dfu.state(), State::ManifestWaitReset);
+    }
+
+    #[test]
+    fn test_upload() {
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut dfu = dfu(attributes::CAN_UPLOAD, 1024);
+        let image: Vec<u8> = (0..300).map(|index| (index % 256) as u8).collect();
+        dfu.storage_mut().data = image.clone();
+
+        let mut uploaded = Vec::new();
+        for block_number in 0.. {
+            let (data, _) = control_in(
+                &mut host,
+                &mut dfu,
+                request(
+                    Direction::DeviceToHost,
+                    ClassRequest::Upload,
+                    block_number,
+                    TRANSFER_SIZE as u16,
+                ),
+            )
+            .unwrap();
+            uploaded.extend_from_slice(&data);
+            if data.len() < TRANSFER_SIZE {
+                break;
+            }
+            assert_eq!(dfu.state(), State::UploadIdle);
+        }
+        assert_eq!(uploaded, image);
+        assert_eq!(dfu.state(), State::DfuIdle);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut host: VirtualHost<256> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut dfu = dfu(attributes::CAN_DOWNLOAD, 64);
+
+        // write past the end of storage
+        let event = control_out(
+            &mut host,
+            &mut dfu,
+            request(Direction::HostToDevice, ClassRequest::Download, 0, 128),
+            &[0; 128],
+        )
+        .unwrap();
+        assert_eq!(event, Some(DfuEvent::Error(Status::Address)));
+        assert_eq!(
+            get_status(&mut host, &mut dfu),
+            (Status::Address, State::Error, None)
+        );
+
+        // the error is cleared with DFU_CLRSTATUS
+        control_out(
+            &mut host,
+            &mut dfu,
+            request(Direction::HostToDevice, ClassRequest::ClearStatus, 0, 0),
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            get_status(&mut host, &mut dfu),
+            (Status::Ok, State::DfuIdle, None)
+        );
+
+        // uploads are not supported and stall
+        host.usb.take_operations();
+        let result = control_in(
+            &mut host,
+            &mut dfu,
+            request(Direction::DeviceToHost, ClassRequest::Upload, 0, 64),
+        );
+        assert!(matches!(result, Err(HostError::Stalled(_))));
+        assert!(host.usb.operations().contains(&Operation::StallIn(0)));
+        let (data, _) = control_in(
+            &mut host,
+            &mut dfu,
+            request(Direction::DeviceToHost, ClassRequest::GetState, 0, 1),
+        )
+        .unwrap();
+        assert_eq!(data, [State::Error as u8]);
+        assert_eq!(dfu.status(), Status::StalledPacket);
+
+        // DFU_ABORT is not valid in the error state
+        let result = control_out(
+            &mut host,
+            &mut dfu,
+            request(Direction::HostToDevice, ClassRequest::Abort, 0, 0),
+            &[],
+        );
+        assert!(matches!(result, Err(HostError::Stalled(_))));
+    }
+}
@@ -0,0 +1,1170 @@
+//! USB Human Interface Device Class (HID)
+//!
+//! Provides the HID class descriptor, a report descriptor builder and
+//! a [`Hid`] interface which handles the HID class requests and sends
+//! input reports on the interrupt IN endpoint.
+//!
+//! For example, the boot keyboard report descriptor from the HID
+//! specification can be built with:
+//!
+//!     use smolusb::class::hid::{usage, usage_page, Collection, ReportDescriptor};
+//!
+//!     static REPORT_DESCRIPTOR: ReportDescriptor<64> = ReportDescriptor::new()
+//!         .usage_page(usage_page::GENERIC_DESKTOP)
+//!         .usage(usage::KEYBOARD)
+//!         .collection(Collection::Application)
+//!         // ...
+//!         .end_collection();
+//!
+//!     assert_eq!(REPORT_DESCRIPTOR.as_bytes(), &[0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0xc0]);
+
+#![allow(non_snake_case)]
+
+use log::{trace, warn};
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+use crate::class::{ControlResult, UsbClass};
+use crate::event::UsbEvent;
+use crate::setup::{Direction, Recipient, Request, RequestType, SetupPacket};
+use crate::traits::UsbDriver;
+
+/// HID interface class code
+pub const INTERFACE_CLASS: u8 = 0x03;
+
+/// HID interface subclass codes
+pub mod subclass {
+    pub const NONE: u8 = 0x00;
+    pub const BOOT: u8 = 0x01;
+}
+
+/// HID interface protocol codes for boot interfaces
+pub mod protocol {
+    pub const NONE: u8 = 0x00;
+    pub const KEYBOARD: u8 = 0x01;
+    pub const MOUSE: u8 = 0x02;
+}
+
+// - DescriptorType -----------------------------------------------------------
+
+/// HID class descriptor types.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum DescriptorType {
+    Hid = 0x21,
+    Report = 0x22,
+    Physical = 0x23,
+    Unknown = 0xff,
+}
+
+impl From<u8> for DescriptorType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x21 => DescriptorType::Hid,
+            0x22 => DescriptorType::Report,
+            0x23 => DescriptorType::Physical,
+            _ => DescriptorType::Unknown,
+        }
+    }
+}
+
+// - HidDescriptor ------------------------------------------------------------
+
+/// HID class descriptor
+///
+/// Follows the interface descriptor in the configuration descriptor,
+/// see: [`InterfaceDescriptor::new_class()`](crate::descriptor::InterfaceDescriptor::new_class)
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct HidDescriptor {
+    pub bLength: u8,         // 9
+    pub bDescriptorType: u8, // 0x21 = HID
+    pub bcdHID: u16,
+    pub bCountryCode: u8,
+    pub bNumDescriptors: u8,
+    pub bReportDescriptorType: u8, // 0x22 = Report
+    pub wDescriptorLength: u16,
+}
+
+impl HidDescriptor {
+    /// Size of the HID descriptor in bytes.
+    pub const LENGTH: usize = core::mem::size_of::<Self>();
+
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(report_descriptor_length: usize) -> Self {
+        Self {
+            bLength: Self::LENGTH as u8,
+            bDescriptorType: DescriptorType::Hid as u8,
+            bcdHID: 0x0111, // v1.11
+            bCountryCode: 0,
+            bNumDescriptors: 1,
+            bReportDescriptorType: DescriptorType::Report as u8,
+            wDescriptorLength: report_descriptor_length as u16,
+        }
+    }
+
+    /// Returns the descriptor as bytes for use in a `const` context.
+    #[must_use]
+    pub const fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let [v0, v1] = self.bcdHID.to_le_bytes();
+        let [l0, l1] = self.wDescriptorLength.to_le_bytes();
+        [
+            self.bLength,
+            self.bDescriptorType,
+            v0,
+            v1,
+            self.bCountryCode,
+            self.bNumDescriptors,
+            self.bReportDescriptorType,
+            l0,
+            l1,
+        ]
+    }
+}
+
+// - ReportDescriptor ---------------------------------------------------------
+
+/// Usage pages
+pub mod usage_page {
+    pub const GENERIC_DESKTOP: u16 = 0x01;
+    pub const KEYBOARD: u16 = 0x07;
+    pub const LEDS: u16 = 0x08;
+    pub const BUTTON: u16 = 0x09;
+    pub const CONSUMER: u16 = 0x0c;
+    pub const VENDOR_DEFINED: u16 = 0xff00;
+}
+
+/// Generic Desktop page usages
+pub mod usage {
+    pub const POINTER: u16 = 0x01;
+    pub const MOUSE: u16 = 0x02;
+    pub const JOYSTICK: u16 = 0x04;
+    pub const GAMEPAD: u16 = 0x05;
+    pub const KEYBOARD: u16 = 0x06;
+    pub const X: u16 = 0x30;
+    pub const Y: u16 = 0x31;
+    pub const Z: u16 = 0x32;
+    pub const WHEEL: u16 = 0x38;
+}
+
+/// Input, Output and Feature item flags
+pub mod flags {
+    pub const DATA: u16 = 0;
+    pub const CONSTANT: u16 = 1 << 0;
+    pub const ARRAY: u16 = 0;
+    pub const VARIABLE: u16 = 1 << 1;
+    pub const ABSOLUTE: u16 = 0;
+    pub const RELATIVE: u16 = 1 << 2;
+    pub const WRAP: u16 = 1 << 3;
+    pub const NON_LINEAR: u16 = 1 << 4;
+    pub const NO_PREFERRED: u16 = 1 << 5;
+    pub const NULL_STATE: u16 = 1 << 6;
+    pub const VOLATILE: u16 = 1 << 7;
+    pub const BUFFERED_BYTES: u16 = 1 << 8;
+}
+
+/// Collection item types
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum Collection {
+    Physical = 0x00,
+    Application = 0x01,
+    Logical = 0x02,
+    Report = 0x03,
+    NamedArray = 0x04,
+    UsageSwitch = 0x05,
+    UsageModifier = 0x06,
+}
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum ItemType {
+    Main = 0,
+    Global = 1,
+    Local = 2,
+}
+
+/// Builder for HID report descriptors
+///
+/// All methods are `const` so report descriptors can be declared
+/// as statics. `N` is the capacity of the descriptor in bytes and
+/// building a descriptor that exceeds it will fail to compile.
+#[derive(Clone, Copy)]
+pub struct ReportDescriptor<const N: usize> {
+    bytes: [u8; N],
+    length: usize,
+}
+
+impl<const N: usize> ReportDescriptor<N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            bytes: [0; N],
+            length: 0,
+        }
+    }
+
+    /// Returns the length of the report descriptor in bytes.
+    #[must_use]
+    pub const fn len(&self) -> usize {
+        self.length
+    }
+
+    #[must_use]
+    pub const fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    #[must_use]
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.length]
+    }
+
+    // - main items --
+
+    #[must_use]
+    pub const fn input(self, flags: u16) -> Self {
+        self.unsigned_item(ItemType::Main, 0x8, flags as u32)
+    }
+
+    #[must_use]
+    pub const fn output(self, flags: u16) -> Self {
+        self.unsigned_item(ItemType::Main, 0x9, flags as u32)
+    }
+
+    #[must_use]
+    pub const fn feature(self, flags: u16) -> Self {
+        self.unsigned_item(ItemType::Main, 0xb, flags as u32)
+    }
+
+    #[must_use]
+    pub const fn collection(self, collection: Collection) -> Self {
+        self.unsigned_item(ItemType::Main, 0xa, collection as u32)
+    }
+
+    #[must_use]
+    pub const fn end_collection(self) -> Self {
+        self.push(&[0xc0], 1)
+    }
+
+    // - global items --
+
+    #[must_use]
+    pub const fn usage_page(self, usage_page: u16) -> Self {
+        self.unsigned_item(ItemType::Global, 0x0, usage_page as u32)
+    }
+
+    #[must_use]
+    pub const fn logical_minimum(self, value: i32) -> Self {
+        self.signed_item(ItemType::Global, 0x1, value)
+    }
+
+    #[must_use]
+    pub const fn logical_maximum(self, value: i32) -> Self {
+        self.signed_item(ItemType::Global, 0x2, value)
+    }
+
+    #[must_use]
+    pub const fn physical_minimum(self, value: i32) -> Self {
+        self.signed_item(ItemType::Global, 0x3, value)
+    }
+
+    #[must_use]
+    pub const fn physical_maximum(self, value: i32) -> Self {
+        self.signed_item(ItemType::Global, 0x4, value)
+    }
+
+    #[must_use]
+    pub const fn report_size(self, bits: u8) -> Self {
+        self.unsigned_item(ItemType::Global, 0x7, bits as u32)
+    }
+
+    #[must_use]
+    pub const fn report_id(self, report_id: u8) -> Self {
+        self.unsigned_item(ItemType::Global, 0x8, report_id as u32)
+    }
+
+    #[must_use]
+    pub const fn report_count(self, count: u8) -> Self {
+        self.unsigned_item(ItemType::Global, 0x9, count as u32)
+    }
+
+    // - local items --
+
+    #[must_use]
+    pub const fn usage(self, usage: u16) -> Self {
+        self.unsigned_item(ItemType::Local, 0x0, usage as u32)
+    }
+
+    #[must_use]
+    pub const fn usage_minimum(self, usage: u16) -> Self {
+        self.unsigned_item(ItemType::Local, 0x1, usage as u32)
+    }
+
+    #[must_use]
+    pub const fn usage_maximum(self, usage: u16) -> Self {
+        self.unsigned_item(ItemType::Local, 0x2, usage as u32)
+    }
+
+    // - helpers --
+
+    /// Appends a short item with the smallest encoding that fits an unsigned value.
+    const fn unsigned_item(self, item_type: ItemType, tag: u8, value: u32) -> Self {
+        let size = if value <= 0xff {
+            1
+        } else if value <= 0xffff {
+            2
+        } else {
+            4
+        };
+        self.short_item(item_type, tag, value.to_le_bytes(), size)
+    }
+
+    /// Appends a short item with the smallest encoding that fits a signed value.
+    const fn signed_item(self, item_type: ItemType, tag: u8, value: i32) -> Self {
+        let size = if value >= i8::MIN as i32 && value <= i8::MAX as i32 {
+            1
+        } else if value >= i16::MIN as i32 && value <= i16::MAX as i32 {
+            2
+        } else {
+            4
+        };
+        self.short_item(item_type, tag, value.to_le_bytes(), size)
+    }
+
+    const fn short_item(self, item_type: ItemType, tag: u8, data: [u8; 4], size: usize) -> Self {
+        let size_code = match size {
+            1 => 0b01,
+            2 => 0b10,
+            _ => 0b11,
+        };
+        let prefix = (tag << 4) | ((item_type as u8) << 2) | size_code;
+        self.push(&[prefix, data[0], data[1], data[2], data[3]], 1 + size)
+    }
+
+    const fn push(mut self, bytes: &[u8], count: usize) -> Self {
+        assert!(
+            self.length + count <= N,
+            "ReportDescriptor capacity exceeded"
+        );
+        let mut index = 0;
+        while index < count {
+            self.bytes[self.length] = bytes[index];
+            self.length += 1;
+            index += 1;
+        }
+        self
+    }
+}
+
+impl<const N: usize> Default for ReportDescriptor<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Boot protocol keyboard report descriptor
+///
+/// See: HID 1.11 Appendix B.1
+pub static BOOT_KEYBOARD_REPORT_DESCRIPTOR: ReportDescriptor<63> = ReportDescriptor::new()
+    .usage_page(usage_page::GENERIC_DESKTOP)
+    .usage(usage::KEYBOARD)
+    .collection(Collection::Application)
+    // modifier keys
+    .usage_page(usage_page::KEYBOARD)
+    .usage_minimum(0xe0)
+    .usage_maximum(0xe7)
+    .logical_minimum(0)
+    .logical_maximum(1)
+    .report_size(1)
+    .report_count(8)
+    .input(flags::DATA | flags::VARIABLE | flags::ABSOLUTE)
+    // reserved
+    .report_count(1)
+    .report_size(8)
+    .input(flags::CONSTANT)
+    // led output report
+    .report_count(5)
+    .report_size(1)
+    .usage_page(usage_page::LEDS)
+    .usage_minimum(1)
+    .usage_maximum(5)
+    .output(flags::DATA | flags::VARIABLE | flags::ABSOLUTE)
+    // led padding
+    .report_count(1)
+    .report_size(3)
+    .output(flags::CONSTANT)
+    // keycodes
+    .report_count(6)
+    .report_size(8)
+    .logical_minimum(0)
+    .logical_maximum(101)
+    .usage_page(usage_page::KEYBOARD)
+    .usage_minimum(0)
+    .usage_maximum(101)
+    .input(flags::DATA | flags::ARRAY)
+    .end_collection();
+
+/// Boot protocol mouse report descriptor
+///
+/// See: HID 1.11 Appendix B.2
+pub static BOOT_MOUSE_REPORT_DESCRIPTOR: ReportDescriptor<50> = ReportDescriptor::new()
+    .usage_page(usage_page::GENERIC_DESKTOP)
+    .usage(usage::MOUSE)
+    .collection(Collection::Application)
+    .usage(usage::POINTER)
+    .collection(Collection::Physical)
+    // buttons
+    .usage_page(usage_page::BUTTON)
+    .usage_minimum(1)
+    .usage_maximum(3)
+    .logical_minimum(0)
+    .logical_maximum(1)
+    .report_count(3)
+    .report_size(1)
+    .input(flags::DATA | flags::VARIABLE | flags::ABSOLUTE)
+    // padding
+    .report_count(1)
+    .report_size(5)
+    .input(flags::CONSTANT)
+    // x, y
+    .usage_page(usage_page::GENERIC_DESKTOP)
+    .usage(usage::X)
+    .usage(usage::Y)
+    .logical_minimum(-127)
+    .logical_maximum(127)
+    .report_size(8)
+    .report_count(2)
+    .input(flags::DATA | flags::VARIABLE | flags::RELATIVE)
+    .end_collection()
+    .end_collection();
+
+// - Boot reports -------------------------------------------------------------
+
+/// Boot protocol keyboard input report
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootKeyboardReport {
+    pub modifiers: u8,
+    pub reserved: u8,
+    pub keycodes: [u8; 6],
+}
+
+/// Boot protocol mouse input report
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct BootMouseReport {
+    pub buttons: u8,
+    pub x: i8,
+    pub y: i8,
+}
+
+// - ClassRequest -------------------------------------------------------------
+
+/// HID Class Requests
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum ClassRequest {
+    GetReport = 0x01,
+    GetIdle = 0x02,
+    GetProtocol = 0x03,
+    SetReport = 0x09,
+    SetIdle = 0x0a,
+    SetProtocol = 0x0b,
+    Unknown(u8),
+}
+
+impl From<u8> for ClassRequest {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => ClassRequest::GetReport,
+            0x02 => ClassRequest::GetIdle,
+            0x03 => ClassRequest::GetProtocol,
+            0x09 => ClassRequest::SetReport,
+            0x0a => ClassRequest::SetIdle,
+            0x0b => ClassRequest::SetProtocol,
+            _ => ClassRequest::Unknown(value),
+        }
+    }
+}
+
+/// HID report types
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum ReportType {
+    Input = 0x01,
+    Output = 0x02,
+    Feature = 0x03,
+    Reserved(u8),
+}
+
+impl From<u8> for ReportType {
+    fn from(value: u8) -> Self {
+        match value {
+            0x01 => ReportType::Input,
+            0x02 => ReportType::Output,
+            0x03 => ReportType::Feature,
+            _ => ReportType::Reserved(value),
+        }
+    }
+}
+
+/// HID protocols
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum Protocol {
+    Boot = 0x00,
+    Report = 0x01,
+}
+
+// - HidEvent -----------------------------------------------------------------
+
+/// Events generated by [`Hid`] that need to be handled by the application.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum HidEvent<'d> {
+    /// The host requested an Output or Feature report.
+    ///
+    /// The application must respond with the report on the control
+    /// endpoint or stall the request.
+    GetReport {
+        report_type: ReportType,
+        report_id: u8,
+        length: u16,
+    },
+    /// The host sent a report. (e.g. the keyboard LED state)
+    SetReport {
+        report_type: ReportType,
+        report_id: u8,
+        data: &'d [u8],
+    },
+    /// The host changed the idle rate in units of 4 milliseconds.
+    IdleChanged(u8),
+    /// The host changed the active protocol.
+    ProtocolChanged(Protocol),
+}
+
+// - Hid ----------------------------------------------------------------------
+
+/// A HID interface with a single interrupt IN endpoint.
+///
+/// Requests received by [`Control`](crate::control::Control) are passed
+/// to [`Hid::handle_setup_packet()`] and endpoint events to
+/// [`Hid::handle_event()`]. Input reports of up to `REPORT_SIZE` bytes
+/// are sent with [`Hid::write_report()`].
+///
+/// The idle rate is honoured by calling [`Hid::tick()`] with the time
+/// elapsed since the last call.
+pub struct Hid<'a, const REPORT_SIZE: usize> {
+    interface_number: u8,
+    endpoint_in: u8,
+    report_descriptor: &'a [u8],
+    boot_protocol: bool,
+
+    protocol: Protocol,
+    idle_rate: u8,
+    idle_elapsed: u32,
+
+    report: [u8; REPORT_SIZE],
+    report_length: usize,
+    report_pending: bool,
+    busy: bool,
+}
+
+impl<'a, const REPORT_SIZE: usize> Hid<'a, REPORT_SIZE> {
+    #[must_use]
+    pub const fn new(interface_number: u8, endpoint_in: u8, report_descriptor: &'a [u8]) -> Self {
+        Self {
+            interface_number,
+            endpoint_in,
+            report_descriptor,
+            boot_protocol: false,
+            protocol: Protocol::Report,
+            idle_rate: 0,
+            idle_elapsed: 0,
+            report: [0; REPORT_SIZE],
+            report_length: 0,
+            report_pending: false,
+            busy: false,
+        }
+    }
+
+    /// Enables the GET_PROTOCOL and SET_PROTOCOL requests for
+    /// interfaces with the boot subclass.
+    #[must_use]
+    pub const fn with_boot_protocol(mut self) -> Self {
+        self.boot_protocol = true;
+        self
+    }
+
+    /// Returns the HID descriptor for this interface.
+    #[must_use]
+    pub const fn hid_descriptor(&self) -> HidDescriptor {
+        HidDescriptor::new(self.report_descriptor.len())
+    }
+
+    /// Returns the active protocol.
+    #[must_use]
+    pub fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    /// Returns the idle rate in units of 4 milliseconds, `0` means
+    /// reports are only sent when they change.
+    #[must_use]
+    pub fn idle_rate(&self) -> u8 {
+        self.idle_rate
+    }
+
+    /// Returns the interface to its default state.
+    pub fn reset(&mut self) {
+        self.protocol = Protocol::Report;
+        self.idle_rate = 0;
+        self.idle_elapsed = 0;
+        self.report_length = 0;
+        self.report_pending = false;
+        self.busy = false;
+    }
+
+    /// Handles a request passed on by the control endpoint.
+    ///
+    /// `data` holds any data received from the host during the data
+    /// stage of the request. Requests that are not addressed to this
+    /// interface are ignored.
+    pub fn handle_setup_packet<'d, D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &'d [u8],
+    ) -> Option<HidEvent<'d>>
+    where
+        D: UsbDriver,
+    {
+        if setup_packet.recipient() != Recipient::Interface
+            || (setup_packet.index & 0xff) as u8 != self.interface_number
+        {
+            return None;
+        }
+
+        let requested_length = usize::from(setup_packet.length);
+        let [_, value_hi] = setup_packet.value.to_le_bytes();
+
+        match (
+            setup_packet.direction(),
+            setup_packet.request_type(),
+            setup_packet.request(),
+        ) {
+            (Direction::DeviceToHost, RequestType::Standard, Request::GetDescriptor) => {
+                match DescriptorType::from(value_hi) {
+                    DescriptorType::Hid => {
+                        usb.write_requested(
+                            control_endpoint,
+                            requested_length,
+                            self.hid_descriptor()
+                                .to_bytes()
+                                .into_iter()
+                                .take(requested_length),
+                        );
+                    }
+                    DescriptorType::Report => {
+                        usb.write_requested(
+                            control_endpoint,
+                            requested_length,
+                            self.report_descriptor
+                                .iter()
+                                .copied()
+                                .take(requested_length),
+                        );
+                    }
+                    descriptor_type => {
+                        warn!("Hid stall - unknown descriptor {:?}", descriptor_type);
+                        usb.stall_endpoint_in(control_endpoint);
+                    }
+                }
+                None
+            }
+            // hid class request numbers overlap the standard requests
+            (direction, RequestType::Class, _) => self.handle_class_request(
+                usb,
+                control_endpoint,
+                setup_packet,
+                direction,
+                setup_packet.request,
+                data,
+            ),
+            _ => None,
+        }
+    }
+
+    fn handle_class_request<'d, D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        direction: Direction,
+        request: u8,
+        data: &'d [u8],
+    ) -> Option<HidEvent<'d>>
+    where
+        D: UsbDriver,
+    {
+        let requested_length = usize::from(setup_packet.length);
+        let [value_lo, value_hi] = setup_packet.value.to_le_bytes();
+
+        match (direction, ClassRequest::from(request)) {
+            (Direction::DeviceToHost, ClassRequest::GetReport) => {
+                let report_type = ReportType::from(value_hi);
+                if report_type == ReportType::Input {
+                    usb.write_requested(
+                        control_endpoint,
+                        requested_length,
+                        self.report[..self.report_length]
+                            .iter()
+                            .copied()
+                            .take(requested_length),
+                    );
+                    return None;
+                }
+                Some(HidEvent::GetReport {
+                    report_type,
+                    report_id: value_lo,
+                    length: setup_packet.length,
+                })
+            }
+            (Direction::HostToDevice, ClassRequest::SetReport) => {
+                // reports with data have already been acknowledged by the control endpoint
+                if setup_packet.length == 0 {
+                    usb.write(control_endpoint, [].into_iter());
+                }
+                Some(HidEvent::SetReport {
+                    report_type: ReportType::from(value_hi),
+                    report_id: value_lo,
+                    data,
+                })
+            }
+            (Direction::DeviceToHost, ClassRequest::GetIdle) => {
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    [self.idle_rate].into_iter(),
+                );
+                None
+            }
+            (Direction::HostToDevice, ClassRequest::SetIdle) => {
+                trace!("Hid SET_IDLE {} report_id:{}", value_hi, value_lo);
+                self.idle_rate = value_hi;
+                self.idle_elapsed = 0;
+                usb.write(control_endpoint, [].into_iter());
+                Some(HidEvent::IdleChanged(value_hi))
+            }
+            (Direction::DeviceToHost, ClassRequest::GetProtocol) if self.boot_protocol => {
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    [self.protocol as u8].into_iter(),
+                );
+                None
+            }
+            (Direction::HostToDevice, ClassRequest::SetProtocol) if self.boot_protocol => {
+                self.protocol = if value_lo == Protocol::Boot as u8 {
+                    Protocol::Boot
+                } else {
+                    Protocol::Report
+                };
+                usb.write(control_endpoint, [].into_iter());
+                Some(HidEvent::ProtocolChanged(self.protocol))
+            }
+            (direction, request) => {
+                warn!("Hid stall - unhandled class request {:?}", request);
+                match direction {
+                    Direction::DeviceToHost => usb.stall_endpoint_in(control_endpoint),
+                    Direction::HostToDevice => usb.stall_endpoint_out(control_endpoint),
+                }
+                None
+            }
+        }
+    }
+
+    /// Handles a USB event for the interrupt IN endpoint.
+    pub fn handle_event<D>(&mut self, usb: &D, event: UsbEvent)
+    where
+        D: UsbDriver,
+    {
+        match event {
+            UsbEvent::BusReset => self.reset(),
+            UsbEvent::SendComplete(endpoint_number) if endpoint_number == self.endpoint_in => {
+                self.busy = false;
+                if self.report_pending {
+                    self.send_report(usb);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Queues an input report for transmission to the host.
+    ///
+    /// Returns `false` if the report is unchanged and doesn't need to
+    /// be sent until the idle rate expires.
+    pub fn write_report<D>(&mut self, usb: &D, report: &[u8]) -> bool
+    where
+        D: UsbDriver,
+    {
+        let report = &report[..report.len().min(REPORT_SIZE)];
+        if report == &self.report[..self.report_length] {
+            return false;
+        }
+
+        self.report[..report.len()].copy_from_slice(report);
+        self.report_length = report.len();
+        if self.busy {
+            self.report_pending = true;
+        } else {
+            self.send_report(usb);
+        }
+        true
+    }
+
+    /// Advances the idle timer by `milliseconds` and resends the last
+    /// input report if the idle rate has expired.
+    pub fn tick<D>(&mut self, usb: &D, milliseconds: u32)
+    where
+        D: UsbDriver,
+    {
+        if self.idle_rate == 0 || self.report_length == 0 {
+            return;
+        }
+
+        self.idle_elapsed = self.idle_elapsed.saturating_add(milliseconds);
+        if self.idle_elapsed >= u32::from(self.idle_rate) * 4 {
+            if self.busy {
+                self.report_pending = true;
+            } else {
+                self.send_report(usb);
+            }
+        }
+    }
+
+    fn send_report<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        self.busy = true;
+        self.report_pending = false;
+        self.idle_elapsed = 0;
+        usb.write(
+            self.endpoint_in,
+            self.report[..self.report_length].iter().copied(),
+        );
+    }
+}
+
+impl<'a, D, const REPORT_SIZE: usize> UsbClass<D> for Hid<'a, REPORT_SIZE>
+where
+    D: UsbDriver,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        interface_number == self.interface_number
+    }
+
+    fn has_endpoint(&self, endpoint_address: u8) -> bool {
+        endpoint_address == self.endpoint_in | 0x80
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &'d [u8],
+    ) -> ControlResult<'d> {
+        let is_hid_request = match setup_packet.request_type() {
+            RequestType::Standard => setup_packet.request() == Request::GetDescriptor,
+            RequestType::Class => true,
+            _ => false,
+        };
+        if !is_hid_request
+            || setup_packet.recipient() != Recipient::Interface
+            || (setup_packet.index & 0xff) as u8 != self.interface_number
+        {
+            return ControlResult::Unhandled;
+        }
+        self.handle_setup_packet(usb, control_endpoint, setup_packet, data).into()
+    }
+
+    fn handle_endpoint_event(&mut self, usb: &D, event: UsbEvent) {
+        self.handle_event(usb, event);
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn configuration_changed(&mut self, _usb: &D, _configuration: u8) {
+        self.reset();
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::descriptor::{
+        ConfigurationDescriptor, ConfigurationDescriptorHeader, EndpointDescriptor,
+        InterfaceDescriptor, InterfaceDescriptorHeader,
+    };
+    use crate::testing::{class_request, standard_request, Operation, VirtualHost};
+
+    // - fixtures -------------------------------------------------------------
+
+    const BOOT_KEYBOARD_BYTES: [u8; 63] = [
+        0x05, 0x01, 0x09, 0x06, 0xa1, 0x01, 0x05, 0x07, 0x19, 0xe0, 0x29, 0xe7, 0x15, 0x00, 0x25,
+        0x01, 0x75, 0x01, 0x95, 0x08, 0x81, 0x02, 0x95, 0x01, 0x75, 0x08, 0x81, 0x01, 0x95, 0x05,
+        0x75, 0x01, 0x05, 0x08, 0x19, 0x01, 0x29, 0x05, 0x91, 0x02, 0x95, 0x01, 0x75, 0x03, 0x91,
+        0x01, 0x95, 0x06, 0x75, 0x08, 0x15, 0x00, 0x25, 0x65, 0x05, 0x07, 0x19, 0x00, 0x29, 0x65,
+        0x81, 0x00, 0xc0,
+    ];
+
+    const BOOT_MOUSE_BYTES: [u8; 50] = [
+        0x05, 0x01, 0x09, 0x02, 0xa1, 0x01, 0x09, 0x01, 0xa1, 0x00, 0x05, 0x09, 0x19, 0x01, 0x29,
+        0x03, 0x15, 0x00, 0x25, 0x01, 0x95, 0x03, 0x75, 0x01, 0x81, 0x02, 0x95, 0x01, 0x75, 0x05,
+        0x81, 0x01, 0x05, 0x01, 0x09, 0x30, 0x09, 0x31, 0x15, 0x81, 0x25, 0x7f, 0x75, 0x08, 0x95,
+        0x02, 0x81, 0x06, 0xc0, 0xc0,
+    ];
+
+    const HID_DESCRIPTOR: [u8; HidDescriptor::LENGTH] =
+        HidDescriptor::new(BOOT_KEYBOARD_REPORT_DESCRIPTOR.len()).to_bytes();
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[InterfaceDescriptor::new_class(
+            InterfaceDescriptorHeader {
+                iInterfaceNumber: 0,
+                bInterfaceClass: INTERFACE_CLASS,
+                bInterfaceSubClass: subclass::BOOT,
+                bInterfaceProtocol: protocol::KEYBOARD,
+                ..InterfaceDescriptorHeader::new()
+            },
+            &HID_DESCRIPTOR,
+            &[EndpointDescriptor {
+                bEndpointAddress: 0x81,
+                bmAttributes: 0x03, // Interrupt
+                wMaxPacketSize: 8,
+                bInterval: 10,
+                ..EndpointDescriptor::new()
+            }],
+        )],
+    )];
+
+    fn keyboard() -> Hid<'static, 8> {
+        Hid::new(0, 1, BOOT_KEYBOARD_REPORT_DESCRIPTOR.as_bytes()).with_boot_protocol()
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_report_descriptor_builder() {
+        assert_eq!(
+            BOOT_KEYBOARD_REPORT_DESCRIPTOR.as_bytes(),
+            BOOT_KEYBOARD_BYTES
+        );
+        assert_eq!(BOOT_MOUSE_REPORT_DESCRIPTOR.as_bytes(), BOOT_MOUSE_BYTES);
+
+        let descriptor: ReportDescriptor<16> = ReportDescriptor::new()
+            .usage_page(usage_page::VENDOR_DEFINED)
+            .logical_minimum(-1000)
+            .logical_maximum(100_000);
+        assert_eq!(
+            descriptor.as_bytes(),
+            [0x06, 0x00, 0xff, 0x16, 0x18, 0xfc, 0x27, 0xa0, 0x86, 0x01, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_get_descriptor() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut keyboard = keyboard();
+
+        let data = host
+            .control_in_with(
+                standard_request(
+                    Direction::DeviceToHost,
+                    Recipient::Interface,
+                    0x06,
+                    0x2200,
+                    0,
+                    255,
+                ),
+                |usb, setup_packet, data| {
+                    keyboard.handle_setup_packet(usb, 0, setup_packet, data);
+                },
+            )
+            .unwrap();
+        assert_eq!(data, BOOT_KEYBOARD_BYTES);
+
+        let data = host
+            .control_in_with(
+                standard_request(
+                    Direction::DeviceToHost,
+                    Recipient::Interface,
+                    0x06,
+                    0x2100,
+                    0,
+                    255,
+                ),
+                |usb, setup_packet, data| {
+                    keyboard.handle_setup_packet(usb, 0, setup_packet, data);
+                },
+            )
+            .unwrap();
+        assert_eq!(data, [0x09, 0x21, 0x11, 0x01, 0x00, 0x01, 0x22, 63, 0x00]);
+    }
+
+    #[test]
+    fn test_class_requests() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut keyboard = keyboard();
+        let mut idle_rate = None;
+        let mut protocol = None;
+
+        // SET_IDLE 500ms, SET_PROTOCOL boot
+        for setup_packet in [
+            class_request(
+                Direction::HostToDevice,
+                Recipient::Interface,
+                0x0a,
+                125 << 8,
+                0,
+                0,
+            ),
+            class_request(Direction::HostToDevice, Recipient::Interface, 0x0b, 0, 0, 0),
+        ] {
+            host.control_out_with(setup_packet, &[], |usb, setup_packet, data| match keyboard
+                .handle_setup_packet(usb, 0, setup_packet, data)
+            {
+                Some(HidEvent::IdleChanged(value)) => idle_rate = Some(value),
+                Some(HidEvent::ProtocolChanged(value)) => protocol = Some(value),
+                _ => (),
+            })
+            .unwrap();
+        }
+        assert_eq!(idle_rate, Some(125));
+        assert_eq!(protocol, Some(Protocol::Boot));
+
+        // GET_IDLE, GET_PROTOCOL
+        for (setup_packet, expected) in [
+            (
+                class_request(Direction::DeviceToHost, Recipient::Interface, 0x02, 0, 0, 1),
+                125,
+            ),
+            (
+                class_request(Direction::DeviceToHost, Recipient::Interface, 0x03, 0, 0, 1),
+                0,
+            ),
+        ] {
+            let data = host
+                .control_in_with(setup_packet, |usb, setup_packet, data| {
+                    keyboard.handle_setup_packet(usb, 0, setup_packet, data);
+                })
+                .unwrap();
+            assert_eq!(data, [expected]);
+        }
+
+        // SET_REPORT output report with the keyboard leds
+        let mut leds = Vec::new();
+        host.control_out_with(
+            class_request(
+                Direction::HostToDevice,
+                Recipient::Interface,
+                0x09,
+                0x0200,
+                0,
+                1,
+            ),
+            &[0b101],
+            |usb, setup_packet, data| {
+                if let Some(HidEvent::SetReport {
+                    report_type: ReportType::Output,
+                    data,
+                    ..
+                }) = keyboard.handle_setup_packet(usb, 0, setup_packet, data)
+                {
+                    leds.extend_from_slice(data);
+                }
+            },
+        )
+        .unwrap();
+        assert_eq!(leds, [0b101]);
+    }
+
+    #[test]
+    fn test_protocol_requires_boot_subclass() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut mouse: Hid<3> = Hid::new(0, 1, BOOT_MOUSE_REPORT_DESCRIPTOR.as_bytes());
+
+        let result = host.control_in_with(
+            class_request(Direction::DeviceToHost, Recipient::Interface, 0x03, 0, 0, 1),
+            |usb, setup_packet, data| {
+                mouse.handle_setup_packet(usb, 0, setup_packet, data);
+            },
+        );
+        assert!(result.is_err());
+        assert!(host.usb.operations().contains(&Operation::StallIn(0)));
+    }
+
+    #[test]
+    fn test_write_report() {
+        let host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut keyboard = keyboard();
+        let report = BootKeyboardReport {
+            keycodes: [0x04, 0, 0, 0, 0, 0],
+            ..BootKeyboardReport::default()
+        };
+
+        // reports are only sent when they change
+        assert!(keyboard.write_report(&host.usb, report.as_bytes()));
+        keyboard.handle_event(&host.usb, UsbEvent::SendComplete(1));
+        assert!(!keyboard.write_report(&host.usb, report.as_bytes()));
+        keyboard.tick(&host.usb, 1000);
+        assert_eq!(host.usb.writes(1).len(), 1);
+
+        // or when the idle rate expires
+        let mut host = host;
+        host.control_out_with(
+            class_request(
+                Direction::HostToDevice,
+                Recipient::Interface,
+                0x0a,
+                1 << 8,
+                0,
+                0,
+            ),
+            &[],
+            |usb, setup_packet, data| {
+                keyboard.handle_setup_packet(usb, 0, setup_packet, data);
+            },
+        )
+        .unwrap();
+        keyboard.tick(&host.usb, 2);
+        assert_eq!(host.usb.writes(1).len(), 1);
+        keyboard.tick(&host.usb, 2);
+        assert_eq!(host.usb.writes(1).len(), 2);
+
+        // while busy the latest report is queued
+        let released = BootKeyboardReport::default();
+        assert!(keyboard.write_report(&host.usb, released.as_bytes()));
+        assert_eq!(host.usb.writes(1).len(), 2);
+        keyboard.handle_event(&host.usb, UsbEvent::SendComplete(1));
+        assert_eq!(
+            host.usb.writes(1),
+            [report.as_bytes(), report.as_bytes(), released.as_bytes()]
+        );
+    }
+}
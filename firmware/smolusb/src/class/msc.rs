@@ -0,0 +1,1240 @@
+//! USB Mass Storage Class - Bulk-Only Transport (BOT) with a SCSI command subset
+//!
+//! [`MassStorage`] implements the Command Block Wrapper (CBW) /
+//! Command Status Wrapper (CSW) state machine and exposes a
+//! [`BlockDevice`] to the host as a single logical unit.
+//!
+//! Supported SCSI commands are: TEST UNIT READY, REQUEST SENSE,
+//! INQUIRY, MODE SENSE(6), PREVENT ALLOW MEDIUM REMOVAL,
+//! READ CAPACITY(10), READ(10) and WRITE(10).
+
+use log::{debug, trace, warn};
+
+use crate::class::{ControlResult, UsbClass};
+use crate::event::UsbEvent;
+use crate::setup::{Direction, Recipient, RequestType, SetupPacket};
+use crate::traits::UsbDriver;
+
+/// Mass Storage interface class code
+pub const INTERFACE_CLASS: u8 = 0x08;
+/// SCSI transparent command set subclass code
+pub const INTERFACE_SUBCLASS_SCSI: u8 = 0x06;
+/// Bulk-Only Transport protocol code
+pub const INTERFACE_PROTOCOL_BOT: u8 = 0x50;
+
+// - ClassRequest -------------------------------------------------------------
+
+/// Bulk-Only Mass Storage Class Requests
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum ClassRequest {
+    GetMaxLun = 0xfe,
+    BulkOnlyMassStorageReset = 0xff,
+    Unknown(u8) = 0x00,
+}
+
+impl From<u8> for ClassRequest {
+    fn from(value: u8) -> Self {
+        match value {
+            0xfe => ClassRequest::GetMaxLun,
+            0xff => ClassRequest::BulkOnlyMassStorageReset,
+            _ => ClassRequest::Unknown(value),
+        }
+    }
+}
+
+// - BlockDevice --------------------------------------------------------------
+
+/// Errors reported by a [`BlockDevice`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum BlockDeviceError {
+    /// The medium is not present or not ready.
+    NotReady,
+    /// The block could not be read.
+    ReadFault,
+    /// The block could not be written.
+    WriteFault,
+    /// The medium is write protected.
+    WriteProtected,
+}
+
+/// A block storage device exposed by [`MassStorage`].
+///
+/// Blocks are `BLOCK_SIZE` bytes as given by the [`MassStorage`] the
+/// device is used with.
+pub trait BlockDevice {
+    /// Returns the number of blocks on the device.
+    fn block_count(&self) -> u32;
+
+    /// Reads the block at `lba` into `block`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`BlockDeviceError`] if the block could not be read.
+    fn read_block(&mut self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError>;
+
+    /// Writes `block` to the block at `lba`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`BlockDeviceError`] if the block could not be written.
+    fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError>;
+
+    /// Returns `true` if the device is ready for reading and writing.
+    fn is_ready(&self) -> bool {
+        true
+    }
+
+    /// Returns `true` if the device is write protected.
+    fn is_write_protected(&self) -> bool {
+        false
+    }
+}
+
+// - CommandBlockWrapper ------------------------------------------------------
+
+/// Bulk-Only Transport Command Block Wrapper
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CommandBlockWrapper {
+    pub tag: u32,
+    pub data_transfer_length: u32,
+    pub direction: Direction,
+    pub lun: u8,
+    pub command_block: [u8; 16],
+}
+
+impl CommandBlockWrapper {
+    /// Size of a CBW in bytes.
+    pub const LENGTH: usize = 31;
+    /// `dCBWSignature`
+    pub const SIGNATURE: u32 = 0x4342_5355;
+
+    const EMPTY: Self = Self {
+        tag: 0,
+        data_transfer_length: 0,
+        direction: Direction::HostToDevice,
+        lun: 0,
+        command_block: [0; 16],
+    };
+
+    /// Parses a CBW, returning `None` if it is not valid and meaningful.
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LENGTH
+            || u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) != Self::SIGNATURE
+        {
+            return None;
+        }
+        let command_block_length = usize::from(bytes[14] & 0x1f);
+        if command_block_length == 0 || command_block_length > 16 {
+            return None;
+        }
+        let mut command_block = [0; 16];
+        command_block[..command_block_length]
+            .copy_from_slice(&bytes[15..15 + command_block_length]);
+
+        Some(Self {
+            tag: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            data_transfer_length: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            direction: Direction::from(bytes[12]),
+            lun: bytes[13] & 0x0f,
+            command_block,
+        })
+    }
+}
+
+// - CommandStatusWrapper -----------------------------------------------------
+
+/// `bCSWStatus` values
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CommandStatus {
+    #[default]
+    Passed = 0x00,
+    Failed = 0x01,
+    PhaseError = 0x02,
+}
+
+/// Bulk-Only Transport Command Status Wrapper
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommandStatusWrapper {
+    pub tag: u32,
+    pub data_residue: u32,
+    pub status: CommandStatus,
+}
+
+impl CommandStatusWrapper {
+    /// Size of a CSW in bytes.
+    pub const LENGTH: usize = 13;
+    /// `dCSWSignature`
+    pub const SIGNATURE: u32 = 0x5342_5355;
+
+    #[must_use]
+    pub fn to_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut bytes = [0; Self::LENGTH];
+        bytes[0..4].copy_from_slice(&Self::SIGNATURE.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.tag.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.data_residue.to_le_bytes());
+        bytes[12] = self.status as u8;
+        bytes
+    }
+
+    /// Parses a CSW, returning `None` if it is not valid.
+    #[must_use]
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != Self::LENGTH
+            || u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) != Self::SIGNATURE
+        {
+            return None;
+        }
+        let status = match bytes[12] {
+            0x00 => CommandStatus::Passed,
+            0x01 => CommandStatus::Failed,
+            _ => CommandStatus::PhaseError,
+        };
+        Some(Self {
+            tag: u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            data_residue: u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            status,
+        })
+    }
+}
+
+// - ScsiCommand --------------------------------------------------------------
+
+/// SCSI operation codes
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum ScsiCommand {
+    TestUnitReady = 0x00,
+    RequestSense = 0x03,
+    Inquiry = 0x12,
+    ModeSense6 = 0x1a,
+    PreventAllowMediumRemoval = 0x1e,
+    ReadCapacity10 = 0x25,
+    Read10 = 0x28,
+    Write10 = 0x2a,
+    Unknown(u8),
+}
+
+impl From<u8> for ScsiCommand {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => ScsiCommand::TestUnitReady,
+            0x03 => ScsiCommand::RequestSense,
+            0x12 => ScsiCommand::Inquiry,
+            0x1a => ScsiCommand::ModeSense6,
+            0x1e => ScsiCommand::PreventAllowMediumRemoval,
+            0x25 => ScsiCommand::ReadCapacity10,
+            0x28 => ScsiCommand::Read10,
+            0x2a => ScsiCommand::Write10,
+            _ => ScsiCommand::Unknown(value),
+        }
+    }
+}
+
+/// SCSI sense data
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sense {
+    pub key: u8,
+    pub asc: u8,
+    pub ascq: u8,
+}
+
+impl Sense {
+    pub const NO_SENSE: Sense = Sense::new(0x00, 0x00, 0x00);
+    pub const MEDIUM_NOT_PRESENT: Sense = Sense::new(0x02, 0x3a, 0x00);
+    pub const UNRECOVERED_READ_ERROR: Sense = Sense::new(0x03, 0x11, 0x00);
+    pub const WRITE_ERROR: Sense = Sense::new(0x03, 0x0c, 0x00);
+    pub const INVALID_COMMAND: Sense = Sense::new(0x05, 0x20, 0x00);
+    pub const LBA_OUT_OF_RANGE: Sense = Sense::new(0x05, 0x21, 0x00);
+    pub const INVALID_FIELD_IN_CDB: Sense = Sense::new(0x05, 0x24, 0x00);
+    pub const WRITE_PROTECTED: Sense = Sense::new(0x07, 0x27, 0x00);
+
+    #[must_use]
+    pub const fn new(key: u8, asc: u8, ascq: u8) -> Self {
+        Self { key, asc, ascq }
+    }
+
+    fn from_error(error: BlockDeviceError) -> Self {
+        match error {
+            BlockDeviceError::NotReady => Sense::MEDIUM_NOT_PRESENT,
+            BlockDeviceError::ReadFault => Sense::UNRECOVERED_READ_ERROR,
+            BlockDeviceError::WriteFault => Sense::WRITE_ERROR,
+            BlockDeviceError::WriteProtected => Sense::WRITE_PROTECTED,
+        }
+    }
+}
+
+// - MassStorage --------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Stage {
+    /// Waiting for a CBW.
+    Command,
+    /// Sending data to the host.
+    DataIn,
+    /// Receiving data from the host.
+    DataOut,
+    /// Sending a zero length packet to terminate a short data-in stage.
+    Terminate,
+    /// Sending the CSW.
+    Status,
+    /// An invalid CBW was received, waiting for reset recovery.
+    ResetRecovery,
+}
+
+/// A Bulk-Only Transport mass storage interface with a single
+/// logical unit backed by a [`BlockDevice`].
+///
+/// Class requests received by [`Control`](crate::control::Control) are
+/// passed to [`MassStorage::handle_setup_packet()`] and endpoint events to
+/// [`MassStorage::handle_event()`]. `BLOCK_SIZE` is the block size of the
+/// device and must be a multiple of the bulk endpoint max packet size.
+pub struct MassStorage<'a, B, const BLOCK_SIZE: usize> {
+    interface_number: u8,
+    endpoint_in: u8,
+    endpoint_out: u8,
+    max_packet_size: usize,
+    vendor: &'a str,
+    product: &'a str,
+    revision: &'a str,
+
+    device: B,
+    sense: Sense,
+
+    stage: Stage,
+    cbw: CommandBlockWrapper,
+    status: CommandStatus,
+    buffer: [u8; BLOCK_SIZE],
+    buffer_offset: usize,
+    buffer_length: usize,
+    lba: u32,
+    blocks: bool,
+    length: u32,
+    transferred: u32,
+    last_packet_length: usize,
+}
+
+impl<'a, B, const BLOCK_SIZE: usize> MassStorage<'a, B, BLOCK_SIZE>
+where
+    B: BlockDevice,
+{
+    /// Creates a new mass storage interface.
+    ///
+    /// `vendor`, `product` and `revision` are reported to the host in
+    /// the INQUIRY response and truncated to 8, 16 and 4 characters.
+    #[must_use]
+    #[allow(clippy::too_many_arguments)]
+    pub const fn new(
+        interface_number: u8,
+        endpoint_in: u8,
+        endpoint_out: u8,
+        max_packet_size: usize,
+        device: B,
+        vendor: &'a str,
+        product: &'a str,
+        revision: &'a str,
+    ) -> Self {
+        Self {
+            interface_number,
+            endpoint_in,
+            endpoint_out,
+            max_packet_size,
+            vendor,
+            product,
+            revision,
+            device,
+            sense: Sense::NO_SENSE,
+            stage: Stage::Command,
+            cbw: CommandBlockWrapper::EMPTY,
+            status: CommandStatus::Passed,
+            buffer: [0; BLOCK_SIZE],
+            buffer_offset: 0,
+            buffer_length: 0,
+            lba: 0,
+            blocks: false,
+            length: 0,
+            transferred: 0,
+            last_packet_length: 0,
+        }
+    }
+
+    /// Returns a reference to the block device.
+    #[must_use]
+    pub fn device(&self) -> &B {
+        &self.device
+    }
+
+    /// Returns a mutable reference to the block device.
+    pub fn device_mut(&mut self) -> &mut B {
+        &mut self.device
+    }
+
+    /// Starts the interface once the host has selected a configuration
+    /// containing it.
+    pub fn configure<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        self.reset();
+        usb.ep_out_prime_receive(self.endpoint_out);
+    }
+
+    /// Returns the interface to its initial state.
+    pub fn reset(&mut self) {
+        self.stage = Stage::Command;
+        self.sense = Sense::NO_SENSE;
+        self.status = CommandStatus::Passed;
+    }
+
+    /// Handles a class request passed on by the control endpoint.
+    pub fn handle_setup_packet<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+    ) where
+        D: UsbDriver,
+    {
+        if setup_packet.request_type() != RequestType::Class
+            || setup_packet.recipient() != Recipient::Interface
+            || (setup_packet.index & 0xff) as u8 != self.interface_number
+        {
+            return;
+        }
+
+        match (
+            setup_packet.direction(),
+            ClassRequest::from(setup_packet.request),
+        ) {
+            (Direction::DeviceToHost, ClassRequest::GetMaxLun) => {
+                // we only support a single logical unit
+                usb.write_requested(control_endpoint, 1, [0].into_iter());
+            }
+            (Direction::HostToDevice, ClassRequest::BulkOnlyMassStorageReset) => {
+                debug!("MassStorage reset");
+                self.reset();
+                usb.write(control_endpoint, [].into_iter());
+                usb.ep_out_prime_receive(self.endpoint_out);
+            }
+            (direction, request) => {
+                warn!("MassStorage stall - unhandled class request {:?}", request);
+                match direction {
+                    Direction::DeviceToHost => usb.stall_endpoint_in(control_endpoint),
+                    Direction::HostToDevice => usb.stall_endpoint_out(control_endpoint),
+                }
+            }
+        }
+    }
+
+    /// Handles a USB event for the bulk endpoints.
+    pub fn handle_event<D>(&mut self, usb: &D, event: UsbEvent)
+    where
+        D: UsbDriver,
+    {
+        match (event, self.stage) {
+            (UsbEvent::BusReset, _) => self.reset(),
+
+            (UsbEvent::ReceivePacket(endpoint_number), Stage::Command)
+                if endpoint_number == self.endpoint_out =>
+            {
+                let mut packet = [0; crate::EP_MAX_PACKET_SIZE];
+                let bytes_read = usb.read(endpoint_number, &mut packet);
+                match CommandBlockWrapper::from_bytes(&packet[..bytes_read]) {
+                    Some(cbw) => self.handle_command(usb, cbw),
+                    None => {
+                        warn!("MassStorage invalid CBW, stalling until reset");
+                        self.stage = Stage::ResetRecovery;
+                        usb.stall_endpoint_in(self.endpoint_in);
+                        usb.stall_endpoint_out(self.endpoint_out);
+                    }
+                }
+            }
+
+            (UsbEvent::ReceivePacket(endpoint_number), Stage::ResetRecovery)
+                if endpoint_number == self.endpoint_out =>
+            {
+                let mut packet = [0; crate::EP_MAX_PACKET_SIZE];
+                let _bytes_read = usb.read(endpoint_number, &mut packet);
+                usb.stall_endpoint_out(self.endpoint_out);
+            }
+
+            (UsbEvent::ReceivePacket(endpoint_number), Stage::DataOut)
+                if endpoint_number == self.endpoint_out =>
+            {
+                self.receive_data(usb);
+            }
+
+            (UsbEvent::SendComplete(endpoint_number), Stage::DataIn)
+                if endpoint_number == self.endpoint_in =>
+            {
+                self.send_data(usb);
+            }
+
+            (UsbEvent::SendComplete(endpoint_number), Stage::Terminate)
+                if endpoint_number == self.endpoint_in =>
+            {
+                self.send_status(usb);
+            }
+
+            (UsbEvent::SendComplete(endpoint_number), Stage::Status)
+                if endpoint_number == self.endpoint_in =>
+            {
+                // ready for the next command
+                self.stage = Stage::Command;
+                usb.ep_out_prime_receive(self.endpoint_out);
+            }
+
+            _ => (),
+        }
+    }
+
+    // - commands --
+
+    fn handle_command<D>(&mut self, usb: &D, cbw: CommandBlockWrapper)
+    where
+        D: UsbDriver,
+    {
+        let command = ScsiCommand::from(cbw.command_block[0]);
+        trace!("MassStorage {:?} tag:{}", command, cbw.tag);
+
+        self.cbw = cbw;
+        self.status = CommandStatus::Passed;
+        self.buffer_offset = 0;
+        self.buffer_length = 0;
+        self.blocks = false;
+        self.length = 0;
+        self.transferred = 0;
+        self.last_packet_length = 0;
+
+        let command_block = cbw.command_block;
+        match command {
+            ScsiCommand::TestUnitReady => {
+                if !self.device.is_ready() {
+                    self.fail(Sense::MEDIUM_NOT_PRESENT);
+                }
+                self.finish(usb);
+            }
+            ScsiCommand::RequestSense => {
+                let sense = self.sense;
+                self.sense = Sense::NO_SENSE;
+                let mut response = [0; 18];
+                response[0] = 0x70; // current errors, fixed format
+                response[2] = sense.key;
+                response[7] = 10; // additional sense length
+                response[12] = sense.asc;
+                response[13] = sense.ascq;
+                self.respond(usb, &response, usize::from(command_block[4]));
+            }
+            ScsiCommand::Inquiry => {
+                if command_block[1] & 0x01 != 0 {
+                    // vital product data pages are not supported
+                    self.fail(Sense::INVALID_FIELD_IN_CDB);
+                    self.finish(usb);
+                    return;
+                }
+                let mut response = [b' '; 36];
+                response[0] = 0x00; // direct access block device
+                response[1] = 0x80; // removable
+                response[2] = 0x04; // SPC-2
+                response[3] = 0x02; // response data format
+                response[4] = 36 - 5; // additional length
+                response[5..8].copy_from_slice(&[0, 0, 0]);
+                copy_padded(&mut response[8..16], self.vendor);
+                copy_padded(&mut response[16..32], self.product);
+                copy_padded(&mut response[32..36], self.revision);
+                let allocation_length = u16::from_be_bytes([command_block[3], command_block[4]]);
+                self.respond(usb, &response, usize::from(allocation_length));
+            }
+            ScsiCommand::ModeSense6 => {
+                let device_specific = if self.device.is_write_protected() {
+                    0x80
+                } else {
+                    0x00
+                };
+                // mode parameter header without any pages
+                let response = [3, 0x00, device_specific, 0];
+                self.respond(usb, &response, usize::from(command_block[4]));
+            }
+            ScsiCommand::PreventAllowMediumRemoval => {
+                self.finish(usb);
+            }
+            ScsiCommand::ReadCapacity10 => {
+                if !self.device.is_ready() {
+                    self.fail(Sense::MEDIUM_NOT_PRESENT);
+                    self.finish(usb);
+                    return;
+                }
+                let last_lba = self.device.block_count().saturating_sub(1);
+                let mut response = [0; 8];
+                response[0..4].copy_from_slice(&last_lba.to_be_bytes());
+                response[4..8].copy_from_slice(&(BLOCK_SIZE as u32).to_be_bytes());
+                self.respond(usb, &response, response.len());
+            }
+            ScsiCommand::Read10 | ScsiCommand::Write10 => {
+                let lba = u32::from_be_bytes([
+                    command_block[2],
+                    command_block[3],
+                    command_block[4],
+                    command_block[5],
+                ]);
+                let block_count = u16::from_be_bytes([command_block[7], command_block[8]]);
+                let is_write = command == ScsiCommand::Write10;
+
+                if !self.device.is_ready() {
+                    self.fail(Sense::MEDIUM_NOT_PRESENT);
+                } else if u64::from(lba) + u64::from(block_count)
+                    > u64::from(self.device.block_count())
+                {
+                    self.fail(Sense::LBA_OUT_OF_RANGE);
+                } else if is_write && self.device.is_write_protected() {
+                    self.fail(Sense::WRITE_PROTECTED);
+                }
+                if self.status != CommandStatus::Passed {
+                    self.finish(usb);
+                    return;
+                }
+
+                let length = u32::from(block_count) * BLOCK_SIZE as u32;
+                let expected_direction = if is_write {
+                    Direction::HostToDevice
+                } else {
+                    Direction::DeviceToHost
+                };
+                if cbw.data_transfer_length < length
+                    || (length > 0 && cbw.direction != expected_direction)
+                {
+                    // the host and device disagree about the transfer
+                    self.status = CommandStatus::PhaseError;
+                    self.finish(usb);
+                    return;
+                }
+
+                self.lba = lba;
+                self.blocks = true;
+                self.length = length;
+                if length == 0 {
+                    self.finish(usb);
+                } else if is_write {
+                    self.stage = Stage::DataOut;
+                    usb.ep_out_prime_receive(self.endpoint_out);
+                } else {
+                    self.stage = Stage::DataIn;
+                    self.send_data(usb);
+                }
+            }
+            ScsiCommand::Unknown(operation_code) => {
+                debug!("MassStorage unsupported command 0x{:02x}", operation_code);
+                self.fail(Sense::INVALID_COMMAND);
+                self.finish(usb);
+            }
+        }
+    }
+
+    fn fail(&mut self, sense: Sense) {
+        self.sense = sense;
+        self.status = CommandStatus::Failed;
+    }
+
+    /// Starts a data-in stage with the given response.
+    fn respond<D>(&mut self, usb: &D, response: &[u8], allocation_length: usize)
+    where
+        D: UsbDriver,
+    {
+        let length = response
+            .len()
+            .min(allocation_length)
+            .min(self.cbw.data_transfer_length as usize);
+        if self.cbw.direction != Direction::DeviceToHost && self.cbw.data_transfer_length > 0 {
+            self.status = CommandStatus::PhaseError;
+            self.finish(usb);
+            return;
+        }
+
+        self.buffer[..length].copy_from_slice(&response[..length]);
+        self.buffer_length = length;
+        self.length = length as u32;
+        if length == 0 {
+            self.finish(usb);
+        } else {
+            self.stage = Stage::DataIn;
+            self.send_data(usb);
+        }
+    }
+
+    // - data stages --
+
+    fn send_data<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        // account for the packet that has just been sent
+        self.buffer_offset += self.last_packet_length;
+        self.transferred += self.last_packet_length as u32;
+        self.last_packet_length = 0;
+
+        if self.transferred >= self.length {
+            self.finish(usb);
+            return;
+        }
+
+        // fetch the next block
+        if self.blocks && self.buffer_offset >= self.buffer_length {
+            if self.buffer_length > 0 {
+                self.lba += 1;
+            }
+            if let Err(error) = self.device.read_block(self.lba, &mut self.buffer) {
+                warn!("MassStorage read error at lba {}: {:?}", self.lba, error);
+                self.fail(Sense::from_error(error));
+                self.finish(usb);
+                return;
+            }
+            self.buffer_offset = 0;
+            self.buffer_length = BLOCK_SIZE;
+        }
+
+        let packet_length = (self.buffer_length - self.buffer_offset)
+            .min(self.max_packet_size)
+            .min((self.length - self.transferred) as usize);
+        self.last_packet_length = packet_length;
+        usb.write_with_packet_size(
+            self.endpoint_in,
+            Some(packet_length),
+            self.buffer[self.buffer_offset..self.buffer_offset + packet_length]
+                .iter()
+                .copied(),
+            self.max_packet_size,
+        );
+    }
+
+    fn receive_data<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        let offset = self.buffer_offset;
+        let bytes_read = usb.read(self.endpoint_out, &mut self.buffer[offset..]);
+        self.buffer_offset += bytes_read;
+        self.transferred += bytes_read as u32;
+
+        if self.buffer_offset >= BLOCK_SIZE {
+            // keep accepting data after an error so the host can complete the transfer
+            if self.status == CommandStatus::Passed {
+                if let Err(error) = self.device.write_block(self.lba, &self.buffer) {
+                    warn!("MassStorage write error at lba {}: {:?}", self.lba, error);
+                    self.fail(Sense::from_error(error));
+                }
+            }
+            self.lba += 1;
+            self.buffer_offset = 0;
+        }
+
+        if self.transferred >= self.length || bytes_read == 0 {
+            self.finish(usb);
+        } else {
+            usb.ep_out_prime_receive(self.endpoint_out);
+        }
+    }
+
+    /// Ends the data stage, if any, and sends the CSW.
+    fn finish<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        let expected = self.cbw.data_transfer_length;
+        if self.transferred < expected {
+            match self.cbw.direction {
+                // terminate the data stage with a short packet
+                Direction::DeviceToHost
+                    if self.transferred == 0
+                        || self.transferred as usize % self.max_packet_size == 0 =>
+                {
+                    self.stage = Stage::Terminate;
+                    usb.write(self.endpoint_in, [].into_iter());
+                    return;
+                }
+                Direction::DeviceToHost => (),
+                // refuse any further data from the host
+                Direction::HostToDevice => usb.stall_endpoint_out(self.endpoint_out),
+            }
+        }
+        self.send_status(usb);
+    }
+
+    fn send_status<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        let csw = CommandStatusWrapper {
+            tag: self.cbw.tag,
+            data_residue: self
+                .cbw
+                .data_transfer_length
+                .saturating_sub(self.transferred),
+            status: self.status,
+        };
+        self.stage = Stage::Status;
+        usb.write(self.endpoint_in, csw.to_bytes().into_iter());
+    }
+}
+
+impl<'a, D, B, const BLOCK_SIZE: usize> UsbClass<D> for MassStorage<'a, B, BLOCK_SIZE>
+where
+    D: UsbDriver,
+    B: BlockDevice,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        interface_number == self.interface_number
+    }
+
+    fn has_endpoint(&self, endpoint_address: u8) -> bool {
+        endpoint_address == self.endpoint_in | 0x80 || endpoint_address == self.endpoint_out
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        _data: &'d [u8],
+    ) -> ControlResult<'d> {
+        if setup_packet.request_type() != RequestType::Class
+            || setup_packet.recipient() != Recipient::Interface
+            || (setup_packet.index & 0xff) as u8 != self.interface_number
+        {
+            return ControlResult::Unhandled;
+        }
+        self.handle_setup_packet(usb, control_endpoint, setup_packet);
+        ControlResult::Handled
+    }
+
+    fn handle_endpoint_event(&mut self, usb: &D, event: UsbEvent) {
+        self.handle_event(usb, event);
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn configuration_changed(&mut self, usb: &D, configuration: u8) {
+        if configuration == 0 {
+            self.reset();
+        } else {
+            self.configure(usb);
+        }
+    }
+}
+
+/// Copies `string` into `buffer`, truncating it or padding it with spaces.
+fn copy_padded(buffer: &mut [u8], string: &str) {
+    for (index, byte) in buffer.iter_mut().enumerate() {
+        *byte = string.as_bytes().get(index).copied().unwrap_or(b' ');
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::descriptor::{
+        ConfigurationDescriptor, ConfigurationDescriptorHeader, EndpointDescriptor,
+        InterfaceDescriptor, InterfaceDescriptorHeader,
+    };
+    use crate::testing::{class_request, MockUsbDriver, Operation, VirtualHost};
+
+    // - fixtures -------------------------------------------------------------
+
+    const BLOCK_SIZE: usize = 512;
+    const MAX_PACKET_SIZE: usize = 64;
+    const ENDPOINT: u8 = 1;
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[InterfaceDescriptor::new(
+            InterfaceDescriptorHeader {
+                iInterfaceNumber: 0,
+                bInterfaceClass: INTERFACE_CLASS,
+                bInterfaceSubClass: INTERFACE_SUBCLASS_SCSI,
+                bInterfaceProtocol: INTERFACE_PROTOCOL_BOT,
+                ..InterfaceDescriptorHeader::new()
+            },
+            &[
+                EndpointDescriptor {
+                    bEndpointAddress: 0x81,
+                    bmAttributes: 0x02, // Bulk
+                    wMaxPacketSize: MAX_PACKET_SIZE as u16,
+                    ..EndpointDescriptor::new()
+                },
+                EndpointDescriptor {
+                    bEndpointAddress: 0x01,
+                    bmAttributes: 0x02, // Bulk
+                    wMaxPacketSize: MAX_PACKET_SIZE as u16,
+                    ..EndpointDescriptor::new()
+                },
+            ],
+        )],
+    )];
+
+    struct RamDisk {
+        blocks: Vec<[u8; BLOCK_SIZE]>,
+        write_protected: bool,
+    }
+
+    impl BlockDevice for RamDisk {
+        fn block_count(&self) -> u32 {
+            self.blocks.len() as u32
+        }
+
+        fn read_block(&mut self, lba: u32, block: &mut [u8]) -> Result<(), BlockDeviceError> {
+            let data = self
+                .blocks
+                .get(lba as usize)
+                .ok_or(BlockDeviceError::ReadFault)?;
+            block.copy_from_slice(data);
+            Ok(())
+        }
+
+        fn write_block(&mut self, lba: u32, block: &[u8]) -> Result<(), BlockDeviceError> {
+            let data = self
+                .blocks
+                .get_mut(lba as usize)
+                .ok_or(BlockDeviceError::WriteFault)?;
+            data.copy_from_slice(block);
+            Ok(())
+        }
+
+        fn is_write_protected(&self) -> bool {
+            self.write_protected
+        }
+    }
+
+    fn storage(block_count: usize) -> MassStorage<'static, RamDisk, BLOCK_SIZE> {
+        let mut blocks = vec![[0; BLOCK_SIZE]; block_count];
+        for (lba, block) in blocks.iter_mut().enumerate() {
+            block.fill(lba as u8);
+        }
+        let device = RamDisk {
+            blocks,
+            write_protected: false,
+        };
+        MassStorage::new(
+            0,
+            ENDPOINT,
+            ENDPOINT,
+            MAX_PACKET_SIZE,
+            device,
+            "GSG",
+            "Cynthion RAM Disk",
+            "1.0",
+        )
+    }
+
+    fn cbw(tag: u32, length: u32, direction: Direction, command_block: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&CommandBlockWrapper::SIGNATURE.to_le_bytes());
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&length.to_le_bytes());
+        bytes.push(direction as u8);
+        bytes.push(0); // lun
+        bytes.push(command_block.len() as u8);
+        bytes.extend_from_slice(command_block);
+        bytes.resize(CommandBlockWrapper::LENGTH, 0);
+        bytes
+    }
+
+    struct Transfer {
+        data: Vec<u8>,
+        csw: Option<CommandStatusWrapper>,
+        stalled: bool,
+    }
+
+    /// Acts as the host side of a single Bulk-Only Transport command.
+    fn transfer(
+        storage: &mut MassStorage<'static, RamDisk, BLOCK_SIZE>,
+        usb: &MockUsbDriver,
+        cbw: &[u8],
+        data_out: &[u8],
+    ) -> Transfer {
+        usb.take_operations();
+        usb.push_out_data(ENDPOINT, cbw);
+        storage.handle_event(usb, UsbEvent::ReceivePacket(ENDPOINT));
+
+        let mut data_out = data_out.chunks(MAX_PACKET_SIZE);
+        let mut packets: Vec<Vec<u8>> = Vec::new();
+        let mut stalled = false;
+        loop {
+            let operations = usb.take_operations();
+            if operations.is_empty() {
+                break;
+            }
+            for operation in operations {
+                match operation {
+                    Operation::Write(ENDPOINT, packet) => {
+                        packets.push(packet);
+                        storage.handle_event(usb, UsbEvent::SendComplete(ENDPOINT));
+                    }
+                    // a stalled OUT endpoint ends the data stage
+                    Operation::PrimeReceive(ENDPOINT) if !stalled => {
+                        if let Some(chunk) = data_out.next() {
+                            usb.push_out_data(ENDPOINT, chunk);
+                            storage.handle_event(usb, UsbEvent::ReceivePacket(ENDPOINT));
+                        }
+                    }
+                    Operation::StallIn(_) | Operation::StallOut(_) => stalled = true,
+                    _ => (),
+                }
+            }
+        }
+
+        let csw = packets
+            .pop()
+            .and_then(|packet| CommandStatusWrapper::from_bytes(&packet));
+        Transfer {
+            data: packets.concat(),
+            csw,
+            stalled,
+        }
+    }
+
+    fn request_sense(
+        storage: &mut MassStorage<'static, RamDisk, BLOCK_SIZE>,
+        usb: &MockUsbDriver,
+    ) -> (u8, u8) {
+        let result = transfer(
+            storage,
+            usb,
+            &cbw(99, 18, Direction::IN, &[0x03, 0, 0, 0, 18, 0]),
+            &[],
+        );
+        assert_eq!(result.data.len(), 18);
+        (result.data[2], result.data[12])
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_class_requests() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut storage = storage(4);
+
+        // GET_MAX_LUN
+        let setup_packet = class_request(Direction::IN, Recipient::Interface, 0xfe, 0, 0, 1);
+        let data = host
+            .control_in_with(setup_packet, |usb, setup_packet, _data| {
+                storage.handle_setup_packet(usb, 0, setup_packet);
+            })
+            .unwrap();
+        assert_eq!(data, [0]);
+
+        // Bulk-Only Mass Storage Reset
+        let setup_packet = class_request(Direction::OUT, Recipient::Interface, 0xff, 0, 0, 0);
+        host.usb.take_operations();
+        host.control_out_with(setup_packet, &[], |usb, setup_packet, _data| {
+            storage.handle_setup_packet(usb, 0, setup_packet);
+        })
+        .unwrap();
+        assert!(host
+            .usb
+            .operations()
+            .contains(&Operation::PrimeReceive(ENDPOINT)));
+    }
+
+    #[test]
+    fn test_inquiry_and_capacity() {
+        let usb = MockUsbDriver::new();
+        let mut storage = storage(16);
+        storage.configure(&usb);
+
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(1, 36, Direction::IN, &[0x12, 0, 0, 0, 36, 0]),
+            &[],
+        );
+        let csw = result.csw.unwrap();
+        assert_eq!(csw.tag, 1);
+        assert_eq!(csw.status, CommandStatus::Passed);
+        assert_eq!(csw.data_residue, 0);
+        assert_eq!(result.data.len(), 36);
+        assert_eq!(result.data[1], 0x80);
+        assert_eq!(&result.data[8..16], b"GSG     ");
+        assert_eq!(&result.data[16..32], b"Cynthion RAM Dis");
+        assert_eq!(&result.data[32..36], b"1.0 ");
+
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(2, 8, Direction::IN, &[0x25, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            &[],
+        );
+        assert_eq!(result.csw.unwrap().status, CommandStatus::Passed);
+        assert_eq!(result.data, [0, 0, 0, 15, 0, 0, 0x02, 0x00]);
+
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(3, 0, Direction::OUT, &[0x00, 0, 0, 0, 0, 0]),
+            &[],
+        );
+        assert_eq!(result.csw.unwrap().status, CommandStatus::Passed);
+
+        // MODE SENSE(6) with a larger allocation length than the response
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(4, 192, Direction::IN, &[0x1a, 0, 0x3f, 0, 192, 0]),
+            &[],
+        );
+        let csw = result.csw.unwrap();
+        assert_eq!(csw.status, CommandStatus::Passed);
+        assert_eq!(csw.data_residue, 192 - 4);
+        assert_eq!(result.data, [3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_read_write() {
+        let usb = MockUsbDriver::new();
+        let mut storage = storage(8);
+        storage.configure(&usb);
+
+        // READ(10) two blocks from lba 3
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(1, 1024, Direction::IN, &[0x28, 0, 0, 0, 0, 3, 0, 0, 2, 0]),
+            &[],
+        );
+        assert_eq!(result.csw.unwrap().status, CommandStatus::Passed);
+        assert_eq!(result.data.len(), 1024);
+        assert!(result.data[..512].iter().all(|&byte| byte == 3));
+        assert!(result.data[512..].iter().all(|&byte| byte == 4));
+        assert!(usb
+            .writes(ENDPOINT)
+            .iter()
+            .all(|packet| packet.len() <= MAX_PACKET_SIZE));
+
+        // WRITE(10) two blocks to lba 6
+        let data: Vec<u8> = (0..1024).map(|index| (index % 251) as u8).collect();
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(2, 1024, Direction::OUT, &[0x2a, 0, 0, 0, 0, 6, 0, 0, 2, 0]),
+            &data,
+        );
+        let csw = result.csw.unwrap();
+        assert_eq!(csw.tag, 2);
+        assert_eq!(csw.status, CommandStatus::Passed);
+        assert_eq!(csw.data_residue, 0);
+        assert_eq!(storage.device().blocks[6], data[..512]);
+        assert_eq!(storage.device().blocks[7], data[512..]);
+        assert!(storage.device().blocks[5].iter().all(|&byte| byte == 5));
+
+        // and read them back
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(3, 1024, Direction::IN, &[0x28, 0, 0, 0, 0, 6, 0, 0, 2, 0]),
+            &[],
+        );
+        assert_eq!(result.csw.unwrap().status, CommandStatus::Passed);
+        assert_eq!(result.data, data);
+    }
+
+    #[test]
+    fn test_command_errors() {
+        let usb = MockUsbDriver::new();
+        let mut storage = storage(8);
+        storage.configure(&usb);
+
+        // READ(10) past the end of the device
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(1, 1024, Direction::IN, &[0x28, 0, 0, 0, 0, 7, 0, 0, 2, 0]),
+            &[],
+        );
+        let csw = result.csw.unwrap();
+        assert_eq!(csw.status, CommandStatus::Failed);
+        assert_eq!(csw.data_residue, 1024);
+        assert!(result.data.is_empty());
+        assert_eq!(request_sense(&mut storage, &usb), (0x05, 0x21));
+
+        // sense data is cleared once read
+        assert_eq!(request_sense(&mut storage, &usb), (0x00, 0x00));
+
+        // unsupported command
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(2, 0, Direction::OUT, &[0x1b, 0, 0, 0, 0, 0]),
+            &[],
+        );
+        assert_eq!(result.csw.unwrap().status, CommandStatus::Failed);
+        assert_eq!(request_sense(&mut storage, &usb), (0x05, 0x20));
+
+        // WRITE(10) to a write protected device
+        storage.device_mut().write_protected = true;
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(3, 512, Direction::OUT, &[0x2a, 0, 0, 0, 0, 0, 0, 0, 1, 0]),
+            &[0xff; 512],
+        );
+        assert_eq!(result.csw.unwrap().status, CommandStatus::Failed);
+        assert!(result.stalled);
+        assert_eq!(request_sense(&mut storage, &usb), (0x07, 0x27));
+        assert!(storage.device().blocks[0].iter().all(|&byte| byte == 0));
+
+        // READ(10) with a direction mismatch
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(4, 512, Direction::OUT, &[0x28, 0, 0, 0, 0, 0, 0, 0, 1, 0]),
+            &[],
+        );
+        assert_eq!(result.csw.unwrap().status, CommandStatus::PhaseError);
+    }
+
+    #[test]
+    fn test_invalid_cbw() {
+        let usb = MockUsbDriver::new();
+        let mut storage = storage(8);
+        storage.configure(&usb);
+
+        let mut invalid = cbw(1, 0, Direction::OUT, &[0x00, 0, 0, 0, 0, 0]);
+        invalid[0] = 0;
+        let result = transfer(&mut storage, &usb, &invalid, &[]);
+        assert!(result.stalled);
+        assert!(result.csw.is_none());
+
+        // further commands are ignored until reset recovery
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(2, 0, Direction::OUT, &[0x00, 0, 0, 0, 0, 0]),
+            &[],
+        );
+        assert!(result.csw.is_none());
+
+        let setup_packet = class_request(Direction::OUT, Recipient::Interface, 0xff, 0, 0, 0);
+        storage.handle_setup_packet(&usb, 0, setup_packet);
+
+        let result = transfer(
+            &mut storage,
+            &usb,
+            &cbw(3, 0, Direction::OUT, &[0x00, 0, 0, 0, 0, 0]),
+            &[],
+        );
+        let csw = result.csw.unwrap();
+        assert_eq!(csw.tag, 3);
+        assert_eq!(csw.status, CommandStatus::Passed);
+    }
+}
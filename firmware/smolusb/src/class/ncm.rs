@@ -0,0 +1,1342 @@
+//! USB Communications Device Class - Network Control Model (CDC-NCM)
+//!
+//! Ethernet frames are exchanged with the host in NCM Transfer Blocks
+//! (NTBs) which bundle one or more datagrams behind an NTH16 header
+//! and NDP16 datagram pointer tables. [`pack_ntb16()`] and
+//! [`Ntb16::parse()`] convert between frames and transfer blocks and
+//! [`CdcNcm`] implements the interface itself.
+
+use log::{debug, trace, warn};
+
+use crate::class::{ControlResult, UsbClass};
+use crate::event::UsbEvent;
+use crate::setup::{Direction, Recipient, RequestType, SetupPacket};
+use crate::traits::UsbDriver;
+
+/// Communications interface class code
+pub const INTERFACE_CLASS: u8 = 0x02;
+/// Network Control Model subclass code
+pub const INTERFACE_SUBCLASS: u8 = 0x0d;
+/// Communications interface protocol code
+pub const INTERFACE_PROTOCOL: u8 = 0x00;
+
+/// Data interface class code
+pub const DATA_INTERFACE_CLASS: u8 = 0x0a;
+/// Data interface protocol code for Network Transfer Blocks
+pub const DATA_INTERFACE_PROTOCOL: u8 = 0x01;
+
+/// Ethernet maximum segment size, excluding the frame check sequence
+pub const MAX_SEGMENT_SIZE: u16 = 1514;
+
+/// `bmNetworkCapabilities` of the NCM functional descriptor
+pub mod network_capabilities {
+    pub const SET_ETHERNET_PACKET_FILTER: u8 = 1 << 0;
+    pub const NET_ADDRESS: u8 = 1 << 1;
+    pub const ENCAPSULATED_COMMAND: u8 = 1 << 2;
+    pub const MAX_DATAGRAM_SIZE: u8 = 1 << 3;
+    pub const CRC_MODE: u8 = 1 << 4;
+    pub const NTB_INPUT_SIZE_8_BYTE: u8 = 1 << 5;
+}
+
+/// `SET_ETHERNET_PACKET_FILTER` bits
+pub mod packet_filter {
+    pub const PROMISCUOUS: u16 = 1 << 0;
+    pub const ALL_MULTICAST: u16 = 1 << 1;
+    pub const DIRECTED: u16 = 1 << 2;
+    pub const BROADCAST: u16 = 1 << 3;
+    pub const MULTICAST: u16 = 1 << 4;
+}
+
+// - functional descriptors ---------------------------------------------------
+
+/// Length of the functional descriptors returned by [`functional_descriptors()`].
+pub const FUNCTIONAL_DESCRIPTORS_LENGTH: usize = 5 + 5 + 13 + 6;
+
+/// Returns the Header, Union, Ethernet Networking and NCM functional
+/// descriptors which follow the communications interface descriptor.
+///
+/// `mac_address_index` is the index of a string descriptor holding the
+/// device's MAC address as 12 hexadecimal digits.
+#[must_use]
+pub const fn functional_descriptors(
+    control_interface: u8,
+    data_interface: u8,
+    mac_address_index: u8,
+) -> [u8; FUNCTIONAL_DESCRIPTORS_LENGTH] {
+    let [segment_lo, segment_hi] = MAX_SEGMENT_SIZE.to_le_bytes();
+    [
+        // Header Functional Descriptor
+        5,
+        0x24, // CS_INTERFACE
+        0x00, // Header
+        0x10, // bcdCDC v1.10
+        0x01,
+        // Union Functional Descriptor
+        5,
+        0x24, // CS_INTERFACE
+        0x06, // Union
+        control_interface,
+        data_interface,
+        // Ethernet Networking Functional Descriptor
+        13,
+        0x24, // CS_INTERFACE
+        0x0f, // Ethernet Networking
+        mac_address_index,
+        0x00, // bmEthernetStatistics
+        0x00,
+        0x00,
+        0x00,
+        segment_lo, // wMaxSegmentSize
+        segment_hi,
+        0x00, // wNumberMCFilters
+        0x00,
+        0x00, // bNumberPowerFilters
+        // NCM Functional Descriptor
+        6,
+        0x24, // CS_INTERFACE
+        0x1a, // NCM
+        0x00, // bcdNcmVersion v1.00
+        0x01,
+        network_capabilities::SET_ETHERNET_PACKET_FILTER,
+    ]
+}
+
+// - ClassRequest -------------------------------------------------------------
+
+/// NCM Class Requests
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum ClassRequest {
+    SetEthernetPacketFilter = 0x43,
+    GetNtbParameters = 0x80,
+    GetNetAddress = 0x81,
+    SetNetAddress = 0x82,
+    GetNtbFormat = 0x83,
+    SetNtbFormat = 0x84,
+    GetNtbInputSize = 0x85,
+    SetNtbInputSize = 0x86,
+    GetMaxDatagramSize = 0x87,
+    SetMaxDatagramSize = 0x88,
+    GetCrcMode = 0x89,
+    SetCrcMode = 0x8a,
+    Unknown(u8),
+}
+
+impl From<u8> for ClassRequest {
+    fn from(value: u8) -> Self {
+        match value {
+            0x43 => ClassRequest::SetEthernetPacketFilter,
+            0x80 => ClassRequest::GetNtbParameters,
+            0x81 => ClassRequest::GetNetAddress,
+            0x82 => ClassRequest::SetNetAddress,
+            0x83 => ClassRequest::GetNtbFormat,
+            0x84 => ClassRequest::SetNtbFormat,
+            0x85 => ClassRequest::GetNtbInputSize,
+            0x86 => ClassRequest::SetNtbInputSize,
+            0x87 => ClassRequest::GetMaxDatagramSize,
+            0x88 => ClassRequest::SetMaxDatagramSize,
+            0x89 => ClassRequest::GetCrcMode,
+            0x8a => ClassRequest::SetCrcMode,
+            _ => ClassRequest::Unknown(value),
+        }
+    }
+}
+
+/// NCM Notifications
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum Notification {
+    NetworkConnection = 0x00,
+    ConnectionSpeedChange = 0x2a,
+}
+
+// - NTB16 --------------------------------------------------------------------
+
+/// NTH16 signature, "NCMH"
+pub const NTH16_SIGNATURE: u32 = 0x484d_434e;
+/// NDP16 signature without CRC, "NCM0"
+pub const NDP16_SIGNATURE: u32 = 0x304d_434e;
+/// Length of the NTH16 header
+pub const NTH16_LENGTH: usize = 12;
+/// Alignment of NDP16 tables and datagrams in the NTBs we send
+pub const NTB_ALIGNMENT: usize = 4;
+
+/// Errors returned when parsing a malformed NTB.
+#[derive(Debug, PartialEq)]
+pub enum NtbError {
+    /// The NTB is shorter than its header or block length.
+    TooShort,
+    /// The NTH16 signature or header length is invalid.
+    InvalidHeader,
+    /// The first NDP16 is out of bounds or has an invalid signature.
+    InvalidNdp,
+}
+
+/// A parsed NTB16 transfer block.
+#[derive(Clone, Copy, Debug)]
+pub struct Ntb16<'a> {
+    bytes: &'a [u8],
+    sequence: u16,
+    ndp_index: usize,
+}
+
+impl<'a> Ntb16<'a> {
+    /// Parses and validates the NTH16 header of a transfer block.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`NtbError`] if the header or first datagram pointer
+    /// table is invalid.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, NtbError> {
+        if bytes.len() < NTH16_LENGTH {
+            return Err(NtbError::TooShort);
+        }
+        if read_u32(bytes, 0) != NTH16_SIGNATURE || usize::from(read_u16(bytes, 4)) != NTH16_LENGTH
+        {
+            return Err(NtbError::InvalidHeader);
+        }
+        let block_length = usize::from(read_u16(bytes, 8));
+        if block_length < NTH16_LENGTH {
+            return Err(NtbError::InvalidHeader);
+        }
+        if block_length > bytes.len() {
+            return Err(NtbError::TooShort);
+        }
+        let bytes = &bytes[..block_length];
+
+        let ndp_index = usize::from(read_u16(bytes, 10));
+        if !is_valid_ndp(bytes, ndp_index) {
+            return Err(NtbError::InvalidNdp);
+        }
+
+        Ok(Self {
+            bytes,
+            sequence: read_u16(bytes, 6),
+            ndp_index,
+        })
+    }
+
+    /// Returns the sequence number of the transfer block.
+    #[must_use]
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Returns an iterator over the datagrams in the transfer block.
+    ///
+    /// Iteration stops at the first malformed datagram pointer.
+    #[must_use]
+    pub fn datagrams(&self) -> Datagrams<'a> {
+        Datagrams {
+            bytes: self.bytes,
+            ndp_index: self.ndp_index,
+            entry: 0,
+        }
+    }
+}
+
+/// Iterator over the datagrams of an [`Ntb16`].
+pub struct Datagrams<'a> {
+    bytes: &'a [u8],
+    ndp_index: usize,
+    entry: usize,
+}
+
+impl<'a> Iterator for Datagrams<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // a datagram pointer table can be followed by up to this many others
+        for _ in 0..8 {
+            if self.ndp_index == 0 {
+                return None;
+            }
+            if !is_valid_ndp(self.bytes, self.ndp_index) {
+                warn!("Ntb16 invalid NDP16 at {}", self.ndp_index);
+                self.ndp_index = 0;
+                return None;
+            }
+
+            let ndp_length = usize::from(read_u16(self.bytes, self.ndp_index + 4));
+            let pointer = self.ndp_index + 8 + self.entry * 4;
+            let (index, length) = if pointer + 4 <= self.ndp_index + ndp_length {
+                (
+                    usize::from(read_u16(self.bytes, pointer)),
+                    usize::from(read_u16(self.bytes, pointer + 2)),
+                )
+            } else {
+                (0, 0)
+            };
+
+            // a null entry terminates the table, continue with the next one
+            if index == 0 || length == 0 {
+                self.ndp_index = usize::from(read_u16(self.bytes, self.ndp_index + 6));
+                self.entry = 0;
+                continue;
+            }
+
+            if index < NTH16_LENGTH || index + length > self.bytes.len() {
+                warn!("Ntb16 datagram out of bounds: {}+{}", index, length);
+                self.ndp_index = 0;
+                return None;
+            }
+
+            self.entry += 1;
+            return Some(&self.bytes[index..index + length]);
+        }
+        self.ndp_index = 0;
+        None
+    }
+}
+
+/// Packs `datagrams` into an NTB16 transfer block in `buffer` and
+/// returns its length.
+///
+/// The transfer block consists of the NTH16 header, a single NDP16 and
+/// the datagrams, each aligned to [`NTB_ALIGNMENT`]. Returns `None` if
+/// the transfer block does not fit in `buffer`.
+#[allow(clippy::cast_possible_truncation)]
+pub fn pack_ntb16(buffer: &mut [u8], sequence: u16, datagrams: &[&[u8]]) -> Option<usize> {
+    let ndp_index = NTH16_LENGTH;
+    // datagram pointers are followed by a null entry
+    let ndp_length = (8 + (datagrams.len() + 1) * 4).max(16);
+    let block_length = datagrams
+        .iter()
+        .fold(ndp_index + ndp_length, |offset, datagram| {
+            align(offset) + datagram.len()
+        });
+    if block_length > buffer.len() || block_length > usize::from(u16::MAX) {
+        return None;
+    }
+    buffer[ndp_index..ndp_index + ndp_length].fill(0);
+
+    // lay out the datagrams after the datagram pointer table
+    let mut offset = ndp_index + ndp_length;
+    let mut pointer = ndp_index + 8;
+    for datagram in datagrams {
+        let start = align(offset);
+        buffer[offset..start].fill(0);
+        buffer[start..start + datagram.len()].copy_from_slice(datagram);
+        buffer[pointer..pointer + 2].copy_from_slice(&(start as u16).to_le_bytes());
+        buffer[pointer + 2..pointer + 4].copy_from_slice(&(datagram.len() as u16).to_le_bytes());
+        pointer += 4;
+        offset = start + datagram.len();
+    }
+
+    // NDP16, wNextNdpIndex is left as zero
+    buffer[ndp_index..ndp_index + 4].copy_from_slice(&NDP16_SIGNATURE.to_le_bytes());
+    buffer[ndp_index + 4..ndp_index + 6].copy_from_slice(&(ndp_length as u16).to_le_bytes());
+
+    // NTH16
+    buffer[0..4].copy_from_slice(&NTH16_SIGNATURE.to_le_bytes());
+    buffer[4..6].copy_from_slice(&(NTH16_LENGTH as u16).to_le_bytes());
+    buffer[6..8].copy_from_slice(&sequence.to_le_bytes());
+    buffer[8..10].copy_from_slice(&(block_length as u16).to_le_bytes());
+    buffer[10..12].copy_from_slice(&(ndp_index as u16).to_le_bytes());
+
+    Some(block_length)
+}
+
+// - NcmEvent -----------------------------------------------------------------
+
+/// Events reported by [`CdcNcm`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NcmEvent {
+    /// The host selected the data interface's alternate setting with
+    /// endpoints and the network is available.
+    Enabled,
+    /// The host deselected the data interface's endpoints.
+    Disabled,
+    /// The host changed the Ethernet packet filter.
+    PacketFilterChanged(u16),
+    /// A transfer block with one or more frames was received and can
+    /// be read with [`CdcNcm::receive()`].
+    FramesReceived,
+}
+
+// - CdcNcm -------------------------------------------------------------------
+
+/// A CDC-NCM network interface.
+///
+/// Class requests received by [`Control`](crate::control::Control) are
+/// passed to [`CdcNcm::handle_setup_packet()`], alternate setting
+/// changes to [`CdcNcm::set_alternate_setting()`] and endpoint events
+/// to [`CdcNcm::handle_event()`].
+///
+/// Received transfer blocks and the transfer block being sent are
+/// buffered in two buffers of `NTB_SIZE` bytes which must be large
+/// enough to hold a full Ethernet frame and the NTB headers. Only one
+/// transfer block is received or sent at a time.
+pub struct CdcNcm<const NTB_SIZE: usize> {
+    interface_number: u8,
+    notification_endpoint: u8,
+    data_endpoint_in: u8,
+    data_endpoint_out: u8,
+    max_packet_size: usize,
+
+    enabled: bool,
+    connected: bool,
+    connection_speed: u32,
+    packet_filter: u16,
+    ntb_input_size: usize,
+    notification_pending: Option<Notification>,
+    notification_busy: bool,
+
+    rx_buffer: [u8; NTB_SIZE],
+    rx_length: usize,
+    rx_overflow: bool,
+    rx_datagram: Option<usize>,
+
+    tx_buffer: [u8; NTB_SIZE],
+    tx_length: usize,
+    tx_offset: usize,
+    tx_busy: bool,
+    tx_zlp_pending: bool,
+    tx_sequence: u16,
+}
+
+impl<const NTB_SIZE: usize> CdcNcm<NTB_SIZE> {
+    /// Minimum transfer block size that can hold a full Ethernet frame.
+    const MIN_NTB_SIZE: usize = align(NTH16_LENGTH + 16) + MAX_SEGMENT_SIZE as usize;
+
+    /// Creates a new CDC-NCM network interface for the given
+    /// communications interface and endpoint numbers.
+    #[must_use]
+    pub const fn new(
+        interface_number: u8,
+        notification_endpoint: u8,
+        data_endpoint_in: u8,
+        data_endpoint_out: u8,
+        max_packet_size: usize,
+    ) -> Self {
+        assert!(NTB_SIZE >= Self::MIN_NTB_SIZE, "NTB_SIZE too small");
+        assert!(
+            NTB_SIZE <= u16::MAX as usize,
+            "NTB_SIZE too large for NTB16"
+        );
+        Self {
+            interface_number,
+            notification_endpoint,
+            data_endpoint_in,
+            data_endpoint_out,
+            max_packet_size,
+            enabled: false,
+            connected: false,
+            connection_speed: 0,
+            packet_filter: 0,
+            ntb_input_size: NTB_SIZE,
+            notification_pending: None,
+            notification_busy: false,
+            rx_buffer: [0; NTB_SIZE],
+            rx_length: 0,
+            rx_overflow: false,
+            rx_datagram: None,
+            tx_buffer: [0; NTB_SIZE],
+            tx_length: 0,
+            tx_offset: 0,
+            tx_busy: false,
+            tx_zlp_pending: false,
+            tx_sequence: 0,
+        }
+    }
+
+    /// Returns `true` if the host has enabled the data interface.
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns the current Ethernet packet filter.
+    #[must_use]
+    pub fn packet_filter(&self) -> u16 {
+        self.packet_filter
+    }
+
+    /// Returns the maximum size of the transfer blocks sent to the host.
+    #[must_use]
+    pub fn ntb_input_size(&self) -> usize {
+        self.ntb_input_size
+    }
+
+    /// Returns `true` if a frame can be sent.
+    #[must_use]
+    pub fn can_send(&self) -> bool {
+        self.enabled && !self.tx_busy
+    }
+
+    /// Stops the network interface and discards any buffered data.
+    pub fn reset(&mut self) {
+        self.enabled = false;
+        self.packet_filter = 0;
+        self.ntb_input_size = NTB_SIZE;
+        self.notification_pending = None;
+        self.notification_busy = false;
+        self.rx_length = 0;
+        self.rx_overflow = false;
+        self.rx_datagram = None;
+        self.tx_length = 0;
+        self.tx_offset = 0;
+        self.tx_busy = false;
+        self.tx_zlp_pending = false;
+        self.tx_sequence = 0;
+    }
+
+    /// Returns `true` if the [`SetupPacket`] is a class request for this interface.
+    #[must_use]
+    pub fn is_class_request(&self, setup_packet: &SetupPacket) -> bool {
+        setup_packet.request_type() == RequestType::Class
+            && setup_packet.recipient() == Recipient::Interface
+            && (setup_packet.index & 0xff) as u8 == self.interface_number
+    }
+
+    /// Handles a class request passed on by the control endpoint.
+    ///
+    /// `data` holds any data received from the host during the data
+    /// stage of the request. Requests that are not addressed to this
+    /// interface are ignored.
+    pub fn handle_setup_packet<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &[u8],
+    ) -> Option<NcmEvent>
+    where
+        D: UsbDriver,
+    {
+        if !self.is_class_request(&setup_packet) {
+            return None;
+        }
+
+        let requested_length = usize::from(setup_packet.length);
+        match (
+            setup_packet.direction(),
+            ClassRequest::from(setup_packet.request),
+        ) {
+            (Direction::HostToDevice, ClassRequest::SetEthernetPacketFilter) => {
+                trace!(
+                    "CdcNcm SET_ETHERNET_PACKET_FILTER {:#x}",
+                    setup_packet.value
+                );
+                self.packet_filter = setup_packet.value;
+                usb.write(control_endpoint, [].into_iter());
+                Some(NcmEvent::PacketFilterChanged(self.packet_filter))
+            }
+            (Direction::DeviceToHost, ClassRequest::GetNtbParameters) => {
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    self.ntb_parameters().into_iter().take(requested_length),
+                );
+                None
+            }
+            (Direction::DeviceToHost, ClassRequest::GetNtbFormat) => {
+                // only NTB16 is supported
+                usb.write_requested(control_endpoint, requested_length, [0, 0].into_iter());
+                None
+            }
+            (Direction::HostToDevice, ClassRequest::SetNtbFormat) if setup_packet.value == 0 => {
+                usb.write(control_endpoint, [].into_iter());
+                None
+            }
+            (Direction::DeviceToHost, ClassRequest::GetNtbInputSize) => {
+                #[allow(clippy::cast_possible_truncation)]
+                let ntb_input_size = self.ntb_input_size as u32;
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    ntb_input_size.to_le_bytes().into_iter(),
+                );
+                None
+            }
+            (Direction::HostToDevice, ClassRequest::SetNtbInputSize) => {
+                // the control endpoint has already acknowledged the data stage
+                let Some(bytes) = data.get(..4) else {
+                    warn!("CdcNcm SET_NTB_INPUT_SIZE with invalid data: {:?}", data);
+                    return None;
+                };
+                let ntb_input_size = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+                match usize::try_from(ntb_input_size) {
+                    Ok(size) if (Self::MIN_NTB_SIZE..=NTB_SIZE).contains(&size) => {
+                        trace!("CdcNcm SET_NTB_INPUT_SIZE {}", size);
+                        self.ntb_input_size = size;
+                    }
+                    _ => warn!("CdcNcm unsupported NTB input size: {}", ntb_input_size),
+                }
+                None
+            }
+            (direction, request) => {
+                warn!("CdcNcm stall - unhandled class request {:?}", request);
+                match direction {
+                    Direction::DeviceToHost => usb.stall_endpoint_in(control_endpoint),
+                    Direction::HostToDevice => usb.stall_endpoint_out(control_endpoint),
+                }
+                None
+            }
+        }
+    }
+
+    /// Handles an alternate setting change of the given interface.
+    ///
+    /// The data interface is enabled by selecting its alternate
+    /// setting `1` at which point the current connection state is
+    /// reported to the host.
+    pub fn set_alternate_setting<D>(
+        &mut self,
+        usb: &D,
+        interface_number: u8,
+        alternate_setting: u8,
+    ) -> Option<NcmEvent>
+    where
+        D: UsbDriver,
+    {
+        // the data interface follows the communications interface
+        if interface_number != self.interface_number + 1 {
+            return None;
+        }
+
+        let connected = self.connected;
+        let connection_speed = self.connection_speed;
+        self.reset();
+        self.connected = connected;
+        self.connection_speed = connection_speed;
+
+        if alternate_setting == 0 {
+            debug!("CdcNcm disabled");
+            return Some(NcmEvent::Disabled);
+        }
+
+        debug!("CdcNcm enabled");
+        self.enabled = true;
+        usb.ep_out_prime_receive(self.data_endpoint_out);
+        // hosts expect the connection speed before the connection state
+        self.notification_pending = Some(Notification::NetworkConnection);
+        self.send_notification(usb, Notification::ConnectionSpeedChange);
+        Some(NcmEvent::Enabled)
+    }
+
+    /// Reports the state of the network connection and its speed in
+    /// bits per second to the host.
+    pub fn set_connected<D>(&mut self, usb: &D, connected: bool, connection_speed: u32)
+    where
+        D: UsbDriver,
+    {
+        self.connected = connected;
+        self.connection_speed = connection_speed;
+        if !self.enabled {
+            return;
+        }
+        if self.notification_busy {
+            self.notification_pending = Some(Notification::ConnectionSpeedChange);
+        } else {
+            self.notification_pending = Some(Notification::NetworkConnection);
+            self.send_notification(usb, Notification::ConnectionSpeedChange);
+        }
+    }
+
+    /// Handles a USB event for the notification and data endpoints.
+    pub fn handle_event<D>(&mut self, usb: &D, event: UsbEvent) -> Option<NcmEvent>
+    where
+        D: UsbDriver,
+    {
+        match event {
+            UsbEvent::BusReset => {
+                self.reset();
+                self.connected = false;
+                None
+            }
+            UsbEvent::ReceivePacket(endpoint_number)
+                if self.enabled && endpoint_number == self.data_endpoint_out =>
+            {
+                self.receive_packet(usb)
+            }
+            UsbEvent::SendComplete(endpoint_number)
+                if self.enabled && endpoint_number == self.data_endpoint_in =>
+            {
+                self.tx_busy = false;
+                self.flush(usb);
+                None
+            }
+            UsbEvent::SendComplete(endpoint_number)
+                if self.enabled && endpoint_number == self.notification_endpoint =>
+            {
+                self.notification_busy = false;
+                if let Some(notification) = self.notification_pending.take() {
+                    if notification == Notification::ConnectionSpeedChange {
+                        self.notification_pending = Some(Notification::NetworkConnection);
+                    }
+                    self.send_notification(usb, notification);
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads the next received frame into `buffer` and returns its length.
+    ///
+    /// Frames longer than `buffer` are truncated. Returns `None` once
+    /// all frames of the received transfer block have been read.
+    pub fn receive<D>(&mut self, usb: &D, buffer: &mut [u8]) -> Option<usize>
+    where
+        D: UsbDriver,
+    {
+        let index = self.rx_datagram?;
+        let ntb = Ntb16::parse(&self.rx_buffer[..self.rx_length]).ok()?;
+        let mut datagrams = ntb.datagrams().skip(index);
+        let Some(datagram) = datagrams.next() else {
+            self.next_ntb(usb);
+            return None;
+        };
+
+        let length = datagram.len().min(buffer.len());
+        if length < datagram.len() {
+            warn!(
+                "CdcNcm frame of {} bytes truncated to {}",
+                datagram.len(),
+                length
+            );
+        }
+        buffer[..length].copy_from_slice(&datagram[..length]);
+
+        if datagrams.next().is_some() {
+            self.rx_datagram = Some(index + 1);
+        } else {
+            self.next_ntb(usb);
+        }
+        Some(length)
+    }
+
+    /// Sends a frame to the host and returns `true` if it was queued.
+    ///
+    /// Returns `false` if the interface is not enabled, the previous
+    /// frame is still being sent or the frame is too large.
+    pub fn send<D>(&mut self, usb: &D, frame: &[u8]) -> bool
+    where
+        D: UsbDriver,
+    {
+        if !self.can_send() {
+            return false;
+        }
+
+        let ntb_input_size = self.ntb_input_size;
+        let Some(length) = pack_ntb16(
+            &mut self.tx_buffer[..ntb_input_size],
+            self.tx_sequence,
+            &[frame],
+        ) else {
+            warn!("CdcNcm frame of {} bytes too large", frame.len());
+            return false;
+        };
+        self.tx_sequence = self.tx_sequence.wrapping_add(1);
+        self.tx_length = length;
+        self.tx_offset = 0;
+        self.flush(usb);
+        true
+    }
+
+    fn ntb_parameters(&self) -> [u8; 28] {
+        #[allow(clippy::cast_possible_truncation)]
+        let ntb_size = (NTB_SIZE as u32).to_le_bytes();
+        #[allow(clippy::cast_possible_truncation)]
+        let alignment = (NTB_ALIGNMENT as u16).to_le_bytes();
+        [
+            28, // wLength
+            0,
+            0x01, // bmNtbFormatsSupported: NTB16
+            0x00,
+            ntb_size[0], // dwNtbInMaxSize
+            ntb_size[1],
+            ntb_size[2],
+            ntb_size[3],
+            alignment[0], // wNdpInDivisor
+            alignment[1],
+            0, // wNdpInPayloadRemainder
+            0,
+            alignment[0], // wNdpInAlignment
+            alignment[1],
+            0, // reserved
+            0,
+            ntb_size[0], // dwNtbOutMaxSize
+            ntb_size[1],
+            ntb_size[2],
+            ntb_size[3],
+            alignment[0], // wNdpOutDivisor
+            alignment[1],
+            0, // wNdpOutPayloadRemainder
+            0,
+            alignment[0], // wNdpOutAlignment
+            alignment[1],
+            0, // wNtbOutMaxDatagrams: unlimited
+            0,
+        ]
+    }
+
+    fn send_notification<D>(&mut self, usb: &D, notification: Notification)
+    where
+        D: UsbDriver,
+    {
+        let header = |notification: Notification, value: u16, length: u16| {
+            let [value_lo, value_hi] = value.to_le_bytes();
+            let [length_lo, length_hi] = length.to_le_bytes();
+            [
+                0b1010_0001, // bmRequestType: DeviceToHost | Class | Interface
+                notification as u8,
+                value_lo,
+                value_hi,
+                self.interface_number, // wIndex
+                0x00,
+                length_lo,
+                length_hi,
+            ]
+        };
+
+        trace!("CdcNcm notification {:?}", notification);
+        self.notification_busy = true;
+        match notification {
+            Notification::NetworkConnection => {
+                let notification = header(notification, u16::from(self.connected), 0);
+                usb.write(self.notification_endpoint, notification.into_iter());
+            }
+            Notification::ConnectionSpeedChange => {
+                // downstream and upstream bit rates
+                let speed = self.connection_speed.to_le_bytes();
+                let notification = header(notification, 0, 8)
+                    .into_iter()
+                    .chain(speed)
+                    .chain(speed);
+                usb.write(self.notification_endpoint, notification);
+            }
+        }
+    }
+
+    fn receive_packet<D>(&mut self, usb: &D) -> Option<NcmEvent>
+    where
+        D: UsbDriver,
+    {
+        let mut packet_buffer = [0; crate::EP_MAX_PACKET_SIZE];
+        let bytes_read = usb.read(self.data_endpoint_out, &mut packet_buffer);
+        if self.rx_length + bytes_read <= NTB_SIZE {
+            self.rx_buffer[self.rx_length..self.rx_length + bytes_read]
+                .copy_from_slice(&packet_buffer[..bytes_read]);
+            self.rx_length += bytes_read;
+        } else {
+            self.rx_overflow = true;
+        }
+
+        // a transfer block ends with a short packet or once its block length has been received
+        let block_length = if self.rx_length >= NTH16_LENGTH {
+            usize::from(read_u16(&self.rx_buffer, 8))
+        } else {
+            usize::MAX
+        };
+        if bytes_read == self.max_packet_size && self.rx_length < block_length {
+            usb.ep_out_prime_receive(self.data_endpoint_out);
+            return None;
+        }
+
+        if self.rx_overflow {
+            warn!("CdcNcm receive buffer overflow, dropped transfer block");
+            self.next_ntb(usb);
+            return None;
+        }
+        match Ntb16::parse(&self.rx_buffer[..self.rx_length]) {
+            Ok(ntb) if ntb.datagrams().next().is_some() => {
+                // wait for the frames to be read before receiving the next transfer block
+                self.rx_datagram = Some(0);
+                Some(NcmEvent::FramesReceived)
+            }
+            Ok(_) => {
+                self.next_ntb(usb);
+                None
+            }
+            Err(e) => {
+                warn!("CdcNcm dropped invalid transfer block: {:?}", e);
+                self.next_ntb(usb);
+                None
+            }
+        }
+    }
+
+    fn next_ntb<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        self.rx_length = 0;
+        self.rx_overflow = false;
+        self.rx_datagram = None;
+        usb.ep_out_prime_receive(self.data_endpoint_out);
+    }
+
+    fn flush<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        if self.tx_busy {
+            return;
+        }
+
+        if self.tx_offset == self.tx_length {
+            // terminate a transfer that ended on a packet boundary
+            if self.tx_zlp_pending {
+                self.tx_zlp_pending = false;
+                self.tx_busy = true;
+                usb.write(self.data_endpoint_in, [].into_iter());
+            }
+            return;
+        }
+
+        let packet_length = (self.tx_length - self.tx_offset).min(self.max_packet_size);
+        let bytes_written = usb.write_with_packet_size(
+            self.data_endpoint_in,
+            Some(packet_length),
+            self.tx_buffer[self.tx_offset..self.tx_offset + packet_length]
+                .iter()
+                .copied(),
+            self.max_packet_size,
+        );
+        if bytes_written > 0 {
+            self.tx_offset += bytes_written;
+            self.tx_busy = true;
+            // no short packet is needed for a maximum size transfer block
+            self.tx_zlp_pending = bytes_written == self.max_packet_size
+                && self.tx_offset == self.tx_length
+                && self.tx_length < self.ntb_input_size;
+        }
+    }
+}
+
+impl<D, const NTB_SIZE: usize> UsbClass<D> for CdcNcm<NTB_SIZE>
+where
+    D: UsbDriver,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        // the data interface follows the communications interface
+        interface_number == self.interface_number || interface_number == self.interface_number + 1
+    }
+
+    fn has_endpoint(&self, endpoint_address: u8) -> bool {
+        endpoint_address == self.notification_endpoint | 0x80
+            || endpoint_address == self.data_endpoint_in | 0x80
+            || endpoint_address == self.data_endpoint_out
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        data: &'d [u8],
+    ) -> ControlResult<'d> {
+        if !self.is_class_request(&setup_packet) {
+            return ControlResult::Unhandled;
+        }
+        self.handle_setup_packet(usb, control_endpoint, setup_packet, data).into()
+    }
+
+    fn handle_endpoint_event(&mut self, usb: &D, event: UsbEvent) {
+        self.handle_event(usb, event);
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+        self.connected = false;
+    }
+
+    fn configuration_changed(&mut self, _usb: &D, _configuration: u8) {
+        self.reset();
+    }
+
+    fn alternate_setting_changed(&mut self, usb: &D, interface_number: u8, alternate_setting: u8) {
+        self.set_alternate_setting(usb, interface_number, alternate_setting);
+    }
+}
+
+// - helpers ------------------------------------------------------------------
+
+const fn align(offset: usize) -> usize {
+    (offset + NTB_ALIGNMENT - 1) & !(NTB_ALIGNMENT - 1)
+}
+
+fn is_valid_ndp(bytes: &[u8], ndp_index: usize) -> bool {
+    ndp_index >= NTH16_LENGTH
+        && ndp_index % 4 == 0
+        && ndp_index + 8 <= bytes.len()
+        && read_u32(bytes, ndp_index) == NDP16_SIGNATURE
+        && usize::from(read_u16(bytes, ndp_index + 4)) >= 16
+        && ndp_index + usize::from(read_u16(bytes, ndp_index + 4)) <= bytes.len()
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::descriptor::{
+        ConfigurationDescriptor, ConfigurationDescriptorHeader, DescriptorType, EndpointDescriptor,
+        InterfaceDescriptor, InterfaceDescriptorHeader,
+    };
+    use crate::testing::{class_request, HostError, MockUsbDriver, Operation, VirtualHost};
+
+    // - fixtures -------------------------------------------------------------
+
+    const NTB_SIZE: usize = 2048;
+
+    const FUNCTIONAL_DESCRIPTORS: [u8; FUNCTIONAL_DESCRIPTORS_LENGTH] =
+        functional_descriptors(0, 1, 4);
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[
+            InterfaceDescriptor::new_class(
+                InterfaceDescriptorHeader {
+                    iInterfaceNumber: 0,
+                    bInterfaceClass: INTERFACE_CLASS,
+                    bInterfaceSubClass: INTERFACE_SUBCLASS,
+                    bInterfaceProtocol: INTERFACE_PROTOCOL,
+                    ..InterfaceDescriptorHeader::new()
+                },
+                &FUNCTIONAL_DESCRIPTORS,
+                &[EndpointDescriptor {
+                    bEndpointAddress: 0x81,
+                    bmAttributes: 0x03, // Interrupt
+                    wMaxPacketSize: 16,
+                    bInterval: 11,
+                    ..EndpointDescriptor::new()
+                }],
+            ),
+            InterfaceDescriptor::new(
+                InterfaceDescriptorHeader {
+                    iInterfaceNumber: 1,
+                    bAlternateSetting: 0,
+                    bInterfaceClass: DATA_INTERFACE_CLASS,
+                    bInterfaceProtocol: DATA_INTERFACE_PROTOCOL,
+                    ..InterfaceDescriptorHeader::new()
+                },
+                &[],
+            ),
+            InterfaceDescriptor::new(
+                InterfaceDescriptorHeader {
+                    iInterfaceNumber: 1,
+                    bAlternateSetting: 1,
+                    bInterfaceClass: DATA_INTERFACE_CLASS,
+                    bInterfaceProtocol: DATA_INTERFACE_PROTOCOL,
+                    ..InterfaceDescriptorHeader::new()
+                },
+                &[
+                    EndpointDescriptor {
+                        bEndpointAddress: 0x82,
+                        bmAttributes: 0x02, // Bulk
+                        wMaxPacketSize: 64,
+                        ..EndpointDescriptor::new()
+                    },
+                    EndpointDescriptor {
+                        bEndpointAddress: 0x02,
+                        bmAttributes: 0x02, // Bulk
+                        wMaxPacketSize: 64,
+                        ..EndpointDescriptor::new()
+                    },
+                ],
+            ),
+        ],
+    )];
+
+    fn enabled_ncm(usb: &MockUsbDriver) -> CdcNcm<NTB_SIZE> {
+        let mut ncm = CdcNcm::new(0, 1, 2, 2, 64);
+        assert_eq!(
+            ncm.set_alternate_setting(usb, 1, 1),
+            Some(NcmEvent::Enabled)
+        );
+        ncm.handle_event(usb, UsbEvent::SendComplete(1));
+        ncm.handle_event(usb, UsbEvent::SendComplete(1));
+        usb.take_operations();
+        ncm
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_functional_descriptors() {
+        assert_eq!(
+            FUNCTIONAL_DESCRIPTORS,
+            [
+                0x05, 0x24, 0x00, 0x10, 0x01, // Header
+                0x05, 0x24, 0x06, 0x00, 0x01, // Union
+                0x0d, 0x24, 0x0f, 0x04, 0x00, 0x00, 0x00, 0x00, 0xea, 0x05, 0x00, 0x00,
+                0x00, // Ethernet Networking
+                0x06, 0x24, 0x1a, 0x00, 0x01, 0x01, // NCM
+            ]
+        );
+
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let configuration = host
+            .get_descriptor(DescriptorType::Configuration, 0, 0, 255)
+            .expect("configuration descriptor");
+        assert_eq!(&configuration[18..47], FUNCTIONAL_DESCRIPTORS);
+    }
+
+    #[test]
+    fn test_ntb16() {
+        let first: Vec<u8> = (0..61).collect();
+        let second: Vec<u8> = (100..200).collect();
+        let mut buffer = [0; 256];
+        let length = pack_ntb16(&mut buffer, 7, &[&first, &second]).unwrap();
+
+        // header, pointer table with a null entry and datagrams aligned to 4 bytes
+        assert_eq!(&buffer[..4], b"NCMH");
+        assert_eq!(&buffer[12..16], b"NCM0");
+        assert_eq!(&buffer[20..24], &[32, 0, 61, 0]);
+        assert_eq!(&buffer[24..28], &[96, 0, 100, 0]);
+        assert_eq!(length, 196);
+
+        let ntb = Ntb16::parse(&buffer[..length]).unwrap();
+        assert_eq!(ntb.sequence(), 7);
+        let datagrams: Vec<&[u8]> = ntb.datagrams().collect();
+        assert_eq!(datagrams, [&first[..], &second[..]]);
+
+        // trailing bytes after the block length are ignored
+        assert_eq!(Ntb16::parse(&buffer).unwrap().datagrams().count(), 2);
+
+        assert_eq!(pack_ntb16(&mut buffer[..150], 0, &[&first, &second]), None);
+        assert_eq!(Ntb16::parse(&buffer[..100]).err(), Some(NtbError::TooShort));
+        let mut invalid = buffer;
+        invalid[0] = b'X';
+        assert_eq!(Ntb16::parse(&invalid).err(), Some(NtbError::InvalidHeader));
+        let mut invalid = buffer;
+        invalid[10] = 14;
+        assert_eq!(Ntb16::parse(&invalid).err(), Some(NtbError::InvalidNdp));
+
+        // a datagram pointing past the end of the block ends iteration
+        let mut invalid = buffer;
+        invalid[26] = 0xff;
+        let ntb = Ntb16::parse(&invalid[..length]).unwrap();
+        assert_eq!(ntb.datagrams().count(), 1);
+    }
+
+    #[test]
+    fn test_ntb16_chained_ndp() {
+        let mut ntb = Vec::new();
+        ntb.extend_from_slice(b"NCMH");
+        ntb.extend_from_slice(&[12, 0, 1, 0, 56, 0, 12, 0]);
+        // first NDP16 pointing at the second one
+        ntb.extend_from_slice(b"NCM0");
+        ntb.extend_from_slice(&[16, 0, 40, 0, 28, 0, 4, 0, 0, 0, 0, 0]);
+        ntb.extend_from_slice(&[1, 2, 3, 4]);
+        ntb.extend_from_slice(&[0; 8]);
+        // second NDP16
+        ntb.extend_from_slice(b"NCM0");
+        ntb.extend_from_slice(&[16, 0, 0, 0, 32, 0, 2, 0, 0, 0, 0, 0]);
+        assert_eq!(ntb.len(), 56);
+
+        let ntb = Ntb16::parse(&ntb).unwrap();
+        let datagrams: Vec<&[u8]> = ntb.datagrams().collect();
+        assert_eq!(datagrams, [&[1, 2, 3, 4][..], &[0, 0][..]]);
+    }
+
+    #[test]
+    fn test_class_requests() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut ncm: CdcNcm<NTB_SIZE> = CdcNcm::new(0, 1, 2, 2, 64);
+
+        let data = host
+            .control_in_with(
+                class_request(
+                    Direction::DeviceToHost,
+                    Recipient::Interface,
+                    0x80,
+                    0,
+                    0,
+                    28,
+                ),
+                |usb, setup_packet, data| {
+                    ncm.handle_setup_packet(usb, 0, setup_packet, data);
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            data,
+            [28, 0, 1, 0, 0, 8, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 0, 8, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0]
+        );
+
+        let mut event = None;
+        host.control_out_with(
+            class_request(
+                Direction::HostToDevice,
+                Recipient::Interface,
+                0x43,
+                0x000e,
+                0,
+                0,
+            ),
+            &[],
+            |usb, setup_packet, data| {
+                event = ncm.handle_setup_packet(usb, 0, setup_packet, data);
+            },
+        )
+        .unwrap();
+        assert_eq!(event, Some(NcmEvent::PacketFilterChanged(0x000e)));
+
+        host.control_out_with(
+            class_request(Direction::HostToDevice, Recipient::Interface, 0x86, 0, 0, 4),
+            &1600_u32.to_le_bytes(),
+            |usb, setup_packet, data| {
+                ncm.handle_setup_packet(usb, 0, setup_packet, data);
+            },
+        )
+        .unwrap();
+        assert_eq!(ncm.ntb_input_size(), 1600);
+        let data = host
+            .control_in_with(
+                class_request(Direction::DeviceToHost, Recipient::Interface, 0x85, 0, 0, 4),
+                |usb, setup_packet, data| {
+                    ncm.handle_setup_packet(usb, 0, setup_packet, data);
+                },
+            )
+            .unwrap();
+        assert_eq!(data, 1600_u32.to_le_bytes());
+
+        // NTB32 is not supported
+        let result = host.control_out_with(
+            class_request(Direction::HostToDevice, Recipient::Interface, 0x84, 1, 0, 0),
+            &[],
+            |usb, setup_packet, data| {
+                ncm.handle_setup_packet(usb, 0, setup_packet, data);
+            },
+        );
+        assert!(matches!(result, Err(HostError::Stalled(_))));
+    }
+
+    #[test]
+    fn test_notifications() {
+        let usb = MockUsbDriver::new();
+        let mut ncm: CdcNcm<NTB_SIZE> = CdcNcm::new(0, 1, 2, 2, 64);
+        ncm.set_connected(&usb, true, 100_000_000);
+        assert!(usb.writes(1).is_empty());
+
+        assert_eq!(
+            ncm.set_alternate_setting(&usb, 1, 1),
+            Some(NcmEvent::Enabled)
+        );
+        assert!(usb.operations().contains(&Operation::PrimeReceive(2)));
+        ncm.handle_event(&usb, UsbEvent::SendComplete(1));
+        ncm.handle_event(&usb, UsbEvent::SendComplete(1));
+        assert_eq!(
+            usb.writes(1),
+            [
+                Vec::from([
+                    0xa1, 0x2a, 0, 0, 0, 0, 8, 0, 0x00, 0xe1, 0xf5, 0x05, 0x00, 0xe1, 0xf5, 0x05
+                ]),
+                Vec::from([0xa1, 0x00, 1, 0, 0, 0, 0, 0]),
+            ]
+        );
+
+        // a change while a notification is in flight is sent once it completes
+        usb.take_operations();
+        ncm.set_connected(&usb, false, 0);
+        ncm.set_connected(&usb, false, 0);
+        ncm.handle_event(&usb, UsbEvent::SendComplete(1));
+        ncm.handle_event(&usb, UsbEvent::SendComplete(1));
+        ncm.handle_event(&usb, UsbEvent::SendComplete(1));
+        let writes = usb.writes(1);
+        assert_eq!(writes.len(), 3);
+        assert_eq!(writes[2], [0xa1, 0x00, 0, 0, 0, 0, 0, 0]);
+
+        assert_eq!(
+            ncm.set_alternate_setting(&usb, 1, 0),
+            Some(NcmEvent::Disabled)
+        );
+        assert!(!ncm.is_enabled());
+    }
+
+    #[test]
+    fn test_receive() {
+        let usb = MockUsbDriver::new();
+        let mut ncm = enabled_ncm(&usb);
+
+        let first: Vec<u8> = (0..100).collect();
+        let second: Vec<u8> = (0..60).rev().collect();
+        let mut ntb = [0; 256];
+        let length = pack_ntb16(&mut ntb, 0, &[&first, &second]).unwrap();
+        assert_eq!(length, 192);
+
+        // the final full packet completes the transfer block without a short packet
+        for (index, packet) in ntb[..length].chunks(64).enumerate() {
+            usb.push_out_data(2, packet);
+            let event = ncm.handle_event(&usb, UsbEvent::ReceivePacket(2));
+            if index < 2 {
+                assert_eq!(event, None);
+            } else {
+                assert_eq!(event, Some(NcmEvent::FramesReceived));
+            }
+        }
+        assert_eq!(
+            usb.take_operations()
+                .into_iter()
+                .filter(|operation| *operation == Operation::PrimeReceive(2))
+                .count(),
+            2
+        );
+
+        let mut frame = [0; 1514];
+        assert_eq!(ncm.receive(&usb, &mut frame), Some(100));
+        assert_eq!(&frame[..100], first);
+        assert!(usb.operations().is_empty());
+        assert_eq!(ncm.receive(&usb, &mut frame), Some(60));
+        assert_eq!(&frame[..60], second);
+        assert_eq!(usb.take_operations(), [Operation::PrimeReceive(2)]);
+        assert_eq!(ncm.receive(&usb, &mut frame), None);
+
+        // invalid transfer blocks are dropped
+        usb.push_out_data(2, &[0; 20]);
+        assert_eq!(ncm.handle_event(&usb, UsbEvent::ReceivePacket(2)), None);
+        assert_eq!(
+            usb.take_operations(),
+            [Operation::Read(2, 20), Operation::PrimeReceive(2)]
+        );
+        assert_eq!(ncm.receive(&usb, &mut frame), None);
+    }
+
+    #[test]
+    fn test_send() {
+        let usb = MockUsbDriver::new();
+        let mut ncm = enabled_ncm(&usb);
+
+        // a 100 byte frame fills two packets exactly and needs a zero length packet
+        let frame: Vec<u8> = (0..100).collect();
+        assert!(ncm.send(&usb, &frame));
+        assert!(!ncm.send(&usb, &frame));
+        ncm.handle_event(&usb, UsbEvent::SendComplete(2));
+        ncm.handle_event(&usb, UsbEvent::SendComplete(2));
+        assert!(!ncm.can_send());
+        ncm.handle_event(&usb, UsbEvent::SendComplete(2));
+        assert!(ncm.can_send());
+
+        let writes = usb.writes(2);
+        assert_eq!(writes.iter().map(Vec::len).collect::<Vec<_>>(), [64, 64, 0]);
+        let ntb: Vec<u8> = writes.concat();
+        let ntb = Ntb16::parse(&ntb).unwrap();
+        assert_eq!(ntb.sequence(), 0);
+        assert_eq!(ntb.datagrams().collect::<Vec<_>>(), [&frame[..]]);
+
+        assert!(!ncm.send(&usb, &[0; 2048]));
+    }
+}
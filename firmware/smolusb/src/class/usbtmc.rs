@@ -0,0 +1,1231 @@
+//! USB Test and Measurement Class (USBTMC) with the USB488 subclass
+//!
+//! Device dependent messages, e.g. SCPI commands such as `*IDN?`,
+//! received from the host are passed to a [`CommandHandler`] and its
+//! responses are returned to the host on request.
+
+use log::{debug, trace, warn};
+
+use crate::class::{ControlResult, UsbClass};
+use crate::event::UsbEvent;
+use crate::setup::{Direction, Recipient, RequestType, SetupPacket};
+use crate::traits::UsbDriver;
+
+/// Application specific interface class code
+pub const INTERFACE_CLASS: u8 = 0xfe;
+/// USBTMC interface subclass code
+pub const INTERFACE_SUBCLASS: u8 = 0x03;
+/// USB488 interface protocol code
+pub const INTERFACE_PROTOCOL_USB488: u8 = 0x01;
+
+/// Length of the bulk transfer message headers
+pub const HEADER_LENGTH: usize = 12;
+
+/// USB488 status byte bit indicating a response is available
+pub const STATUS_BYTE_MAV: u8 = 1 << 4;
+
+// - MessageId ----------------------------------------------------------------
+
+/// Bulk transfer message identifiers
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum MessageId {
+    DevDepMsgOut = 1,
+    RequestDevDepMsgIn = 2,
+    VendorSpecificOut = 126,
+    RequestVendorSpecificIn = 127,
+    Trigger = 128,
+    Unknown(u8),
+}
+
+impl From<u8> for MessageId {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => MessageId::DevDepMsgOut,
+            2 => MessageId::RequestDevDepMsgIn,
+            126 => MessageId::VendorSpecificOut,
+            127 => MessageId::RequestVendorSpecificIn,
+            128 => MessageId::Trigger,
+            _ => MessageId::Unknown(value),
+        }
+    }
+}
+
+/// `bmTransferAttributes` bits
+pub mod transfer_attributes {
+    /// The last byte of the transfer is the end of the message.
+    pub const EOM: u8 = 1 << 0;
+    /// The host requests the transfer to end at the termination character.
+    pub const TERM_CHAR_ENABLED: u8 = 1 << 1;
+    /// The transfer ended at the termination character.
+    pub const TERM_CHAR: u8 = 1 << 1;
+}
+
+// - ClassRequest -------------------------------------------------------------
+
+/// USBTMC and USB488 Class Requests
+#[derive(Debug, PartialEq)]
+#[repr(u8)]
+pub enum ClassRequest {
+    InitiateAbortBulkOut = 1,
+    CheckAbortBulkOutStatus = 2,
+    InitiateAbortBulkIn = 3,
+    CheckAbortBulkInStatus = 4,
+    InitiateClear = 5,
+    CheckClearStatus = 6,
+    GetCapabilities = 7,
+    IndicatorPulse = 64,
+    ReadStatusByte = 128,
+    RenControl = 160,
+    GoToLocal = 161,
+    LocalLockout = 162,
+    Unknown(u8) = 0,
+}
+
+impl From<u8> for ClassRequest {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => ClassRequest::InitiateAbortBulkOut,
+            2 => ClassRequest::CheckAbortBulkOutStatus,
+            3 => ClassRequest::InitiateAbortBulkIn,
+            4 => ClassRequest::CheckAbortBulkInStatus,
+            5 => ClassRequest::InitiateClear,
+            6 => ClassRequest::CheckClearStatus,
+            7 => ClassRequest::GetCapabilities,
+            64 => ClassRequest::IndicatorPulse,
+            128 => ClassRequest::ReadStatusByte,
+            160 => ClassRequest::RenControl,
+            161 => ClassRequest::GoToLocal,
+            162 => ClassRequest::LocalLockout,
+            _ => ClassRequest::Unknown(value),
+        }
+    }
+}
+
+/// Class request status values
+pub mod status {
+    pub const SUCCESS: u8 = 0x01;
+    pub const PENDING: u8 = 0x02;
+    pub const INTERRUPT_IN_BUSY: u8 = 0x20;
+    pub const FAILED: u8 = 0x80;
+    pub const TRANSFER_NOT_IN_PROGRESS: u8 = 0x81;
+    pub const SPLIT_NOT_IN_PROGRESS: u8 = 0x82;
+    pub const SPLIT_IN_PROGRESS: u8 = 0x83;
+}
+
+// - CommandHandler -----------------------------------------------------------
+
+/// Application hook for the messages sent to a [`UsbTmc`] interface.
+pub trait CommandHandler {
+    /// Handles a complete device dependent message from the host, e.g.
+    /// a SCPI command, and writes any response to `response`.
+    ///
+    /// Returns the length of the response.
+    fn command(&mut self, message: &[u8], response: &mut [u8]) -> usize;
+
+    /// Handles a USB488 `TRIGGER` message.
+    fn trigger(&mut self) {}
+
+    /// Handles an `INITIATE_CLEAR` request by clearing any device state.
+    fn clear(&mut self) {}
+
+    /// Handles an `INDICATOR_PULSE` request, e.g. by blinking an LED.
+    fn indicator_pulse(&mut self) {}
+
+    /// Returns the USB488 status byte. The MAV bit is set by [`UsbTmc`].
+    fn status_byte(&mut self) -> u8 {
+        0
+    }
+}
+
+// - UsbTmc -------------------------------------------------------------------
+
+/// A USBTMC interface implementing the USB488 subclass.
+///
+/// Class requests received by [`Control`](crate::control::Control) are
+/// passed to [`UsbTmc::handle_setup_packet()`] and endpoint events to
+/// [`UsbTmc::handle_event()`]. Messages and responses are buffered in
+/// buffers of `BUFFER_SIZE` bytes.
+pub struct UsbTmc<H, const BUFFER_SIZE: usize> {
+    interface_number: u8,
+    endpoint_in: u8,
+    endpoint_out: u8,
+    endpoint_interrupt: Option<u8>,
+    max_packet_size: usize,
+    indicator_pulse: bool,
+    handler: H,
+
+    // bulk-out
+    out_header: [u8; HEADER_LENGTH],
+    out_header_length: usize,
+    out_tag: u8,
+    out_remaining: usize,
+    out_transferred: u32,
+    out_eom: bool,
+    message: [u8; BUFFER_SIZE],
+    message_length: usize,
+    message_overflow: bool,
+
+    // bulk-in
+    response: [u8; BUFFER_SIZE],
+    response_length: usize,
+    response_offset: usize,
+    in_header: [u8; HEADER_LENGTH],
+    in_tag: u8,
+    in_data_length: usize,
+    in_transfer_length: usize,
+    in_requested_length: usize,
+    in_sent: usize,
+    in_busy: bool,
+    in_zlp_pending: bool,
+    in_transferred: u32,
+
+    interrupt_busy: bool,
+}
+
+impl<H, const BUFFER_SIZE: usize> UsbTmc<H, BUFFER_SIZE>
+where
+    H: CommandHandler,
+{
+    /// Creates a new USBTMC interface for the given bulk endpoint numbers.
+    #[must_use]
+    pub const fn new(
+        interface_number: u8,
+        endpoint_in: u8,
+        endpoint_out: u8,
+        max_packet_size: usize,
+        handler: H,
+    ) -> Self {
+        Self {
+            interface_number,
+            endpoint_in,
+            endpoint_out,
+            endpoint_interrupt: None,
+            max_packet_size,
+            indicator_pulse: false,
+            handler,
+            out_header: [0; HEADER_LENGTH],
+            out_header_length: 0,
+            out_tag: 0,
+            out_remaining: 0,
+            out_transferred: 0,
+            out_eom: false,
+            message: [0; BUFFER_SIZE],
+            message_length: 0,
+            message_overflow: false,
+            response: [0; BUFFER_SIZE],
+            response_length: 0,
+            response_offset: 0,
+            in_header: [0; HEADER_LENGTH],
+            in_tag: 0,
+            in_data_length: 0,
+            in_transfer_length: 0,
+            in_requested_length: 0,
+            in_sent: 0,
+            in_busy: false,
+            in_zlp_pending: false,
+            in_transferred: 0,
+            interrupt_busy: false,
+        }
+    }
+
+    /// Returns the interface with an interrupt endpoint used for USB488
+    /// status byte and service request notifications.
+    #[must_use]
+    pub const fn with_interrupt_endpoint(mut self, endpoint_interrupt: u8) -> Self {
+        self.endpoint_interrupt = Some(endpoint_interrupt);
+        self
+    }
+
+    /// Returns the interface with support for the `INDICATOR_PULSE` request.
+    #[must_use]
+    pub const fn with_indicator_pulse(mut self) -> Self {
+        self.indicator_pulse = true;
+        self
+    }
+
+    #[must_use]
+    pub fn handler(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn handler_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    /// Returns `true` if a response is waiting to be read by the host.
+    #[must_use]
+    pub fn response_available(&self) -> bool {
+        self.response_offset < self.response_length
+    }
+
+    /// Starts the interface once the host has selected a configuration
+    /// containing it.
+    pub fn configure<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        self.reset();
+        usb.ep_out_prime_receive(self.endpoint_out);
+    }
+
+    /// Returns the interface to its initial state.
+    pub fn reset(&mut self) {
+        self.abort_bulk_out();
+        self.abort_bulk_in();
+        self.in_busy = false;
+        self.interrupt_busy = false;
+    }
+
+    /// Returns `true` if the [`SetupPacket`] is a class request for this
+    /// interface or one of its bulk endpoints.
+    #[must_use]
+    pub fn is_class_request(&self, setup_packet: &SetupPacket) -> bool {
+        let index = (setup_packet.index & 0xff) as u8;
+        setup_packet.request_type() == RequestType::Class
+            && match setup_packet.recipient() {
+                Recipient::Interface => index == self.interface_number,
+                Recipient::Endpoint => {
+                    index == self.endpoint_out || index == self.endpoint_in | 0x80
+                }
+                _ => false,
+            }
+    }
+
+    /// Handles a class request passed on by the control endpoint.
+    ///
+    /// Requests that are not addressed to this interface are ignored.
+    pub fn handle_setup_packet<D>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+    ) where
+        D: UsbDriver,
+    {
+        if !self.is_class_request(&setup_packet) {
+            return;
+        }
+
+        let request = ClassRequest::from(setup_packet.request);
+        let tag = (setup_packet.value & 0xff) as u8;
+        let requested_length = usize::from(setup_packet.length);
+        let recipient = setup_packet.recipient();
+        trace!("UsbTmc {:?} tag:{}", request, tag);
+
+        let response: Option<([u8; 24], usize)> =
+            match (setup_packet.direction(), recipient, request) {
+                (
+                    Direction::DeviceToHost,
+                    Recipient::Endpoint,
+                    ClassRequest::InitiateAbortBulkOut,
+                ) => {
+                    let status = if self.out_remaining == 0 {
+                        status::FAILED
+                    } else if tag != self.out_tag {
+                        status::TRANSFER_NOT_IN_PROGRESS
+                    } else {
+                        debug!("UsbTmc abort bulk-out tag:{}", tag);
+                        self.abort_bulk_out();
+                        usb.stall_endpoint_out(self.endpoint_out);
+                        status::SUCCESS
+                    };
+                    Some(response(&[status, self.out_tag]))
+                }
+                (
+                    Direction::DeviceToHost,
+                    Recipient::Endpoint,
+                    ClassRequest::CheckAbortBulkOutStatus,
+                ) => {
+                    let [b0, b1, b2, b3] = self.out_transferred.to_le_bytes();
+                    Some(response(&[status::SUCCESS, 0, 0, 0, b0, b1, b2, b3]))
+                }
+                (
+                    Direction::DeviceToHost,
+                    Recipient::Endpoint,
+                    ClassRequest::InitiateAbortBulkIn,
+                ) => {
+                    let status = if self.in_transfer_length == 0 {
+                        status::FAILED
+                    } else if tag != self.in_tag {
+                        status::TRANSFER_NOT_IN_PROGRESS
+                    } else {
+                        debug!("UsbTmc abort bulk-in tag:{}", tag);
+                        // terminate the transfer with a short packet
+                        let zlp_required =
+                            self.in_sent > 0 && self.in_sent % self.max_packet_size == 0;
+                        self.abort_bulk_in();
+                        self.in_zlp_pending = zlp_required;
+                        self.flush(usb);
+                        status::SUCCESS
+                    };
+                    Some(response(&[status, self.in_tag]))
+                }
+                (
+                    Direction::DeviceToHost,
+                    Recipient::Endpoint,
+                    ClassRequest::CheckAbortBulkInStatus,
+                ) => {
+                    let status = if self.in_busy {
+                        status::PENDING
+                    } else {
+                        status::SUCCESS
+                    };
+                    let [b0, b1, b2, b3] = self.in_transferred.to_le_bytes();
+                    Some(response(&[status, 0, 0, 0, b0, b1, b2, b3]))
+                }
+                (Direction::DeviceToHost, Recipient::Interface, ClassRequest::InitiateClear) => {
+                    debug!("UsbTmc clear");
+                    self.abort_bulk_out();
+                    self.abort_bulk_in();
+                    self.handler.clear();
+                    Some(response(&[status::SUCCESS]))
+                }
+                (Direction::DeviceToHost, Recipient::Interface, ClassRequest::CheckClearStatus) => {
+                    Some(response(&[status::SUCCESS, 0]))
+                }
+                (Direction::DeviceToHost, Recipient::Interface, ClassRequest::GetCapabilities) => {
+                    Some((self.capabilities(), 24))
+                }
+                (Direction::DeviceToHost, Recipient::Interface, ClassRequest::IndicatorPulse)
+                    if self.indicator_pulse =>
+                {
+                    self.handler.indicator_pulse();
+                    Some(response(&[status::SUCCESS]))
+                }
+                (Direction::DeviceToHost, Recipient::Interface, ClassRequest::ReadStatusByte) => {
+                    let tag = tag & 0x7f;
+                    let status_byte = self.status_byte();
+                    match self.endpoint_interrupt {
+                        // the status byte is returned on the interrupt endpoint
+                        Some(_) if self.interrupt_busy => {
+                            Some(response(&[status::INTERRUPT_IN_BUSY, tag, 0]))
+                        }
+                        Some(endpoint_interrupt) => {
+                            self.interrupt_busy = true;
+                            usb.write(endpoint_interrupt, [0x80 | tag, status_byte].into_iter());
+                            Some(response(&[status::SUCCESS, tag, 0]))
+                        }
+                        None => Some(response(&[status::SUCCESS, tag, status_byte])),
+                    }
+                }
+                _ => None,
+            };
+
+        match response {
+            Some((bytes, length)) => {
+                usb.write_requested(
+                    control_endpoint,
+                    requested_length,
+                    bytes.into_iter().take(length.min(requested_length)),
+                );
+            }
+            None => {
+                warn!(
+                    "UsbTmc stall - unhandled class request {:?}",
+                    ClassRequest::from(setup_packet.request)
+                );
+                match setup_packet.direction() {
+                    Direction::DeviceToHost => usb.stall_endpoint_in(control_endpoint),
+                    Direction::HostToDevice => usb.stall_endpoint_out(control_endpoint),
+                }
+            }
+        }
+    }
+
+    /// Handles a USB event for the bulk and interrupt endpoints.
+    pub fn handle_event<D>(&mut self, usb: &D, event: UsbEvent)
+    where
+        D: UsbDriver,
+    {
+        match event {
+            UsbEvent::BusReset => self.reset(),
+            UsbEvent::ReceivePacket(endpoint_number) if endpoint_number == self.endpoint_out => {
+                let mut packet = [0; crate::EP_MAX_PACKET_SIZE];
+                let bytes_read = usb.read(endpoint_number, &mut packet);
+                self.receive_packet(usb, &packet[..bytes_read]);
+                usb.ep_out_prime_receive(self.endpoint_out);
+            }
+            UsbEvent::SendComplete(endpoint_number) if endpoint_number == self.endpoint_in => {
+                self.in_busy = false;
+                self.flush(usb);
+            }
+            UsbEvent::SendComplete(endpoint_number)
+                if Some(endpoint_number) == self.endpoint_interrupt =>
+            {
+                self.interrupt_busy = false;
+            }
+            _ => (),
+        }
+    }
+
+    /// Sends a USB488 service request notification with the current
+    /// status byte and returns `true` if it was sent.
+    pub fn request_service<D>(&mut self, usb: &D) -> bool
+    where
+        D: UsbDriver,
+    {
+        let Some(endpoint_interrupt) = self.endpoint_interrupt else {
+            return false;
+        };
+        if self.interrupt_busy {
+            return false;
+        }
+        let status_byte = self.status_byte();
+        self.interrupt_busy = true;
+        usb.write(endpoint_interrupt, [0x81, status_byte].into_iter());
+        true
+    }
+
+    fn status_byte(&mut self) -> u8 {
+        let status_byte = self.handler.status_byte() & !STATUS_BYTE_MAV;
+        if self.response_available() {
+            status_byte | STATUS_BYTE_MAV
+        } else {
+            status_byte
+        }
+    }
+
+    fn capabilities(&self) -> [u8; 24] {
+        let interrupt = u8::from(self.endpoint_interrupt.is_some());
+        [
+            status::SUCCESS,
+            0,
+            0x00, // bcdUSBTMC v1.00
+            0x01,
+            u8::from(self.indicator_pulse) << 2, // interface capabilities
+            0x00,                                // device capabilities
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0x00, // bcdUSB488 v1.00
+            0x01,
+            0b0000_0101,                  // USB488.2 | TRIGGER
+            0b0000_1001 | interrupt << 2, // SCPI | DT1 | SR1
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+        ]
+    }
+
+    fn abort_bulk_out(&mut self) {
+        self.out_header_length = 0;
+        self.out_remaining = 0;
+        self.message_length = 0;
+        self.message_overflow = false;
+    }
+
+    /// Discards the current transfer and any pending response. A packet
+    /// already handed to the endpoint still completes.
+    fn abort_bulk_in(&mut self) {
+        self.in_transfer_length = 0;
+        self.in_sent = 0;
+        self.in_zlp_pending = false;
+        self.response_length = 0;
+        self.response_offset = 0;
+    }
+
+    fn receive_packet<D>(&mut self, usb: &D, mut packet: &[u8])
+    where
+        D: UsbDriver,
+    {
+        // a header starts every transfer
+        if self.out_header_length < HEADER_LENGTH {
+            let length = (HEADER_LENGTH - self.out_header_length).min(packet.len());
+            self.out_header[self.out_header_length..self.out_header_length + length]
+                .copy_from_slice(&packet[..length]);
+            self.out_header_length += length;
+            packet = &packet[length..];
+            if self.out_header_length < HEADER_LENGTH {
+                return;
+            }
+            if !self.handle_out_header(usb) {
+                self.out_header_length = 0;
+                return;
+            }
+        }
+
+        // followed by the message data and alignment padding
+        let length = self.out_remaining.min(packet.len());
+        let data = &packet[..length];
+        let available = BUFFER_SIZE - self.message_length;
+        if data.len() > available {
+            self.message_overflow = true;
+        }
+        let copied = data.len().min(available);
+        self.message[self.message_length..self.message_length + copied]
+            .copy_from_slice(&data[..copied]);
+        self.message_length += copied;
+        self.out_remaining -= length;
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            self.out_transferred += length as u32;
+        }
+
+        if self.out_remaining > 0 {
+            return;
+        }
+
+        // the transfer is complete, any remaining bytes are padding
+        self.out_header_length = 0;
+        if self.out_eom {
+            self.dispatch_message();
+        }
+    }
+
+    /// Returns `true` if the header is followed by message data.
+    fn handle_out_header<D>(&mut self, usb: &D) -> bool
+    where
+        D: UsbDriver,
+    {
+        let header = self.out_header;
+        let [message_id, tag, tag_inverse, _] = [header[0], header[1], header[2], header[3]];
+        if tag != !tag_inverse {
+            warn!("UsbTmc invalid bulk-out header: {:?}", header);
+            usb.stall_endpoint_out(self.endpoint_out);
+            return false;
+        }
+        let transfer_size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        self.out_tag = tag;
+
+        match MessageId::from(message_id) {
+            MessageId::DevDepMsgOut => {
+                trace!("UsbTmc DEV_DEP_MSG_OUT tag:{} size:{}", tag, transfer_size);
+                self.out_remaining = transfer_size as usize;
+                self.out_transferred = 0;
+                self.out_eom = header[8] & transfer_attributes::EOM != 0;
+                if self.out_remaining == 0 && self.out_eom {
+                    self.dispatch_message();
+                }
+                self.out_remaining > 0
+            }
+            MessageId::RequestDevDepMsgIn => {
+                trace!(
+                    "UsbTmc REQUEST_DEV_DEP_MSG_IN tag:{} size:{}",
+                    tag,
+                    transfer_size
+                );
+                let term_char = if header[8] & transfer_attributes::TERM_CHAR_ENABLED != 0 {
+                    Some(header[9])
+                } else {
+                    None
+                };
+                self.start_bulk_in(usb, tag, transfer_size as usize, term_char);
+                false
+            }
+            MessageId::Trigger => {
+                trace!("UsbTmc TRIGGER tag:{}", tag);
+                self.handler.trigger();
+                false
+            }
+            message_id => {
+                warn!("UsbTmc unsupported message: {:?}", message_id);
+                usb.stall_endpoint_out(self.endpoint_out);
+                false
+            }
+        }
+    }
+
+    fn dispatch_message(&mut self) {
+        if self.message_overflow {
+            warn!("UsbTmc message too long, dropped");
+        } else {
+            let response = &mut self.response[self.response_length..];
+            let length = self
+                .handler
+                .command(&self.message[..self.message_length], response);
+            self.response_length += length.min(response.len());
+        }
+        self.message_length = 0;
+        self.message_overflow = false;
+    }
+
+    fn start_bulk_in<D>(&mut self, usb: &D, tag: u8, transfer_size: usize, term_char: Option<u8>)
+    where
+        D: UsbDriver,
+    {
+        if self.in_transfer_length > 0 {
+            warn!("UsbTmc bulk-in transfer already in progress");
+            return;
+        }
+
+        let pending = &self.response[self.response_offset..self.response_length];
+        let mut length = pending.len().min(transfer_size);
+        let mut attributes = 0;
+        if let Some(position) = term_char
+            .and_then(|term_char| pending[..length].iter().position(|&byte| byte == term_char))
+        {
+            length = position + 1;
+            attributes |= transfer_attributes::TERM_CHAR;
+        }
+        if length == pending.len() {
+            attributes |= transfer_attributes::EOM;
+        }
+
+        #[allow(clippy::cast_possible_truncation)]
+        let [s0, s1, s2, s3] = (length as u32).to_le_bytes();
+        self.in_header = [
+            MessageId::RequestDevDepMsgIn.into(),
+            tag,
+            !tag,
+            0,
+            s0,
+            s1,
+            s2,
+            s3,
+            attributes,
+            0,
+            0,
+            0,
+        ];
+        self.in_tag = tag;
+        self.in_data_length = length;
+        self.in_transfer_length = align(HEADER_LENGTH + length);
+        self.in_requested_length = align(HEADER_LENGTH + transfer_size);
+        self.in_sent = 0;
+        self.in_transferred = 0;
+        self.flush(usb);
+    }
+
+    fn flush<D>(&mut self, usb: &D)
+    where
+        D: UsbDriver,
+    {
+        if self.in_busy {
+            return;
+        }
+
+        if self.in_sent == self.in_transfer_length {
+            // terminate a short or aborted transfer that ended on a packet boundary
+            if self.in_zlp_pending {
+                self.in_zlp_pending = false;
+                self.in_busy = true;
+                usb.write(self.endpoint_in, [].into_iter());
+            } else if self.in_transfer_length > 0 {
+                self.response_offset += self.in_data_length;
+                if self.response_offset == self.response_length {
+                    self.response_offset = 0;
+                    self.response_length = 0;
+                }
+                self.in_transfer_length = 0;
+                self.in_sent = 0;
+            }
+            return;
+        }
+
+        let data = &self.response[self.response_offset..self.response_offset + self.in_data_length];
+        let padding = self.in_transfer_length - HEADER_LENGTH - data.len();
+        let packet_length = (self.in_transfer_length - self.in_sent).min(self.max_packet_size);
+        let bytes_written = usb.write_with_packet_size(
+            self.endpoint_in,
+            Some(packet_length),
+            self.in_header
+                .into_iter()
+                .chain(data.iter().copied())
+                .chain(core::iter::repeat(0).take(padding))
+                .skip(self.in_sent)
+                .take(packet_length),
+            self.max_packet_size,
+        );
+        if bytes_written > 0 {
+            self.in_sent += bytes_written;
+            self.in_busy = true;
+            #[allow(clippy::cast_possible_truncation)]
+            {
+                self.in_transferred =
+                    self.in_sent.saturating_sub(HEADER_LENGTH).min(data.len()) as u32;
+            }
+            self.in_zlp_pending = bytes_written == self.max_packet_size
+                && self.in_sent == self.in_transfer_length
+                && self.in_transfer_length < self.in_requested_length;
+        }
+    }
+}
+
+impl<D, H, const BUFFER_SIZE: usize> UsbClass<D> for UsbTmc<H, BUFFER_SIZE>
+where
+    D: UsbDriver,
+    H: CommandHandler,
+{
+    fn has_interface(&self, interface_number: u8) -> bool {
+        interface_number == self.interface_number
+    }
+
+    fn has_endpoint(&self, endpoint_address: u8) -> bool {
+        endpoint_address == self.endpoint_in | 0x80
+            || endpoint_address == self.endpoint_out
+            || self
+                .endpoint_interrupt
+                .map(|endpoint_number| endpoint_number | 0x80)
+                == Some(endpoint_address)
+    }
+
+    fn handle_control_request<'d>(
+        &mut self,
+        usb: &D,
+        control_endpoint: u8,
+        setup_packet: SetupPacket,
+        _data: &'d [u8],
+    ) -> ControlResult<'d> {
+        if !self.is_class_request(&setup_packet) {
+            return ControlResult::Unhandled;
+        }
+        self.handle_setup_packet(usb, control_endpoint, setup_packet);
+        ControlResult::Handled
+    }
+
+    fn handle_endpoint_event(&mut self, usb: &D, event: UsbEvent) {
+        self.handle_event(usb, event);
+    }
+
+    fn reset(&mut self) {
+        self.reset();
+    }
+
+    fn configuration_changed(&mut self, usb: &D, configuration: u8) {
+        if configuration == 0 {
+            self.reset();
+        } else {
+            self.configure(usb);
+        }
+    }
+}
+
+impl From<MessageId> for u8 {
+    fn from(message_id: MessageId) -> Self {
+        match message_id {
+            MessageId::DevDepMsgOut => 1,
+            MessageId::RequestDevDepMsgIn => 2,
+            MessageId::VendorSpecificOut => 126,
+            MessageId::RequestVendorSpecificIn => 127,
+            MessageId::Trigger => 128,
+            MessageId::Unknown(value) => value,
+        }
+    }
+}
+
+/// Returns a class request response padded to the longest response.
+fn response(bytes: &[u8]) -> ([u8; 24], usize) {
+    let mut response = [0; 24];
+    response[..bytes.len()].copy_from_slice(bytes);
+    (response, bytes.len())
+}
+
+const fn align(length: usize) -> usize {
+    (length + 3) & !3
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::descriptor::{
+        ConfigurationDescriptor, ConfigurationDescriptorHeader, EndpointDescriptor,
+        InterfaceDescriptor, InterfaceDescriptorHeader,
+    };
+    use crate::testing::{class_request, HostError, Operation, VirtualHost};
+
+    // - fixtures -------------------------------------------------------------
+
+    const IDN: &[u8] = b"Great Scott Gadgets,Cynthion,0,1.0\n";
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[InterfaceDescriptor::new(
+            InterfaceDescriptorHeader {
+                iInterfaceNumber: 0,
+                bInterfaceClass: INTERFACE_CLASS,
+                bInterfaceSubClass: INTERFACE_SUBCLASS,
+                bInterfaceProtocol: INTERFACE_PROTOCOL_USB488,
+                ..InterfaceDescriptorHeader::new()
+            },
+            &[
+                EndpointDescriptor {
+                    bEndpointAddress: 0x81,
+                    bmAttributes: 0x02, // Bulk
+                    wMaxPacketSize: 64,
+                    ..EndpointDescriptor::new()
+                },
+                EndpointDescriptor {
+                    bEndpointAddress: 0x01,
+                    bmAttributes: 0x02, // Bulk
+                    wMaxPacketSize: 64,
+                    ..EndpointDescriptor::new()
+                },
+                EndpointDescriptor {
+                    bEndpointAddress: 0x82,
+                    bmAttributes: 0x03, // Interrupt
+                    wMaxPacketSize: 8,
+                    bInterval: 4,
+                    ..EndpointDescriptor::new()
+                },
+            ],
+        )],
+    )];
+
+    #[derive(Default)]
+    struct Instrument {
+        commands: Vec<Vec<u8>>,
+        triggers: usize,
+        clears: usize,
+        status_byte: u8,
+    }
+
+    impl CommandHandler for Instrument {
+        fn command(&mut self, message: &[u8], response: &mut [u8]) -> usize {
+            self.commands.push(message.to_vec());
+            let reply: &[u8] = match message {
+                b"*IDN?\n" => IDN,
+                b"DATA?\n" => b"0,1,2,3,4,5,6,7,8,9\n10,11,12,13,14,15,16,17,18,19\n",
+                b"CURVE?\n" => &[b'#'; 100],
+                _ => b"",
+            };
+            response[..reply.len()].copy_from_slice(reply);
+            reply.len()
+        }
+
+        fn trigger(&mut self) {
+            self.triggers += 1;
+        }
+
+        fn clear(&mut self) {
+            self.clears += 1;
+        }
+
+        fn status_byte(&mut self) -> u8 {
+            self.status_byte
+        }
+    }
+
+    type Tmc = UsbTmc<Instrument, 256>;
+
+    fn tmc(host: &VirtualHost<64>) -> Tmc {
+        let mut tmc = UsbTmc::new(0, 1, 1, 64, Instrument::default());
+        tmc.configure(&host.usb);
+        host.usb.take_operations();
+        tmc
+    }
+
+    fn control_in(
+        host: &mut VirtualHost<'static, 64>,
+        tmc: &mut Tmc,
+        setup_packet: SetupPacket,
+    ) -> Result<Vec<u8>, HostError> {
+        host.control_in_with(setup_packet, |usb, setup_packet, _data| {
+            tmc.handle_setup_packet(usb, 0, setup_packet);
+        })
+    }
+
+    fn dev_dep_msg_out(tag: u8, message: &[u8], eom: bool) -> Vec<u8> {
+        let mut transfer = Vec::from([1, tag, !tag, 0]);
+        transfer.extend_from_slice(&(message.len() as u32).to_le_bytes());
+        transfer.extend_from_slice(&[u8::from(eom), 0, 0, 0]);
+        transfer.extend_from_slice(message);
+        transfer.resize(align(transfer.len()), 0);
+        transfer
+    }
+
+    fn request_dev_dep_msg_in(tag: u8, transfer_size: u32, term_char: Option<u8>) -> Vec<u8> {
+        let mut transfer = Vec::from([2, tag, !tag, 0]);
+        transfer.extend_from_slice(&transfer_size.to_le_bytes());
+        match term_char {
+            Some(term_char) => transfer.extend_from_slice(&[0b10, term_char, 0, 0]),
+            None => transfer.extend_from_slice(&[0, 0, 0, 0]),
+        }
+        transfer
+    }
+
+    fn bulk_out(host: &VirtualHost<64>, tmc: &mut Tmc, transfer: &[u8]) {
+        for packet in transfer.chunks(64) {
+            host.usb.push_out_data(1, packet);
+            tmc.handle_event(&host.usb, UsbEvent::ReceivePacket(1));
+        }
+    }
+
+    /// Completes an IN transfer and returns the packets sent.
+    fn bulk_in(host: &VirtualHost<64>, tmc: &mut Tmc) -> Vec<Vec<u8>> {
+        let mut packets = Vec::new();
+        loop {
+            let writes = host.usb.writes(1);
+            host.usb.take_operations();
+            if writes.is_empty() {
+                return packets;
+            }
+            packets.extend(writes);
+            tmc.handle_event(&host.usb, UsbEvent::SendComplete(1));
+        }
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_get_capabilities() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut tmc = tmc(&host);
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Interface, 7, 0, 0, 0x18),
+        )
+        .unwrap();
+        assert_eq!(
+            data,
+            [
+                0x01, 0, 0x00, 0x01, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0x00, 0x01, 0x05, 0x09, 0, 0, 0,
+                0, 0, 0, 0, 0
+            ]
+        );
+
+        let mut tmc = UsbTmc::<_, 256>::new(0, 1, 1, 64, Instrument::default())
+            .with_interrupt_endpoint(2)
+            .with_indicator_pulse();
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Interface, 7, 0, 0, 0x18),
+        )
+        .unwrap();
+        assert_eq!((data[4], data[15]), (0x04, 0x0d));
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Interface, 64, 0, 0, 1),
+        )
+        .unwrap();
+        assert_eq!(data, [status::SUCCESS]);
+
+        // remote/local control is not supported
+        let result = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Interface, 160, 1, 0, 1),
+        );
+        assert!(matches!(result, Err(HostError::Stalled(_))));
+    }
+
+    #[test]
+    fn test_query() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut tmc = tmc(&host);
+
+        bulk_out(&host, &mut tmc, &dev_dep_msg_out(1, b"*IDN?\n", true));
+        assert_eq!(tmc.handler().commands, [b"*IDN?\n"]);
+        assert!(tmc.response_available());
+
+        // the message available bit is set until the response has been read
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Interface, 128, 2, 0, 3),
+        )
+        .unwrap();
+        assert_eq!(data, [status::SUCCESS, 2, STATUS_BYTE_MAV]);
+
+        bulk_out(&host, &mut tmc, &request_dev_dep_msg_in(2, 256, None));
+        let packets = bulk_in(&host, &mut tmc);
+        assert_eq!(packets.len(), 1);
+        let mut expected = Vec::from([2, 2, !2, 0, 35, 0, 0, 0, 0x01, 0, 0, 0]);
+        expected.extend_from_slice(IDN);
+        expected.push(0);
+        assert_eq!(packets[0], expected);
+        assert!(!tmc.response_available());
+
+        // a request without a pending response returns an empty message
+        bulk_out(&host, &mut tmc, &request_dev_dep_msg_in(3, 256, None));
+        assert_eq!(
+            bulk_in(&host, &mut tmc),
+            [[2, 3, !3, 0, 0, 0, 0, 0, 0x01, 0, 0, 0]]
+        );
+
+        bulk_out(&host, &mut tmc, &[128, 4, !4, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(tmc.handler().triggers, 1);
+    }
+
+    #[test]
+    fn test_split_transfers() {
+        let host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut tmc = tmc(&host);
+
+        // a message split across two transfers spanning several packets
+        let message: Vec<u8> = b"DATA?\n".to_vec();
+        let mut first = dev_dep_msg_out(1, &message[..3], false);
+        first.extend(dev_dep_msg_out(2, &message[3..], true));
+        bulk_out(&host, &mut tmc, &first[..16]);
+        bulk_out(&host, &mut tmc, &first[16..]);
+        assert_eq!(tmc.handler().commands, [message]);
+
+        // the response is returned up to the termination character
+        bulk_out(
+            &host,
+            &mut tmc,
+            &request_dev_dep_msg_in(3, 256, Some(b'\n')),
+        );
+        let transfer = bulk_in(&host, &mut tmc).concat();
+        assert_eq!(&transfer[..12], [2, 3, !3, 0, 20, 0, 0, 0, 0b10, 0, 0, 0]);
+        assert_eq!(&transfer[12..32], b"0,1,2,3,4,5,6,7,8,9\n");
+
+        // and in transfers of the requested size
+        bulk_out(&host, &mut tmc, &request_dev_dep_msg_in(4, 8, None));
+        let transfer = bulk_in(&host, &mut tmc).concat();
+        assert_eq!(&transfer[..12], [2, 4, !4, 0, 8, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&transfer[12..], b"10,11,12");
+
+        // the remainder of the response ends the message
+        bulk_out(&host, &mut tmc, &request_dev_dep_msg_in(5, 256, None));
+        let packets = bulk_in(&host, &mut tmc);
+        assert_eq!(packets.iter().map(Vec::len).collect::<Vec<_>>(), [36]);
+        assert_eq!(packets[0][8], 0x01);
+        assert_eq!(&packets[0][12..35], b",13,14,15,16,17,18,19\n\0");
+    }
+
+    #[test]
+    fn test_abort() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut tmc = tmc(&host);
+
+        // abort a bulk-out transfer after the first packet
+        let transfer = dev_dep_msg_out(7, &[b'x'; 100], true);
+        bulk_out(&host, &mut tmc, &transfer[..64]);
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Endpoint, 1, 6, 0x01, 2),
+        )
+        .unwrap();
+        assert_eq!(data, [status::TRANSFER_NOT_IN_PROGRESS, 7]);
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Endpoint, 1, 7, 0x01, 2),
+        )
+        .unwrap();
+        assert_eq!(data, [status::SUCCESS, 7]);
+        assert!(host.usb.operations().contains(&Operation::StallOut(1)));
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Endpoint, 2, 0, 0x01, 8),
+        )
+        .unwrap();
+        assert_eq!(data, [status::SUCCESS, 0, 0, 0, 52, 0, 0, 0]);
+        assert!(tmc.handler().commands.is_empty());
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Endpoint, 1, 7, 0x01, 2),
+        )
+        .unwrap();
+        assert_eq!(data[0], status::FAILED);
+
+        // abort a bulk-in transfer while its first packet is in flight
+        bulk_out(&host, &mut tmc, &dev_dep_msg_out(8, b"CURVE?\n", true));
+        bulk_out(&host, &mut tmc, &request_dev_dep_msg_in(9, 256, None));
+        assert_eq!(host.usb.writes(1).len(), 1);
+        host.usb.take_operations();
+        let mut setup_packet =
+            class_request(Direction::DeviceToHost, Recipient::Endpoint, 3, 9, 0x01, 2);
+        setup_packet.index = 0x81;
+        let data = control_in(&mut host, &mut tmc, setup_packet).unwrap();
+        assert_eq!(data, [status::SUCCESS, 9]);
+        assert!(!tmc.response_available());
+
+        // the transfer is terminated with a zero length packet once the endpoint is idle
+        setup_packet.request = 4;
+        setup_packet.length = 8;
+        let data = control_in(&mut host, &mut tmc, setup_packet).unwrap();
+        assert_eq!(data, [status::PENDING, 0, 0, 0, 52, 0, 0, 0]);
+        assert!(host.usb.writes(1).is_empty());
+        tmc.handle_event(&host.usb, UsbEvent::SendComplete(1));
+        assert_eq!(host.usb.writes(1), [Vec::<u8>::new()]);
+        tmc.handle_event(&host.usb, UsbEvent::SendComplete(1));
+        let data = control_in(&mut host, &mut tmc, setup_packet).unwrap();
+        assert_eq!(data[0], status::SUCCESS);
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut tmc = tmc(&host);
+
+        bulk_out(&host, &mut tmc, &dev_dep_msg_out(1, b"*IDN?\n", true));
+        bulk_out(&host, &mut tmc, &dev_dep_msg_out(2, b"*RST", false));
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Interface, 5, 0, 0, 1),
+        )
+        .unwrap();
+        assert_eq!(data, [status::SUCCESS]);
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(Direction::DeviceToHost, Recipient::Interface, 6, 0, 0, 2),
+        )
+        .unwrap();
+        assert_eq!(data, [status::SUCCESS, 0]);
+        assert_eq!(tmc.handler().clears, 1);
+        assert!(!tmc.response_available());
+
+        // the partial message was discarded
+        bulk_out(&host, &mut tmc, &dev_dep_msg_out(3, b"*IDN?\n", true));
+        assert_eq!(tmc.handler().commands, [b"*IDN?\n", b"*IDN?\n"]);
+    }
+
+    #[test]
+    fn test_status_byte_interrupt() {
+        let mut host: VirtualHost<64> = VirtualHost::configured(CONFIGURATION_DESCRIPTORS);
+        let mut tmc =
+            UsbTmc::<_, 256>::new(0, 1, 1, 64, Instrument::default()).with_interrupt_endpoint(2);
+        tmc.handler_mut().status_byte = 0x40;
+
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(
+                Direction::DeviceToHost,
+                Recipient::Interface,
+                128,
+                0x85,
+                0,
+                3,
+            ),
+        )
+        .unwrap();
+        assert_eq!(data, [status::SUCCESS, 0x05, 0]);
+        assert_eq!(host.usb.writes(2), [[0x85, 0x40]]);
+        let data = control_in(
+            &mut host,
+            &mut tmc,
+            class_request(
+                Direction::DeviceToHost,
+                Recipient::Interface,
+                128,
+                0x06,
+                0,
+                3,
+            ),
+        )
+        .unwrap();
+        assert_eq!(data, [status::INTERRUPT_IN_BUSY, 0x06, 0]);
+
+        tmc.handle_event(&host.usb, UsbEvent::SendComplete(2));
+        host.usb.take_operations();
+        assert!(tmc.request_service(&host.usb));
+        assert_eq!(host.usb.writes(2), [[0x81, 0x40]]);
+    }
+}
@@ -2,10 +2,11 @@ use core::marker::PhantomData;
 
 use log::{error, info, trace, warn};
 
-use crate::descriptor::microsoft10;
-use crate::device::Descriptors;
+use crate::descriptor::{microsoft10, microsoft20};
+use crate::device::{DescriptorResult, Descriptors, DeviceState};
+use crate::error::ErrorKind;
 use crate::event::UsbEvent;
-use crate::setup::{Direction, Feature, Recipient, Request, RequestType, SetupPacket};
+use crate::setup::{Direction, Feature, Recipient, Request, RequestType, SetupPacket, TestMode};
 use crate::traits::{AsByteSliceIterator, UsbDriver};
 
 // - State --------------------------------------------------------------------
@@ -20,9 +21,30 @@ pub enum State {
     ReceiveHostData(SetupPacket),
     FinishHostData(SetupPacket),
     Complete,
+    TestMode(TestMode),
     Stall,
 }
 
+// - ControlEvent -------------------------------------------------------------
+
+/// Events generated by the Control interface that need to be handled
+/// by the application.
+#[derive(Clone, Copy, Debug)]
+pub enum ControlEvent {
+    /// A [`SetupPacket`] that could not be handled by the [`Control`]
+    /// interface. (e.g. a [`RequestType::Class`] or [`RequestType::Vendor`] request)
+    SetupPacket(SetupPacket),
+    /// The host has selected a new device configuration.
+    ///
+    /// Contains the `bConfigurationValue` of the active configuration,
+    /// or `0` if the device has been returned to the unconfigured state.
+    ConfigurationChanged(u8),
+    /// The host has selected a new alternate setting for an interface.
+    ///
+    /// Contents is (`interface_number`, `alternate_setting`)
+    AlternateSettingChanged(u8, u8),
+}
+
 // - Control ------------------------------------------------------------------
 
 /// Implements a USB Control endpoint.
@@ -31,8 +53,13 @@ pub struct Control<'a, D, const RX_BUFFER_SIZE: usize> {
     descriptors: Descriptors<'a>,
 
     next: State,
+    state: DeviceState,
+    suspended: bool,
     configuration: Option<u8>,
+    alternate_settings: [u8; crate::MAX_INTERFACES],
     feature_remote_wakeup: bool,
+    halted_out: u16,
+    halted_in: u16,
 
     rx_buffer: [u8; RX_BUFFER_SIZE],
     rx_buffer_position: usize,
@@ -44,9 +71,131 @@ impl<'a, D, const RX_BUFFER_SIZE: usize> Control<'a, D, RX_BUFFER_SIZE>
 where
     D: UsbDriver,
 {
+    /// Returns the endpoint number of the control endpoint.
+    #[must_use]
+    pub fn endpoint_number(&self) -> u8 {
+        self.endpoint_number
+    }
+
+    /// Returns the current state of the device.
+    #[must_use]
+    pub fn state(&self) -> DeviceState {
+        if self.suspended {
+            DeviceState::Suspended
+        } else {
+            self.state
+        }
+    }
+
+    /// Returns the `bConfigurationValue` of the active configuration, if any.
+    #[must_use]
+    pub fn configuration(&self) -> Option<u8> {
+        self.configuration
+    }
+
+    /// Returns the current alternate setting of the given interface.
+    #[must_use]
+    pub fn alternate_setting(&self, interface_number: u8) -> u8 {
+        self.alternate_settings
+            .get(usize::from(interface_number))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns `true` if the host has enabled remote wakeup with
+    /// `SET_FEATURE(DEVICE_REMOTE_WAKEUP)`.
+    #[must_use]
+    pub fn remote_wakeup_enabled(&self) -> bool {
+        self.feature_remote_wakeup
+    }
+
+    /// Signals the host to resume from suspend.
+    ///
+    /// The device leaves the suspended state once the host resumes the
+    /// bus and the driver reports [`UsbEvent::Resume`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ErrorKind::RemoteWakeupDisabled`] if the host has not
+    /// enabled remote wakeup, [`ErrorKind::NotSuspended`] if the bus
+    /// is not suspended or [`ErrorKind::Unsupported`] if the driver can
+    /// not drive resume signalling.
+    pub fn remote_wakeup(&self, usb: &D) -> Result<(), ErrorKind> {
+        if !self.feature_remote_wakeup {
+            return Err(ErrorKind::RemoteWakeupDisabled);
+        }
+        if !self.suspended {
+            return Err(ErrorKind::NotSuspended);
+        }
+        usb.remote_wakeup()?;
+        Ok(())
+    }
+
+    /// Returns `true` if the given endpoint address has been halted by
+    /// the host with `SET_FEATURE(ENDPOINT_HALT)`.
+    #[must_use]
+    pub fn endpoint_halted(&self, endpoint_address: u8) -> bool {
+        let mask = 1 << (endpoint_address & 0x0f);
+        match Direction::from(endpoint_address) {
+            Direction::HostToDevice => self.halted_out & mask != 0,
+            Direction::DeviceToHost => self.halted_in & mask != 0,
+        }
+    }
+
+    fn set_endpoint_halted(&mut self, endpoint_address: u8, halted: bool) {
+        let mask = 1 << (endpoint_address & 0x0f);
+        let halted_endpoints = match Direction::from(endpoint_address) {
+            Direction::HostToDevice => &mut self.halted_out,
+            Direction::DeviceToHost => &mut self.halted_in,
+        };
+        if halted {
+            *halted_endpoints |= mask;
+        } else {
+            *halted_endpoints &= !mask;
+        }
+    }
+
+    /// Returns `true` if the interface exists in the active configuration.
+    fn has_interface(&self, interface_number: u8) -> bool {
+        match self.configuration {
+            Some(configuration) if usize::from(interface_number) < crate::MAX_INTERFACES => self
+                .descriptors
+                .interface_descriptor(configuration, interface_number, 0)
+                .is_some(),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the endpoint address is the control endpoint
+    /// or exists in the active configuration.
+    fn has_endpoint(&self, endpoint_address: u8) -> bool {
+        if endpoint_address & 0x7f == self.endpoint_number {
+            return true;
+        }
+        match self.configuration {
+            Some(configuration) => self
+                .descriptors
+                .endpoint_descriptor(configuration, endpoint_address)
+                .is_some(),
+            None => false,
+        }
+    }
+
+    /// Returns the `GET_STATUS` response for the device.
+    fn device_status(&self) -> u16 {
+        // bit 0: self-powered, reported by the active or first configuration
+        let self_powered = self
+            .configuration
+            .and_then(|configuration| self.descriptors.configuration_descriptor(configuration))
+            .or_else(|| self.descriptors.configuration_descriptors.first())
+            .map_or(false, |descriptor| descriptor.head.bmAttributes & 0x40 != 0);
+        // bit 1: remote-wakeup
+        u16::from(self_powered) | u16::from(self.feature_remote_wakeup) << 1
+    }
+
     /// Returns the last received control data from the host.
     #[must_use]
-    pub fn data(&'a self) -> &'a [u8] {
+    pub fn data(&self) -> &[u8] {
         &self.rx_buffer[..self.rx_buffer_position]
     }
 
@@ -65,12 +214,23 @@ where
 {
     #[must_use]
     pub fn new(endpoint_number: u8, descriptors: Descriptors<'a>) -> Self {
+        if let Err(e) = descriptors.validate() {
+            error!("Control descriptors failed validation: {}", e);
+        } else if let Err(e) = descriptors.validate_peripheral(D::PERIPHERAL_LIMITS) {
+            error!("Control descriptors exceed peripheral limits: {}", e);
+        }
+
         Self {
             endpoint_number,
-            descriptors: descriptors.set_total_lengths(), // TODO figure out a better solution
+            descriptors,
             next: State::Idle,
+            state: DeviceState::Powered,
+            suspended: false,
             configuration: None,
+            alternate_settings: [0; crate::MAX_INTERFACES],
             feature_remote_wakeup: false,
+            halted_out: 0,
+            halted_in: 0,
             rx_buffer: [0; RX_BUFFER_SIZE],
             rx_buffer_position: 0,
             _marker: PhantomData,
@@ -80,20 +240,37 @@ where
     /// Dispatches an interrupt event generated by the USB peripheral
     /// for handling by the [`Control`] interface.
     ///
-    /// Returns the last [`SetupPacket`] received if it could not be
-    /// handled by the [`Control`] interface.  (e.g. if it was a
-    /// [`RequestType::Class`] or [`RequestType::Vendor`] request)
+    /// Returns a [`ControlEvent`] if the event requires further handling
+    /// by the application. For example, if the last [`SetupPacket`]
+    /// received could not be handled by the [`Control`] interface or
+    /// the host changed the active configuration.
     #[allow(clippy::too_many_lines)] // sometimes you can't have too much of a good thing!
-    pub fn dispatch_event(&mut self, usb: &D, event: UsbEvent) -> Option<SetupPacket> {
+    pub fn dispatch_event(&mut self, usb: &D, event: UsbEvent) -> Option<ControlEvent> {
         // The Control interface state machine operates on the latest
         // receive event and the current state of the interface.
         match (event, &self.next.clone()) {
             (UsbEvent::BusReset, _state) => {
                 // reset
                 self.next = State::Idle;
+                self.state = DeviceState::Default;
+                self.suspended = false;
+                self.configuration = None;
+                self.feature_remote_wakeup = false;
+                self.alternate_settings = [0; crate::MAX_INTERFACES];
+                self.halted_out = 0;
+                self.halted_in = 0;
                 // self.bus_reset(); - irq handler is doing the reset for us
             }
 
+            (UsbEvent::Suspend, _state) => {
+                self.suspended = true;
+            }
+
+            // any bus activity ends a suspend
+            (UsbEvent::Resume | UsbEvent::StartOfFrame(_), _state) => {
+                self.suspended = false;
+            }
+
             (
                 UsbEvent::ReceiveSetupPacket(endpoint_number, setup_packet),
                 State::Idle | State::Stall,
@@ -173,12 +350,59 @@ where
                         }
                     }
 
+                    // handle microsoft os 2.0 descriptor requests
+                    //
+                    // the vendor request code is advertised to the host by the
+                    // platform capability descriptor in our binary object store
+                    (Direction::DeviceToHost, RequestType::Vendor, _)
+                        if matches!(
+                            &self.descriptors.microsoft20,
+                            Some(descriptors) if descriptors.vendor_code == setup_packet.request
+                        ) =>
+                    {
+                        let recipient = setup_packet.recipient();
+                        let vendor_index = microsoft20::VendorIndex::from(setup_packet.index);
+
+                        match (&recipient, &vendor_index, &self.descriptors.microsoft20) {
+                            (
+                                Recipient::Device,
+                                microsoft20::VendorIndex::DescriptorSet,
+                                Some(descriptors),
+                            ) => {
+                                self.next = State::Send;
+                                usb.write_requested(
+                                    self.endpoint_number,
+                                    requested_length,
+                                    descriptors.descriptor_set.iter().take(requested_length),
+                                );
+                            }
+                            _ => {
+                                self.next = State::Stall;
+                                error!(
+                                    "Control error. Could not handle Microsoft OS 2.0 Request: '{:?}'.",
+                                    setup_packet
+                                );
+                                usb.stall_endpoint_in(self.endpoint_number);
+                            }
+                        }
+                    }
+
                     // - standard requests
-                    (Direction::DeviceToHost, RequestType::Standard, Request::GetDescriptor) => {
-                        self.next = State::Send;
-                        return self
+                    (Direction::DeviceToHost, RequestType::Standard, Request::GetDescriptor)
+                        if setup_packet.recipient() == Recipient::Device =>
+                    {
+                        match self
                             .descriptors
-                            .write(usb, self.endpoint_number, setup_packet);
+                            .write(usb, self.endpoint_number, setup_packet)
+                        {
+                            DescriptorResult::Written => self.next = State::Send,
+                            DescriptorResult::Stalled => self.next = State::Stall,
+                            DescriptorResult::Unhandled(setup_packet) => {
+                                // nothing was sent, wait for the next request
+                                self.next = State::Idle;
+                                return Some(ControlEvent::SetupPacket(setup_packet));
+                            }
+                        }
                     }
                     (Direction::HostToDevice, RequestType::Standard, Request::SetAddress) => {
                         let address: u8 = (setup_packet.value & 0x7f) as u8;
@@ -188,17 +412,28 @@ where
                     (Direction::HostToDevice, RequestType::Standard, Request::SetConfiguration) => {
                         let configuration: u8 = (setup_packet.value & 0xff) as u8;
                         // check whether this is a valid configuration
-                        // TODO support multiple configurations
-                        if configuration > 1 {
+                        if configuration != 0
+                            && self
+                                .descriptors
+                                .configuration_descriptor(configuration)
+                                .is_none()
+                        {
                             warn!("Control stall - unknown configuration {}", configuration);
-                            self.configuration = None;
                             self.next = State::Stall;
                             usb.stall_endpoint_out(self.endpoint_number);
                             return None;
                         }
-                        self.configuration = Some(configuration);
+                        (self.configuration, self.state) = if configuration == 0 {
+                            (None, DeviceState::Addressed)
+                        } else {
+                            (Some(configuration), DeviceState::Configured)
+                        };
+                        self.alternate_settings = [0; crate::MAX_INTERFACES];
+                        self.halted_out = 0;
+                        self.halted_in = 0;
                         self.next = State::Complete;
                         self.write_zlp(usb);
+                        return Some(ControlEvent::ConfigurationChanged(configuration));
                     }
                     (Direction::DeviceToHost, RequestType::Standard, Request::GetConfiguration) => {
                         self.next = State::Send;
@@ -208,21 +443,102 @@ where
                             usb.write(self.endpoint_number, [0].into_iter());
                         }
                     }
-                    (Direction::DeviceToHost, RequestType::Standard, Request::GetStatus) => {
-                        let status: u16 = 0b01; // bit 1:remote-wakeup bit 0:self-powered
-                        let status = status | u16::from(self.feature_remote_wakeup) << 1;
+                    (Direction::DeviceToHost, RequestType::Standard, Request::GetInterface) => {
+                        let interface_number = (setup_packet.index & 0xff) as u8;
+                        // check whether the interface exists in the active configuration
+                        if !self.has_interface(interface_number) {
+                            warn!("Control stall - unknown interface {}", interface_number);
+                            self.next = State::Stall;
+                            usb.stall_endpoint_in(self.endpoint_number);
+                            return None;
+                        }
+                        let alternate_setting =
+                            self.alternate_settings[usize::from(interface_number)];
                         self.next = State::Send;
-                        usb.write(0, status.to_le_bytes().into_iter());
+                        usb.write(self.endpoint_number, [alternate_setting].into_iter());
                     }
-                    (direction, RequestType::Standard, Request::ClearFeature) => {
-                        info!("  TODO Request::ClearFeature {:?}", direction);
+                    (Direction::HostToDevice, RequestType::Standard, Request::SetInterface) => {
+                        let interface_number = (setup_packet.index & 0xff) as u8;
+                        let alternate_setting = (setup_packet.value & 0xff) as u8;
+                        // check whether this is a valid alternate setting for the active configuration
+                        let valid = match self.configuration {
+                            Some(configuration)
+                                if usize::from(interface_number) < crate::MAX_INTERFACES =>
+                            {
+                                self.descriptors
+                                    .interface_descriptor(
+                                        configuration,
+                                        interface_number,
+                                        alternate_setting,
+                                    )
+                                    .is_some()
+                            }
+                            _ => false,
+                        };
+                        if !valid {
+                            warn!(
+                                "Control stall - unknown alternate setting {} for interface {}",
+                                alternate_setting, interface_number
+                            );
+                            self.next = State::Stall;
+                            usb.stall_endpoint_out(self.endpoint_number);
+                            return None;
+                        }
+                        self.alternate_settings[usize::from(interface_number)] = alternate_setting;
+                        // selecting an alternate setting clears the halt on its endpoints
+                        if let Some(interface) = self.configuration.and_then(|configuration| {
+                            self.descriptors.interface_descriptor(
+                                configuration,
+                                interface_number,
+                                alternate_setting,
+                            )
+                        }) {
+                            for endpoint in interface.tail2 {
+                                self.set_endpoint_halted(endpoint.bEndpointAddress, false);
+                            }
+                        }
+                        self.next = State::Complete;
+                        self.write_zlp(usb);
+                        return Some(ControlEvent::AlternateSettingChanged(
+                            interface_number,
+                            alternate_setting,
+                        ));
+                    }
+                    (Direction::DeviceToHost, RequestType::Standard, Request::GetStatus) => {
+                        let recipient = setup_packet.recipient();
+                        let index = (setup_packet.index & 0xff) as u8;
+                        let status = match recipient {
+                            Recipient::Device => Some(self.device_status()),
+                            Recipient::Interface if self.has_interface(index) => Some(0),
+                            // bit 0: halt
+                            Recipient::Endpoint if self.has_endpoint(index) => {
+                                Some(u16::from(self.endpoint_halted(index)))
+                            }
+                            _ => None,
+                        };
+                        if let Some(status) = status {
+                            self.next = State::Send;
+                            usb.write(self.endpoint_number, status.to_le_bytes().into_iter());
+                        } else {
+                            warn!(
+                                "Control stall - unknown get status {:?}, {}",
+                                recipient, index
+                            );
+                            self.next = State::Stall;
+                            usb.stall_endpoint_in(self.endpoint_number);
+                        }
+                    }
+                    (_direction, RequestType::Standard, Request::ClearFeature) => {
                         let recipient = setup_packet.recipient();
                         let feature = Feature::from(setup_packet.value);
+                        let endpoint_address = (setup_packet.index & 0xff) as u8;
                         match (&recipient, &feature) {
-                            (Recipient::Endpoint, Feature::EndpointHalt) => {
-                                let endpoint_address = (setup_packet.index & 0xff) as u8;
+                            (Recipient::Endpoint, Feature::EndpointHalt)
+                                if self.has_endpoint(endpoint_address) =>
+                            {
                                 let endpoint_number = endpoint_address & 0x7f;
                                 let direction = Direction::from(endpoint_address);
+                                self.set_endpoint_halted(endpoint_address, false);
                                 usb.clear_feature_endpoint_halt(endpoint_number, direction);
                                 self.next = State::Complete;
                                 self.write_zlp(usb);
@@ -242,16 +558,54 @@ where
                             }
                         }
                     }
-                    (direction, RequestType::Standard, Request::SetFeature) => {
-                        info!("  TODO Request::SetFeature {:?}", direction);
+                    (_direction, RequestType::Standard, Request::SetFeature) => {
                         let recipient = setup_packet.recipient();
                         let feature = Feature::from(setup_packet.value);
+                        let endpoint_address = (setup_packet.index & 0xff) as u8;
                         self.next = State::Complete;
                         match (&recipient, &feature) {
+                            // the default control pipe does not support a functional stall
+                            (Recipient::Endpoint, Feature::EndpointHalt)
+                                if endpoint_address & 0x7f != self.endpoint_number
+                                    && self.has_endpoint(endpoint_address) =>
+                            {
+                                let endpoint_number = endpoint_address & 0x7f;
+                                self.set_endpoint_halted(endpoint_address, true);
+                                match Direction::from(endpoint_address) {
+                                    Direction::HostToDevice => {
+                                        usb.stall_endpoint_out(endpoint_number);
+                                    }
+                                    Direction::DeviceToHost => {
+                                        usb.stall_endpoint_in(endpoint_number);
+                                    }
+                                }
+                                self.write_zlp(usb);
+                            }
                             (Recipient::Device, Feature::DeviceRemoteWakeup) => {
                                 self.feature_remote_wakeup = true;
                                 self.write_zlp(usb);
                             }
+                            (Recipient::Device, Feature::DeviceTestMode)
+                                if setup_packet.index & 0xff == 0 =>
+                            {
+                                match TestMode::from((setup_packet.index >> 8) as u8) {
+                                    TestMode::Reserved(selector) => {
+                                        warn!("SETUP stall: unknown test selector {}", selector);
+                                        usb.stall_endpoint_in(self.endpoint_number);
+                                        self.next = State::Stall;
+                                    }
+                                    test_mode if !usb.supports_test_mode(test_mode) => {
+                                        warn!("SETUP stall: unsupported test mode {:?}", test_mode);
+                                        usb.stall_endpoint_in(self.endpoint_number);
+                                        self.next = State::Stall;
+                                    }
+                                    test_mode => {
+                                        // the test mode is entered after the status stage
+                                        self.next = State::TestMode(test_mode);
+                                        self.write_zlp(usb);
+                                    }
+                                }
+                            }
                             _ => {
                                 warn!(
                                     "SETUP stall: unhandled set feature {:?}, {:?}",
@@ -279,7 +633,7 @@ where
                             request
                         );
                         self.next = State::Idle;
-                        return Some(setup_packet);
+                        return Some(ControlEvent::SetupPacket(setup_packet));
                     }
                 }
             }
@@ -316,6 +670,11 @@ where
             {
                 self.next = State::Idle;
                 usb.set_address(address); // set address
+                self.state = if address == 0 {
+                    DeviceState::Default
+                } else {
+                    DeviceState::Addressed
+                };
             }
 
             (UsbEvent::SendComplete(endpoint_number), State::Complete)
@@ -324,6 +683,14 @@ where
                 self.next = State::Idle;
             }
 
+            (UsbEvent::SendComplete(endpoint_number), &State::TestMode(test_mode))
+                if endpoint_number == self.endpoint_number =>
+            {
+                self.next = State::Idle;
+                info!("Control entering test mode {:?}", test_mode);
+                usb.test_mode(test_mode);
+            }
+
             (UsbEvent::ReceivePacket(endpoint_number), &State::ReceiveHostData(setup_packet))
                 if endpoint_number == self.endpoint_number =>
             {
@@ -382,7 +749,7 @@ where
                     );
                 }
 
-                return Some(setup_packet);
+                return Some(ControlEvent::SetupPacket(setup_packet));
             }
 
             // we'll get these if someone is writing directly to usb1 outside control
@@ -411,3 +778,382 @@ where
         None
     }
 }
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::descriptor::{
+        ConfigurationDescriptor, ConfigurationDescriptorHeader, DeviceDescriptor,
+        EndpointDescriptor, InterfaceDescriptor, InterfaceDescriptorHeader, LanguageId,
+        StringDescriptor, StringDescriptorZero,
+    };
+    use crate::device::Speed;
+    use crate::testing::{HostError, Operation, VirtualHost};
+
+    // - fixtures -------------------------------------------------------------
+
+    static STRING_DESCRIPTOR_1: StringDescriptor = StringDescriptor::new("manufacturer");
+    static STRING_DESCRIPTOR_2: StringDescriptor = StringDescriptor::new("product");
+    static STRING_DESCRIPTORS: &[&StringDescriptor] = &[&STRING_DESCRIPTOR_1, &STRING_DESCRIPTOR_2];
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            bmAttributes: 0xc0, // self-powered
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[InterfaceDescriptor::new(
+            InterfaceDescriptorHeader {
+                iInterfaceNumber: 0,
+                ..InterfaceDescriptorHeader::new()
+            },
+            &[
+                EndpointDescriptor {
+                    bEndpointAddress: 0x01,
+                    bmAttributes: 0x02, // Bulk
+                    wMaxPacketSize: 64,
+                    ..EndpointDescriptor::new()
+                },
+                EndpointDescriptor {
+                    bEndpointAddress: 0x81,
+                    bmAttributes: 0x02, // Bulk
+                    wMaxPacketSize: 64,
+                    ..EndpointDescriptor::new()
+                },
+            ],
+        )],
+    )];
+
+    static BUS_POWERED_CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] =
+        &[ConfigurationDescriptor::new(
+            ConfigurationDescriptorHeader {
+                bConfigurationValue: 1,
+                bmAttributes: 0x80, // bus-powered
+                ..ConfigurationDescriptorHeader::new()
+            },
+            &[],
+        )];
+
+    fn descriptors(device_speed: Speed) -> Descriptors<'static> {
+        Descriptors {
+            device_speed,
+            device_descriptor: DeviceDescriptor {
+                bMaxPacketSize: 64,
+                iManufacturer: 1,
+                iProduct: 2,
+                bNumConfigurations: 1,
+                ..DeviceDescriptor::new()
+            },
+            configuration_descriptors: CONFIGURATION_DESCRIPTORS,
+            string_descriptor_zero: StringDescriptorZero::new(&[LanguageId::EnglishUnitedStates]),
+            string_descriptors: STRING_DESCRIPTORS,
+            device_qualifier_descriptor: None,
+            other_speed_configuration_descriptors: None,
+            binary_object_store: None,
+            microsoft10: None,
+            microsoft20: None,
+        }
+    }
+
+    /// Returns a host for a device in the addressed or configured state.
+    fn host(device_speed: Speed, configured: bool) -> VirtualHost<'static, 64> {
+        let mut host = VirtualHost::new(descriptors(device_speed));
+        host.bus_reset();
+        host.set_address(1).unwrap();
+        if configured {
+            host.set_configuration(1).unwrap();
+        }
+        host.take_events();
+        host
+    }
+
+    /// Performs a control transfer without a host data stage.
+    fn transfer(
+        host: &mut VirtualHost<'static, 64>,
+        setup_packet: SetupPacket,
+    ) -> Result<Vec<u8>, HostError> {
+        match setup_packet.direction() {
+            Direction::DeviceToHost => host.control_in(setup_packet),
+            Direction::HostToDevice => host.control_out(setup_packet, &[]).map(|()| Vec::new()),
+        }
+    }
+
+    #[derive(Clone, Copy, Debug)]
+    enum Expect {
+        Data(&'static [u8]),
+        Length(usize),
+        Ack,
+        Stall,
+    }
+
+    struct Case {
+        name: &'static str,
+        device_speed: Speed,
+        configured: bool,
+        setup_packet: [u8; 8],
+        expect: Expect,
+    }
+
+    const fn case(name: &'static str, setup_packet: [u8; 8], expect: Expect) -> Case {
+        Case {
+            name,
+            device_speed: Speed::High,
+            configured: true,
+            setup_packet,
+            expect,
+        }
+    }
+
+    const fn addressed(mut case: Case) -> Case {
+        case.configured = false;
+        case
+    }
+
+    const fn full_speed(mut case: Case) -> Case {
+        case.device_speed = Speed::Full;
+        case
+    }
+
+    #[rustfmt::skip]
+    static CHAPTER9: &[Case] = &[
+        // GET_STATUS
+        case("get status device", [0x80, 0, 0, 0, 0, 0, 2, 0], Expect::Data(&[0b01, 0])),
+        case("get status interface", [0x81, 0, 0, 0, 0, 0, 2, 0], Expect::Data(&[0, 0])),
+        case("get status unknown interface", [0x81, 0, 0, 0, 1, 0, 2, 0], Expect::Stall),
+        addressed(case("get status interface when addressed", [0x81, 0, 0, 0, 0, 0, 2, 0], Expect::Stall)),
+        case("get status endpoint", [0x82, 0, 0, 0, 0x81, 0, 2, 0], Expect::Data(&[0, 0])),
+        case("get status unknown endpoint", [0x82, 0, 0, 0, 0x82, 0, 2, 0], Expect::Stall),
+        addressed(case("get status control endpoint when addressed", [0x82, 0, 0, 0, 0x80, 0, 2, 0], Expect::Data(&[0, 0]))),
+        addressed(case("get status endpoint when addressed", [0x82, 0, 0, 0, 0x81, 0, 2, 0], Expect::Stall)),
+        // SET_FEATURE / CLEAR_FEATURE
+        case("set feature endpoint halt", [0x02, 3, 0, 0, 0x81, 0, 0, 0], Expect::Ack),
+        case("set feature halt control endpoint", [0x02, 3, 0, 0, 0x00, 0, 0, 0], Expect::Stall),
+        case("set feature halt unknown endpoint", [0x02, 3, 0, 0, 0x82, 0, 0, 0], Expect::Stall),
+        addressed(case("set feature halt when addressed", [0x02, 3, 0, 0, 0x01, 0, 0, 0], Expect::Stall)),
+        case("clear feature endpoint halt", [0x02, 1, 0, 0, 0x01, 0, 0, 0], Expect::Ack),
+        case("clear feature halt control endpoint", [0x02, 1, 0, 0, 0x80, 0, 0, 0], Expect::Ack),
+        case("clear feature halt unknown endpoint", [0x02, 1, 0, 0, 0x82, 0, 0, 0], Expect::Stall),
+        case("set feature unknown device feature", [0x00, 3, 0x10, 0, 0, 0, 0, 0], Expect::Stall),
+        case("clear feature test mode", [0x00, 1, 2, 0, 0, 0, 0, 0], Expect::Stall),
+        // GET_DESCRIPTOR
+        case("get device descriptor", [0x80, 6, 0, 1, 0, 0, 64, 0], Expect::Length(18)),
+        case("get device descriptor short", [0x80, 6, 0, 1, 0, 0, 8, 0], Expect::Length(8)),
+        case("get unknown configuration descriptor", [0x80, 6, 1, 2, 0, 0, 255, 0], Expect::Stall),
+        case("get device qualifier", [0x80, 6, 0, 6, 0, 0, 10, 0], Expect::Length(10)),
+        full_speed(case("get device qualifier full-speed", [0x80, 6, 0, 6, 0, 0, 10, 0], Expect::Stall)),
+        case("get other speed configuration", [0x80, 6, 0, 7, 0, 0, 255, 0], Expect::Length(9 + 9 + 7 + 7)),
+        full_speed(case("get other speed configuration full-speed", [0x80, 6, 0, 7, 0, 0, 255, 0], Expect::Stall)),
+        case("get string descriptor zero", [0x80, 6, 0, 3, 0, 0, 255, 0], Expect::Data(&[4, 3, 0x09, 0x04])),
+        case("get last string descriptor", [0x80, 6, 2, 3, 0x09, 0x04, 255, 0], Expect::Length(2 + 2 * 7)),
+        case("get string descriptor past the last", [0x80, 6, 3, 3, 0x09, 0x04, 255, 0], Expect::Stall),
+        case("get unknown string descriptor", [0x80, 6, 0x42, 3, 0x09, 0x04, 255, 0], Expect::Stall),
+        case("get microsoft os string descriptor without one", [0x80, 6, 0xee, 3, 0, 0, 255, 0], Expect::Stall),
+        case("get bos descriptor without a bos", [0x80, 6, 0, 15, 0, 0, 255, 0], Expect::Stall),
+        // configurations and interfaces
+        addressed(case("get configuration when addressed", [0x80, 8, 0, 0, 0, 0, 1, 0], Expect::Data(&[0]))),
+        case("get configuration", [0x80, 8, 0, 0, 0, 0, 1, 0], Expect::Data(&[1])),
+        case("set unknown configuration", [0x00, 9, 2, 0, 0, 0, 0, 0], Expect::Stall),
+        case("get interface", [0x81, 10, 0, 0, 0, 0, 1, 0], Expect::Data(&[0])),
+        addressed(case("get interface when addressed", [0x81, 10, 0, 0, 0, 0, 1, 0], Expect::Stall)),
+        case("get unknown interface", [0x81, 10, 0, 0, 1, 0, 1, 0], Expect::Stall),
+        case("set interface", [0x01, 11, 0, 0, 0, 0, 0, 0], Expect::Ack),
+        case("set unknown alternate setting", [0x01, 11, 1, 0, 0, 0, 0, 0], Expect::Stall),
+    ];
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_chapter9_conformance() {
+        for case in CHAPTER9 {
+            let mut host = host(case.device_speed, case.configured);
+            let setup_packet = SetupPacket::from(case.setup_packet);
+
+            let result = transfer(&mut host, setup_packet);
+
+            let passed = match (case.expect, &result) {
+                (Expect::Data(expected), Ok(data)) => data[..] == expected[..],
+                (Expect::Length(expected), Ok(data)) => data.len() == expected,
+                (Expect::Ack, Ok(data)) => data.is_empty(),
+                (Expect::Stall, Err(HostError::Stalled(_))) => true,
+                _ => false,
+            };
+            assert!(
+                passed,
+                "{}: expected {:?}, got {:?}",
+                case.name, case.expect, result
+            );
+
+            // stalled requests are not passed on to the application
+            if let Expect::Stall = case.expect {
+                let events = host.take_events();
+                assert!(
+                    events.is_empty(),
+                    "{}: stalled request generated {:?}",
+                    case.name,
+                    events
+                );
+            }
+
+            // the device must accept the next request
+            assert_eq!(
+                transfer(&mut host, SetupPacket::from([0x80, 0, 0, 0, 0, 0, 2, 0])).unwrap(),
+                [0b01, 0],
+                "{}: request after",
+                case.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_endpoint_halt() {
+        let mut host = host(Speed::High, true);
+        let get_status = SetupPacket::from([0x82, 0, 0, 0, 0x81, 0, 2, 0]);
+        let set_halt = SetupPacket::from([0x02, 3, 0, 0, 0x81, 0, 0, 0]);
+        let clear_halt = SetupPacket::from([0x02, 1, 0, 0, 0x81, 0, 0, 0]);
+
+        // halting an endpoint stalls it and is reported by get status
+        host.usb.take_operations();
+        transfer(&mut host, set_halt).unwrap();
+        assert!(host.usb.operations().contains(&Operation::StallIn(1)));
+        assert!(host.control.endpoint_halted(0x81));
+        assert!(!host.control.endpoint_halted(0x01));
+        assert_eq!(transfer(&mut host, get_status).unwrap(), [1, 0]);
+
+        // clearing the halt resets the endpoint
+        host.usb.take_operations();
+        transfer(&mut host, clear_halt).unwrap();
+        assert!(host
+            .usb
+            .operations()
+            .contains(&Operation::ClearHalt(1, Direction::DeviceToHost)));
+        assert_eq!(transfer(&mut host, get_status).unwrap(), [0, 0]);
+
+        // selecting a configuration or alternate setting clears the halt
+        transfer(&mut host, set_halt).unwrap();
+        host.set_configuration(1).unwrap();
+        assert!(!host.control.endpoint_halted(0x81));
+
+        transfer(&mut host, set_halt).unwrap();
+        transfer(&mut host, SetupPacket::from([0x01, 11, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert!(!host.control.endpoint_halted(0x81));
+
+        // as does a bus reset
+        transfer(&mut host, set_halt).unwrap();
+        host.bus_reset();
+        assert!(!host.control.endpoint_halted(0x81));
+    }
+
+    #[test]
+    fn test_device_status() {
+        let get_status = SetupPacket::from([0x80, 0, 0, 0, 0, 0, 2, 0]);
+        let set_remote_wakeup = SetupPacket::from([0x00, 3, 1, 0, 0, 0, 0, 0]);
+
+        // the self-powered bit follows the configuration bmAttributes
+        let mut host = host(Speed::High, true);
+        assert_eq!(transfer(&mut host, get_status).unwrap(), [0b01, 0]);
+        transfer(&mut host, set_remote_wakeup).unwrap();
+        assert_eq!(transfer(&mut host, get_status).unwrap(), [0b11, 0]);
+
+        // bus-powered devices do not report self-powered
+        let mut host: VirtualHost<64> =
+            VirtualHost::configured(BUS_POWERED_CONFIGURATION_DESCRIPTORS);
+        assert_eq!(transfer(&mut host, get_status).unwrap(), [0b00, 0]);
+        transfer(&mut host, set_remote_wakeup).unwrap();
+        assert_eq!(transfer(&mut host, get_status).unwrap(), [0b10, 0]);
+    }
+
+    #[test]
+    fn test_remote_wakeup() {
+        let mut host = host(Speed::High, true);
+
+        // refused until the host enables remote wakeup
+        host.dispatch(UsbEvent::Suspend);
+        assert_eq!(
+            host.control.remote_wakeup(&host.usb),
+            Err(ErrorKind::RemoteWakeupDisabled)
+        );
+        host.dispatch(UsbEvent::Resume);
+
+        let set_feature = SetupPacket {
+            request_type: 0b0000_0000, // Standard, Device
+            request: 3,                // SET_FEATURE
+            value: 1,                  // DEVICE_REMOTE_WAKEUP
+            index: 0,
+            length: 0,
+        };
+        host.control_out(set_feature, &[]).unwrap();
+        assert!(host.control.remote_wakeup_enabled());
+
+        // refused while the bus is active
+        assert_eq!(
+            host.control.remote_wakeup(&host.usb),
+            Err(ErrorKind::NotSuspended)
+        );
+
+        host.dispatch(UsbEvent::Suspend);
+        host.usb.take_operations();
+        assert_eq!(host.control.remote_wakeup(&host.usb), Ok(()));
+        assert_eq!(host.usb.take_operations(), [Operation::RemoteWakeup]);
+
+        // refused if the driver can not drive resume signalling
+        host.usb.set_remote_wakeup_unsupported(true);
+        assert_eq!(
+            host.control.remote_wakeup(&host.usb),
+            Err(ErrorKind::Unsupported)
+        );
+        assert!(host.usb.take_operations().is_empty());
+
+        // bus reset disables remote wakeup again
+        host.bus_reset();
+        assert!(!host.control.remote_wakeup_enabled());
+    }
+
+    #[test]
+    fn test_test_mode() {
+        let mut host = host(Speed::High, true);
+
+        let set_feature = |index: u16| SetupPacket {
+            request_type: 0b0000_0000, // Standard, Device
+            request: 3,                // SET_FEATURE
+            value: 2,                  // TEST_MODE
+            index,
+            length: 0,
+        };
+
+        // reserved test selectors and a non-zero lower byte are stalled
+        for index in [0x0000, 0x0600, 0x0401] {
+            assert!(matches!(
+                host.control_out(set_feature(index), &[]),
+                Err(HostError::Stalled(_))
+            ));
+        }
+
+        // the test mode is entered after the status stage
+        host.usb.take_operations();
+        host.control_out(set_feature(0x0400), &[]).unwrap();
+        assert_eq!(
+            host.usb.take_operations()[..],
+            [
+                Operation::Write(0, Vec::new()),
+                Operation::TestMode(TestMode::TestPacket),
+            ]
+        );
+
+        // test modes the driver does not support are stalled
+        host.usb.set_test_mode_unsupported(true);
+        assert!(matches!(
+            host.control_out(set_feature(0x0400), &[]),
+            Err(HostError::Stalled(_))
+        ));
+        assert!(!host
+            .usb
+            .operations()
+            .iter()
+            .any(|operation| matches!(operation, Operation::TestMode(_))));
+    }
+}
@@ -12,6 +12,8 @@ use zerocopy::{AsBytes, FromBytes, FromZeroes};
 use crate::traits::AsByteSliceIterator;
 
 pub mod microsoft10;
+pub mod microsoft20;
+pub mod parser;
 
 /// USB descriptor type.
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -163,6 +165,20 @@ impl DeviceQualifierDescriptor {
             bReserved: 0,
         }
     }
+
+    /// Returns the device qualifier descriptor for the given device descriptor.
+    #[must_use]
+    pub const fn from_device_descriptor(device_descriptor: &DeviceDescriptor) -> Self {
+        Self {
+            bcdUSB: device_descriptor.bcdUSB,
+            bDeviceClass: device_descriptor.bDeviceClass,
+            bDeviceSubClass: device_descriptor.bDeviceSubClass,
+            bDeviceProtocol: device_descriptor.bDeviceProtocol,
+            bMaxPacketSize0: device_descriptor.bMaxPacketSize,
+            bNumConfigurations: device_descriptor.bNumConfigurations,
+            ..Self::new()
+        }
+    }
 }
 
 impl Default for DeviceQualifierDescriptor {
@@ -221,12 +237,27 @@ impl<'a> ConfigurationDescriptor<'a> {
         tail: &'a [InterfaceDescriptor],
     ) -> Self {
         head.bLength = size_of::<ConfigurationDescriptorHeader>() as u8;
-        head.bNumInterfaces = tail.len() as u8;
+
+        // alternate settings do not count towards the number of interfaces
+        let mut num_interfaces = 0;
+        let mut total_length = size_of::<ConfigurationDescriptorHeader>();
+        let mut index = 0;
+        while index < tail.len() {
+            if tail[index].head.bAlternateSetting == 0 {
+                num_interfaces += 1;
+            }
+            total_length += tail[index].length();
+            index += 1;
+        }
+        head.bNumInterfaces = num_interfaces;
+        head.wTotalLength = total_length as u16;
 
         Self { head, tail }
     }
 
     /// Calculate and update the descriptor total length field
+    ///
+    /// Note: `wTotalLength` is now calculated by [`ConfigurationDescriptor::new()`].
     pub fn set_total_length(&mut self) -> usize {
         let total_length = self.iter().count();
         if let Ok(total_length) = u16::try_from(total_length) {
@@ -247,6 +278,27 @@ impl<'a> ConfigurationDescriptor<'a> {
     pub fn iter(&self) -> ConfigurationDescriptorIterator {
         ConfigurationDescriptorIterator::new(self)
     }
+
+    /// Returns an iterator to the other speed configuration
+    /// descriptor derived from this high-speed configuration.
+    ///
+    /// See: [`EndpointDescriptor::other_speed()`]
+    pub fn iter_other_speed(&'a self) -> impl Iterator<Item = u8> + 'a {
+        // patch bDescriptorType
+        let head_iter = self.head.as_iter().enumerate().map(|(index, byte)| {
+            if index == 1 {
+                DescriptorType::OtherSpeedConfiguration as u8
+            } else {
+                *byte
+            }
+        });
+        let tail_iter = self
+            .tail
+            .iter()
+            .flat_map(InterfaceDescriptor::iter_other_speed);
+
+        head_iter.chain(tail_iter)
+    }
 }
 
 /// USB configuration descriptor iterator
@@ -279,8 +331,16 @@ impl<'a> Iterator for ConfigurationDescriptorIterator<'a> {
 // - InterfaceDescriptor ------------------------------------------------------
 
 // type aliases for sanity
-pub type InterfaceDescriptorIterator<'a> =
-    CompositeIterator3<'a, InterfaceDescriptorHeader, ClassSpecificDescriptor, EndpointDescriptor>;
+pub type InterfaceDescriptorIterator<'a> = iter::Chain<
+    iter::Chain<
+        iter::Chain<
+            slice::Iter<'a, u8>,
+            CompositeIterator<'a, InterfaceDescriptorHeader, ClassSpecificDescriptor>,
+        >,
+        slice::Iter<'a, u8>,
+    >,
+    EndpointDescriptorIterator<'a>,
+>;
 pub type ConfigurationDescriptorTailIterator<'a> = iter::FlatMap<
     slice::Iter<'a, InterfaceDescriptor<'a>>,
     InterfaceDescriptorIterator<'a>,
@@ -324,9 +384,15 @@ impl InterfaceDescriptorHeader {
 
 /// USB interface descriptor
 pub struct InterfaceDescriptor<'a> {
-    head: InterfaceDescriptorHeader,
-    tail1: &'a [ClassSpecificDescriptor],
-    tail2: &'a [EndpointDescriptor],
+    pub association: Option<InterfaceAssociationDescriptor>,
+    pub head: InterfaceDescriptorHeader,
+    pub tail1: &'a [ClassSpecificDescriptor],
+    /// Raw class-specific descriptors, emitted before the endpoint descriptors.
+    pub class_descriptors: &'a [u8],
+    pub tail2: &'a [EndpointDescriptor],
+    /// Raw class-specific endpoint descriptors, each emitted after the
+    /// endpoint descriptor with the same index.
+    pub endpoint_class_descriptors: &'a [&'a [u8]],
 }
 
 impl<'a> InterfaceDescriptor<'a> {
@@ -336,9 +402,12 @@ impl<'a> InterfaceDescriptor<'a> {
         head.bLength = size_of::<InterfaceDescriptorHeader>() as u8;
         head.bNumEndpoints = tail2.len() as u8;
         Self {
+            association: None,
             head,
             tail1: &[],
+            class_descriptors: &[],
             tail2,
+            endpoint_class_descriptors: &[],
         }
     }
 
@@ -351,21 +420,200 @@ impl<'a> InterfaceDescriptor<'a> {
     ) -> Self {
         head.bLength = size_of::<InterfaceDescriptorHeader>() as u8;
         head.bNumEndpoints = tail2.len() as u8;
-        Self { head, tail1, tail2 }
+        Self {
+            association: None,
+            head,
+            tail1,
+            class_descriptors: &[],
+            tail2,
+            endpoint_class_descriptors: &[],
+        }
+    }
+
+    /// Returns an interface descriptor followed by the given raw
+    /// class-specific descriptor bytes. (e.g. a HID descriptor)
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new_class(
+        mut head: InterfaceDescriptorHeader,
+        class_descriptors: &'a [u8],
+        tail2: &'a [EndpointDescriptor],
+    ) -> Self {
+        head.bLength = size_of::<InterfaceDescriptorHeader>() as u8;
+        head.bNumEndpoints = tail2.len() as u8;
+        Self {
+            association: None,
+            head,
+            tail1: &[],
+            class_descriptors,
+            tail2,
+            endpoint_class_descriptors: &[],
+        }
+    }
+
+    /// Returns the interface descriptor preceded by the given
+    /// interface association descriptor.
+    ///
+    /// Use this on the first interface of a function spanning
+    /// multiple interfaces.
+    #[must_use]
+    pub const fn with_association(mut self, association: InterfaceAssociationDescriptor) -> Self {
+        self.association = Some(association);
+        self
+    }
+
+    /// Returns the interface descriptor with the given raw class-specific
+    /// descriptors following each of its endpoint descriptors. (e.g. an
+    /// audio class isochronous data endpoint descriptor)
+    #[must_use]
+    pub const fn with_endpoint_class_descriptors(
+        mut self,
+        endpoint_class_descriptors: &'a [&'a [u8]],
+    ) -> Self {
+        self.endpoint_class_descriptors = endpoint_class_descriptors;
+        self
     }
 
+    /// Returns the length of the interface descriptor and its
+    /// association, class-specific and endpoint descriptors in bytes.
     #[must_use]
+    pub const fn length(&self) -> usize {
+        let association_length = match self.association {
+            Some(_) => size_of::<InterfaceAssociationDescriptor>(),
+            None => 0,
+        };
+        let mut endpoint_class_length = 0;
+        let mut index = 0;
+        while index < self.endpoint_class_descriptors.len() {
+            endpoint_class_length += self.endpoint_class_descriptors[index].len();
+            index += 1;
+        }
+        association_length
+            + size_of::<InterfaceDescriptorHeader>()
+            + self.tail1.len() * size_of::<ClassSpecificDescriptor>()
+            + self.class_descriptors.len()
+            + self.tail2.len() * size_of::<EndpointDescriptor>()
+            + endpoint_class_length
+    }
+
     #[allow(clippy::iter_without_into_iter)]
-    pub fn iter(
-        &'a self,
-    ) -> CompositeIterator3<
-        'a,
-        InterfaceDescriptorHeader,
-        ClassSpecificDescriptor,
-        EndpointDescriptor,
-    > {
-        let iter = CompositeIterator3::new(&self.head, self.tail1, self.tail2);
-        iter
+    pub fn iter(&'a self) -> InterfaceDescriptorIterator<'a> {
+        let association: &'a [u8] = match &self.association {
+            Some(association) => association.as_bytes(),
+            None => &[],
+        };
+        let endpoints =
+            EndpointDescriptorIterator::new(self.tail2, self.endpoint_class_descriptors);
+        association
+            .iter()
+            .chain(CompositeIterator::new(&self.head, self.tail1))
+            .chain(self.class_descriptors.iter())
+            .chain(endpoints)
+    }
+
+    /// Returns an iterator to the interface descriptor with its
+    /// endpoint descriptors converted to the other speed.
+    pub fn iter_other_speed(&'a self) -> impl Iterator<Item = u8> + 'a {
+        let association: &'a [u8] = match &self.association {
+            Some(association) => association.as_bytes(),
+            None => &[],
+        };
+        let endpoints = self
+            .tail2
+            .iter()
+            .enumerate()
+            .flat_map(move |(index, endpoint)| {
+                let bytes: [u8; size_of::<EndpointDescriptor>()] =
+                    zerocopy::transmute!(endpoint.other_speed());
+                let class_descriptors: &'a [u8] = self
+                    .endpoint_class_descriptors
+                    .get(index)
+                    .copied()
+                    .unwrap_or(&[]);
+                bytes.into_iter().chain(class_descriptors.iter().copied())
+            });
+
+        association
+            .iter()
+            .chain(self.head.as_iter())
+            .chain(self.tail1.iter().flat_map(ClassSpecificDescriptor::as_iter))
+            .chain(self.class_descriptors.iter())
+            .copied()
+            .chain(endpoints)
+    }
+}
+
+/// Iterator over the endpoint descriptors of an interface, each
+/// followed by its class-specific endpoint descriptors.
+pub struct EndpointDescriptorIterator<'a> {
+    endpoints: slice::Iter<'a, EndpointDescriptor>,
+    class_descriptors: slice::Iter<'a, &'a [u8]>,
+    current: iter::Chain<slice::Iter<'a, u8>, slice::Iter<'a, u8>>,
+}
+
+impl<'a> EndpointDescriptorIterator<'a> {
+    fn new(endpoints: &'a [EndpointDescriptor], class_descriptors: &'a [&'a [u8]]) -> Self {
+        let empty: &'a [u8] = &[];
+        Self {
+            endpoints: endpoints.iter(),
+            class_descriptors: class_descriptors.iter(),
+            current: empty.iter().chain(empty.iter()),
+        }
+    }
+}
+
+impl<'a> Iterator for EndpointDescriptorIterator<'a> {
+    type Item = &'a u8;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(byte) = self.current.next() {
+                return Some(byte);
+            }
+            let endpoint = self.endpoints.next()?;
+            let class_descriptors: &'a [u8] = self.class_descriptors.next().copied().unwrap_or(&[]);
+            self.current = endpoint.as_iter().chain(class_descriptors.iter());
+        }
+    }
+}
+
+// - InterfaceAssociationDescriptor -------------------------------------------
+
+/// USB interface association descriptor
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct InterfaceAssociationDescriptor {
+    pub bLength: u8,         // 8
+    pub bDescriptorType: u8, // 11 = InterfaceAssociation
+    pub bFirstInterface: u8,
+    pub bInterfaceCount: u8,
+    pub bFunctionClass: u8,
+    pub bFunctionSubClass: u8,
+    pub bFunctionProtocol: u8,
+    pub iFunction: u8,
+}
+
+impl AsByteSliceIterator for InterfaceAssociationDescriptor {}
+
+impl InterfaceAssociationDescriptor {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new() -> Self {
+        Self {
+            bLength: size_of::<Self>() as u8,
+            bDescriptorType: DescriptorType::InterfaceAssociation as u8,
+            bFirstInterface: 0,
+            bInterfaceCount: 0,
+            bFunctionClass: 0,
+            bFunctionSubClass: 0,
+            bFunctionProtocol: 0,
+            iFunction: 0,
+        }
+    }
+}
+
+impl Default for InterfaceAssociationDescriptor {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -399,6 +647,69 @@ impl ClassSpecificDescriptor {
 
 // - EndpointDescriptor -------------------------------------------------------
 
+/// Endpoint transfer type. (`bmAttributes` bits 0..=1)
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum TransferType {
+    Control = 0b00,
+    Isochronous = 0b01,
+    Bulk = 0b10,
+    Interrupt = 0b11,
+}
+
+impl From<u8> for TransferType {
+    fn from(value: u8) -> Self {
+        match value & 0b11 {
+            0b00 => TransferType::Control,
+            0b01 => TransferType::Isochronous,
+            0b10 => TransferType::Bulk,
+            _ => TransferType::Interrupt,
+        }
+    }
+}
+
+/// Isochronous endpoint synchronization type. (`bmAttributes` bits 2..=3)
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum SynchronizationType {
+    NoSynchronization = 0b00,
+    Asynchronous = 0b01,
+    Adaptive = 0b10,
+    Synchronous = 0b11,
+}
+
+impl From<u8> for SynchronizationType {
+    fn from(value: u8) -> Self {
+        match (value >> 2) & 0b11 {
+            0b00 => SynchronizationType::NoSynchronization,
+            0b01 => SynchronizationType::Asynchronous,
+            0b10 => SynchronizationType::Adaptive,
+            _ => SynchronizationType::Synchronous,
+        }
+    }
+}
+
+/// Isochronous endpoint usage type. (`bmAttributes` bits 4..=5)
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum UsageType {
+    Data = 0b00,
+    Feedback = 0b01,
+    ImplicitFeedbackData = 0b10,
+    Reserved = 0b11,
+}
+
+impl From<u8> for UsageType {
+    fn from(value: u8) -> Self {
+        match (value >> 4) & 0b11 {
+            0b00 => UsageType::Data,
+            0b01 => UsageType::Feedback,
+            0b10 => UsageType::ImplicitFeedbackData,
+            _ => UsageType::Reserved,
+        }
+    }
+}
+
 /// USB endpoint descriptor
 #[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
 #[repr(C, packed)]
@@ -426,6 +737,132 @@ impl EndpointDescriptor {
             bInterval: 0,
         }
     }
+
+    /// Returns an isochronous endpoint descriptor.
+    ///
+    /// High-speed, high-bandwidth endpoints may perform up to three
+    /// `transactions_per_microframe` which are encoded in bits 11..=12
+    /// of `wMaxPacketSize`. `interval` is the `bInterval` exponent.
+    #[must_use]
+    pub const fn isochronous(
+        endpoint_address: u8,
+        synchronization_type: SynchronizationType,
+        usage_type: UsageType,
+        max_packet_size: u16,
+        transactions_per_microframe: u8,
+        interval: u8,
+    ) -> Self {
+        Self {
+            bEndpointAddress: endpoint_address,
+            bmAttributes: Self::attributes(
+                TransferType::Isochronous,
+                synchronization_type,
+                usage_type,
+            ),
+            wMaxPacketSize: Self::max_packet_size_with_transactions(
+                max_packet_size,
+                transactions_per_microframe,
+            ),
+            bInterval: interval,
+            ..Self::new()
+        }
+    }
+
+    /// Encodes `bmAttributes` from its transfer, synchronization and usage types.
+    #[must_use]
+    pub const fn attributes(
+        transfer_type: TransferType,
+        synchronization_type: SynchronizationType,
+        usage_type: UsageType,
+    ) -> u8 {
+        transfer_type as u8 | (synchronization_type as u8) << 2 | (usage_type as u8) << 4
+    }
+
+    /// Encodes `wMaxPacketSize` from a packet size and the number of
+    /// transactions per microframe (1..=3) of a high-bandwidth endpoint.
+    #[must_use]
+    pub const fn max_packet_size_with_transactions(
+        max_packet_size: u16,
+        transactions_per_microframe: u8,
+    ) -> u16 {
+        let additional_transactions = if transactions_per_microframe > 1 {
+            (transactions_per_microframe - 1) as u16 & 0b11
+        } else {
+            0
+        };
+        (max_packet_size & 0x07ff) | additional_transactions << 11
+    }
+
+    #[must_use]
+    pub fn transfer_type(&self) -> TransferType {
+        TransferType::from(self.bmAttributes)
+    }
+
+    #[must_use]
+    pub fn synchronization_type(&self) -> SynchronizationType {
+        SynchronizationType::from(self.bmAttributes)
+    }
+
+    #[must_use]
+    pub fn usage_type(&self) -> UsageType {
+        UsageType::from(self.bmAttributes)
+    }
+
+    /// Returns the maximum packet size without the additional transaction bits.
+    #[must_use]
+    pub const fn max_packet_size(&self) -> u16 {
+        self.wMaxPacketSize & 0x07ff
+    }
+
+    /// Returns the number of transactions per microframe for
+    /// high-speed, high-bandwidth endpoints.
+    #[must_use]
+    pub const fn transactions_per_microframe(&self) -> u8 {
+        ((self.wMaxPacketSize >> 11) & 0b11) as u8 + 1
+    }
+
+    /// Returns the full-speed equivalent of this high-speed endpoint
+    /// descriptor for use in an other speed configuration.
+    ///
+    /// Maximum packet sizes are limited to those allowed at full-speed
+    /// and polling intervals are converted from microframes to frames.
+    #[must_use]
+    pub const fn other_speed(&self) -> Self {
+        let max_packet_size = self.wMaxPacketSize & 0x07ff;
+        let (max_packet_size_limit, interval) = match self.bmAttributes & 0b11 {
+            // isochronous: 2^(bInterval-1) microframes => 2^(bInterval-4) frames
+            0b01 => (
+                1023,
+                if self.bInterval > 4 {
+                    self.bInterval - 3
+                } else {
+                    1
+                },
+            ),
+            // interrupt: 2^(bInterval-1) microframes => bInterval frames
+            0b11 => {
+                let interval = if self.bInterval >= 12 {
+                    255
+                } else if self.bInterval > 4 {
+                    1 << (self.bInterval - 4)
+                } else {
+                    1
+                };
+                (64, interval)
+            }
+            // control, bulk
+            _ => (64, 0),
+        };
+        Self {
+            wMaxPacketSize: if max_packet_size > max_packet_size_limit {
+                max_packet_size_limit
+            } else {
+                max_packet_size
+            },
+            bInterval: interval,
+            ..*self
+        }
+    }
 }
 
 impl Default for EndpointDescriptor {
@@ -434,6 +871,226 @@ impl Default for EndpointDescriptor {
     }
 }
 
+// - BinaryObjectStore --------------------------------------------------------
+
+/// USB device capability type. (`bDevCapabilityType`)
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u8)]
+pub enum DeviceCapabilityType {
+    WirelessUsb = 0x01,
+    Usb20Extension = 0x02,
+    SuperSpeedUsb = 0x03,
+    ContainerId = 0x04,
+    Platform = 0x05,
+}
+
+/// USB binary object store descriptor header
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct BinaryObjectStoreHeader {
+    pub bLength: u8,         // 5
+    pub bDescriptorType: u8, // 15 = BinaryDeviceObjectStore
+    pub wTotalLength: u16,
+    pub bNumDeviceCaps: u8,
+}
+
+impl AsByteSliceIterator for BinaryObjectStoreHeader {}
+
+impl BinaryObjectStoreHeader {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new() -> Self {
+        Self {
+            bLength: size_of::<Self>() as u8,
+            bDescriptorType: DescriptorType::BinaryDeviceObjectStore as u8,
+            wTotalLength: 0,
+            bNumDeviceCaps: 0,
+        }
+    }
+}
+
+impl Default for BinaryObjectStoreHeader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// USB binary object store descriptor
+#[derive(Clone, Copy)]
+pub struct BinaryObjectStore<'a> {
+    pub head: BinaryObjectStoreHeader,
+    pub tail: &'a [DeviceCapability<'a>],
+}
+
+impl<'a> BinaryObjectStore<'a> {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(capabilities: &'a [DeviceCapability<'a>]) -> Self {
+        let mut head = BinaryObjectStoreHeader::new();
+
+        let mut total_length = size_of::<BinaryObjectStoreHeader>();
+        let mut index = 0;
+        while index < capabilities.len() {
+            total_length += capabilities[index].length();
+            index += 1;
+        }
+        head.wTotalLength = total_length as u16;
+        head.bNumDeviceCaps = capabilities.len() as u8;
+
+        Self {
+            head,
+            tail: capabilities,
+        }
+    }
+
+    #[allow(clippy::iter_without_into_iter)]
+    pub fn iter(&'a self) -> BinaryObjectStoreIterator<'a> {
+        let tail_iter: BinaryObjectStoreTailIterator<'a> = self
+            .tail
+            .iter()
+            .flat_map(DeviceCapability::iter as fn(&'a DeviceCapability<'a>) -> _);
+        self.head.as_iter().chain(tail_iter)
+    }
+}
+
+// type aliases for sanity
+pub type DeviceCapabilityIterator<'a> = iter::Chain<slice::Iter<'a, u8>, slice::Iter<'a, u8>>;
+pub type BinaryObjectStoreTailIterator<'a> = iter::FlatMap<
+    slice::Iter<'a, DeviceCapability<'a>>,
+    DeviceCapabilityIterator<'a>,
+    fn(&'a DeviceCapability<'a>) -> DeviceCapabilityIterator<'a>,
+>;
+pub type BinaryObjectStoreIterator<'a> =
+    iter::Chain<slice::Iter<'a, u8>, BinaryObjectStoreTailIterator<'a>>;
+
+/// USB device capability descriptor
+#[derive(Clone, Copy)]
+pub enum DeviceCapability<'a> {
+    Usb20Extension(Usb20ExtensionDescriptor),
+    ContainerId(ContainerIdDescriptor),
+    Platform(PlatformDescriptor<'a>),
+}
+
+impl<'a> DeviceCapability<'a> {
+    /// Returns the length of the capability descriptor in bytes.
+    #[must_use]
+    pub const fn length(&self) -> usize {
+        match self {
+            DeviceCapability::Usb20Extension(_) => size_of::<Usb20ExtensionDescriptor>(),
+            DeviceCapability::ContainerId(_) => size_of::<ContainerIdDescriptor>(),
+            DeviceCapability::Platform(descriptor) => {
+                size_of::<PlatformDescriptorHeader>() + descriptor.tail.len()
+            }
+        }
+    }
+
+    #[allow(clippy::iter_without_into_iter)]
+    pub fn iter(&'a self) -> DeviceCapabilityIterator<'a> {
+        match self {
+            DeviceCapability::Usb20Extension(descriptor) => descriptor.as_iter().chain(&[]),
+            DeviceCapability::ContainerId(descriptor) => descriptor.as_iter().chain(&[]),
+            DeviceCapability::Platform(descriptor) => {
+                descriptor.head.as_iter().chain(descriptor.tail.iter())
+            }
+        }
+    }
+}
+
+/// USB 2.0 extension capability descriptor
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct Usb20ExtensionDescriptor {
+    pub bLength: u8,            // 7
+    pub bDescriptorType: u8,    // 16 = DeviceCapability
+    pub bDevCapabilityType: u8, // 2 = Usb20Extension
+    pub bmAttributes: u32,
+}
+
+impl AsByteSliceIterator for Usb20ExtensionDescriptor {}
+
+impl Usb20ExtensionDescriptor {
+    /// Link Power Management support. (`bmAttributes`)
+    pub const LPM: u32 = 1 << 1;
+
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(attributes: u32) -> Self {
+        Self {
+            bLength: size_of::<Self>() as u8,
+            bDescriptorType: DescriptorType::DeviceCapability as u8,
+            bDevCapabilityType: DeviceCapabilityType::Usb20Extension as u8,
+            bmAttributes: attributes,
+        }
+    }
+}
+
+/// Container ID capability descriptor
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct ContainerIdDescriptor {
+    pub bLength: u8,            // 20
+    pub bDescriptorType: u8,    // 16 = DeviceCapability
+    pub bDevCapabilityType: u8, // 4 = ContainerId
+    pub bReserved: u8,
+    pub ContainerID: [u8; 16],
+}
+
+impl AsByteSliceIterator for ContainerIdDescriptor {}
+
+impl ContainerIdDescriptor {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(container_id: [u8; 16]) -> Self {
+        Self {
+            bLength: size_of::<Self>() as u8,
+            bDescriptorType: DescriptorType::DeviceCapability as u8,
+            bDevCapabilityType: DeviceCapabilityType::ContainerId as u8,
+            bReserved: 0,
+            ContainerID: container_id,
+        }
+    }
+}
+
+/// Platform capability descriptor header
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct PlatformDescriptorHeader {
+    pub bLength: u8,            // 20 + length of capability data
+    pub bDescriptorType: u8,    // 16 = DeviceCapability
+    pub bDevCapabilityType: u8, // 5 = Platform
+    pub bReserved: u8,
+    pub PlatformCapabilityUUID: [u8; 16],
+}
+
+impl AsByteSliceIterator for PlatformDescriptorHeader {}
+
+/// Platform capability descriptor
+///
+/// The contents of `tail` are defined by the platform identified by
+/// the descriptor's UUID.
+#[derive(Clone, Copy)]
+pub struct PlatformDescriptor<'a> {
+    pub head: PlatformDescriptorHeader,
+    pub tail: &'a [u8],
+}
+
+impl<'a> PlatformDescriptor<'a> {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(uuid: [u8; 16], capability_data: &'a [u8]) -> Self {
+        Self {
+            head: PlatformDescriptorHeader {
+                bLength: (size_of::<PlatformDescriptorHeader>() + capability_data.len()) as u8,
+                bDescriptorType: DescriptorType::DeviceCapability as u8,
+                bDevCapabilityType: DeviceCapabilityType::Platform as u8,
+                bReserved: 0,
+                PlatformCapabilityUUID: uuid,
+            },
+            tail: capability_data,
+        }
+    }
+}
+
 // - StringDescriptorZero -----------------------------------------------------
 
 /// USB string descriptor language id
@@ -541,8 +1198,78 @@ impl<'a> StringDescriptor<'a> {
 pub type StringDescriptorIterator<'a> =
     iter::Chain<iter::Cloned<slice::Iter<'a, u8>>, Utf16ByteIterator<'a>>;
 
+// - string_descriptors! ------------------------------------------------------
+
+/// Declares a table of string descriptors with automatically assigned
+/// string indices.
+///
+/// For example:
+///
+///     smolusb::string_descriptors! {
+///         pub mod strings {
+///             MANUFACTURER = "Great Scott Gadgets",
+///             PRODUCT = "Cynthion",
+///         }
+///     }
+///
+/// Declares `strings::MANUFACTURER` and `strings::PRODUCT` as the
+/// string indices `1` and `2` for use in descriptor fields such as
+/// `iManufacturer` and `strings::STRING_DESCRIPTORS` for use as
+/// [`Descriptors::string_descriptors`](crate::device::Descriptors::string_descriptors).
+#[macro_export]
+macro_rules! string_descriptors {
+    (
+        $vis:vis mod $module:ident {
+            $($name:ident = $string:expr),+ $(,)?
+        }
+    ) => {
+        $vis mod $module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #[allow(dead_code, non_camel_case_types, clippy::upper_case_acronyms)]
+            #[repr(u8)]
+            enum Index {
+                // string index 0 is reserved for the language id table
+                _Zero = 0,
+                $($name,)+
+            }
+
+            $(
+                #[allow(dead_code)]
+                pub const $name: u8 = Index::$name as u8;
+            )+
+
+            pub static STRING_DESCRIPTORS: &[&$crate::descriptor::StringDescriptor] = &[
+                $(&$crate::descriptor::StringDescriptor::new($string),)+
+            ];
+        }
+    };
+}
+
 // - Utf16ByteIterator --------------------------------------------------------
 
+/// Returns the number of UTF-16 code units required to encode the given string.
+#[must_use]
+pub const fn utf16_length(string: &str) -> usize {
+    let bytes = string.as_bytes();
+    let mut length = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        // count leading bytes, skip continuation bytes
+        if byte & 0b1100_0000 != 0b1000_0000 {
+            length += 1;
+        }
+        // four byte sequences are encoded as a surrogate pair
+        if byte >= 0b1111_0000 {
+            length += 1;
+        }
+        index += 1;
+    }
+    length
+}
+
 #[derive(Clone)]
 pub struct Utf16ByteIterator<'a> {
     encode_utf16: core::str::EncodeUtf16<'a>,
@@ -645,3 +1372,244 @@ impl<'a, H, T1, T2> Iterator for CompositeIterator3<'a, H, T1, T2> {
         self.chain.next()
     }
 }
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // - fixtures -------------------------------------------------------------
+
+    const PLATFORM_UUID: [u8; 16] = [0xaa; 16];
+
+    static BINARY_OBJECT_STORE: BinaryObjectStore = BinaryObjectStore::new(&[
+        DeviceCapability::Usb20Extension(Usb20ExtensionDescriptor::new(
+            Usb20ExtensionDescriptor::LPM,
+        )),
+        DeviceCapability::ContainerId(ContainerIdDescriptor::new([0x55; 16])),
+        DeviceCapability::Platform(PlatformDescriptor::new(PLATFORM_UUID, &[0x01, 0x02, 0x03])),
+    ]);
+
+    static CONFIGURATION_DESCRIPTOR: ConfigurationDescriptor = ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[
+            InterfaceDescriptor::new(
+                InterfaceDescriptorHeader::new(),
+                &[EndpointDescriptor::new(), EndpointDescriptor::new()],
+            ),
+            InterfaceDescriptor::new_cs(
+                InterfaceDescriptorHeader {
+                    iInterfaceNumber: 1,
+                    bAlternateSetting: 0,
+                    ..InterfaceDescriptorHeader::new()
+                },
+                &[ClassSpecificDescriptor::new()],
+                &[],
+            ),
+            InterfaceDescriptor::new(
+                InterfaceDescriptorHeader {
+                    iInterfaceNumber: 1,
+                    bAlternateSetting: 1,
+                    ..InterfaceDescriptorHeader::new()
+                },
+                &[EndpointDescriptor::new()],
+            ),
+        ],
+    );
+
+    static COMPOSITE_CONFIGURATION_DESCRIPTOR: ConfigurationDescriptor =
+        ConfigurationDescriptor::new(
+            ConfigurationDescriptorHeader::new(),
+            &[
+                InterfaceDescriptor::new(
+                    InterfaceDescriptorHeader {
+                        iInterfaceNumber: 0,
+                        ..InterfaceDescriptorHeader::new()
+                    },
+                    &[],
+                )
+                .with_association(InterfaceAssociationDescriptor {
+                    bFirstInterface: 0,
+                    bInterfaceCount: 2,
+                    ..InterfaceAssociationDescriptor::new()
+                }),
+                InterfaceDescriptor::new(
+                    InterfaceDescriptorHeader {
+                        iInterfaceNumber: 1,
+                        ..InterfaceDescriptorHeader::new()
+                    },
+                    &[],
+                ),
+                InterfaceDescriptor::new(
+                    InterfaceDescriptorHeader {
+                        iInterfaceNumber: 2,
+                        ..InterfaceDescriptorHeader::new()
+                    },
+                    &[],
+                ),
+            ],
+        );
+
+    crate::string_descriptors! {
+        mod strings {
+            MANUFACTURER = "Great Scott Gadgets",
+            PRODUCT = "Cynthion",
+        }
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_string_descriptors() {
+        assert_eq!(strings::MANUFACTURER, 1);
+        assert_eq!(strings::PRODUCT, 2);
+        assert_eq!(strings::STRING_DESCRIPTORS.len(), 2);
+        assert_eq!(
+            strings::STRING_DESCRIPTORS[usize::from(strings::PRODUCT) - 1].tail,
+            "Cynthion"
+        );
+    }
+
+    #[test]
+    fn test_other_speed_configuration_descriptor() {
+        static HIGH_SPEED: ConfigurationDescriptor = ConfigurationDescriptor::new(
+            ConfigurationDescriptorHeader::new(),
+            &[InterfaceDescriptor::new(
+                InterfaceDescriptorHeader::new(),
+                &[
+                    EndpointDescriptor {
+                        bEndpointAddress: 0x81,
+                        bmAttributes: 0x02, // Bulk
+                        wMaxPacketSize: 512,
+                        bInterval: 0,
+                        ..EndpointDescriptor::new()
+                    },
+                    EndpointDescriptor {
+                        bEndpointAddress: 0x82,
+                        bmAttributes: 0x03, // Interrupt
+                        wMaxPacketSize: 8,
+                        bInterval: 7, // 2^6 microframes => 8 frames
+                        ..EndpointDescriptor::new()
+                    },
+                ],
+            )],
+        );
+
+        let high_speed: std::vec::Vec<u8> = HIGH_SPEED.iter().copied().collect();
+        let other_speed: std::vec::Vec<u8> = HIGH_SPEED.iter_other_speed().collect();
+
+        assert_eq!(other_speed.len(), high_speed.len());
+        assert_eq!(
+            other_speed[1],
+            DescriptorType::OtherSpeedConfiguration as u8
+        );
+        assert_eq!(&other_speed[2..18], &high_speed[2..18]);
+        assert_eq!(
+            &other_speed[18..25],
+            &[0x07, 0x05, 0x81, 0x02, 64, 0x00, 0x00]
+        );
+        assert_eq!(&other_speed[25..32], &[0x07, 0x05, 0x82, 0x03, 8, 0x00, 8]);
+    }
+
+    #[test]
+    fn test_isochronous_endpoint_descriptor() {
+        static ENDPOINT: EndpointDescriptor = EndpointDescriptor::isochronous(
+            0x83,
+            SynchronizationType::Asynchronous,
+            UsageType::ImplicitFeedbackData,
+            1024,
+            3,
+            1,
+        );
+
+        assert_eq!(
+            ENDPOINT.as_bytes(),
+            &[0x07, 0x05, 0x83, 0b0010_0101, 0x00, 0x14, 0x01]
+        );
+        assert_eq!(ENDPOINT.transfer_type(), TransferType::Isochronous);
+        assert_eq!(
+            ENDPOINT.synchronization_type(),
+            SynchronizationType::Asynchronous
+        );
+        assert_eq!(ENDPOINT.usage_type(), UsageType::ImplicitFeedbackData);
+        assert_eq!(ENDPOINT.max_packet_size(), 1024);
+        assert_eq!(ENDPOINT.transactions_per_microframe(), 3);
+
+        // other speed endpoints drop the additional transactions
+        let other_speed = ENDPOINT.other_speed();
+        assert_eq!(other_speed.max_packet_size(), 1023);
+        assert_eq!(other_speed.transactions_per_microframe(), 1);
+    }
+
+    #[test]
+    fn test_device_qualifier_descriptor() {
+        let device_descriptor = DeviceDescriptor {
+            bcdUSB: 0x0200,
+            bMaxPacketSize: 64,
+            bNumConfigurations: 2,
+            ..DeviceDescriptor::new()
+        };
+        let descriptor = DeviceQualifierDescriptor::from_device_descriptor(&device_descriptor);
+
+        assert_eq!(
+            descriptor.as_bytes(),
+            &[0x0a, 0x06, 0x00, 0x02, 0x00, 0x00, 0x00, 64, 2, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_configuration_descriptor_total_length() {
+        let bytes: std::vec::Vec<u8> = CONFIGURATION_DESCRIPTOR.iter().copied().collect();
+
+        assert_eq!(bytes.len(), 9 + (9 + 7 + 7) + (9 + 5) + (9 + 7));
+        assert_eq!(
+            bytes.len(),
+            usize::from(CONFIGURATION_DESCRIPTOR.head.wTotalLength)
+        );
+        assert_eq!(&bytes[0..5], &[0x09, 0x02, 0x3e, 0x00, 0x02]);
+    }
+
+    #[test]
+    fn test_configuration_descriptor_num_interfaces() {
+        // alternate settings are not counted as separate interfaces
+        assert_eq!(CONFIGURATION_DESCRIPTOR.head.bNumInterfaces, 2);
+    }
+
+    #[test]
+    fn test_interface_association_descriptor() {
+        let bytes: std::vec::Vec<u8> = COMPOSITE_CONFIGURATION_DESCRIPTOR.iter().copied().collect();
+
+        assert_eq!(bytes.len(), 9 + 8 + 9 + 9 + 9);
+        assert_eq!(
+            bytes.len(),
+            usize::from(COMPOSITE_CONFIGURATION_DESCRIPTOR.head.wTotalLength)
+        );
+        assert_eq!(COMPOSITE_CONFIGURATION_DESCRIPTOR.head.bNumInterfaces, 3);
+        // the association descriptor precedes the first interface of the function
+        assert_eq!(
+            &bytes[9..17],
+            &[0x08, 0x0b, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(&bytes[17..20], &[0x09, 0x04, 0x00]);
+        assert_eq!(&bytes[26..29], &[0x09, 0x04, 0x01]);
+        assert_eq!(&bytes[35..38], &[0x09, 0x04, 0x02]);
+    }
+
+    #[test]
+    fn test_binary_object_store() {
+        let bytes: std::vec::Vec<u8> = BINARY_OBJECT_STORE.iter().copied().collect();
+
+        assert_eq!(bytes.len(), 5 + 7 + 20 + 23);
+        assert_eq!(&bytes[0..5], &[0x05, 0x0f, 55, 0x00, 0x03]);
+        assert_eq!(&bytes[5..12], &[0x07, 0x10, 0x02, 0x02, 0x00, 0x00, 0x00]);
+        assert_eq!(&bytes[12..16], &[0x14, 0x10, 0x04, 0x00]);
+        assert_eq!(&bytes[16..32], &[0x55; 16]);
+        assert_eq!(&bytes[32..36], &[0x17, 0x10, 0x05, 0x00]);
+        assert_eq!(&bytes[36..52], &PLATFORM_UUID);
+        assert_eq!(&bytes[52..], &[0x01, 0x02, 0x03]);
+    }
+}
@@ -0,0 +1,624 @@
+//! Microsoft OS 2.0 USB Descriptors
+//!
+//! See: <https://learn.microsoft.com/en-us/windows-hardware/drivers/usbcon/microsoft-os-2-0-descriptors-specification>
+
+use core::mem::size_of;
+
+use zerocopy::{AsBytes, FromBytes, FromZeroes};
+
+use crate::descriptor::{utf16_length, PlatformDescriptor, Utf16ByteIterator};
+use crate::traits::AsByteSliceIterator;
+
+// - Constants ----------------------------------------------------------------
+
+/// Microsoft OS 2.0 Platform Capability UUID
+///
+/// `{D8DD60DF-4589-4CC7-9CD2-659D9E648A9F}`
+pub const PLATFORM_CAPABILITY_UUID: [u8; 16] = [
+    0xdf, 0x60, 0xdd, 0xd8, 0x89, 0x45, 0xc7, 0x4c, 0x9c, 0xd2, 0x65, 0x9d, 0x9e, 0x64, 0x8a, 0x9f,
+];
+
+/// Minimum Windows version for the descriptor set. (`NTDDI_*`)
+#[non_exhaustive]
+pub struct WindowsVersion;
+
+#[allow(non_upper_case_globals)]
+impl WindowsVersion {
+    /// Windows 8.1
+    pub const Windows81: u32 = 0x0603_0000;
+    /// Windows 10
+    pub const Windows10: u32 = 0x0a00_0000;
+}
+
+/// Vendor Indices
+#[repr(u16)]
+#[derive(Debug, PartialEq)]
+pub enum VendorIndex {
+    DescriptorSet = 0x0007,
+    SetAlternateEnumeration = 0x0008,
+    Unknown(u16),
+}
+
+impl From<u16> for VendorIndex {
+    fn from(value: u16) -> Self {
+        match value {
+            0x0007 => VendorIndex::DescriptorSet,
+            0x0008 => VendorIndex::SetAlternateEnumeration,
+            _ => VendorIndex::Unknown(value),
+        }
+    }
+}
+
+/// Microsoft OS 2.0 descriptor type. (`wDescriptorType`)
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u16)]
+pub enum DescriptorType {
+    SetHeaderDescriptor = 0x00,
+    SubsetHeaderConfiguration = 0x01,
+    SubsetHeaderFunction = 0x02,
+    FeatureCompatibleId = 0x03,
+    FeatureRegProperty = 0x04,
+    FeatureMinResumeTime = 0x05,
+    FeatureModelId = 0x06,
+    FeatureCcgpDevice = 0x07,
+    FeatureVendorRevision = 0x08,
+}
+
+/// Registry property data type. (`wPropertyDataType`)
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[repr(u16)]
+pub enum PropertyDataType {
+    RegSz = 1,
+    RegExpandSz = 2,
+    RegBinary = 3,
+    RegDwordLittleEndian = 4,
+    RegDwordBigEndian = 5,
+    RegLink = 6,
+    RegMultiSz = 7,
+}
+
+// - Descriptors --------------------------------------------------------------
+
+/// Microsoft OS 2.0 Descriptors
+pub struct Descriptors<'a> {
+    /// Vendor request code used by the host to retrieve the descriptor set.
+    pub vendor_code: u8,
+    pub descriptor_set: DescriptorSet<'a>,
+    capability_data: [u8; 8],
+}
+
+impl<'a> Descriptors<'a> {
+    #[must_use]
+    pub const fn new(vendor_code: u8, descriptor_set: DescriptorSet<'a>) -> Self {
+        let [v0, v1, v2, v3] = descriptor_set.head.dwWindowsVersion.to_le_bytes();
+        let [l0, l1] = descriptor_set.head.wTotalLength.to_le_bytes();
+        Self {
+            vendor_code,
+            descriptor_set,
+            capability_data: [v0, v1, v2, v3, l0, l1, vendor_code, 0],
+        }
+    }
+
+    /// Returns the Microsoft OS 2.0 platform capability data.
+    ///
+    /// `dwWindowsVersion`, `wMSOSDescriptorSetTotalLength`,
+    /// `bMS_VendorCode` and `bAltEnumCode`
+    #[must_use]
+    pub const fn platform_capability_data(&self) -> &[u8; 8] {
+        &self.capability_data
+    }
+
+    /// Returns the platform capability descriptor pointing the host at
+    /// our descriptor set.
+    ///
+    /// Add this to the device's
+    /// [`BinaryObjectStore`](crate::descriptor::BinaryObjectStore) to
+    /// advertise Microsoft OS 2.0 support alongside other device
+    /// capabilities.
+    #[must_use]
+    pub const fn platform_descriptor(&self) -> PlatformDescriptor {
+        PlatformDescriptor::new(PLATFORM_CAPABILITY_UUID, &self.capability_data)
+    }
+}
+
+// - DescriptorSet ------------------------------------------------------------
+
+/// Microsoft OS 2.0 descriptor set header
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct DescriptorSetHeader {
+    pub wLength: u16,         // 10
+    pub wDescriptorType: u16, // 0 = SetHeaderDescriptor
+    pub dwWindowsVersion: u32,
+    pub wTotalLength: u16,
+}
+
+impl AsByteSliceIterator for DescriptorSetHeader {}
+
+/// Microsoft OS 2.0 descriptor set
+///
+/// Device-wide feature descriptors are given by `features`, if the
+/// device has more than one function use `configurations` to assign
+/// feature descriptors to individual functions.
+#[derive(Clone, Copy)]
+pub struct DescriptorSet<'a> {
+    pub head: DescriptorSetHeader,
+    pub features: &'a [FeatureDescriptor<'a>],
+    pub configurations: &'a [ConfigurationSubset<'a>],
+}
+
+impl<'a> DescriptorSet<'a> {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(
+        windows_version: u32,
+        features: &'a [FeatureDescriptor<'a>],
+        configurations: &'a [ConfigurationSubset<'a>],
+    ) -> Self {
+        let mut total_length = size_of::<DescriptorSetHeader>() + features_length(features);
+        let mut index = 0;
+        while index < configurations.len() {
+            total_length += configurations[index].head.wTotalLength as usize;
+            index += 1;
+        }
+
+        Self {
+            head: DescriptorSetHeader {
+                wLength: size_of::<DescriptorSetHeader>() as u16,
+                wDescriptorType: DescriptorType::SetHeaderDescriptor as u16,
+                dwWindowsVersion: windows_version,
+                wTotalLength: total_length as u16,
+            },
+            features,
+            configurations,
+        }
+    }
+
+    /// Returns an iterator to the descriptor set
+    #[allow(clippy::iter_without_into_iter)]
+    pub fn iter(&'a self) -> impl Iterator<Item = u8> + 'a {
+        self.head
+            .as_iter()
+            .copied()
+            .chain(self.features.iter().flat_map(FeatureDescriptor::iter))
+            .chain(
+                self.configurations
+                    .iter()
+                    .flat_map(ConfigurationSubset::iter),
+            )
+    }
+}
+
+// - ConfigurationSubset ------------------------------------------------------
+
+/// Microsoft OS 2.0 configuration subset header
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct ConfigurationSubsetHeader {
+    pub wLength: u16,         // 8
+    pub wDescriptorType: u16, // 1 = SubsetHeaderConfiguration
+    pub bConfigurationValue: u8,
+    pub bReserved: u8,
+    pub wTotalLength: u16,
+}
+
+impl AsByteSliceIterator for ConfigurationSubsetHeader {}
+
+/// Microsoft OS 2.0 configuration subset
+#[derive(Clone, Copy)]
+pub struct ConfigurationSubset<'a> {
+    pub head: ConfigurationSubsetHeader,
+    pub features: &'a [FeatureDescriptor<'a>],
+    pub functions: &'a [FunctionSubset<'a>],
+}
+
+impl<'a> ConfigurationSubset<'a> {
+    /// Note that, despite its name, Windows interprets the header's
+    /// `bConfigurationValue` field as the zero-based index of the
+    /// configuration rather than its `bConfigurationValue`.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(
+        configuration_index: u8,
+        features: &'a [FeatureDescriptor<'a>],
+        functions: &'a [FunctionSubset<'a>],
+    ) -> Self {
+        let mut total_length = size_of::<ConfigurationSubsetHeader>() + features_length(features);
+        let mut index = 0;
+        while index < functions.len() {
+            total_length += functions[index].head.wSubsetLength as usize;
+            index += 1;
+        }
+
+        Self {
+            head: ConfigurationSubsetHeader {
+                wLength: size_of::<ConfigurationSubsetHeader>() as u16,
+                wDescriptorType: DescriptorType::SubsetHeaderConfiguration as u16,
+                bConfigurationValue: configuration_index,
+                bReserved: 0,
+                wTotalLength: total_length as u16,
+            },
+            features,
+            functions,
+        }
+    }
+
+    #[allow(clippy::iter_without_into_iter)]
+    pub fn iter(&'a self) -> impl Iterator<Item = u8> + 'a {
+        self.head
+            .as_iter()
+            .copied()
+            .chain(self.features.iter().flat_map(FeatureDescriptor::iter))
+            .chain(self.functions.iter().flat_map(FunctionSubset::iter))
+    }
+}
+
+// - FunctionSubset -----------------------------------------------------------
+
+/// Microsoft OS 2.0 function subset header
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct FunctionSubsetHeader {
+    pub wLength: u16,         // 8
+    pub wDescriptorType: u16, // 2 = SubsetHeaderFunction
+    pub bFirstInterface: u8,
+    pub bReserved: u8,
+    pub wSubsetLength: u16,
+}
+
+impl AsByteSliceIterator for FunctionSubsetHeader {}
+
+/// Microsoft OS 2.0 function subset
+#[derive(Clone, Copy)]
+pub struct FunctionSubset<'a> {
+    pub head: FunctionSubsetHeader,
+    pub features: &'a [FeatureDescriptor<'a>],
+}
+
+impl<'a> FunctionSubset<'a> {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(first_interface: u8, features: &'a [FeatureDescriptor<'a>]) -> Self {
+        Self {
+            head: FunctionSubsetHeader {
+                wLength: size_of::<FunctionSubsetHeader>() as u16,
+                wDescriptorType: DescriptorType::SubsetHeaderFunction as u16,
+                bFirstInterface: first_interface,
+                bReserved: 0,
+                wSubsetLength: (size_of::<FunctionSubsetHeader>() + features_length(features))
+                    as u16,
+            },
+            features,
+        }
+    }
+
+    #[allow(clippy::iter_without_into_iter)]
+    pub fn iter(&'a self) -> impl Iterator<Item = u8> + 'a {
+        self.head
+            .as_iter()
+            .copied()
+            .chain(self.features.iter().flat_map(FeatureDescriptor::iter))
+    }
+}
+
+// - FeatureDescriptor --------------------------------------------------------
+
+/// Microsoft OS 2.0 feature descriptor
+#[derive(Clone, Copy)]
+pub enum FeatureDescriptor<'a> {
+    CompatibleId(CompatibleIdDescriptor),
+    RegistryProperty(RegistryPropertyDescriptor<'a>),
+}
+
+impl<'a> FeatureDescriptor<'a> {
+    /// Returns the length of the feature descriptor in bytes.
+    #[must_use]
+    pub const fn length(&self) -> usize {
+        match self {
+            FeatureDescriptor::CompatibleId(_) => size_of::<CompatibleIdDescriptor>(),
+            FeatureDescriptor::RegistryProperty(descriptor) => descriptor.length(),
+        }
+    }
+
+    #[allow(clippy::iter_without_into_iter)]
+    pub fn iter(&'a self) -> impl Iterator<Item = u8> + 'a {
+        let (compatible_id, registry_property) = match self {
+            FeatureDescriptor::CompatibleId(descriptor) => (Some(descriptor.as_iter()), None),
+            FeatureDescriptor::RegistryProperty(descriptor) => (None, Some(descriptor.iter())),
+        };
+        compatible_id
+            .into_iter()
+            .flatten()
+            .copied()
+            .chain(registry_property.into_iter().flatten())
+    }
+}
+
+const fn features_length(features: &[FeatureDescriptor]) -> usize {
+    let mut length = 0;
+    let mut index = 0;
+    while index < features.len() {
+        length += features[index].length();
+        index += 1;
+    }
+    length
+}
+
+// - CompatibleIdDescriptor ---------------------------------------------------
+
+/// Microsoft OS 2.0 compatible ID descriptor
+#[derive(AsBytes, FromBytes, FromZeroes, Clone, Copy)]
+#[repr(C, packed)]
+pub struct CompatibleIdDescriptor {
+    pub wLength: u16,         // 20
+    pub wDescriptorType: u16, // 3 = FeatureCompatibleId
+    pub CompatibleID: [u8; 8],
+    pub SubCompatibleID: [u8; 8],
+}
+
+impl AsByteSliceIterator for CompatibleIdDescriptor {}
+
+impl CompatibleIdDescriptor {
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn new(compatible_id: [u8; 8], sub_compatible_id: [u8; 8]) -> Self {
+        Self {
+            wLength: size_of::<Self>() as u16,
+            wDescriptorType: DescriptorType::FeatureCompatibleId as u16,
+            CompatibleID: compatible_id,
+            SubCompatibleID: sub_compatible_id,
+        }
+    }
+}
+
+// - RegistryPropertyDescriptor -----------------------------------------------
+
+/// Microsoft OS 2.0 registry property value
+#[derive(Clone, Copy)]
+pub enum PropertyData<'a> {
+    /// `REG_SZ`
+    String(&'a str),
+    /// `REG_MULTI_SZ`
+    MultiString(&'a [&'a str]),
+    /// `REG_BINARY`
+    Binary(&'a [u8]),
+    /// `REG_DWORD_LITTLE_ENDIAN`
+    Dword(u32),
+}
+
+impl<'a> PropertyData<'a> {
+    #[must_use]
+    pub const fn data_type(&self) -> PropertyDataType {
+        match self {
+            PropertyData::String(_) => PropertyDataType::RegSz,
+            PropertyData::MultiString(_) => PropertyDataType::RegMultiSz,
+            PropertyData::Binary(_) => PropertyDataType::RegBinary,
+            PropertyData::Dword(_) => PropertyDataType::RegDwordLittleEndian,
+        }
+    }
+
+    /// Returns the length of the encoded property value in bytes.
+    #[must_use]
+    pub const fn length(&self) -> usize {
+        match self {
+            PropertyData::String(string) => (utf16_length(string) + 1) * 2,
+            PropertyData::MultiString(strings) => {
+                let mut length = 2; // terminating null
+                let mut index = 0;
+                while index < strings.len() {
+                    length += (utf16_length(strings[index]) + 1) * 2;
+                    index += 1;
+                }
+                length
+            }
+            PropertyData::Binary(bytes) => bytes.len(),
+            PropertyData::Dword(_) => size_of::<u32>(),
+        }
+    }
+
+    #[allow(clippy::iter_without_into_iter)]
+    pub fn iter(&'a self) -> impl Iterator<Item = u8> + 'a {
+        let (strings, multi_strings, binary, dword): (&[&str], &[&str], &[u8], _) = match self {
+            PropertyData::String(string) => (core::slice::from_ref(string), &[], &[], None),
+            PropertyData::MultiString(strings) => (&[], strings, &[], None),
+            PropertyData::Binary(bytes) => (&[], &[], bytes, None),
+            PropertyData::Dword(value) => (&[], &[], &[], Some(value.to_le_bytes())),
+        };
+
+        // A REG_MULTI_SZ value is terminated by an additional null character.
+        let terminator: &[u8] = if multi_strings.is_empty() {
+            &[]
+        } else {
+            &[0, 0]
+        };
+
+        strings
+            .iter()
+            .chain(multi_strings.iter())
+            .flat_map(|string| Utf16ByteIterator::new(string.encode_utf16()).chain([0, 0]))
+            .chain(terminator.iter().copied())
+            .chain(binary.iter().copied())
+            .chain(dword.into_iter().flatten())
+    }
+}
+
+/// Microsoft OS 2.0 registry property descriptor
+#[derive(Clone, Copy)]
+pub struct RegistryPropertyDescriptor<'a> {
+    pub property_name: &'a str,
+    pub property_data: PropertyData<'a>,
+}
+
+impl<'a> RegistryPropertyDescriptor<'a> {
+    #[must_use]
+    pub const fn new(property_name: &'a str, property_data: PropertyData<'a>) -> Self {
+        Self {
+            property_name,
+            property_data,
+        }
+    }
+
+    /// Returns the length of the property name, including null terminator, in bytes.
+    const fn property_name_length(&self) -> usize {
+        (utf16_length(self.property_name) + 1) * 2
+    }
+
+    /// Returns the length of the descriptor in bytes.
+    #[must_use]
+    pub const fn length(&self) -> usize {
+        // wLength + wDescriptorType + wPropertyDataType + wPropertyNameLength + wPropertyDataLength
+        let fields_length = 5 * size_of::<u16>();
+        fields_length + self.property_name_length() + self.property_data.length()
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    #[allow(clippy::iter_without_into_iter)]
+    pub fn iter(&'a self) -> impl Iterator<Item = u8> + 'a {
+        let [l0, l1] = (self.length() as u16).to_le_bytes();
+        let [t0, t1] = (DescriptorType::FeatureRegProperty as u16).to_le_bytes();
+        let [d0, d1] = (self.property_data.data_type() as u16).to_le_bytes();
+        let [n0, n1] = (self.property_name_length() as u16).to_le_bytes();
+        let [p0, p1] = (self.property_data.length() as u16).to_le_bytes();
+
+        [l0, l1, t0, t1, d0, d1, n0, n1]
+            .into_iter()
+            .chain(Utf16ByteIterator::new(self.property_name.encode_utf16()))
+            .chain([0, 0])
+            .chain([p0, p1])
+            .chain(self.property_data.iter())
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::{BinaryObjectStore, DeviceCapability};
+
+    // - fixtures -------------------------------------------------------------
+
+    const VENDOR_CODE: u8 = 0x42;
+    const COMPATIBLE_ID: [u8; 8] = *b"WINUSB\0\0";
+
+    static DESCRIPTOR_SET: DescriptorSet = DescriptorSet::new(
+        WindowsVersion::Windows81,
+        &[
+            FeatureDescriptor::CompatibleId(CompatibleIdDescriptor::new(COMPATIBLE_ID, [0; 8])),
+            FeatureDescriptor::RegistryProperty(RegistryPropertyDescriptor::new(
+                "DeviceInterfaceGUIDs",
+                PropertyData::MultiString(&["{88BAE032-5A81-49f0-BC3D-A4FF138216D6}"]),
+            )),
+        ],
+        &[],
+    );
+
+    static COMPOSITE_DESCRIPTOR_SET: DescriptorSet = DescriptorSet::new(
+        WindowsVersion::Windows81,
+        &[],
+        &[ConfigurationSubset::new(
+            0,
+            &[],
+            &[
+                FunctionSubset::new(
+                    0,
+                    &[FeatureDescriptor::CompatibleId(
+                        CompatibleIdDescriptor::new(COMPATIBLE_ID, [0; 8]),
+                    )],
+                ),
+                FunctionSubset::new(
+                    2,
+                    &[FeatureDescriptor::RegistryProperty(
+                        RegistryPropertyDescriptor::new("Label", PropertyData::Dword(0x1234)),
+                    )],
+                ),
+            ],
+        )],
+    );
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_descriptor_set_length() {
+        let bytes: std::vec::Vec<u8> = DESCRIPTOR_SET.iter().collect();
+
+        // header + compatible id + registry property
+        let registry_property_length = 10 + (20 + 1) * 2 + (38 + 1 + 1) * 2;
+        assert_eq!(bytes.len(), 10 + 20 + registry_property_length);
+        assert_eq!(bytes.len(), usize::from(DESCRIPTOR_SET.head.wTotalLength));
+        assert_eq!(&bytes[0..4], &[0x0a, 0x00, 0x00, 0x00]);
+        assert_eq!(&bytes[4..8], &0x0603_0000_u32.to_le_bytes());
+        assert_eq!(&bytes[14..20], b"WINUSB");
+    }
+
+    #[test]
+    fn test_registry_property() {
+        let descriptor = RegistryPropertyDescriptor::new("ab", PropertyData::String("c"));
+        let bytes: std::vec::Vec<u8> = descriptor.iter().collect();
+
+        assert_eq!(
+            bytes,
+            [
+                0x14, 0x00, // wLength = 20
+                0x04, 0x00, // wDescriptorType = FeatureRegProperty
+                0x01, 0x00, // wPropertyDataType = REG_SZ
+                0x06, 0x00, // wPropertyNameLength = 6
+                b'a', 0x00, b'b', 0x00, 0x00, 0x00, // PropertyName
+                0x04, 0x00, // wPropertyDataLength = 4
+                b'c', 0x00, 0x00, 0x00, // PropertyData
+            ]
+        );
+        assert_eq!(bytes.len(), descriptor.length());
+    }
+
+    #[test]
+    fn test_composite_descriptor_set() {
+        let bytes: std::vec::Vec<u8> = COMPOSITE_DESCRIPTOR_SET.iter().collect();
+
+        let function0_length = 8 + 20;
+        let function1_length = 8 + 10 + (5 + 1) * 2 + 4;
+        let configuration_length = 8 + function0_length + function1_length;
+
+        assert_eq!(bytes.len(), 10 + configuration_length);
+        assert_eq!(
+            bytes.len(),
+            usize::from(COMPOSITE_DESCRIPTOR_SET.head.wTotalLength)
+        );
+        let configuration = COMPOSITE_DESCRIPTOR_SET.configurations[0].head;
+        assert_eq!(
+            usize::from(configuration.wTotalLength),
+            configuration_length
+        );
+        // configuration subset header follows the descriptor set header
+        assert_eq!(
+            &bytes[10..18],
+            &[0x08, 0x00, 0x01, 0x00, 0x00, 0x00, 0x46, 0x00]
+        );
+        // first function subset header follows the configuration subset header
+        assert_eq!(
+            &bytes[18..26],
+            &[0x08, 0x00, 0x02, 0x00, 0x00, 0x00, 0x1c, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_platform_capability_descriptor() {
+        let descriptors = Descriptors::new(VENDOR_CODE, DESCRIPTOR_SET);
+        let capabilities = [DeviceCapability::Platform(
+            descriptors.platform_descriptor(),
+        )];
+        let bos = BinaryObjectStore::new(&capabilities);
+        let bytes: std::vec::Vec<u8> = bos.iter().copied().collect();
+
+        assert_eq!(bytes.len(), 33);
+        assert_eq!(&bytes[0..5], &[0x05, 0x0f, 0x21, 0x00, 0x01]);
+        assert_eq!(&bytes[5..9], &[0x1c, 0x10, 0x05, 0x00]);
+        assert_eq!(&bytes[9..25], &PLATFORM_CAPABILITY_UUID);
+        assert_eq!(&bytes[25..29], &WindowsVersion::Windows81.to_le_bytes());
+        assert_eq!(
+            &bytes[29..31],
+            &{ DESCRIPTOR_SET.head.wTotalLength }.to_le_bytes()
+        );
+        assert_eq!(&bytes[31..33], &[VENDOR_CODE, 0x00]);
+    }
+}
@@ -0,0 +1,381 @@
+//! Zero-copy USB descriptor parser
+//!
+//! Turns raw descriptor bytes, such as a configuration descriptor
+//! captured from a device, into an iterator of typed descriptors.
+//!
+//! For example:
+//!
+//!     use smolusb::descriptor::parser::{self, Descriptor};
+//!
+//!     let bytes = [0x09, 0x04, 0x00, 0x00, 0x02, 0xff, 0x00, 0x00, 0x00];
+//!     for descriptor in parser::parse(&bytes) {
+//!         match descriptor {
+//!             Ok(Descriptor::Interface(interface)) => {
+//!                 assert_eq!(interface.bNumEndpoints, 2);
+//!             }
+//!             Ok(_) => (),
+//!             Err(e) => panic!("malformed descriptor: {:?}", e),
+//!         }
+//!     }
+
+use zerocopy::FromBytes;
+
+use crate::descriptor::{
+    BinaryObjectStoreHeader, ConfigurationDescriptorHeader, DescriptorType, DeviceDescriptor,
+    DeviceQualifierDescriptor, EndpointDescriptor, InterfaceAssociationDescriptor,
+    InterfaceDescriptorHeader,
+};
+
+// - ParseError ---------------------------------------------------------------
+
+/// Errors encountered while parsing descriptor bytes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ParseError {
+    /// `bLength` is smaller than the two byte descriptor header.
+    ///
+    /// Contents is (`offset`, `bLength`)
+    InvalidLength(usize, u8),
+    /// `bLength` extends past the end of the given bytes.
+    ///
+    /// Contents is (`offset`, `bLength`)
+    UnexpectedEnd(usize, u8),
+    /// `bLength` is too short for the given descriptor type.
+    ///
+    /// Contents is (`offset`, `bDescriptorType`, `bLength`)
+    Truncated(usize, u8, u8),
+}
+
+impl core::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl core::error::Error for ParseError {
+    #[allow(deprecated)]
+    fn description(&self) -> &str {
+        use ParseError::*;
+        match self {
+            InvalidLength(_, _) => "Descriptor length is smaller than the descriptor header",
+            UnexpectedEnd(_, _) => "Descriptor length extends past the end of the data",
+            Truncated(_, _, _) => "Descriptor length is too short for the descriptor type",
+        }
+    }
+}
+
+// - Descriptor ---------------------------------------------------------------
+
+/// A typed reference to a descriptor in a byte slice.
+#[derive(Clone, Copy)]
+pub enum Descriptor<'a> {
+    Device(&'a DeviceDescriptor),
+    DeviceQualifier(&'a DeviceQualifierDescriptor),
+    Configuration(&'a ConfigurationDescriptorHeader),
+    OtherSpeedConfiguration(&'a ConfigurationDescriptorHeader),
+    InterfaceAssociation(&'a InterfaceAssociationDescriptor),
+    Interface(&'a InterfaceDescriptorHeader),
+    /// Endpoint descriptor
+    ///
+    /// Any additional class-specific fields (e.g. `bRefresh` and
+    /// `bSynchAddress` for audio endpoints) are ignored.
+    Endpoint(&'a EndpointDescriptor),
+    String(StringData<'a>),
+    BinaryObjectStore(&'a BinaryObjectStoreHeader),
+    /// Device capability descriptor
+    ///
+    /// Contents is the descriptor bytes following `bDescriptorType`
+    DeviceCapability(&'a [u8]),
+    /// Class-specific descriptor
+    ///
+    /// Contents is (`bDescriptorType`, the descriptor bytes following `bDescriptorType`)
+    ClassSpecific(u8, &'a [u8]),
+    /// Unknown descriptor
+    ///
+    /// Contents is (`bDescriptorType`, the descriptor bytes following `bDescriptorType`)
+    Unknown(u8, &'a [u8]),
+}
+
+impl<'a> Descriptor<'a> {
+    /// Parses the descriptor at the start of the given bytes.
+    ///
+    /// Returns the descriptor and its length in bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ParseError`] with an offset of `0` if the
+    /// descriptor is malformed.
+    pub fn parse(bytes: &'a [u8]) -> Result<(Self, usize), ParseError> {
+        let (length, descriptor_type) = match bytes {
+            [] | [_] => {
+                return Err(ParseError::UnexpectedEnd(
+                    0,
+                    bytes.first().copied().unwrap_or(0),
+                ))
+            }
+            [length, descriptor_type, ..] => (*length, *descriptor_type),
+        };
+        if length < 2 {
+            return Err(ParseError::InvalidLength(0, length));
+        }
+        let Some(bytes) = bytes.get(..usize::from(length)) else {
+            return Err(ParseError::UnexpectedEnd(0, length));
+        };
+        let data = &bytes[2..];
+        let truncated = ParseError::Truncated(0, descriptor_type, length);
+
+        let descriptor = match DescriptorType::from(descriptor_type) {
+            DescriptorType::Device => Descriptor::Device(prefix(bytes).ok_or(truncated)?),
+            DescriptorType::DeviceQualifier => {
+                Descriptor::DeviceQualifier(prefix(bytes).ok_or(truncated)?)
+            }
+            DescriptorType::Configuration => {
+                Descriptor::Configuration(prefix(bytes).ok_or(truncated)?)
+            }
+            DescriptorType::OtherSpeedConfiguration => {
+                Descriptor::OtherSpeedConfiguration(prefix(bytes).ok_or(truncated)?)
+            }
+            DescriptorType::InterfaceAssociation => {
+                Descriptor::InterfaceAssociation(prefix(bytes).ok_or(truncated)?)
+            }
+            DescriptorType::Interface => Descriptor::Interface(prefix(bytes).ok_or(truncated)?),
+            DescriptorType::Endpoint => Descriptor::Endpoint(prefix(bytes).ok_or(truncated)?),
+            DescriptorType::String => Descriptor::String(StringData(data)),
+            DescriptorType::BinaryDeviceObjectStore => {
+                Descriptor::BinaryObjectStore(prefix(bytes).ok_or(truncated)?)
+            }
+            DescriptorType::DeviceCapability => Descriptor::DeviceCapability(data),
+            // class-specific descriptor types are 0x20..=0x3f
+            _ if descriptor_type & 0xe0 == 0x20 => Descriptor::ClassSpecific(descriptor_type, data),
+            _ => Descriptor::Unknown(descriptor_type, data),
+        };
+
+        Ok((descriptor, bytes.len()))
+    }
+}
+
+/// Returns a reference to the descriptor struct at the start of the given bytes.
+fn prefix<T: FromBytes>(bytes: &[u8]) -> Option<&T> {
+    T::ref_from_prefix(bytes)
+}
+
+// - StringData ---------------------------------------------------------------
+
+/// The UTF-16LE encoded contents of a string descriptor.
+#[derive(Clone, Copy)]
+pub struct StringData<'a>(pub &'a [u8]);
+
+impl<'a> StringData<'a> {
+    /// Returns an iterator over the UTF-16 code units of the string.
+    ///
+    /// For string descriptor zero these are the supported language ids.
+    pub fn code_units(&self) -> impl Iterator<Item = u16> + 'a {
+        self.0
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+    }
+
+    /// Returns an iterator over the characters of the string.
+    ///
+    /// Invalid UTF-16 sequences are replaced with `U+FFFD`.
+    pub fn chars(&self) -> impl Iterator<Item = char> + 'a {
+        char::decode_utf16(self.code_units()).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+    }
+}
+
+// - DescriptorIterator -------------------------------------------------------
+
+/// Returns an iterator over the descriptors in the given bytes.
+///
+/// The iterator stops after the first [`ParseError`].
+#[must_use]
+pub fn parse(bytes: &[u8]) -> DescriptorIterator {
+    DescriptorIterator::new(bytes)
+}
+
+/// Iterator over the descriptors in a byte slice.
+pub struct DescriptorIterator<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> DescriptorIterator<'a> {
+    #[must_use]
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            offset: 0,
+            failed: false,
+        }
+    }
+
+    /// Returns the offset of the next descriptor.
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl<'a> Iterator for DescriptorIterator<'a> {
+    type Item = Result<Descriptor<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset >= self.bytes.len() {
+            return None;
+        }
+
+        match Descriptor::parse(&self.bytes[self.offset..]) {
+            Ok((descriptor, length)) => {
+                self.offset += length;
+                Some(Ok(descriptor))
+            }
+            Err(e) => {
+                self.failed = true;
+                // report errors relative to the start of the bytes
+                let offset = self.offset;
+                Some(Err(match e {
+                    ParseError::InvalidLength(_, length) => {
+                        ParseError::InvalidLength(offset, length)
+                    }
+                    ParseError::UnexpectedEnd(_, length) => {
+                        ParseError::UnexpectedEnd(offset, length)
+                    }
+                    ParseError::Truncated(_, descriptor_type, length) => {
+                        ParseError::Truncated(offset, descriptor_type, length)
+                    }
+                }))
+            }
+        }
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::descriptor::{ConfigurationDescriptor, InterfaceDescriptor, StringDescriptor};
+
+    // - fixtures -------------------------------------------------------------
+
+    static CONFIGURATION_DESCRIPTOR: ConfigurationDescriptor = ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[InterfaceDescriptor::new(
+            InterfaceDescriptorHeader {
+                iInterfaceNumber: 0,
+                bInterfaceClass: 0xff,
+                ..InterfaceDescriptorHeader::new()
+            },
+            &[
+                EndpointDescriptor {
+                    bEndpointAddress: 0x81,
+                    bmAttributes: 0x02,
+                    wMaxPacketSize: 512,
+                    ..EndpointDescriptor::new()
+                },
+                EndpointDescriptor {
+                    bEndpointAddress: 0x02,
+                    bmAttributes: 0x02,
+                    wMaxPacketSize: 512,
+                    ..EndpointDescriptor::new()
+                },
+            ],
+        )
+        .with_association(InterfaceAssociationDescriptor {
+            bInterfaceCount: 1,
+            ..InterfaceAssociationDescriptor::new()
+        })],
+    );
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_parse_configuration_descriptor() {
+        let bytes: std::vec::Vec<u8> = CONFIGURATION_DESCRIPTOR.iter().copied().collect();
+        let descriptors: std::vec::Vec<Descriptor> =
+            parse(&bytes).collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(descriptors.len(), 5);
+        assert!(matches!(
+            descriptors[0],
+            Descriptor::Configuration(head) if head.bConfigurationValue == 1 && head.bNumInterfaces == 1
+        ));
+        assert!(matches!(
+            descriptors[1],
+            Descriptor::InterfaceAssociation(iad) if iad.bInterfaceCount == 1
+        ));
+        assert!(matches!(
+            descriptors[2],
+            Descriptor::Interface(head) if head.bInterfaceClass == 0xff && head.bNumEndpoints == 2
+        ));
+        assert!(matches!(
+            descriptors[3],
+            Descriptor::Endpoint(endpoint) if endpoint.bEndpointAddress == 0x81
+        ));
+        assert!(matches!(
+            descriptors[4],
+            Descriptor::Endpoint(endpoint) if { endpoint.wMaxPacketSize } == 512
+        ));
+    }
+
+    #[test]
+    fn test_parse_string_descriptor() {
+        let descriptor = StringDescriptor::new("Cynthion");
+        let bytes: std::vec::Vec<u8> = descriptor.iter().collect();
+
+        let Some(Ok(Descriptor::String(string))) = parse(&bytes).next() else {
+            panic!("expected a string descriptor");
+        };
+        assert!(string.chars().eq("Cynthion".chars()));
+    }
+
+    #[test]
+    fn test_parse_class_specific_and_unknown() {
+        let bytes = [
+            0x05, 0x24, 0x00, 0x10, 0x01, // CS_INTERFACE header
+            0x03, 0x42, 0xaa, // unknown
+        ];
+        let descriptors: std::vec::Vec<Descriptor> =
+            parse(&bytes).collect::<Result<_, _>>().unwrap();
+
+        assert!(matches!(
+            descriptors[0],
+            Descriptor::ClassSpecific(0x24, &[0x00, 0x10, 0x01])
+        ));
+        assert!(matches!(descriptors[1], Descriptor::Unknown(0x42, &[0xaa])));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        // bLength smaller than the descriptor header
+        let bytes = [
+            0x09, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x05,
+        ];
+        let results: std::vec::Vec<_> = parse(&bytes).collect();
+        assert_eq!(results.len(), 2);
+        assert!(matches!(results[1], Err(ParseError::InvalidLength(9, 1))));
+
+        // bLength extends past the end of the bytes
+        let bytes = [0x09, 0x02, 0x09, 0x00];
+        assert!(matches!(
+            parse(&bytes).next(),
+            Some(Err(ParseError::UnexpectedEnd(0, 9)))
+        ));
+
+        // bLength too short for an endpoint descriptor
+        let bytes = [0x04, 0x05, 0x81, 0x02];
+        assert!(matches!(
+            parse(&bytes).next(),
+            Some(Err(ParseError::Truncated(0, 0x05, 4)))
+        ));
+
+        // the iterator stops after an error
+        let bytes = [0x00, 0x00, 0x02, 0x42];
+        assert_eq!(parse(&bytes).count(), 1);
+    }
+}
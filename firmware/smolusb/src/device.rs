@@ -1,12 +1,17 @@
 //! `smolusb` device types
 //!
 
-use crate::descriptor::microsoft10;
+use crate::class::{ClassEvent, ControlResult, UsbClass, UsbClasses};
+use crate::control::{Control, ControlEvent};
+use crate::descriptor::{microsoft10, microsoft20};
 use crate::descriptor::{
-    ConfigurationDescriptor, DescriptorType, DeviceDescriptor, DeviceQualifierDescriptor,
-    StringDescriptor, StringDescriptorNumber, StringDescriptorZero,
+    BinaryObjectStore, ConfigurationDescriptor, DescriptorType, DeviceCapability, DeviceDescriptor,
+    DeviceQualifierDescriptor, EndpointDescriptor, InterfaceDescriptor, StringDescriptor,
+    StringDescriptorNumber, StringDescriptorZero,
 };
-use crate::setup::SetupPacket;
+use crate::error::ErrorKind;
+use crate::event::UsbEvent;
+use crate::setup::{Recipient, SetupPacket};
 use crate::traits::{AsByteSliceIterator, UsbDriver};
 use log::{debug, trace, warn};
 
@@ -15,40 +20,91 @@ pub struct Descriptors<'a> {
     // required
     pub device_speed: Speed,
     pub device_descriptor: DeviceDescriptor,
-    pub configuration_descriptor: ConfigurationDescriptor<'a>,
+    pub configuration_descriptors: &'a [ConfigurationDescriptor<'a>],
     pub string_descriptor_zero: StringDescriptorZero<'a>,
     pub string_descriptors: &'a [&'a StringDescriptor<'a>],
     // optional
     pub device_qualifier_descriptor: Option<DeviceQualifierDescriptor>,
-    pub other_speed_configuration_descriptor: Option<ConfigurationDescriptor<'a>>,
+    pub other_speed_configuration_descriptors: Option<&'a [ConfigurationDescriptor<'a>]>,
+    pub binary_object_store: Option<BinaryObjectStore<'a>>,
     pub microsoft10: Option<microsoft10::Descriptors<'a>>,
+    pub microsoft20: Option<microsoft20::Descriptors<'a>>,
 }
 
-impl Descriptors<'_> {
+/// The result of writing a descriptor with [`Descriptors::write()`].
+#[derive(Clone, Copy, Debug)]
+pub enum DescriptorResult {
+    /// The descriptor was written.
+    Written,
+    /// The descriptor does not exist and the request was stalled.
+    Stalled,
+    /// The descriptor request could not be handled.
+    Unhandled(SetupPacket),
+}
+
+impl<'a> Descriptors<'a> {
     /// Calculates the total length of the descriptor and returns an updated instance.
-    ///
-    /// TODO ugly hack because I haven't figured out how to do this at compile time yet
+    #[deprecated(note = "wTotalLength is now calculated by ConfigurationDescriptor::new()")]
     #[must_use]
-    pub fn set_total_lengths(mut self) -> Self {
-        self.configuration_descriptor.set_total_length();
-        if let Some(other_speed_configuration_descriptor) =
-            self.other_speed_configuration_descriptor.as_mut()
-        {
-            other_speed_configuration_descriptor.set_total_length();
-        }
+    pub fn set_total_lengths(self) -> Self {
         self
     }
 
+    /// Returns the configuration descriptor with the given `bConfigurationValue`.
+    #[must_use]
+    pub fn configuration_descriptor(
+        &self,
+        configuration_value: u8,
+    ) -> Option<&'a ConfigurationDescriptor<'a>> {
+        self.configuration_descriptors
+            .iter()
+            .find(|descriptor| descriptor.head.bConfigurationValue == configuration_value)
+    }
+
+    /// Returns the interface descriptor for the given interface
+    /// number and alternate setting in the configuration with the
+    /// given `bConfigurationValue`.
+    #[must_use]
+    pub fn interface_descriptor(
+        &self,
+        configuration_value: u8,
+        interface_number: u8,
+        alternate_setting: u8,
+    ) -> Option<&'a InterfaceDescriptor<'a>> {
+        self.configuration_descriptor(configuration_value)?
+            .tail
+            .iter()
+            .find(|descriptor| {
+                descriptor.head.iInterfaceNumber == interface_number
+                    && descriptor.head.bAlternateSetting == alternate_setting
+            })
+    }
+
+    /// Returns the endpoint descriptor for the given endpoint address
+    /// in the configuration with the given `bConfigurationValue`.
+    #[must_use]
+    pub fn endpoint_descriptor(
+        &self,
+        configuration_value: u8,
+        endpoint_address: u8,
+    ) -> Option<&'a EndpointDescriptor> {
+        self.configuration_descriptor(configuration_value)?
+            .tail
+            .iter()
+            .flat_map(|interface| interface.tail2.iter())
+            .find(|descriptor| descriptor.bEndpointAddress == endpoint_address)
+    }
+
     /// Writes the descriptor corresponding to the request.
     ///
-    /// Returns the given [`SetupPacket`] if the descriptor request could not be handled.
+    /// Requests for descriptors that do not exist are stalled.
     #[allow(clippy::too_many_lines)] // ...and sometimes clippy has opinions it should keep to itself!
     pub fn write<D>(
         &self,
         usb: &D,
         endpoint_number: u8,
         setup_packet: SetupPacket,
-    ) -> Option<SetupPacket>
+    ) -> DescriptorResult
     where
         D: UsbDriver,
     {
@@ -69,14 +125,22 @@ impl Descriptors<'_> {
                     .copied()
                     .take(requested_length),
             ),
-            (DescriptorType::Configuration, _) => usb.write_requested(
-                endpoint_number,
-                requested_length,
-                self.configuration_descriptor
-                    .iter()
-                    .copied()
-                    .take(requested_length),
-            ),
+            (DescriptorType::Configuration, index) => {
+                if let Some(descriptor) = self.configuration_descriptors.get(usize::from(index)) {
+                    usb.write_requested(
+                        endpoint_number,
+                        requested_length,
+                        descriptor.iter().copied().take(requested_length),
+                    )
+                } else {
+                    warn!(
+                        "Descriptors::write_descriptor() - unknown configuration descriptor {}",
+                        index
+                    );
+                    usb.stall_endpoint_in(endpoint_number);
+                    return DescriptorResult::Stalled;
+                }
+            }
             (DescriptorType::DeviceQualifier, _) => {
                 if self.device_speed == Speed::High {
                     if let Some(descriptor) = &self.device_qualifier_descriptor {
@@ -86,31 +150,82 @@ impl Descriptors<'_> {
                             descriptor.as_iter().copied().take(requested_length),
                         )
                     } else {
-                        // no device qualifier configured, ack HostToDevice instead - TODO check check on mac/windows
-                        debug!("  No device qualifier configured for high-speed device");
-                        usb.write(endpoint_number, [].into_iter())
+                        // no device qualifier configured, derive it from the device descriptor
+                        debug!("  No device qualifier configured, deriving from device descriptor");
+                        usb.write_requested(
+                            endpoint_number,
+                            requested_length,
+                            DeviceQualifierDescriptor::from_device_descriptor(
+                                &self.device_descriptor,
+                            )
+                            .as_iter()
+                            .copied()
+                            .take(requested_length),
+                        )
                     }
                 } else {
-                    // for full/low speed devices, ack HostToDevice instead - TODO check on mac/windows
+                    // full/low speed only devices must respond with a request error
                     trace!(
                         "  Device qualifier request is not supported for full/low-speed devices"
                     );
-                    // FIXME we should stall instead
-                    usb.write(endpoint_number, [].into_iter())
+                    usb.stall_endpoint_in(endpoint_number);
+                    return DescriptorResult::Stalled;
                 }
             }
-            (DescriptorType::OtherSpeedConfiguration, _) => {
-                if let Some(descriptor) = self.other_speed_configuration_descriptor {
+            (DescriptorType::OtherSpeedConfiguration, index) => {
+                if let Some(descriptor) = self
+                    .other_speed_configuration_descriptors
+                    .and_then(|descriptors| descriptors.get(usize::from(index)))
+                {
                     usb.write_requested(
                         endpoint_number,
                         requested_length,
                         descriptor.iter().copied().take(requested_length),
                     )
+                } else if let (Speed::High, None, Some(descriptor)) = (
+                    self.device_speed,
+                    self.other_speed_configuration_descriptors,
+                    self.configuration_descriptors.get(usize::from(index)),
+                ) {
+                    // no other speed configurations, derive it from the high-speed configuration
+                    usb.write_requested(
+                        endpoint_number,
+                        requested_length,
+                        descriptor.iter_other_speed().take(requested_length),
+                    )
                 } else {
-                    // no other speed configuration, ack HostToDevice instead - TODO check check on mac/windows
                     debug!("  Descriptors::write_descriptor() - no other speed configuration descriptor configured");
-                    // FIXME we should stall instead
-                    usb.write(endpoint_number, [].into_iter())
+                    usb.stall_endpoint_in(endpoint_number);
+                    return DescriptorResult::Stalled;
+                }
+            }
+            (DescriptorType::BinaryDeviceObjectStore, 0) => {
+                match (&self.binary_object_store, &self.microsoft20) {
+                    (Some(descriptor), _) => usb.write_requested(
+                        endpoint_number,
+                        requested_length,
+                        descriptor.iter().copied().take(requested_length),
+                    ),
+                    // fall back to a binary object store containing only the
+                    // microsoft os 2.0 platform capability
+                    (None, Some(descriptors)) => {
+                        let capabilities = [DeviceCapability::Platform(
+                            descriptors.platform_descriptor(),
+                        )];
+                        usb.write_requested(
+                            endpoint_number,
+                            requested_length,
+                            BinaryObjectStore::new(&capabilities)
+                                .iter()
+                                .copied()
+                                .take(requested_length),
+                        )
+                    }
+                    _ => {
+                        warn!("Descriptors::write_descriptor() - no binary object store defined");
+                        usb.stall_endpoint_in(endpoint_number);
+                        return DescriptorResult::Stalled;
+                    }
                 }
             }
             (DescriptorType::String, StringDescriptorNumber::Zero) => usb.write_requested(
@@ -133,18 +248,19 @@ impl Descriptors<'_> {
                             "Descriptors::write_descriptor() - no ms os 1.0 string descriptor defined",
                         );
                         usb.stall_endpoint_in(endpoint_number);
-                        return Some(setup_packet);
+                        return DescriptorResult::Stalled;
                     }
                 }
             }
             (DescriptorType::String, number) => {
                 let offset_index: usize = (number - 1).into();
-                if offset_index > self.string_descriptors.len() {
+                if offset_index >= self.string_descriptors.len() {
                     warn!(
                         "Descriptors::write_descriptor() - unknown string descriptor {}",
                         number
                     );
-                    return Some(setup_packet);
+                    usb.stall_endpoint_in(endpoint_number);
+                    return DescriptorResult::Stalled;
                 }
                 usb.write_requested(
                     endpoint_number,
@@ -159,14 +275,13 @@ impl Descriptors<'_> {
                     "  Descriptors::write_descriptor() - unhandled descriptor request {:?}, {}",
                     descriptor_type, descriptor_number
                 );
-                return Some(setup_packet);
+                return DescriptorResult::Unhandled(setup_packet);
             }
         };
 
         trace!("  wrote {} byte descriptor", bytes_written);
 
-        // consumed
-        None
+        DescriptorResult::Written
     }
 }
 
@@ -229,3 +344,610 @@ impl Speed {
         }
     }
 }
+
+// - DeviceState --------------------------------------------------------------
+
+/// The visible states of a USB device as described in the USB 2.0
+/// specification, section 9.1.1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceState {
+    /// The device is powered but has not been reset by the host.
+    Powered,
+    /// The device has been reset and responds at the default address.
+    Default,
+    /// The host has assigned the device an address.
+    Addressed,
+    /// The host has selected a configuration.
+    Configured,
+    /// The bus has been idle and the device is suspended.
+    Suspended,
+}
+
+// - UsbDevice ----------------------------------------------------------------
+
+/// Events generated by a [`UsbDevice`] that need to be handled by the
+/// application.
+#[derive(Clone, Copy, Debug)]
+pub enum DeviceEvent<'d> {
+    /// The bus has been reset and all classes have been returned to
+    /// their initial state.
+    BusReset,
+    /// The bus has been suspended.
+    Suspend,
+    /// The bus has resumed from a suspend.
+    Resume,
+    /// A [`ControlEvent`] that has been passed on to the classes.
+    ///
+    /// [`ControlEvent::SetupPacket`] is only reported for requests that
+    /// were not handled by any class and which the application must
+    /// either handle or stall.
+    Control(ControlEvent),
+    /// A [`ClassEvent`] generated by a class while handling a control
+    /// request.
+    ///
+    /// Some events, such as [`HidEvent::GetReport`](crate::class::hid::HidEvent::GetReport),
+    /// require the application to respond on the control endpoint.
+    Class(ClassEvent<'d>),
+    /// An event for an endpoint that is not owned by any class.
+    Endpoint(UsbEvent),
+}
+
+/// A USB device composed of a [`Control`] endpoint and a set of
+/// [`UsbClass`] implementations.
+///
+/// Control requests are routed by recipient to the class owning the
+/// addressed interface or endpoint and endpoint events to the class
+/// owning the endpoint. The classes are given as a tuple, for example
+/// `(serial, keyboard)`, and can be accessed with
+/// [`UsbDevice::classes_mut()`].
+pub struct UsbDevice<'a, D, C, const RX_BUFFER_SIZE: usize> {
+    control: Control<'a, D, RX_BUFFER_SIZE>,
+    classes: C,
+}
+
+impl<'a, D, C, const RX_BUFFER_SIZE: usize> UsbDevice<'a, D, C, RX_BUFFER_SIZE>
+where
+    D: UsbDriver,
+    C: UsbClasses<D>,
+{
+    #[must_use]
+    pub fn new(control: Control<'a, D, RX_BUFFER_SIZE>, classes: C) -> Self {
+        Self { control, classes }
+    }
+
+    #[must_use]
+    pub fn control(&self) -> &Control<'a, D, RX_BUFFER_SIZE> {
+        &self.control
+    }
+
+    /// Returns the current state of the device.
+    #[must_use]
+    pub fn state(&self) -> DeviceState {
+        self.control.state()
+    }
+
+    /// Signals the host to resume from suspend.
+    ///
+    /// See [`Control::remote_wakeup()`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the host has not enabled remote wakeup, the
+    /// bus is not suspended or the driver does not support remote wakeup.
+    pub fn remote_wakeup(&self, usb: &D) -> Result<(), ErrorKind> {
+        self.control.remote_wakeup(usb)
+    }
+
+    #[must_use]
+    pub fn classes(&self) -> &C {
+        &self.classes
+    }
+
+    pub fn classes_mut(&mut self) -> &mut C {
+        &mut self.classes
+    }
+
+    /// Dispatches an interrupt event generated by the USB peripheral to
+    /// the [`Control`] endpoint or the class owning the endpoint.
+    ///
+    /// Returns a [`DeviceEvent`] if the event requires further handling
+    /// by the application.
+    pub fn dispatch_event(&mut self, usb: &D, event: UsbEvent) -> Option<DeviceEvent<'_>> {
+        let control_endpoint = self.control.endpoint_number();
+
+        match event {
+            UsbEvent::BusReset => {
+                self.control.dispatch_event(usb, event);
+                self.classes.for_each(&mut |class| class.reset());
+                Some(DeviceEvent::BusReset)
+            }
+            UsbEvent::Suspend => {
+                self.control.dispatch_event(usb, event);
+                self.classes.for_each(&mut |class| class.suspend(usb));
+                Some(DeviceEvent::Suspend)
+            }
+            UsbEvent::Resume => {
+                self.control.dispatch_event(usb, event);
+                self.classes.for_each(&mut |class| class.resume(usb));
+                Some(DeviceEvent::Resume)
+            }
+            UsbEvent::StartOfFrame(frame_number) => {
+                // a start-of-frame also ends a suspend
+                let resumed = self.control.state() == DeviceState::Suspended;
+                self.control.dispatch_event(usb, event);
+                if resumed {
+                    self.classes.for_each(&mut |class| class.resume(usb));
+                }
+                self.classes
+                    .for_each(&mut |class| class.start_of_frame(usb, frame_number));
+                resumed.then_some(DeviceEvent::Resume)
+            }
+            UsbEvent::ReceiveControl(endpoint_number)
+            | UsbEvent::ReceiveSetupPacket(endpoint_number, _)
+            | UsbEvent::ReceivePacket(endpoint_number)
+            | UsbEvent::SendComplete(endpoint_number)
+                if endpoint_number == control_endpoint =>
+            {
+                let control_event = self.control.dispatch_event(usb, event)?;
+                self.handle_control_event(usb, control_event)
+            }
+            UsbEvent::ReceivePacket(endpoint_number) => {
+                self.handle_endpoint_event(usb, endpoint_number, event)
+            }
+            UsbEvent::SendComplete(endpoint_number) => {
+                self.handle_endpoint_event(usb, endpoint_number | 0x80, event)
+            }
+            _ => Some(DeviceEvent::Endpoint(event)),
+        }
+    }
+
+    fn handle_control_event(
+        &mut self,
+        usb: &D,
+        control_event: ControlEvent,
+    ) -> Option<DeviceEvent<'_>> {
+        let control_endpoint = self.control.endpoint_number();
+
+        match control_event {
+            ControlEvent::SetupPacket(setup_packet) => {
+                let data = self.control.data();
+                let index = (setup_packet.index & 0xff) as u8;
+                let mut class_event = None;
+                let mut handle = |class: &mut dyn UsbClass<D>| match class.handle_control_request(
+                    usb,
+                    control_endpoint,
+                    setup_packet,
+                    data,
+                ) {
+                    ControlResult::Unhandled => false,
+                    ControlResult::Handled => true,
+                    ControlResult::Event(event) => {
+                        class_event = Some(event);
+                        true
+                    }
+                };
+                let handled = match setup_packet.recipient() {
+                    Recipient::Interface => self
+                        .classes
+                        .any(&mut |class| class.has_interface(index) && handle(class)),
+                    Recipient::Endpoint => self
+                        .classes
+                        .any(&mut |class| class.has_endpoint(index) && handle(class)),
+                    _ => self.classes.any(&mut handle),
+                };
+                if handled {
+                    return class_event.map(DeviceEvent::Class);
+                }
+                trace!("UsbDevice unhandled request {:?}", setup_packet);
+            }
+            ControlEvent::ConfigurationChanged(configuration) => {
+                self.classes
+                    .for_each(&mut |class| class.configuration_changed(usb, configuration));
+            }
+            ControlEvent::AlternateSettingChanged(interface_number, alternate_setting) => {
+                self.classes.for_each(&mut |class| {
+                    if class.has_interface(interface_number) {
+                        class.alternate_setting_changed(usb, interface_number, alternate_setting);
+                    }
+                });
+            }
+        }
+
+        Some(DeviceEvent::Control(control_event))
+    }
+
+    fn handle_endpoint_event(
+        &mut self,
+        usb: &D,
+        endpoint_address: u8,
+        event: UsbEvent,
+    ) -> Option<DeviceEvent<'_>> {
+        let handled = self.classes.any(&mut |class| {
+            if class.has_endpoint(endpoint_address) {
+                class.handle_endpoint_event(usb, event);
+                true
+            } else {
+                false
+            }
+        });
+        if handled {
+            None
+        } else {
+            Some(DeviceEvent::Endpoint(event))
+        }
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    use crate::class::acm::{self, AcmEvent, CdcAcm};
+    use crate::class::dfu::{self, DfuEvent, DfuRuntime};
+    use crate::class::hid::{self, Hid, HidEvent, ReportType};
+    use crate::setup::Direction;
+    use crate::testing::{class_request, MockUsbDriver};
+    use crate::traits::WriteEndpoint;
+
+    // - fixtures -------------------------------------------------------------
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] =
+        &[acm::CONFIGURATION_DESCRIPTOR_0];
+
+    /// A class which records the calls made to it.
+    #[derive(Default)]
+    struct Recorder {
+        calls: Vec<&'static str>,
+    }
+
+    impl UsbClass<MockUsbDriver> for Recorder {
+        fn has_interface(&self, interface_number: u8) -> bool {
+            interface_number == 1
+        }
+
+        fn has_endpoint(&self, endpoint_address: u8) -> bool {
+            endpoint_address == 0x85
+        }
+
+        fn handle_control_request<'d>(
+            &mut self,
+            usb: &MockUsbDriver,
+            control_endpoint: u8,
+            setup_packet: SetupPacket,
+            _data: &'d [u8],
+        ) -> ControlResult<'d> {
+            if setup_packet.request != 0x42 {
+                return ControlResult::Unhandled;
+            }
+            self.calls.push("handle_control_request");
+            usb.write(control_endpoint, [].into_iter());
+            ControlResult::Handled
+        }
+
+        fn handle_endpoint_event(&mut self, _usb: &MockUsbDriver, _event: UsbEvent) {
+            self.calls.push("handle_endpoint_event");
+        }
+
+        fn reset(&mut self) {
+            self.calls.push("reset");
+        }
+
+        fn configuration_changed(&mut self, _usb: &MockUsbDriver, _configuration: u8) {
+            self.calls.push("configuration_changed");
+        }
+
+        fn alternate_setting_changed(
+            &mut self,
+            _usb: &MockUsbDriver,
+            _interface_number: u8,
+            _alternate_setting: u8,
+        ) {
+            self.calls.push("alternate_setting_changed");
+        }
+
+        fn suspend(&mut self, _usb: &MockUsbDriver) {
+            self.calls.push("suspend");
+        }
+
+        fn resume(&mut self, _usb: &MockUsbDriver) {
+            self.calls.push("resume");
+        }
+
+        fn start_of_frame(&mut self, _usb: &MockUsbDriver, _frame_number: u16) {
+            self.calls.push("start_of_frame");
+        }
+    }
+
+    type TestDevice = UsbDevice<'static, MockUsbDriver, (CdcAcm<128>, Recorder), 64>;
+
+    type ClassDevice = UsbDevice<'static, MockUsbDriver, (Hid<'static, 8>, DfuRuntime), 64>;
+
+    fn control() -> Control<'static, MockUsbDriver, 64> {
+        Control::new(
+            0,
+            Descriptors {
+                device_speed: Speed::Full,
+                device_descriptor: acm::DEVICE_DESCRIPTOR,
+                configuration_descriptors: CONFIGURATION_DESCRIPTORS,
+                string_descriptor_zero: acm::STRING_DESCRIPTOR_0,
+                string_descriptors: acm::STRING_DESCRIPTORS,
+                device_qualifier_descriptor: None,
+                other_speed_configuration_descriptors: None,
+                binary_object_store: None,
+                microsoft10: None,
+                microsoft20: None,
+            },
+        )
+    }
+
+    fn device() -> TestDevice {
+        UsbDevice::new(
+            control(),
+            (CdcAcm::new(0, 3, 4, 4, 64), Recorder::default()),
+        )
+    }
+
+    fn class_device() -> ClassDevice {
+        let keyboard = Hid::new(0, 1, hid::BOOT_KEYBOARD_REPORT_DESCRIPTOR.as_bytes());
+        UsbDevice::new(control(), (keyboard, DfuRuntime::new(1)))
+    }
+
+    /// Runs a control transfer without a data stage and returns any
+    /// [`ControlEvent`] passed on to the application.
+    fn control_out(
+        device: &mut TestDevice,
+        usb: &MockUsbDriver,
+        setup_packet: SetupPacket,
+    ) -> Option<ControlEvent> {
+        let event = match device.dispatch_event(usb, UsbEvent::ReceiveSetupPacket(0, setup_packet))
+        {
+            Some(DeviceEvent::Control(event)) => Some(event),
+            Some(event) => panic!("unexpected event {:?}", event),
+            None => None,
+        };
+        assert!(device
+            .dispatch_event(usb, UsbEvent::SendComplete(0))
+            .is_none());
+        event
+    }
+
+    fn request(request_type: u8, request: u8, value: u16, index: u16) -> SetupPacket {
+        SetupPacket {
+            request_type: Direction::HostToDevice as u8 | request_type,
+            request,
+            value,
+            index,
+            length: 0,
+        }
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_route_control_requests() {
+        let usb = MockUsbDriver::new();
+        let mut device = device();
+
+        // SET_CONTROL_LINE_STATE to the serial port interface is
+        // reported to the application as a class event
+        let setup_packet = request(0b0010_0001, 0x22, 0b01, 0);
+        assert!(matches!(
+            device.dispatch_event(&usb, UsbEvent::ReceiveSetupPacket(0, setup_packet)),
+            Some(DeviceEvent::Class(ClassEvent::Acm(
+                AcmEvent::ControlLineStateChanged {
+                    dtr: true,
+                    rts: false
+                }
+            )))
+        ));
+        assert!(device
+            .dispatch_event(&usb, UsbEvent::SendComplete(0))
+            .is_none());
+        assert!(device.classes().0.dtr());
+
+        // vendor requests to the recorder interface and endpoint and
+        // device requests which are offered to every class
+        for setup_packet in [
+            request(0b0100_0001, 0x42, 0, 1),
+            request(0b0100_0010, 0x42, 0, 0x85),
+            request(0b0100_0000, 0x42, 0, 0),
+        ] {
+            let event = control_out(&mut device, &usb, setup_packet);
+            assert!(event.is_none());
+        }
+        assert_eq!(device.classes().1.calls.len(), 3);
+
+        // requests for unknown interfaces or not handled by any class
+        // are passed on to the application
+        for setup_packet in [
+            request(0b0100_0001, 0x42, 0, 7),
+            request(0b0100_0001, 0x43, 0, 1),
+            request(0b0100_0000, 0x43, 0, 0),
+        ] {
+            let event = control_out(&mut device, &usb, setup_packet);
+            assert!(matches!(
+                event,
+                Some(ControlEvent::SetupPacket(packet))
+                    if packet.request == setup_packet.request && packet.index == setup_packet.index
+            ));
+        }
+        assert_eq!(device.classes().1.calls.len(), 3);
+    }
+
+    #[test]
+    fn test_hid_get_feature_report() {
+        let usb = MockUsbDriver::new();
+        let mut device = class_device();
+
+        // GET_REPORT for feature report 2 is passed on to the
+        // application which responds with the report
+        let setup_packet = class_request(
+            Direction::DeviceToHost,
+            Recipient::Interface,
+            0x01,
+            0x0302,
+            0,
+            8,
+        );
+        match device.dispatch_event(&usb, UsbEvent::ReceiveSetupPacket(0, setup_packet)) {
+            Some(DeviceEvent::Class(ClassEvent::Hid(HidEvent::GetReport {
+                report_type: ReportType::Feature,
+                report_id: 2,
+                length: 8,
+            }))) => {
+                usb.write_requested(0, 8, [0x02, 0x2a].into_iter());
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(usb.writes(0), [[0x02, 0x2a]]);
+    }
+
+    #[test]
+    fn test_dfu_detach() {
+        let usb = MockUsbDriver::new();
+        let mut device = class_device();
+
+        // DFU_DETACH is reported to the application
+        let setup_packet = class_request(
+            Direction::HostToDevice,
+            Recipient::Interface,
+            0x00,
+            1000,
+            1,
+            0,
+        );
+        assert!(matches!(
+            device.dispatch_event(&usb, UsbEvent::ReceiveSetupPacket(0, setup_packet)),
+            Some(DeviceEvent::Class(ClassEvent::Dfu(DfuEvent::Detach {
+                timeout: 1000
+            })))
+        ));
+        assert!(device
+            .dispatch_event(&usb, UsbEvent::SendComplete(0))
+            .is_none());
+
+        // and the runtime interface stays detached across the bus reset
+        // on which the application enters DFU mode
+        assert!(matches!(
+            device.dispatch_event(&usb, UsbEvent::BusReset),
+            Some(DeviceEvent::BusReset)
+        ));
+        assert_eq!(device.classes().1.state(), dfu::State::AppDetach);
+    }
+
+    #[test]
+    fn test_route_endpoint_events() {
+        let usb = MockUsbDriver::new();
+        let mut device = device();
+
+        // SET_CONFIGURATION
+        let event = control_out(&mut device, &usb, request(0, 9, 1, 0));
+        assert!(matches!(event, Some(ControlEvent::ConfigurationChanged(1))));
+
+        // SET_INTERFACE
+        let event = control_out(&mut device, &usb, request(0b0000_0001, 11, 0, 1));
+        assert!(matches!(
+            event,
+            Some(ControlEvent::AlternateSettingChanged(1, 0))
+        ));
+
+        usb.push_out_data(4, b"hello");
+        assert!(device
+            .dispatch_event(&usb, UsbEvent::ReceivePacket(4))
+            .is_none());
+        assert_eq!(device.classes().0.bytes_available(), 5);
+
+        assert!(device
+            .dispatch_event(&usb, UsbEvent::SendComplete(5))
+            .is_none());
+        assert!(matches!(
+            device.dispatch_event(&usb, UsbEvent::ReceivePacket(5)),
+            Some(DeviceEvent::Endpoint(UsbEvent::ReceivePacket(5)))
+        ));
+
+        assert!(matches!(
+            device.dispatch_event(&usb, UsbEvent::BusReset),
+            Some(DeviceEvent::BusReset)
+        ));
+        assert_eq!(device.classes().0.bytes_available(), 0);
+        assert_eq!(
+            device.classes().1.calls,
+            [
+                "configuration_changed",
+                "alternate_setting_changed",
+                "handle_endpoint_event",
+                "reset"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_device_state() {
+        let usb = MockUsbDriver::new();
+        let mut device = device();
+        assert_eq!(device.state(), DeviceState::Powered);
+
+        device.dispatch_event(&usb, UsbEvent::BusReset);
+        assert_eq!(device.state(), DeviceState::Default);
+
+        // SET_ADDRESS
+        control_out(&mut device, &usb, request(0, 5, 1, 0));
+        assert_eq!(device.state(), DeviceState::Addressed);
+
+        // SET_CONFIGURATION
+        control_out(&mut device, &usb, request(0, 9, 1, 0));
+        assert_eq!(device.state(), DeviceState::Configured);
+        control_out(&mut device, &usb, request(0, 9, 0, 0));
+        assert_eq!(device.state(), DeviceState::Addressed);
+        control_out(&mut device, &usb, request(0, 9, 1, 0));
+
+        assert!(matches!(
+            device.dispatch_event(&usb, UsbEvent::Suspend),
+            Some(DeviceEvent::Suspend)
+        ));
+        assert_eq!(device.state(), DeviceState::Suspended);
+        assert!(matches!(
+            device.dispatch_event(&usb, UsbEvent::Resume),
+            Some(DeviceEvent::Resume)
+        ));
+        assert_eq!(device.state(), DeviceState::Configured);
+
+        // a start-of-frame ends a suspend
+        device.dispatch_event(&usb, UsbEvent::Suspend);
+        assert!(matches!(
+            device.dispatch_event(&usb, UsbEvent::StartOfFrame(1)),
+            Some(DeviceEvent::Resume)
+        ));
+        assert!(device
+            .dispatch_event(&usb, UsbEvent::StartOfFrame(2))
+            .is_none());
+        assert_eq!(device.state(), DeviceState::Configured);
+
+        // the bus reset returns the device to the default state
+        device.dispatch_event(&usb, UsbEvent::Suspend);
+        device.dispatch_event(&usb, UsbEvent::BusReset);
+        assert_eq!(device.state(), DeviceState::Default);
+
+        assert_eq!(
+            device.classes().1.calls,
+            [
+                "reset",
+                "configuration_changed",
+                "configuration_changed",
+                "configuration_changed",
+                "suspend",
+                "resume",
+                "suspend",
+                "resume",
+                "start_of_frame",
+                "start_of_frame",
+                "suspend",
+                "reset"
+            ]
+        );
+    }
+}
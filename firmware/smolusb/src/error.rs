@@ -4,6 +4,10 @@
 pub enum ErrorKind {
     Timeout(usize),
     Overflow(usize),
+    RemoteWakeupDisabled,
+    NotSuspended,
+    Unsupported,
+    EndpointBusy,
 }
 
 impl core::fmt::Display for ErrorKind {
@@ -20,6 +24,32 @@ impl core::error::Error for ErrorKind {
         match self {
             Timeout(_) => "Blocking operation timed-out",
             Overflow(_) => "Read operation overflowed receive buffer",
+            RemoteWakeupDisabled => "Remote wakeup has not been enabled by the host",
+            NotSuspended => "Device is not suspended",
+            Unsupported => "Operation is not supported by the driver",
+            EndpointBusy => "Endpoint is still busy sending a previous packet",
         }
     }
 }
+
+/// Returned by driver operations that are not supported by the
+/// peripheral.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Unsupported;
+
+impl From<Unsupported> for ErrorKind {
+    fn from(_: Unsupported) -> Self {
+        ErrorKind::Unsupported
+    }
+}
+
+/// Returned by driver operations that could not use an endpoint as
+/// it was still busy sending a previous packet.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct EndpointBusy;
+
+impl From<EndpointBusy> for ErrorKind {
+    fn from(_: EndpointBusy) -> Self {
+        ErrorKind::EndpointBusy
+    }
+}
@@ -22,6 +22,18 @@ pub enum UsbEvent {
     /// Contents is (`endpoint_number`)
     SendComplete(u8) = 13,
 
+    /// The bus has been idle for more than 3ms and the device has
+    /// entered the suspended state
+    Suspend = 14,
+
+    /// Bus activity has resumed after a suspend
+    Resume = 15,
+
+    /// Received a start-of-frame packet
+    ///
+    /// Contents is (`frame_number`)
+    StartOfFrame(u16) = 16,
+
     /// Received a setup packet on `USBx_EP_CONTROL`
     ///
     /// An alternate version of `ReceiveControl` that can be used
@@ -57,6 +69,15 @@ impl core::fmt::Debug for UsbEvent {
             UsbEvent::SendComplete(endpoint) => {
                 write!(f, "SendComplete({endpoint})")
             }
+            UsbEvent::Suspend => {
+                write!(f, "Suspend")
+            }
+            UsbEvent::Resume => {
+                write!(f, "Resume")
+            }
+            UsbEvent::StartOfFrame(frame_number) => {
+                write!(f, "StartOfFrame({frame_number})")
+            }
             UsbEvent::ReceiveSetupPacket(endpoint, setup_packet) => {
                 write!(f, "ReceiveSetupPacket({endpoint}, {setup_packet:?})")
             }
@@ -75,6 +96,9 @@ impl From<UsbEvent> for u8 {
             UsbEvent::ReceiveControl(_) => 11,
             UsbEvent::ReceivePacket(_) => 12,
             UsbEvent::SendComplete(_) => 13,
+            UsbEvent::Suspend => 14,
+            UsbEvent::Resume => 15,
+            UsbEvent::StartOfFrame(_) => 16,
             UsbEvent::ReceiveSetupPacket(_, _) => 201,
             #[cfg(feature = "chonky_events")]
             UsbEvent::ReceiveBuffer(_, _, _) => 202,
@@ -93,11 +117,16 @@ impl core::convert::From<UsbEvent> for [u8; 2] {
             #[cfg(feature = "chonky_events")]
             ReceiveBuffer(endpoint_number, _, _) => [event.into(), endpoint_number],
             SendComplete(endpoint_number) => [event.into(), endpoint_number],
+            // the 11-bit frame number does not fit and is not encoded
+            Suspend | Resume | StartOfFrame(_) => [event.into(), 0],
         }
     }
 }
 
 impl UsbEvent {
+    /// Returns the event identifier followed by the endpoint number.
+    ///
+    /// The frame number of [`UsbEvent::StartOfFrame`] is not encoded.
     #[must_use]
     pub fn into_bytes(self) -> [u8; 2] {
         self.into()
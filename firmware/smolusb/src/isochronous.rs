@@ -0,0 +1,144 @@
+//! Scheduling for isochronous transfers.
+
+use crate::device::Speed;
+
+/// Frame numbers are 11 bits wide and wrap around every 2048 frames.
+const FRAME_NUMBER_MASK: u16 = 0x07ff;
+
+// - FrameSchedule ------------------------------------------------------------
+
+/// Paces the transfers of an isochronous endpoint on start-of-frame
+/// events.
+///
+/// The host polls an isochronous endpoint once per service interval and
+/// never acknowledges the transfer, so the device has no completion
+/// event to queue the next packet on. Instead the class passes the
+/// frame number of each [`UsbEvent::StartOfFrame`](crate::event::UsbEvent::StartOfFrame)
+/// to [`FrameSchedule::is_due()`] and reads or writes a packet with
+/// [`IsochronousEndpoint`](crate::traits::IsochronousEndpoint) when it
+/// returns `true`.
+///
+/// At high speed all eight microframes of a frame share a frame number,
+/// so endpoints with a service interval shorter than a frame are due on
+/// every start-of-frame.
+///
+/// The `lunasoc-hal` driver only reports start-of-frame packets once
+/// they have been enabled with `set_start_of_frame_events()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameSchedule {
+    interval: u16,
+    next: Option<u16>,
+}
+
+impl FrameSchedule {
+    /// Returns the schedule for an endpoint with the given `bInterval`
+    /// exponent at the given device speed.
+    #[must_use]
+    pub const fn new(speed: Speed, interval: u8) -> Self {
+        let exponent = match interval {
+            0 => 0,
+            17..=u8::MAX => 15,
+            interval => interval - 1,
+        };
+        let interval = match speed {
+            Speed::High => (1 << exponent) / 8,
+            _ => 1 << exponent,
+        };
+        Self {
+            // a schedule longer than the frame number range can not be tracked
+            interval: if interval > 1024 { 1024 } else { interval },
+            next: None,
+        }
+    }
+
+    /// Returns the service interval in frames, which is zero for
+    /// endpoints that are serviced every microframe.
+    #[must_use]
+    pub fn interval(&self) -> u16 {
+        self.interval
+    }
+
+    /// Returns `true` if the endpoint is due to be serviced in the given
+    /// frame and schedules the next service interval.
+    ///
+    /// Frames missed since the last service are skipped rather than
+    /// serviced in a burst.
+    pub fn is_due(&mut self, frame_number: u16) -> bool {
+        if self.interval == 0 {
+            return true;
+        }
+
+        let frame_number = frame_number & FRAME_NUMBER_MASK;
+        let is_due = match self.next {
+            // frames up to half the frame number range behind the next
+            // service are treated as already serviced
+            Some(next) => frame_number.wrapping_sub(next) & FRAME_NUMBER_MASK < 0x400,
+            None => true,
+        };
+        if is_due {
+            self.next = Some(frame_number.wrapping_add(self.interval) & FRAME_NUMBER_MASK);
+        }
+        is_due
+    }
+
+    /// Restarts the schedule at the next start-of-frame.
+    pub fn reset(&mut self) {
+        self.next = None;
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    // - fixtures -------------------------------------------------------------
+
+    /// Returns the frames in which the schedule was due.
+    fn due_frames(schedule: &mut FrameSchedule, frames: impl Iterator<Item = u16>) -> Vec<u16> {
+        frames
+            .filter(|frame_number| schedule.is_due(*frame_number))
+            .collect()
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_interval() {
+        assert_eq!(FrameSchedule::new(Speed::Full, 1).interval(), 1);
+        assert_eq!(FrameSchedule::new(Speed::Full, 4).interval(), 8);
+        assert_eq!(FrameSchedule::new(Speed::Full, 16).interval(), 1024);
+        assert_eq!(FrameSchedule::new(Speed::High, 1).interval(), 0);
+        assert_eq!(FrameSchedule::new(Speed::High, 4).interval(), 1);
+        assert_eq!(FrameSchedule::new(Speed::High, 6).interval(), 4);
+    }
+
+    #[test]
+    fn test_is_due() {
+        let mut schedule = FrameSchedule::new(Speed::Full, 3);
+        assert_eq!(due_frames(&mut schedule, 10..22), [10, 14, 18]);
+
+        // missed frames are skipped
+        assert_eq!(
+            due_frames(&mut schedule, [23, 24, 30].into_iter()),
+            [23, 30]
+        );
+
+        // the frame number wraps around
+        schedule.reset();
+        assert_eq!(due_frames(&mut schedule, 2044..2052), [2044, 2048]);
+        assert_eq!(due_frames(&mut schedule, 0..8), [4]);
+
+        // microframes share their frame number
+        let mut schedule = FrameSchedule::new(Speed::High, 4);
+        assert_eq!(
+            due_frames(&mut schedule, [7, 7, 7, 8, 8].into_iter()),
+            [7, 8]
+        );
+        let mut schedule = FrameSchedule::new(Speed::High, 1);
+        assert_eq!(due_frames(&mut schedule, [7, 7, 8].into_iter()), [7, 7, 8]);
+    }
+}
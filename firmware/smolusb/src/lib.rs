@@ -6,18 +6,34 @@
 
 //! A simple peripheral-level USB stack designed for [`luna-soc`](https://github.com/greatscottgadgets/luna-soc/) USB peripherals.
 
+#[cfg(any(test, feature = "testing"))]
+extern crate alloc;
+
+pub mod asynch;
 pub mod class;
 pub mod control;
 pub mod descriptor;
 pub mod device;
 pub mod error;
 pub mod event;
+pub mod isochronous;
+pub mod ringbuffer;
 pub mod setup;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
 pub mod traits;
+pub mod validate;
 
 /// USB devices can define up to 32 endpoints. 16 IN and 16 OUT.
 pub const EP_MAX_ENDPOINTS: usize = 16;
 
+/// Maximum number of interfaces supported per configuration.
+///
+/// `GET_INTERFACE` and `SET_INTERFACE` requests for interface numbers
+/// at or above this limit are stalled. Configurations with more
+/// interfaces are rejected by [`Descriptors::validate()`](crate::device::Descriptors::validate).
+pub const MAX_INTERFACES: usize = 16;
+
 /// Maximum packet size for endpoints.
 pub const EP_MAX_PACKET_SIZE: usize = 512;
 
@@ -0,0 +1,105 @@
+//! A fixed size byte ring buffer shared by the class implementations.
+
+// - RingBuffer ---------------------------------------------------------------
+
+/// A fixed size byte ring buffer.
+pub struct RingBuffer<const N: usize> {
+    buffer: [u8; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes that can be pushed before the buffer is full.
+    #[must_use]
+    pub fn available(&self) -> usize {
+        N - self.len
+    }
+
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+
+    /// Appends as many bytes as will fit and returns the number of bytes appended.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let count = data.len().min(self.available());
+        for &byte in &data[..count] {
+            self.buffer[(self.head + self.len) % N] = byte;
+            self.len += 1;
+        }
+        count
+    }
+
+    /// Removes bytes from the front of the buffer and returns the number of bytes removed.
+    pub fn pop(&mut self, data: &mut [u8]) -> usize {
+        let count = data.len().min(self.len);
+        for (index, byte) in data[..count].iter_mut().enumerate() {
+            *byte = self.buffer[(self.head + index) % N];
+        }
+        self.consume(count);
+        count
+    }
+
+    /// Returns an iterator over the contents of the buffer without removing them.
+    pub fn iter(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.len).map(move |index| self.buffer[(self.head + index) % N])
+    }
+
+    /// Discards up to `count` bytes from the front of the buffer.
+    pub fn consume(&mut self, count: usize) {
+        let count = count.min(self.len);
+        self.head = (self.head + count) % N;
+        self.len -= count;
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::vec::Vec;
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut ring_buffer: RingBuffer<4> = RingBuffer::new();
+        let mut buffer = [0; 4];
+
+        assert_eq!(ring_buffer.push(&[1, 2, 3]), 3);
+        assert_eq!(ring_buffer.pop(&mut buffer[..2]), 2);
+        assert_eq!(ring_buffer.push(&[4, 5, 6, 7]), 3);
+        assert_eq!(ring_buffer.iter().collect::<Vec<_>>(), [3, 4, 5, 6]);
+        assert_eq!(ring_buffer.pop(&mut buffer), 4);
+        assert_eq!(buffer, [3, 4, 5, 6]);
+        assert!(ring_buffer.is_empty());
+    }
+}
@@ -23,6 +23,24 @@ impl From<[u8; 8]> for SetupPacket {
     }
 }
 
+impl From<SetupPacket> for [u8; 8] {
+    fn from(setup_packet: SetupPacket) -> Self {
+        let [value_lo, value_hi] = setup_packet.value.to_le_bytes();
+        let [index_lo, index_hi] = setup_packet.index.to_le_bytes();
+        let [length_lo, length_hi] = setup_packet.length.to_le_bytes();
+        [
+            setup_packet.request_type,
+            setup_packet.request,
+            value_lo,
+            value_hi,
+            index_lo,
+            index_hi,
+            length_lo,
+            length_hi,
+        ]
+    }
+}
+
 impl SetupPacket {
     #[must_use]
     pub fn as_bytes(setup_packet: SetupPacket) -> [u8; 8] {
@@ -189,3 +207,29 @@ impl From<u16> for Feature {
         }
     }
 }
+
+/// Represents the test selector in the upper byte of the `[SetupPacket]`
+/// `index` field of a `Feature::DeviceTestMode` request.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TestMode {
+    TestJ = 1,
+    TestK = 2,
+    TestSe0Nak = 3,
+    TestPacket = 4,
+    TestForceEnable = 5,
+    Reserved(u8) = 255,
+}
+
+impl From<u8> for TestMode {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => TestMode::TestJ,
+            2 => TestMode::TestK,
+            3 => TestMode::TestSe0Nak,
+            4 => TestMode::TestPacket,
+            5 => TestMode::TestForceEnable,
+            _ => TestMode::Reserved(value),
+        }
+    }
+}
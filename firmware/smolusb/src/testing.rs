@@ -0,0 +1,1004 @@
+//! Host-side test support
+//!
+//! Provides a [`MockUsbDriver`] that records every operation performed
+//! on it and a [`VirtualHost`] that can drive a [`Control`] endpoint
+//! through complete control transfers and a full enumeration sequence.
+//!
+//! This module requires an allocator and is only available for tests
+//! or when the `testing` feature is enabled.
+//!
+//! See the tests at the end of this module for an example of
+//! enumerating a device with the [`VirtualHost`].
+
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cell::{Cell, RefCell};
+
+use crate::control::{Control, ControlEvent};
+use crate::descriptor::parser::{self, Descriptor, ParseError};
+use crate::descriptor::{
+    ConfigurationDescriptor, DescriptorType, DeviceDescriptor, LanguageId, StringDescriptorZero,
+};
+use crate::device::{Descriptors, Speed};
+use crate::error::{EndpointBusy, Unsupported};
+use crate::event::UsbEvent;
+use crate::setup::{Direction, Recipient, SetupPacket, TestMode};
+use crate::traits::{
+    IsochronousEndpoint, ReadControl, ReadEndpoint, UsbDriver, UsbDriverOperations, WriteEndpoint,
+};
+
+// - Operation ----------------------------------------------------------------
+
+/// An operation performed on a [`MockUsbDriver`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum Operation {
+    Connect(Speed),
+    Disconnect,
+    BusReset,
+    /// Contents is (`endpoint_number`, `direction`)
+    Ack(u8, Direction),
+    SetAddress(u8),
+    StallIn(u8),
+    StallOut(u8),
+    /// Contents is (`endpoint_number`, `direction`)
+    ClearHalt(u8, Direction),
+    RemoteWakeup,
+    TestMode(TestMode),
+    PrimeReceive(u8),
+    /// Contents is (`endpoint_number`, `bytes_read`)
+    Read(u8, usize),
+    /// Contents is (`endpoint_number`, `data`)
+    Write(u8, Vec<u8>),
+    /// Contents is (`endpoint_number`, `data`)
+    WriteIsochronous(u8, Vec<u8>),
+}
+
+// - MockUsbDriver ------------------------------------------------------------
+
+/// A fake [`UsbDriver`] for exercising smolusb on the host.
+///
+/// Every operation is recorded and can be inspected with
+/// [`MockUsbDriver::operations()`]. SETUP packets and OUT data are
+/// queued with [`MockUsbDriver::push_setup_packet()`] and
+/// [`MockUsbDriver::push_out_data()`] and handed out to the next
+/// matching read.
+#[derive(Default)]
+pub struct MockUsbDriver {
+    operations: RefCell<Vec<Operation>>,
+    setup_packets: RefCell<VecDeque<SetupPacket>>,
+    out_data: RefCell<[VecDeque<Vec<u8>>; crate::EP_MAX_ENDPOINTS]>,
+    address: Cell<u8>,
+    isochronous_busy: Cell<bool>,
+    remote_wakeup_unsupported: Cell<bool>,
+    test_mode_unsupported: Cell<bool>,
+}
+
+impl MockUsbDriver {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a SETUP packet to be returned by the next call to
+    /// [`ReadControl::read_control()`].
+    pub fn push_setup_packet(&self, setup_packet: SetupPacket) {
+        self.setup_packets.borrow_mut().push_back(setup_packet);
+    }
+
+    /// Queues a packet of OUT data to be returned by the next call to
+    /// [`ReadEndpoint::read()`] for the given endpoint.
+    ///
+    /// An empty slice queues a zero length packet.
+    pub fn push_out_data(&self, endpoint_number: u8, data: &[u8]) {
+        self.out_data.borrow_mut()[usize::from(endpoint_number)].push_back(data.to_vec());
+    }
+
+    /// Makes [`IsochronousEndpoint::write_isochronous()`] fail as if the
+    /// IN FIFO was in use by another endpoint.
+    pub fn set_isochronous_busy(&self, busy: bool) {
+        self.isochronous_busy.set(busy);
+    }
+
+    /// Makes [`UsbDriverOperations::remote_wakeup()`] fail as if the
+    /// peripheral could not drive resume signalling.
+    pub fn set_remote_wakeup_unsupported(&self, unsupported: bool) {
+        self.remote_wakeup_unsupported.set(unsupported);
+    }
+
+    /// Makes [`UsbDriverOperations::supports_test_mode()`] return
+    /// `false` for every test mode.
+    pub fn set_test_mode_unsupported(&self, unsupported: bool) {
+        self.test_mode_unsupported.set(unsupported);
+    }
+
+    /// Returns all operations recorded so far.
+    #[must_use]
+    pub fn operations(&self) -> Vec<Operation> {
+        self.operations.borrow().clone()
+    }
+
+    /// Returns and clears all operations recorded so far.
+    pub fn take_operations(&self) -> Vec<Operation> {
+        core::mem::take(&mut *self.operations.borrow_mut())
+    }
+
+    /// Returns the data of every write to the given endpoint.
+    #[must_use]
+    pub fn writes(&self, endpoint_number: u8) -> Vec<Vec<u8>> {
+        self.operations
+            .borrow()
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Write(number, data) if *number == endpoint_number => Some(data.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the last address set by the device.
+    #[must_use]
+    pub fn address(&self) -> u8 {
+        self.address.get()
+    }
+
+    fn record(&self, operation: Operation) {
+        self.operations.borrow_mut().push(operation);
+    }
+}
+
+impl UsbDriver for MockUsbDriver {}
+
+impl UsbDriverOperations for MockUsbDriver {
+    fn connect(&mut self, device_speed: Speed) {
+        self.record(Operation::Connect(device_speed));
+    }
+    fn disconnect(&mut self) {
+        self.record(Operation::Disconnect);
+    }
+    fn bus_reset(&self) {
+        self.address.set(0);
+        self.record(Operation::BusReset);
+    }
+    fn ack(&self, endpoint_number: u8, direction: Direction) {
+        self.record(Operation::Ack(endpoint_number, direction));
+    }
+    fn set_address(&self, address: u8) {
+        self.address.set(address & 0x7f);
+        self.record(Operation::SetAddress(address));
+    }
+    fn stall_endpoint_in(&self, endpoint_number: u8) {
+        self.record(Operation::StallIn(endpoint_number));
+    }
+    fn stall_endpoint_out(&self, endpoint_number: u8) {
+        self.record(Operation::StallOut(endpoint_number));
+    }
+    fn clear_feature_endpoint_halt(&self, endpoint_number: u8, direction: Direction) {
+        self.record(Operation::ClearHalt(endpoint_number, direction));
+    }
+    fn remote_wakeup(&self) -> Result<(), Unsupported> {
+        if self.remote_wakeup_unsupported.get() {
+            return Err(Unsupported);
+        }
+        self.record(Operation::RemoteWakeup);
+        Ok(())
+    }
+    fn supports_test_mode(&self, _test_mode: TestMode) -> bool {
+        !self.test_mode_unsupported.get()
+    }
+    fn test_mode(&self, test_mode: TestMode) {
+        self.record(Operation::TestMode(test_mode));
+    }
+}
+
+impl ReadControl for MockUsbDriver {
+    fn read_control(&self, buffer: &mut [u8]) -> usize {
+        let Some(setup_packet) = self.setup_packets.borrow_mut().pop_front() else {
+            return 0;
+        };
+        let bytes: [u8; 8] = setup_packet.into();
+        let bytes_read = bytes.len().min(buffer.len());
+        buffer[..bytes_read].copy_from_slice(&bytes[..bytes_read]);
+        bytes_read
+    }
+}
+
+impl ReadEndpoint for MockUsbDriver {
+    fn ep_out_prime_receive(&self, endpoint_number: u8) {
+        self.record(Operation::PrimeReceive(endpoint_number));
+    }
+
+    fn ep_out_enable(&self) {}
+
+    fn read(&self, endpoint_number: u8, buffer: &mut [u8]) -> usize {
+        let packet = self.out_data.borrow_mut()[usize::from(endpoint_number)].pop_front();
+        let bytes_read = match packet {
+            Some(packet) => {
+                let bytes_read = packet.len().min(buffer.len());
+                buffer[..bytes_read].copy_from_slice(&packet[..bytes_read]);
+                bytes_read
+            }
+            None => 0,
+        };
+        self.record(Operation::Read(endpoint_number, bytes_read));
+        bytes_read
+    }
+}
+
+impl WriteEndpoint for MockUsbDriver {
+    fn write<I>(&self, endpoint_number: u8, iter: I) -> usize
+    where
+        I: Iterator<Item = u8>,
+    {
+        let data: Vec<u8> = iter.collect();
+        let bytes_written = data.len();
+        self.record(Operation::Write(endpoint_number, data));
+        bytes_written
+    }
+
+    fn write_requested<I>(&self, endpoint_number: u8, requested_length: usize, iter: I) -> usize
+    where
+        I: Iterator<Item = u8>,
+    {
+        self.write(endpoint_number, iter.take(requested_length))
+    }
+
+    fn write_with_packet_size<I>(
+        &self,
+        endpoint_number: u8,
+        requested_length: Option<usize>,
+        iter: I,
+        _packet_size: usize,
+    ) -> usize
+    where
+        I: Iterator<Item = u8>,
+    {
+        self.write(
+            endpoint_number,
+            iter.take(requested_length.unwrap_or(usize::MAX)),
+        )
+    }
+
+    fn write_packet<I>(&self, endpoint_number: u8, iter: I) -> Result<usize, EndpointBusy>
+    where
+        I: Iterator<Item = u8>,
+    {
+        Ok(self.write(endpoint_number, iter))
+    }
+}
+
+impl IsochronousEndpoint for MockUsbDriver {
+    fn write_isochronous<I>(&self, endpoint_number: u8, iter: I) -> usize
+    where
+        I: Iterator<Item = u8>,
+    {
+        if self.isochronous_busy.get() {
+            return 0;
+        }
+        let data: Vec<u8> = iter.collect();
+        let bytes_written = data.len();
+        self.record(Operation::WriteIsochronous(endpoint_number, data));
+        bytes_written
+    }
+
+    fn read_isochronous(&self, endpoint_number: u8, buffer: &mut [u8]) -> usize {
+        let bytes_read = self.read(endpoint_number, buffer);
+        self.ep_out_prime_receive(endpoint_number);
+        bytes_read
+    }
+}
+
+// - HostError ----------------------------------------------------------------
+
+/// Errors reported by the [`VirtualHost`].
+#[derive(Debug, Copy, Clone)]
+pub enum HostError {
+    /// The device stalled the request.
+    Stalled(SetupPacket),
+    /// The request was not handled by [`Control`] and passed on to the application.
+    Unhandled(SetupPacket),
+    /// The device sent more data than the host requested.
+    ///
+    /// Contents is (`setup_packet`, `bytes_sent`)
+    Overrun(SetupPacket, usize),
+    /// The device did not complete the status stage.
+    NoStatus(SetupPacket),
+    /// The device responded with malformed descriptor data.
+    Parse(ParseError),
+    /// The device responded with unexpected data.
+    InvalidResponse(&'static str),
+}
+
+impl core::fmt::Display for HostError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+impl From<ParseError> for HostError {
+    fn from(error: ParseError) -> Self {
+        HostError::Parse(error)
+    }
+}
+
+// - SetupPacket helpers ------------------------------------------------------
+
+/// Returns a standard request [`SetupPacket`] for the given recipient.
+#[must_use]
+pub fn standard_request(
+    direction: Direction,
+    recipient: Recipient,
+    request: u8,
+    value: u16,
+    index: u16,
+    length: u16,
+) -> SetupPacket {
+    SetupPacket {
+        request_type: direction as u8 | recipient as u8,
+        request,
+        value,
+        index,
+        length,
+    }
+}
+
+/// Returns a class request [`SetupPacket`] for the given recipient.
+#[must_use]
+pub fn class_request(
+    direction: Direction,
+    recipient: Recipient,
+    request: u8,
+    value: u16,
+    index: u16,
+    length: u16,
+) -> SetupPacket {
+    SetupPacket {
+        request_type: direction as u8 | 0b0010_0000 | recipient as u8, // RequestType::Class
+        request,
+        value,
+        index,
+        length,
+    }
+}
+
+// - Enumeration --------------------------------------------------------------
+
+/// Everything a [`VirtualHost`] learned about a device while enumerating it.
+#[derive(Debug, Clone, Default)]
+pub struct Enumeration {
+    pub address: u8,
+    pub device_descriptor: Vec<u8>,
+    pub configuration_descriptors: Vec<Vec<u8>>,
+    pub language_ids: Vec<u16>,
+    /// Contents is (`index`, `string`)
+    pub strings: Vec<(u8, String)>,
+}
+
+// - VirtualHost --------------------------------------------------------------
+
+/// A small USB host that drives a [`Control`] endpoint on a
+/// [`MockUsbDriver`] through complete control transfers.
+pub struct VirtualHost<'a, const RX_BUFFER_SIZE: usize> {
+    pub usb: MockUsbDriver,
+    pub control: Control<'a, MockUsbDriver, RX_BUFFER_SIZE>,
+    events: Vec<ControlEvent>,
+}
+
+impl<'a, const RX_BUFFER_SIZE: usize> VirtualHost<'a, RX_BUFFER_SIZE> {
+    /// Control endpoint used by the virtual host.
+    pub const ENDPOINT_NUMBER: u8 = 0;
+
+    #[must_use]
+    pub fn new(descriptors: Descriptors<'a>) -> Self {
+        Self {
+            usb: MockUsbDriver::new(),
+            control: Control::new(Self::ENDPOINT_NUMBER, descriptors),
+            events: Vec::new(),
+        }
+    }
+
+    /// Returns a host attached to a full-speed device with the given
+    /// configuration descriptors which has been enumerated and placed
+    /// in its first configuration.
+    ///
+    /// The operations performed during enumeration are discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the device could not be enumerated or configured.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn configured(configuration_descriptors: &'a [ConfigurationDescriptor<'a>]) -> Self {
+        Self::configured_with(Descriptors {
+            device_speed: Speed::Full,
+            device_descriptor: DeviceDescriptor {
+                bNumConfigurations: configuration_descriptors.len() as u8,
+                ..DeviceDescriptor::new()
+            },
+            configuration_descriptors,
+            string_descriptor_zero: StringDescriptorZero::new(&[LanguageId::EnglishUnitedStates]),
+            string_descriptors: &[],
+            device_qualifier_descriptor: None,
+            other_speed_configuration_descriptors: None,
+            binary_object_store: None,
+            microsoft10: None,
+            microsoft20: None,
+        })
+    }
+
+    /// Returns a host attached to a device with the given descriptors
+    /// which has been enumerated and placed in its first configuration.
+    ///
+    /// The operations performed during enumeration are discarded.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the device could not be enumerated or configured.
+    #[must_use]
+    pub fn configured_with(descriptors: Descriptors<'a>) -> Self {
+        let configuration_value = descriptors
+            .configuration_descriptors
+            .first()
+            .map_or(1, |descriptor| descriptor.head.bConfigurationValue);
+        let mut host = Self::new(descriptors);
+        host.enumerate(1).expect("enumeration failed");
+        host.set_configuration(configuration_value)
+            .expect("configuration failed");
+        host.usb.take_operations();
+        host
+    }
+
+    /// Returns and clears all [`ControlEvent`]s the device passed on to the application.
+    pub fn take_events(&mut self) -> Vec<ControlEvent> {
+        core::mem::take(&mut self.events)
+    }
+
+    /// Dispatches an event to the [`Control`] endpoint and records any resulting [`ControlEvent`].
+    pub fn dispatch(&mut self, event: UsbEvent) -> Option<ControlEvent> {
+        self.dispatch_with(event, &mut |_, _, _| ())
+    }
+
+    fn dispatch_with<F>(&mut self, event: UsbEvent, handler: &mut F) -> Option<ControlEvent>
+    where
+        F: FnMut(&MockUsbDriver, SetupPacket, &[u8]),
+    {
+        let control_event = self.control.dispatch_event(&self.usb, event);
+        if let Some(control_event) = control_event {
+            if let ControlEvent::SetupPacket(setup_packet) = control_event {
+                handler(&self.usb, setup_packet, self.control.data());
+            }
+            self.events.push(control_event);
+        }
+        control_event
+    }
+
+    /// Resets the bus.
+    pub fn bus_reset(&mut self) {
+        // the irq handler is responsible for resetting the peripheral
+        self.usb.bus_reset();
+        self.dispatch(UsbEvent::BusReset);
+    }
+
+    /// Performs a control transfer with a data stage from the device to the host.
+    ///
+    /// Returns the data sent by the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not complete the transfer.
+    pub fn control_in(&mut self, setup_packet: SetupPacket) -> Result<Vec<u8>, HostError> {
+        self.control_in_with(setup_packet, |_, _, _| ())
+    }
+
+    /// Performs a control transfer with a data stage from the device
+    /// to the host, calling `handler` for every request [`Control`]
+    /// passes on to the application.
+    ///
+    /// The handler receives the driver, the request and any data
+    /// received from the host.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not complete the transfer.
+    pub fn control_in_with<F>(
+        &mut self,
+        setup_packet: SetupPacket,
+        mut handler: F,
+    ) -> Result<Vec<u8>, HostError>
+    where
+        F: FnMut(&MockUsbDriver, SetupPacket, &[u8]),
+    {
+        let start = self.usb.operations.borrow().len();
+
+        // setup stage
+        self.send_setup_packet(setup_packet, &mut handler);
+        self.check_stall(start, setup_packet)?;
+
+        // data stage
+        let data: Vec<u8> = self.usb.operations.borrow()[start..]
+            .iter()
+            .filter_map(|operation| match operation {
+                Operation::Write(Self::ENDPOINT_NUMBER, data) => Some(data.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .concat();
+        if data.is_empty() && self.has_unhandled_event(setup_packet) {
+            return Err(HostError::Unhandled(setup_packet));
+        }
+        if data.len() > usize::from(setup_packet.length) {
+            return Err(HostError::Overrun(setup_packet, data.len()));
+        }
+        self.dispatch_with(UsbEvent::SendComplete(Self::ENDPOINT_NUMBER), &mut handler);
+
+        // status stage
+        self.usb.push_out_data(Self::ENDPOINT_NUMBER, &[]);
+        self.dispatch_with(UsbEvent::ReceivePacket(Self::ENDPOINT_NUMBER), &mut handler);
+
+        Ok(data)
+    }
+
+    /// Performs a control transfer with an optional data stage from
+    /// the host to the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not complete the transfer.
+    pub fn control_out(&mut self, setup_packet: SetupPacket, data: &[u8]) -> Result<(), HostError> {
+        self.control_out_with(setup_packet, data, |_, _, _| ())
+    }
+
+    /// Performs a control transfer with an optional data stage from
+    /// the host to the device, calling `handler` for every request
+    /// [`Control`] passes on to the application.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not complete the transfer.
+    pub fn control_out_with<F>(
+        &mut self,
+        setup_packet: SetupPacket,
+        data: &[u8],
+        mut handler: F,
+    ) -> Result<(), HostError>
+    where
+        F: FnMut(&MockUsbDriver, SetupPacket, &[u8]),
+    {
+        let start = self.usb.operations.borrow().len();
+
+        // setup stage
+        self.send_setup_packet(setup_packet, &mut handler);
+        self.check_stall(start, setup_packet)?;
+
+        // data stage
+        if !data.is_empty() {
+            let max_packet_size = crate::max_packet_size(Speed::High, Self::ENDPOINT_NUMBER);
+            for packet in data.chunks(max_packet_size) {
+                self.usb.push_out_data(Self::ENDPOINT_NUMBER, packet);
+                self.dispatch_with(UsbEvent::ReceivePacket(Self::ENDPOINT_NUMBER), &mut handler);
+                self.check_stall(start, setup_packet)?;
+            }
+        }
+
+        // status stage
+        let status = self.usb.operations.borrow()[start..].iter().any(
+            |operation| matches!(operation, Operation::Write(Self::ENDPOINT_NUMBER, data) if data.is_empty()),
+        );
+        if !status {
+            if self.has_unhandled_event(setup_packet) {
+                return Err(HostError::Unhandled(setup_packet));
+            }
+            return Err(HostError::NoStatus(setup_packet));
+        }
+        self.dispatch_with(UsbEvent::SendComplete(Self::ENDPOINT_NUMBER), &mut handler);
+
+        Ok(())
+    }
+
+    /// Requests a descriptor from the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not complete the transfer.
+    pub fn get_descriptor(
+        &mut self,
+        descriptor_type: DescriptorType,
+        index: u8,
+        language_id: u16,
+        length: u16,
+    ) -> Result<Vec<u8>, HostError> {
+        self.control_in(SetupPacket {
+            request_type: Direction::DeviceToHost as u8,
+            request: 6, // Request::GetDescriptor
+            value: u16::from_le_bytes([index, descriptor_type as u8]),
+            index: language_id,
+            length,
+        })
+    }
+
+    /// Assigns an address to the device.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not complete the transfer.
+    pub fn set_address(&mut self, address: u8) -> Result<(), HostError> {
+        self.control_out(
+            SetupPacket {
+                request_type: Direction::HostToDevice as u8,
+                request: 5, // Request::SetAddress
+                value: u16::from(address),
+                index: 0,
+                length: 0,
+            },
+            &[],
+        )
+    }
+
+    /// Selects a device configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not complete the transfer.
+    pub fn set_configuration(&mut self, configuration_value: u8) -> Result<(), HostError> {
+        self.control_out(
+            SetupPacket {
+                request_type: Direction::HostToDevice as u8,
+                request: 9, // Request::SetConfiguration
+                value: u16::from(configuration_value),
+                index: 0,
+                length: 0,
+            },
+            &[],
+        )
+    }
+
+    /// Returns the active device configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not complete the transfer.
+    pub fn get_configuration(&mut self) -> Result<u8, HostError> {
+        let data = self.control_in(SetupPacket {
+            request_type: Direction::DeviceToHost as u8,
+            request: 8, // Request::GetConfiguration
+            value: 0,
+            index: 0,
+            length: 1,
+        })?;
+        match data[..] {
+            [configuration_value] => Ok(configuration_value),
+            _ => Err(HostError::InvalidResponse("GET_CONFIGURATION length")),
+        }
+    }
+
+    /// Runs a full enumeration sequence against the device, checking
+    /// each response, and selects the first configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`HostError`] if the device did not respond as expected.
+    pub fn enumerate(&mut self, address: u8) -> Result<Enumeration, HostError> {
+        let mut enumeration = Enumeration {
+            address,
+            ..Enumeration::default()
+        };
+
+        // read the first packet of the device descriptor at the default address
+        self.bus_reset();
+        let data = self.get_descriptor(DescriptorType::Device, 0, 0, 64)?;
+        if data.len() < 8 {
+            return Err(HostError::InvalidResponse("device descriptor too short"));
+        }
+        self.bus_reset();
+
+        // assign the device an address
+        self.set_address(address)?;
+        if self.usb.address() != address {
+            return Err(HostError::InvalidResponse("SET_ADDRESS was not applied"));
+        }
+
+        // read the full device descriptor
+        let data = self.get_descriptor(DescriptorType::Device, 0, 0, 18)?;
+        let Descriptor::Device(device_descriptor) = Descriptor::parse(&data)?.0 else {
+            return Err(HostError::InvalidResponse("expected a device descriptor"));
+        };
+        let mut string_indices = Vec::from([
+            device_descriptor.iManufacturer,
+            device_descriptor.iProduct,
+            device_descriptor.iSerialNumber,
+        ]);
+        let num_configurations = device_descriptor.bNumConfigurations;
+        enumeration.device_descriptor = data;
+
+        // read each configuration descriptor, first the header and then the whole thing
+        for index in 0..num_configurations {
+            let data = self.get_descriptor(DescriptorType::Configuration, index, 0, 9)?;
+            let Descriptor::Configuration(header) = Descriptor::parse(&data)?.0 else {
+                return Err(HostError::InvalidResponse(
+                    "expected a configuration descriptor",
+                ));
+            };
+            let total_length = header.wTotalLength;
+
+            let data =
+                self.get_descriptor(DescriptorType::Configuration, index, 0, total_length)?;
+            if data.len() != usize::from(total_length) {
+                return Err(HostError::InvalidResponse("wTotalLength mismatch"));
+            }
+            for descriptor in parser::parse(&data) {
+                match descriptor? {
+                    Descriptor::Configuration(header) => string_indices.push(header.iConfiguration),
+                    Descriptor::Interface(header) => string_indices.push(header.iInterface),
+                    _ => (),
+                }
+            }
+            enumeration.configuration_descriptors.push(data);
+        }
+
+        // read the supported languages and strings
+        let data = self.get_descriptor(DescriptorType::String, 0, 0, 255)?;
+        let Descriptor::String(language_ids) = Descriptor::parse(&data)?.0 else {
+            return Err(HostError::InvalidResponse(
+                "expected string descriptor zero",
+            ));
+        };
+        enumeration.language_ids = language_ids.code_units().collect();
+        let language_id = enumeration.language_ids.first().copied().unwrap_or(0x0409);
+
+        string_indices.retain(|&index| index != 0);
+        string_indices.sort_unstable();
+        string_indices.dedup();
+        for index in string_indices {
+            let data = self.get_descriptor(DescriptorType::String, index, language_id, 255)?;
+            let Descriptor::String(string) = Descriptor::parse(&data)?.0 else {
+                return Err(HostError::InvalidResponse("expected a string descriptor"));
+            };
+            enumeration.strings.push((index, string.chars().collect()));
+        }
+
+        // select the first configuration
+        let Some(Descriptor::Configuration(header)) = enumeration
+            .configuration_descriptors
+            .first()
+            .and_then(|data| parser::parse(data).next())
+            .and_then(Result::ok)
+        else {
+            return Err(HostError::InvalidResponse("device has no configurations"));
+        };
+        let configuration_value = header.bConfigurationValue;
+        self.set_configuration(configuration_value)?;
+        if self.get_configuration()? != configuration_value {
+            return Err(HostError::InvalidResponse(
+                "SET_CONFIGURATION was not applied",
+            ));
+        }
+
+        Ok(enumeration)
+    }
+
+    fn send_setup_packet<F>(&mut self, setup_packet: SetupPacket, handler: &mut F)
+    where
+        F: FnMut(&MockUsbDriver, SetupPacket, &[u8]),
+    {
+        self.usb.push_setup_packet(setup_packet);
+        let mut buffer = [0_u8; 8];
+        self.usb.read_control(&mut buffer);
+        self.dispatch_with(
+            UsbEvent::ReceiveSetupPacket(Self::ENDPOINT_NUMBER, SetupPacket::from(buffer)),
+            handler,
+        );
+    }
+
+    fn check_stall(&self, start: usize, setup_packet: SetupPacket) -> Result<(), HostError> {
+        let stalled = self.usb.operations.borrow()[start..]
+            .iter()
+            .any(|operation| {
+                matches!(
+                    operation,
+                    Operation::StallIn(Self::ENDPOINT_NUMBER)
+                        | Operation::StallOut(Self::ENDPOINT_NUMBER)
+                )
+            });
+        if stalled {
+            Err(HostError::Stalled(setup_packet))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn has_unhandled_event(&self, setup_packet: SetupPacket) -> bool {
+        let bytes: [u8; 8] = setup_packet.into();
+        self.events.iter().any(|event| {
+            matches!(event, ControlEvent::SetupPacket(packet) if <[u8; 8]>::from(*packet) == bytes)
+        })
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::descriptor::{
+        ConfigurationDescriptor, ConfigurationDescriptorHeader, DeviceDescriptor,
+        EndpointDescriptor, InterfaceDescriptor, InterfaceDescriptorHeader, LanguageId,
+        StringDescriptor, StringDescriptorZero,
+    };
+
+    // - fixtures -------------------------------------------------------------
+
+    static STRING_DESCRIPTOR_1: StringDescriptor = StringDescriptor::new("manufacturer");
+    static STRING_DESCRIPTOR_2: StringDescriptor = StringDescriptor::new("product");
+    static STRING_DESCRIPTOR_3: StringDescriptor = StringDescriptor::new("interface");
+    static STRING_DESCRIPTORS: &[&StringDescriptor] = &[
+        &STRING_DESCRIPTOR_1,
+        &STRING_DESCRIPTOR_2,
+        &STRING_DESCRIPTOR_3,
+    ];
+
+    static CONFIGURATION_DESCRIPTORS: &[ConfigurationDescriptor] = &[ConfigurationDescriptor::new(
+        ConfigurationDescriptorHeader {
+            bConfigurationValue: 1,
+            ..ConfigurationDescriptorHeader::new()
+        },
+        &[InterfaceDescriptor::new(
+            InterfaceDescriptorHeader {
+                iInterfaceNumber: 0,
+                bNumEndpoints: 2,
+                iInterface: 3,
+                ..InterfaceDescriptorHeader::new()
+            },
+            &[
+                EndpointDescriptor {
+                    bEndpointAddress: 0x01,
+                    bmAttributes: 0x02, // Bulk
+                    wMaxPacketSize: 512,
+                    ..EndpointDescriptor::new()
+                },
+                EndpointDescriptor {
+                    bEndpointAddress: 0x81,
+                    bmAttributes: 0x02, // Bulk
+                    wMaxPacketSize: 512,
+                    ..EndpointDescriptor::new()
+                },
+            ],
+        )],
+    )];
+
+    fn descriptors() -> Descriptors<'static> {
+        Descriptors {
+            device_speed: Speed::High,
+            device_descriptor: DeviceDescriptor {
+                bMaxPacketSize: 64,
+                iManufacturer: 1,
+                iProduct: 2,
+                bNumConfigurations: 1,
+                ..DeviceDescriptor::new()
+            },
+            configuration_descriptors: CONFIGURATION_DESCRIPTORS,
+            string_descriptor_zero: StringDescriptorZero::new(&[LanguageId::EnglishUnitedStates]),
+            string_descriptors: STRING_DESCRIPTORS,
+            device_qualifier_descriptor: None,
+            other_speed_configuration_descriptors: None,
+            binary_object_store: None,
+            microsoft10: None,
+            microsoft20: None,
+        }
+    }
+
+    fn vendor_request(direction: Direction, length: u16) -> SetupPacket {
+        SetupPacket {
+            request_type: direction as u8 | 0b0100_0000, // RequestType::Vendor
+            request: 0x42,
+            value: 0,
+            index: 0,
+            length,
+        }
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_mock_driver_records_operations() {
+        let usb = MockUsbDriver::new();
+
+        usb.write(1, [1, 2, 3].into_iter());
+        usb.write_requested(1, 2, [4, 5, 6].into_iter());
+        usb.stall_endpoint_in(2);
+        usb.ack(3, Direction::HostToDevice);
+        usb.set_address(7);
+
+        assert_eq!(usb.writes(1), [[1, 2, 3].to_vec(), [4, 5].to_vec()]);
+        assert_eq!(usb.address(), 7);
+        assert_eq!(
+            usb.take_operations()[2..],
+            [
+                Operation::StallIn(2),
+                Operation::Ack(3, Direction::HostToDevice),
+                Operation::SetAddress(7),
+            ]
+        );
+        assert!(usb.operations().is_empty());
+    }
+
+    #[test]
+    fn test_mock_driver_reads_queued_data() {
+        let usb = MockUsbDriver::new();
+        let mut buffer = [0; 8];
+
+        usb.push_out_data(1, &[1, 2, 3]);
+        usb.push_out_data(1, &[]);
+        assert_eq!(usb.read(2, &mut buffer), 0);
+        assert_eq!(usb.read(1, &mut buffer), 3);
+        assert_eq!(buffer[..3], [1, 2, 3]);
+        assert_eq!(usb.read(1, &mut buffer), 0);
+
+        usb.push_setup_packet(vendor_request(Direction::DeviceToHost, 4));
+        assert_eq!(usb.read_control(&mut buffer), 8);
+        assert_eq!(buffer, [0xc0, 0x42, 0, 0, 0, 0, 4, 0]);
+        assert_eq!(usb.read_control(&mut buffer), 0);
+    }
+
+    #[test]
+    fn test_enumeration() {
+        let mut host: VirtualHost<128> = VirtualHost::new(descriptors());
+
+        let enumeration = host.enumerate(42).unwrap();
+
+        assert_eq!(host.usb.address(), 42);
+        assert_eq!(host.control.configuration(), Some(1));
+        assert_eq!(enumeration.device_descriptor.len(), 18);
+        assert_eq!(enumeration.configuration_descriptors.len(), 1);
+        assert_eq!(
+            enumeration.configuration_descriptors[0].len(),
+            9 + 9 + 7 + 7
+        );
+        assert_eq!(enumeration.language_ids, [0x0409]);
+        assert_eq!(
+            enumeration.strings,
+            [
+                (1, String::from("manufacturer")),
+                (2, String::from("product")),
+                (3, String::from("interface")),
+            ]
+        );
+        assert!(matches!(
+            host.take_events()[..],
+            [ControlEvent::ConfigurationChanged(1)]
+        ));
+    }
+
+    #[test]
+    fn test_unknown_configuration_stalls() {
+        let mut host: VirtualHost<128> = VirtualHost::new(descriptors());
+
+        assert!(matches!(
+            host.set_configuration(2),
+            Err(HostError::Stalled(_))
+        ));
+        assert_eq!(host.control.configuration(), None);
+    }
+
+    #[test]
+    fn test_vendor_requests() {
+        let mut host: VirtualHost<128> = VirtualHost::new(descriptors());
+
+        // device to host requests are passed on to the application
+        assert!(matches!(
+            host.control_in(vendor_request(Direction::DeviceToHost, 4)),
+            Err(HostError::Unhandled(_))
+        ));
+        host.take_events();
+
+        // host to device data is received before the request is passed on
+        let data: Vec<u8> = (0..100).collect();
+        host.control_out(vendor_request(Direction::HostToDevice, 100), &data)
+            .unwrap();
+        assert!(matches!(
+            host.take_events()[..],
+            [ControlEvent::SetupPacket(SetupPacket { length: 100, .. })]
+        ));
+        assert_eq!(host.control.data(), &data[..]);
+    }
+}
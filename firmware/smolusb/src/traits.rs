@@ -1,8 +1,11 @@
 use crate::device::Speed;
-use crate::setup::Direction;
+use crate::error::{EndpointBusy, Unsupported};
+use crate::setup::{Direction, SetupPacket, TestMode};
+use crate::validate::PeripheralLimits;
 
 use zerocopy::AsBytes;
 
+use core::future::Future;
 use core::slice;
 
 // - UsbDriverOperations ------------------------------------------------------
@@ -11,6 +14,11 @@ use core::slice;
 pub trait UsbDriver: ReadControl + ReadEndpoint + WriteEndpoint + UsbDriverOperations {}
 
 pub trait UsbDriverOperations {
+    /// Endpoint limits of the peripheral, checked by
+    /// [`Control`](crate::control::Control) with
+    /// [`Descriptors::validate_peripheral()`](crate::device::Descriptors::validate_peripheral).
+    const PERIPHERAL_LIMITS: PeripheralLimits = PeripheralLimits::SPECIFICATION;
+
     /// Connect the device.
     fn connect(&mut self, device_speed: Speed);
     /// Disconnect the device.
@@ -28,6 +36,36 @@ pub trait UsbDriverOperations {
 
     /// Clear a halt condition on the target endpoint address.
     fn clear_feature_endpoint_halt(&self, endpoint_number: u8, direction: Direction);
+
+    /// Drive resume signalling on the bus to wake a suspended host.
+    ///
+    /// Prefer [`Control::remote_wakeup()`](crate::control::Control::remote_wakeup)
+    /// which checks that the host has enabled remote wakeup.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`Unsupported`] if the peripheral can not drive resume
+    /// signalling, which is the default.
+    fn remote_wakeup(&self) -> Result<(), Unsupported> {
+        Err(Unsupported)
+    }
+
+    /// Returns `true` if the device can be placed in the given USB 2.0
+    /// electrical test mode.
+    ///
+    /// [`Control`](crate::control::Control) stalls `SET_FEATURE(TEST_MODE)`
+    /// requests for test modes that are not supported. By default no
+    /// test modes are supported.
+    fn supports_test_mode(&self, _test_mode: TestMode) -> bool {
+        false
+    }
+
+    /// Place the device in the given USB 2.0 electrical test mode.
+    ///
+    /// Only called for test modes [`supports_test_mode()`](Self::supports_test_mode)
+    /// returns `true` for. The device can only leave a test mode by
+    /// being power cycled.
+    fn test_mode(&self, _test_mode: TestMode) {}
 }
 
 /// These are used to deal with the situation where we need to block
@@ -36,8 +74,8 @@ pub trait UsbDriverOperations {
 /// [`UsbEvent::SendComplete`](crate::event::UsbEvent::SendComplete)
 /// interrupt events.
 ///
-/// Not having to do this is a powerful argument for implementing
-/// async support.
+/// Not having to do this is a powerful argument for using the async
+/// endpoint traits in [`crate::asynch`] instead.
 ///
 /// This is not a particularly safe approach.
 pub trait UnsafeUsbDriverOperations {
@@ -116,6 +154,97 @@ pub trait WriteEndpoint {
     ) -> usize
     where
         I: Iterator<Item = u8>;
+
+    /// Write a single packet to the endpoint and prime it for
+    /// transmission without waiting for the host to acknowledge it.
+    ///
+    /// Returns the number of bytes written to the endpoint.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EndpointBusy`] without consuming the iterator or
+    /// priming the endpoint if it is still busy sending a previous
+    /// packet.
+    fn write_packet<I>(&self, endpoint_number: u8, iter: I) -> Result<usize, EndpointBusy>
+    where
+        I: Iterator<Item = u8>;
+}
+
+// - Isochronous --------------------------------------------------------------
+
+/// Isochronous transfers are scheduled by the host once per service
+/// interval and are never acknowledged, so unlike [`WriteEndpoint`]
+/// these operations never wait for a handshake.
+///
+/// The host does not acknowledge isochronous packets so drivers may
+/// only report [`UsbEvent::SendComplete`](crate::event::UsbEvent::SendComplete)
+/// for them if the peripheral signals that a packet was sent, which the
+/// `lunasoc-hal` driver does not. Packets are instead queued once per
+/// service interval on [`UsbEvent::StartOfFrame`](crate::event::UsbEvent::StartOfFrame)
+/// as paced by a [`FrameSchedule`](crate::isochronous::FrameSchedule).
+///
+/// Each call transfers a single packet of at most
+/// [`EP_MAX_PACKET_SIZE`](crate::EP_MAX_PACKET_SIZE) bytes. High-bandwidth
+/// endpoints with more than one transaction per microframe are not
+/// supported.
+pub trait IsochronousEndpoint {
+    /// Queue a single packet on the given isochronous IN endpoint to be
+    /// sent the next time the host polls it.
+    ///
+    /// A packet still queued from a previous interval is discarded.
+    ///
+    /// Returns the number of bytes queued, which is zero if the
+    /// endpoint FIFO is in use by another endpoint.
+    fn write_isochronous<I>(&self, endpoint_number: u8, iter: I) -> usize
+    where
+        I: Iterator<Item = u8>;
+
+    /// Read a packet from the given isochronous OUT endpoint and
+    /// immediately prime it for the next interval.
+    ///
+    /// Returns the number of bytes read from the endpoint.
+    fn read_isochronous(&self, endpoint_number: u8, buffer: &mut [u8]) -> usize;
+}
+
+// - AsyncRead/AsyncWrite ---------------------------------------------------
+
+/// Async counterpart of [`ReadControl`].
+///
+/// See [`crate::asynch`] for an implementation driven by [`UsbEvent`](crate::event::UsbEvent)s.
+pub trait AsyncReadControl {
+    type ReadControlFuture<'a>: Future<Output = SetupPacket>
+    where
+        Self: 'a;
+
+    /// Wait for a setup packet to be received on the control endpoint.
+    fn read_control_async(&self) -> Self::ReadControlFuture<'_>;
+}
+
+/// Async counterpart of [`ReadEndpoint`].
+pub trait AsyncReadEndpoint {
+    type ReadFuture<'a>: Future<Output = usize>
+    where
+        Self: 'a;
+
+    /// Prime the given OUT endpoint for reception and wait for a packet.
+    ///
+    /// Returns the number of bytes read from the endpoint.
+    fn read_async<'a>(&'a self, endpoint_number: u8, buffer: &'a mut [u8]) -> Self::ReadFuture<'a>;
+}
+
+/// Async counterpart of [`WriteEndpoint`].
+pub trait AsyncWriteEndpoint {
+    type WriteFuture<'a, I>: Future<Output = usize>
+    where
+        Self: 'a,
+        I: Iterator<Item = u8> + Unpin + 'a;
+
+    /// Write iterator to endpoint and wait for the host to acknowledge it.
+    ///
+    /// Returns the number of bytes written to the endpoint.
+    fn write_async<'a, I>(&'a self, endpoint_number: u8, iter: I) -> Self::WriteFuture<'a, I>
+    where
+        I: Iterator<Item = u8> + Unpin + 'a;
 }
 
 // - AsIterator ---------------------------------------------------------------
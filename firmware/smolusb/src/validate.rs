@@ -0,0 +1,780 @@
+//! Consistency checks for `smolusb` device descriptors
+//!
+//! Descriptor mistakes usually only show up as confusing host-side
+//! enumeration failures. [`Descriptors::validate()`] checks a set of
+//! descriptors for consistency so they can be caught in a unit test
+//! or when the [`Control`](crate::control::Control) interface is created.
+//!
+//! [`Descriptors::validate()`] only checks the limits of the USB
+//! specification. [`Descriptors::validate_peripheral()`] additionally
+//! checks endpoints against the [`PeripheralLimits`] of a driver.
+
+use crate::descriptor::{ConfigurationDescriptor, StringDescriptorNumber, TransferType};
+use crate::device::{Descriptors, Speed};
+
+// - ValidationError ----------------------------------------------------------
+
+/// Describes the first inconsistency found in a set of [`Descriptors`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum ValidationError {
+    /// `bMaxPacketSize0` is not valid for the device speed.
+    DeviceMaxPacketSize { max_packet_size: u8 },
+    /// `bNumConfigurations` does not match the number of configuration descriptors.
+    DeviceNumConfigurations { expected: u8, actual: usize },
+    /// A device descriptor field refers to a string index that does not exist.
+    DeviceStringIndex { index: u8 },
+
+    /// `bConfigurationValue` is zero or used by more than one configuration.
+    ConfigurationValue { configuration: u8 },
+    /// `wTotalLength` does not match the length of the configuration.
+    ConfigurationTotalLength {
+        configuration: u8,
+        expected: u16,
+        actual: usize,
+    },
+    /// `bNumInterfaces` does not match the number of interfaces in the configuration.
+    ConfigurationNumInterfaces {
+        configuration: u8,
+        expected: u8,
+        actual: usize,
+    },
+    /// The configuration has more interfaces than [`MAX_INTERFACES`](crate::MAX_INTERFACES).
+    ConfigurationMaxInterfaces {
+        configuration: u8,
+        interfaces: usize,
+    },
+    /// `iConfiguration` refers to a string index that does not exist.
+    ConfigurationStringIndex { configuration: u8, index: u8 },
+
+    /// An interface and alternate setting is declared more than once.
+    InterfaceDuplicate {
+        configuration: u8,
+        interface: u8,
+        alternate_setting: u8,
+    },
+    /// Interface numbers are not contiguous and starting from zero.
+    InterfaceNumber { configuration: u8, interface: u8 },
+    /// `bNumEndpoints` does not match the number of endpoint descriptors.
+    InterfaceNumEndpoints {
+        configuration: u8,
+        interface: u8,
+        alternate_setting: u8,
+        expected: u8,
+        actual: usize,
+    },
+    /// `iInterface` or `iFunction` refers to a string index that does not exist.
+    InterfaceStringIndex {
+        configuration: u8,
+        interface: u8,
+        index: u8,
+    },
+
+    /// `bEndpointAddress` is the control endpoint or out of range.
+    EndpointAddress { configuration: u8, address: u8 },
+    /// `bEndpointAddress` is used by more than one interface.
+    EndpointDuplicate { configuration: u8, address: u8 },
+    /// `wMaxPacketSize` exceeds the maximum packet size for the device
+    /// speed or peripheral.
+    EndpointMaxPacketSize {
+        configuration: u8,
+        address: u8,
+        max_packet_size: u16,
+    },
+    /// `wMaxPacketSize` requests more transactions per microframe than
+    /// the endpoint type, device speed or peripheral allows.
+    EndpointTransactions {
+        configuration: u8,
+        address: u8,
+        transactions: u8,
+    },
+
+    /// A string descriptor is too long to be encoded.
+    StringLength { index: u8 },
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self, f)
+    }
+}
+
+#[cfg(feature = "nightly")]
+impl core::error::Error for ValidationError {
+    #[allow(deprecated)]
+    fn description(&self) -> &str {
+        "Inconsistent USB descriptors"
+    }
+}
+
+pub type ValidationResult = core::result::Result<(), ValidationError>;
+
+// - PeripheralLimits ---------------------------------------------------------
+
+/// Endpoint limits of a device peripheral which are stricter than those
+/// of the USB specification.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PeripheralLimits {
+    /// The largest `wMaxPacketSize` of any endpoint.
+    pub max_packet_size: usize,
+    /// Whether high-bandwidth endpoints with more than one transaction
+    /// per microframe are supported.
+    pub high_bandwidth: bool,
+}
+
+impl PeripheralLimits {
+    /// The limits of the USB specification.
+    pub const SPECIFICATION: Self = Self {
+        max_packet_size: 1024,
+        high_bandwidth: true,
+    };
+}
+
+// - Descriptors::validate ----------------------------------------------------
+
+impl Descriptors<'_> {
+    /// Checks the descriptors for consistency.
+    ///
+    /// Returns the first [`ValidationError`] found.
+    pub fn validate(&self) -> ValidationResult {
+        self.validate_device()?;
+        self.validate_strings()?;
+
+        for configuration in self.configuration_descriptors {
+            self.validate_configuration(configuration, self.device_speed)?;
+        }
+        for (index, configuration) in self.configuration_descriptors.iter().enumerate() {
+            let value = configuration.head.bConfigurationValue;
+            let duplicate = self.configuration_descriptors[..index]
+                .iter()
+                .any(|other| other.head.bConfigurationValue == value);
+            if value == 0 || duplicate {
+                return Err(ValidationError::ConfigurationValue {
+                    configuration: value,
+                });
+            }
+        }
+
+        if let Some(other_speed_configuration_descriptors) =
+            self.other_speed_configuration_descriptors
+        {
+            for configuration in other_speed_configuration_descriptors {
+                self.validate_configuration(configuration, Speed::Full)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_device(&self) -> ValidationResult {
+        let descriptor = &self.device_descriptor;
+
+        let max_packet_size = descriptor.bMaxPacketSize;
+        let valid = match self.device_speed {
+            Speed::High => max_packet_size == 64,
+            Speed::Low => max_packet_size == 8,
+            _ => matches!(max_packet_size, 8 | 16 | 32 | 64),
+        };
+        if !valid {
+            return Err(ValidationError::DeviceMaxPacketSize { max_packet_size });
+        }
+
+        if usize::from(descriptor.bNumConfigurations) != self.configuration_descriptors.len() {
+            return Err(ValidationError::DeviceNumConfigurations {
+                expected: descriptor.bNumConfigurations,
+                actual: self.configuration_descriptors.len(),
+            });
+        }
+
+        for index in [
+            descriptor.iManufacturer,
+            descriptor.iProduct,
+            descriptor.iSerialNumber,
+        ] {
+            if !self.is_valid_string_index(index) {
+                return Err(ValidationError::DeviceStringIndex { index });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_configuration(
+        &self,
+        descriptor: &ConfigurationDescriptor,
+        speed: Speed,
+    ) -> ValidationResult {
+        let head = &descriptor.head;
+        let configuration = head.bConfigurationValue;
+
+        let total_length = descriptor.iter().count();
+        if usize::from(head.wTotalLength) != total_length {
+            return Err(ValidationError::ConfigurationTotalLength {
+                configuration,
+                expected: head.wTotalLength,
+                actual: total_length,
+            });
+        }
+
+        if !self.is_valid_string_index(head.iConfiguration) {
+            return Err(ValidationError::ConfigurationStringIndex {
+                configuration,
+                index: head.iConfiguration,
+            });
+        }
+
+        // alternate settings do not count towards the number of interfaces
+        let num_interfaces = descriptor
+            .tail
+            .iter()
+            .filter(|interface| interface.head.bAlternateSetting == 0)
+            .count();
+        if usize::from(head.bNumInterfaces) != num_interfaces {
+            return Err(ValidationError::ConfigurationNumInterfaces {
+                configuration,
+                expected: head.bNumInterfaces,
+                actual: num_interfaces,
+            });
+        }
+        if num_interfaces > crate::MAX_INTERFACES {
+            return Err(ValidationError::ConfigurationMaxInterfaces {
+                configuration,
+                interfaces: num_interfaces,
+            });
+        }
+
+        for (index, interface) in descriptor.tail.iter().enumerate() {
+            let interface_number = interface.head.iInterfaceNumber;
+            let alternate_setting = interface.head.bAlternateSetting;
+
+            if usize::from(interface_number) >= num_interfaces {
+                return Err(ValidationError::InterfaceNumber {
+                    configuration,
+                    interface: interface_number,
+                });
+            }
+
+            if descriptor.tail[..index].iter().any(|other| {
+                other.head.iInterfaceNumber == interface_number
+                    && other.head.bAlternateSetting == alternate_setting
+            }) {
+                return Err(ValidationError::InterfaceDuplicate {
+                    configuration,
+                    interface: interface_number,
+                    alternate_setting,
+                });
+            }
+
+            if usize::from(interface.head.bNumEndpoints) != interface.tail2.len() {
+                return Err(ValidationError::InterfaceNumEndpoints {
+                    configuration,
+                    interface: interface_number,
+                    alternate_setting,
+                    expected: interface.head.bNumEndpoints,
+                    actual: interface.tail2.len(),
+                });
+            }
+
+            let function_string_index = interface
+                .association
+                .map_or(0, |association| association.iFunction);
+            for string_index in [interface.head.iInterface, function_string_index] {
+                if !self.is_valid_string_index(string_index) {
+                    return Err(ValidationError::InterfaceStringIndex {
+                        configuration,
+                        interface: interface_number,
+                        index: string_index,
+                    });
+                }
+            }
+
+            for (endpoint_index, endpoint) in interface.tail2.iter().enumerate() {
+                let address = endpoint.bEndpointAddress;
+                let endpoint_number = address & 0x7f;
+
+                if endpoint_number == 0 || usize::from(endpoint_number) >= crate::EP_MAX_ENDPOINTS {
+                    return Err(ValidationError::EndpointAddress {
+                        configuration,
+                        address,
+                    });
+                }
+
+                // endpoints may only be shared by alternate settings of the same interface
+                let duplicate_in_interface = interface.tail2[..endpoint_index]
+                    .iter()
+                    .any(|other| other.bEndpointAddress == address);
+                let duplicate_in_configuration =
+                    descriptor.tail[..index].iter().any(|other_interface| {
+                        other_interface.head.iInterfaceNumber != interface_number
+                            && other_interface
+                                .tail2
+                                .iter()
+                                .any(|other| other.bEndpointAddress == address)
+                    });
+                if duplicate_in_interface || duplicate_in_configuration {
+                    return Err(ValidationError::EndpointDuplicate {
+                        configuration,
+                        address,
+                    });
+                }
+
+                // only high-speed isochronous and interrupt endpoints may
+                // be high-bandwidth or exceed the bulk packet size
+                let transfer_type = endpoint.transfer_type();
+                let periodic = matches!(
+                    transfer_type,
+                    TransferType::Isochronous | TransferType::Interrupt
+                );
+                let (max_packet_size_limit, max_transactions) = match speed {
+                    Speed::High if periodic => (1024, 3),
+                    _ if transfer_type == TransferType::Isochronous => (1023, 1),
+                    _ => (crate::max_packet_size(speed, endpoint_number), 1),
+                };
+
+                let max_packet_size = endpoint.max_packet_size();
+                if usize::from(max_packet_size) > max_packet_size_limit {
+                    return Err(ValidationError::EndpointMaxPacketSize {
+                        configuration,
+                        address,
+                        max_packet_size,
+                    });
+                }
+
+                let transactions = endpoint.transactions_per_microframe();
+                if transactions > max_transactions {
+                    return Err(ValidationError::EndpointTransactions {
+                        configuration,
+                        address,
+                        transactions,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks the endpoints of all configurations against the limits
+    /// of a device peripheral.
+    ///
+    /// Returns the first [`ValidationError`] found.
+    pub fn validate_peripheral(&self, limits: PeripheralLimits) -> ValidationResult {
+        let other_speed_configuration_descriptors =
+            self.other_speed_configuration_descriptors.unwrap_or(&[]);
+
+        for configuration in self
+            .configuration_descriptors
+            .iter()
+            .chain(other_speed_configuration_descriptors)
+        {
+            let endpoints = configuration
+                .tail
+                .iter()
+                .flat_map(|interface| interface.tail2.iter());
+            for endpoint in endpoints {
+                let max_packet_size = endpoint.max_packet_size();
+                if usize::from(max_packet_size) > limits.max_packet_size {
+                    return Err(ValidationError::EndpointMaxPacketSize {
+                        configuration: configuration.head.bConfigurationValue,
+                        address: endpoint.bEndpointAddress,
+                        max_packet_size,
+                    });
+                }
+
+                let transactions = endpoint.transactions_per_microframe();
+                if transactions > 1 && !limits.high_bandwidth {
+                    return Err(ValidationError::EndpointTransactions {
+                        configuration: configuration.head.bConfigurationValue,
+                        address: endpoint.bEndpointAddress,
+                        transactions,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_strings(&self) -> ValidationResult {
+        for (offset, descriptor) in self.string_descriptors.iter().enumerate() {
+            // string descriptors are limited to 255 bytes
+            let length = 2 + descriptor.tail.encode_utf16().count() * 2;
+            if length > usize::from(u8::MAX) {
+                #[allow(clippy::cast_possible_truncation)]
+                return Err(ValidationError::StringLength {
+                    index: (offset + 1) as u8,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_valid_string_index(&self, index: u8) -> bool {
+        match index {
+            StringDescriptorNumber::Zero => true,
+            StringDescriptorNumber::Microsoft => self.microsoft10.is_some(),
+            index => usize::from(index) <= self.string_descriptors.len(),
+        }
+    }
+}
+
+// - tests --------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::descriptor::{
+        ConfigurationDescriptorHeader, DeviceDescriptor, EndpointDescriptor, InterfaceDescriptor,
+        InterfaceDescriptorHeader, LanguageId, StringDescriptor, StringDescriptorZero,
+        SynchronizationType, UsageType,
+    };
+
+    // - fixtures -------------------------------------------------------------
+
+    static STRING_DESCRIPTOR_1: StringDescriptor = StringDescriptor::new("manufacturer");
+    static STRING_DESCRIPTOR_2: StringDescriptor = StringDescriptor::new("product");
+    static STRING_DESCRIPTORS: &[&StringDescriptor] = &[&STRING_DESCRIPTOR_1, &STRING_DESCRIPTOR_2];
+
+    const DEVICE_DESCRIPTOR: DeviceDescriptor = DeviceDescriptor {
+        bMaxPacketSize: 64,
+        iManufacturer: 1,
+        iProduct: 2,
+        bNumConfigurations: 1,
+        ..DeviceDescriptor::new()
+    };
+
+    const ENDPOINT_DESCRIPTOR: EndpointDescriptor = EndpointDescriptor {
+        bEndpointAddress: 0x81,
+        bmAttributes: 0x02, // Bulk
+        wMaxPacketSize: 512,
+        ..EndpointDescriptor::new()
+    };
+
+    fn descriptors<'a>(
+        device_descriptor: DeviceDescriptor,
+        configuration_descriptors: &'a [ConfigurationDescriptor<'a>],
+    ) -> Descriptors<'a> {
+        Descriptors {
+            device_speed: Speed::High,
+            device_descriptor,
+            configuration_descriptors,
+            string_descriptor_zero: StringDescriptorZero::new(&[LanguageId::EnglishUnitedStates]),
+            string_descriptors: STRING_DESCRIPTORS,
+            device_qualifier_descriptor: None,
+            other_speed_configuration_descriptors: None,
+            binary_object_store: None,
+            microsoft10: None,
+            microsoft20: None,
+        }
+    }
+
+    fn configuration<'a>(interfaces: &'a [InterfaceDescriptor<'a>]) -> ConfigurationDescriptor<'a> {
+        ConfigurationDescriptor::new(
+            ConfigurationDescriptorHeader {
+                bConfigurationValue: 1,
+                ..ConfigurationDescriptorHeader::new()
+            },
+            interfaces,
+        )
+    }
+
+    fn interface<'a>(
+        number: u8,
+        alternate_setting: u8,
+        endpoints: &'a [EndpointDescriptor],
+    ) -> InterfaceDescriptor<'a> {
+        InterfaceDescriptor::new(
+            InterfaceDescriptorHeader {
+                iInterfaceNumber: number,
+                bAlternateSetting: alternate_setting,
+                ..InterfaceDescriptorHeader::new()
+            },
+            endpoints,
+        )
+    }
+
+    // - tests ----------------------------------------------------------------
+
+    #[test]
+    fn test_valid_descriptors() {
+        let interfaces = [
+            interface(0, 0, &[ENDPOINT_DESCRIPTOR]),
+            interface(0, 1, &[ENDPOINT_DESCRIPTOR]),
+        ];
+        let configurations = [configuration(&interfaces)];
+
+        assert_eq!(
+            descriptors(DEVICE_DESCRIPTOR, &configurations).validate(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn test_device_num_configurations() {
+        let interfaces = [interface(0, 0, &[])];
+        let configurations = [configuration(&interfaces)];
+        let device_descriptor = DeviceDescriptor {
+            bNumConfigurations: 2,
+            ..DEVICE_DESCRIPTOR
+        };
+
+        assert_eq!(
+            descriptors(device_descriptor, &configurations).validate(),
+            Err(ValidationError::DeviceNumConfigurations {
+                expected: 2,
+                actual: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_string_index() {
+        let interfaces = [interface(0, 0, &[])];
+        let configurations = [configuration(&interfaces)];
+        let device_descriptor = DeviceDescriptor {
+            iSerialNumber: 3,
+            ..DEVICE_DESCRIPTOR
+        };
+
+        assert_eq!(
+            descriptors(device_descriptor, &configurations).validate(),
+            Err(ValidationError::DeviceStringIndex { index: 3 })
+        );
+    }
+
+    #[test]
+    fn test_interface_num_endpoints() {
+        let mut interfaces = [interface(0, 0, &[ENDPOINT_DESCRIPTOR])];
+        interfaces[0].head.bNumEndpoints = 2;
+        let configurations = [configuration(&interfaces)];
+
+        assert_eq!(
+            descriptors(DEVICE_DESCRIPTOR, &configurations).validate(),
+            Err(ValidationError::InterfaceNumEndpoints {
+                configuration: 1,
+                interface: 0,
+                alternate_setting: 0,
+                expected: 2,
+                actual: 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_configuration_max_interfaces() {
+        let interfaces: std::vec::Vec<InterfaceDescriptor> = (0..)
+            .take(crate::MAX_INTERFACES + 1)
+            .map(|number| interface(number, 0, &[]))
+            .collect();
+        let configurations = [configuration(&interfaces)];
+
+        assert_eq!(
+            descriptors(DEVICE_DESCRIPTOR, &configurations).validate(),
+            Err(ValidationError::ConfigurationMaxInterfaces {
+                configuration: 1,
+                interfaces: crate::MAX_INTERFACES + 1,
+            })
+        );
+    }
+
+    #[test]
+    fn test_endpoint_duplicate() {
+        let interfaces = [
+            interface(0, 0, &[ENDPOINT_DESCRIPTOR]),
+            interface(1, 0, &[ENDPOINT_DESCRIPTOR]),
+        ];
+        let configurations = [configuration(&interfaces)];
+
+        assert_eq!(
+            descriptors(DEVICE_DESCRIPTOR, &configurations).validate(),
+            Err(ValidationError::EndpointDuplicate {
+                configuration: 1,
+                address: 0x81,
+            })
+        );
+    }
+
+    #[test]
+    fn test_endpoint_max_packet_size() {
+        let endpoints = [EndpointDescriptor {
+            wMaxPacketSize: 1024,
+            ..ENDPOINT_DESCRIPTOR
+        }];
+        let interfaces = [interface(0, 0, &endpoints)];
+        let configurations = [configuration(&interfaces)];
+
+        assert_eq!(
+            descriptors(DEVICE_DESCRIPTOR, &configurations).validate(),
+            Err(ValidationError::EndpointMaxPacketSize {
+                configuration: 1,
+                address: 0x81,
+                max_packet_size: 1024,
+            })
+        );
+    }
+
+    #[test]
+    fn test_other_speed_max_packet_size() {
+        let interfaces = [interface(0, 0, &[ENDPOINT_DESCRIPTOR])];
+        let configurations = [configuration(&interfaces)];
+        let mut descriptors = descriptors(DEVICE_DESCRIPTOR, &configurations);
+        descriptors.other_speed_configuration_descriptors = Some(&configurations);
+
+        assert_eq!(
+            descriptors.validate(),
+            Err(ValidationError::EndpointMaxPacketSize {
+                configuration: 1,
+                address: 0x81,
+                max_packet_size: 512,
+            })
+        );
+    }
+
+    #[test]
+    fn test_isochronous_endpoint() {
+        let endpoints = [EndpointDescriptor::isochronous(
+            0x81,
+            SynchronizationType::Asynchronous,
+            UsageType::Data,
+            1024,
+            1,
+            1,
+        )];
+        let interfaces = [interface(0, 0, &endpoints)];
+        let configurations = [configuration(&interfaces)];
+        let mut descriptors = descriptors(DEVICE_DESCRIPTOR, &configurations);
+        assert_eq!(descriptors.validate(), Ok(()));
+
+        // full-speed isochronous packets are limited to 1023 bytes
+        descriptors.device_speed = Speed::Full;
+        assert_eq!(
+            descriptors.validate(),
+            Err(ValidationError::EndpointMaxPacketSize {
+                configuration: 1,
+                address: 0x81,
+                max_packet_size: 1024,
+            })
+        );
+
+        // but are larger than bulk packets
+        let endpoints = [EndpointDescriptor::isochronous(
+            0x81,
+            SynchronizationType::Asynchronous,
+            UsageType::Data,
+            1023,
+            1,
+            1,
+        )];
+        let interfaces = [interface(0, 0, &endpoints)];
+        let configurations = [configuration(&interfaces)];
+        descriptors.configuration_descriptors = &configurations;
+        assert_eq!(descriptors.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_high_bandwidth_endpoint() {
+        let endpoints = [EndpointDescriptor::isochronous(
+            0x81,
+            SynchronizationType::Asynchronous,
+            UsageType::Data,
+            1024,
+            3,
+            1,
+        )];
+        let interfaces = [interface(0, 0, &endpoints)];
+        let configurations = [configuration(&interfaces)];
+        let mut descriptors = descriptors(DEVICE_DESCRIPTOR, &configurations);
+        assert_eq!(descriptors.validate(), Ok(()));
+
+        // high-bandwidth endpoints are only allowed at high-speed
+        descriptors.device_speed = Speed::Full;
+        assert_eq!(
+            descriptors.validate(),
+            Err(ValidationError::EndpointMaxPacketSize {
+                configuration: 1,
+                address: 0x81,
+                max_packet_size: 1024,
+            })
+        );
+
+        // and only for isochronous and interrupt endpoints
+        let endpoints = [EndpointDescriptor {
+            wMaxPacketSize: EndpointDescriptor::max_packet_size_with_transactions(512, 2),
+            ..ENDPOINT_DESCRIPTOR
+        }];
+        let interfaces = [interface(0, 0, &endpoints)];
+        let configurations = [configuration(&interfaces)];
+        descriptors.configuration_descriptors = &configurations;
+        descriptors.device_speed = Speed::High;
+        assert_eq!(
+            descriptors.validate(),
+            Err(ValidationError::EndpointTransactions {
+                configuration: 1,
+                address: 0x81,
+                transactions: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_peripheral_limits() {
+        const LIMITS: PeripheralLimits = PeripheralLimits {
+            max_packet_size: 512,
+            high_bandwidth: false,
+        };
+
+        let interfaces = [interface(0, 0, &[ENDPOINT_DESCRIPTOR])];
+        let configurations = [configuration(&interfaces)];
+        let mut descriptors = descriptors(DEVICE_DESCRIPTOR, &configurations);
+        assert_eq!(descriptors.validate_peripheral(LIMITS), Ok(()));
+
+        // isochronous packets can not exceed the peripheral limit
+        let endpoints = [EndpointDescriptor::isochronous(
+            0x81,
+            SynchronizationType::Asynchronous,
+            UsageType::Data,
+            1024,
+            1,
+            1,
+        )];
+        let interfaces = [interface(0, 0, &endpoints)];
+        let configurations = [configuration(&interfaces)];
+        descriptors.configuration_descriptors = &configurations;
+        assert_eq!(descriptors.validate(), Ok(()));
+        assert_eq!(
+            descriptors.validate_peripheral(LIMITS),
+            Err(ValidationError::EndpointMaxPacketSize {
+                configuration: 1,
+                address: 0x81,
+                max_packet_size: 1024,
+            })
+        );
+        assert_eq!(
+            descriptors.validate_peripheral(PeripheralLimits::SPECIFICATION),
+            Ok(())
+        );
+
+        // and high-bandwidth endpoints may not be supported
+        let endpoints = [EndpointDescriptor::isochronous(
+            0x81,
+            SynchronizationType::Asynchronous,
+            UsageType::Data,
+            512,
+            2,
+            1,
+        )];
+        let interfaces = [interface(0, 0, &endpoints)];
+        let configurations = [configuration(&interfaces)];
+        descriptors.configuration_descriptors = &configurations;
+        assert_eq!(descriptors.validate(), Ok(()));
+        assert_eq!(
+            descriptors.validate_peripheral(LIMITS),
+            Err(ValidationError::EndpointTransactions {
+                configuration: 1,
+                address: 0x81,
+                transactions: 2,
+            })
+        );
+    }
+}